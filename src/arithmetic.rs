@@ -0,0 +1,329 @@
+use std::ops::{Add, AddAssign, Mul, Sub};
+use crate::element::Element;
+use crate::matrix::Matrix;
+use crate::multiplication::{multiply, MultiplyError};
+use crate::parallel::parallel_over_rows;
+use crate::shape::mismatch_message;
+
+impl<T> Matrix<T> {
+    /// Applies `op` to every pair of elements at the same position.
+    fn zip_with(&self, other: &Matrix<T>, op: impl Fn(&T, &T) -> T) -> Result<Matrix<T>, String> {
+        if self.shape() != other.shape() {
+            return Err(mismatch_message(self.shape(), other.shape()))
+        }
+
+        let numbers = self.get_numbers().iter().zip(other.get_numbers().iter()).map(|(a, b)| op(a, b)).collect();
+
+        Matrix::new(self.rows(), self.columns(), numbers)
+    }
+}
+
+impl<T> Matrix<T>
+    where
+        for<'a> &'a T: Add<Output=T> {
+
+    /// Element-wise A + B; the shapes must be equal.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(&self, other: &Matrix<T>) -> Result<Matrix<T>, String> {
+        self.zip_with(other, |a, b| a + b)
+    }
+}
+
+impl<T> Matrix<T>
+    where
+        for<'a> &'a T: Sub<Output=T> {
+
+    /// Element-wise A - B; the shapes must be equal.
+    #[allow(clippy::should_implement_trait)]
+    pub fn sub(&self, other: &Matrix<T>) -> Result<Matrix<T>, String> {
+        self.zip_with(other, |a, b| a - b)
+    }
+}
+
+impl<T: Clone> Matrix<T> {
+    /// The main diagonal of a square matrix, from the top left.
+    pub fn diagonal(&self) -> Result<Vec<T>, String> {
+        if self.rows() != self.columns() {
+            return Err(format!("Diagonal needs a square matrix, got {}", self.shape()))
+        }
+
+        let n = self.rows();
+        let numbers = self.get_numbers();
+
+        Ok((0..n).map(|i| numbers[i * n + i].clone()).collect())
+    }
+}
+
+impl<T: Element + Add<Output=T>> Matrix<T> {
+    /// Sum of the main diagonal of a square matrix, zero if it's empty.
+    pub fn trace(&self) -> Result<T, String> {
+        if self.rows() != self.columns() {
+            return Err(format!("Trace needs a square matrix, got {}", self.shape()))
+        }
+
+        Ok(self.diagonal()?.into_iter().fold(T::ZERO, |sum, num| sum + num))
+    }
+}
+
+impl<T: Element> Matrix<T> {
+    /// The n×n matrix with `values` on the main diagonal and zeros elsewhere.
+    pub fn from_diagonal(values: Vec<T>) -> Matrix<T> {
+        let n = values.len();
+
+        Matrix::from_fn(n, n, |i, j| if i == j { values[i] } else { T::ZERO }).unwrap()
+    }
+}
+
+impl<T> Matrix<T>
+    where
+        for<'a> &'a T: Mul<Output=T> {
+
+    /// Row i multiplied by `factors[i]`: the same as D·A for D =
+    /// [`Matrix::from_diagonal`] of the factors, in O(n²) rather than O(n³).
+    pub fn scale_rows(&self, factors: &[T]) -> Result<Matrix<T>, String> {
+        if factors.len() != self.rows() {
+            return Err(format!("Got {} row factors for {}", factors.len(), self.shape()))
+        }
+
+        Ok(self.map_indexed(|row, _, num| &factors[row] * num))
+    }
+}
+
+impl<T> Matrix<T>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Element + Sync + Send {
+
+    /// xᵀ·B for the coefficients x: the sum of every row scaled by its
+    /// coefficient, reading the rows contiguously. Each thread sums a band of
+    /// rows and the partial vectors are added at the end. Rows with a zero
+    /// coefficient are skipped, so their infinities and NaNs don't propagate.
+    pub fn combine_rows(&self, coefficients: &[T], num_of_threads: usize) -> Result<Vec<T>, String> {
+        let (rows, columns) = (self.rows(), self.columns());
+
+        if coefficients.len() != rows {
+            return Err(format!("Got {} coefficients for a matrix with {} rows", coefficients.len(), rows))
+        }
+
+        if num_of_threads == 0 {
+            return Err(MultiplyError::NoThreads.into())
+        }
+
+        if num_of_threads > rows {
+            return Err(MultiplyError::TooManyThreads { num_of_threads, rows }.into())
+        }
+
+        let numbers = self.get_numbers();
+
+        parallel_over_rows(rows, num_of_threads, |band| {
+            let mut sum = vec![T::ZERO; columns];
+            for row in band.filter(|&row| coefficients[row] != T::ZERO) {
+                for (total, num) in sum.iter_mut().zip(&numbers[row * columns..(row + 1) * columns]) {
+                    *total += &coefficients[row] * num;
+                }
+            }
+            sum
+        }, |partial_sums| partial_sums.into_iter().reduce(|mut sum, partial| {
+            for (total, num) in sum.iter_mut().zip(partial) {
+                *total += num;
+            }
+            sum
+        }).unwrap_or_else(|| vec![T::ZERO; columns]))
+    }
+}
+
+impl<T> Matrix<T>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Element + Sync + Send + 'static {
+
+    /// A^`exp` of a square matrix by repeated squaring, so A^16 takes four
+    /// multiplications. A^0 is the identity and A^1 a copy.
+    pub fn pow(&self, exp: u32, num_of_threads: usize) -> Result<Matrix<T>, String> {
+        let n = self.rows();
+
+        if n != self.columns() {
+            return Err(format!("Power needs a square matrix, got {}", self.shape()))
+        }
+
+        if num_of_threads == 0 {
+            return Err(MultiplyError::NoThreads.into())
+        }
+
+        if num_of_threads > n {
+            return Err(MultiplyError::TooManyThreads { num_of_threads, rows: n }.into())
+        }
+
+        let (mut result, mut square, mut exp) = (None, self.clone(), exp);
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = Some(match result {
+                    Some(result) => multiply(&result, &square, num_of_threads)?,
+                    None => square.clone(),
+                });
+            }
+
+            exp >>= 1;
+            if exp > 0 {
+                square = multiply(&square, &square, num_of_threads)?;
+            }
+        }
+
+        Ok(result.unwrap_or_else(|| Matrix::<T>::identity(n)))
+    }
+}
+
+/// `&a + &b`, panicking if the shapes differ; [`Matrix::add`] returns the error instead.
+impl<T> Add for &Matrix<T>
+    where
+        for<'a> &'a T: Add<Output=T> {
+
+    type Output = Matrix<T>;
+
+    fn add(self, other: &Matrix<T>) -> Matrix<T> {
+        Matrix::<T>::add(self, other).unwrap_or_else(|error| panic!("Cannot add matrices: {}", error))
+    }
+}
+
+/// `&a - &b`, panicking if the shapes differ; [`Matrix::sub`] returns the error instead.
+impl<T> Sub for &Matrix<T>
+    where
+        for<'a> &'a T: Sub<Output=T> {
+
+    type Output = Matrix<T>;
+
+    fn sub(self, other: &Matrix<T>) -> Matrix<T> {
+        Matrix::<T>::sub(self, other).unwrap_or_else(|error| panic!("Cannot subtract matrices: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod arithmetic_test {
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+
+    #[test]
+    fn add_and_sub_integers_and_floats() {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+        let b = Matrix::new(2, 2, vec![10, -20, 30, 0]).unwrap();
+
+        assert_eq!(a.add(&b), Matrix::new(2, 2, vec![11, -18, 33, 4]));
+        assert_eq!(&a - &b, Matrix::new(2, 2, vec![-9, 22, -27, 4]).unwrap());
+
+        let x = Matrix::new(1, 3, vec![0.5, 1.0, -2.0]).unwrap();
+        let y = Matrix::new(1, 3, vec![0.25, 1.0, 2.0]).unwrap();
+
+        assert_eq!(&x + &y, Matrix::new(1, 3, vec![0.75, 2.0, 0.0]).unwrap());
+        assert_eq!(x.sub(&y), Matrix::new(1, 3, vec![0.25, 0.0, -4.0]));
+    }
+
+    #[test]
+    fn shape_mismatch_names_both_shapes() {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+        let b = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(a.add(&b).unwrap_err(), "Dimension mismatch: expected 2x2, got 2x3");
+        assert_eq!(b.sub(&a).unwrap_err(), "Dimension mismatch: expected 2x3, got 2x2");
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add matrices: Dimension mismatch: expected 1x2, got 2x1")]
+    fn add_operator_panics_on_mismatch() {
+        let _ = &Matrix::new(1, 2, vec![1, 2]).unwrap() + &Matrix::new(2, 1, vec![1, 2]).unwrap();
+    }
+
+    #[test]
+    fn trace_and_diagonal() {
+        for n in [0, 1, 4, 9] {
+            assert_eq!(Matrix::<i64>::identity(n).trace(), Ok(n as i64));
+        }
+
+        let matrix = Matrix::new(3, 3, vec![1.5, 9.0, 9.0, 9.0, -2.0, 9.0, 9.0, 9.0, 0.25]).unwrap();
+        assert_eq!(matrix.diagonal(), Ok(vec![1.5, -2.0, 0.25]));
+        assert_eq!(matrix.trace(), Ok(-0.25));
+
+        let wide = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(wide.trace().unwrap_err(), "Trace needs a square matrix, got 2x3");
+        assert_eq!(wide.diagonal().unwrap_err(), "Diagonal needs a square matrix, got 2x3");
+    }
+
+    #[test]
+    fn from_diagonal_and_scale_rows() {
+        assert_eq!(Matrix::from_diagonal(vec![2, -1, 5]), Matrix::new(3, 3, vec![2, 0, 0, 0, -1, 0, 0, 0, 5]).unwrap());
+
+        let matrix = Matrix::new(3, 4, (0..12).map(|num| num as f64 - 5.5).collect()).unwrap();
+        let factors = [0.5, -3.0, 0.0];
+        assert_eq!(matrix.scale_rows(&factors), multiply(&Matrix::from_diagonal(factors.to_vec()), &matrix, 2));
+        assert_eq!(matrix.scale_rows(&[1.0]).unwrap_err(), "Got 1 row factors for 3x4");
+
+        let empty = Matrix::<i32>::from_diagonal(vec![]);
+        assert_eq!((empty.rows(), empty.columns()), (0, 0));
+        assert_eq!(empty.scale_rows(&[]), Ok(empty.clone()));
+    }
+
+    #[test]
+    fn combine_rows_matches_row_vector_product() {
+        let matrix = Matrix::new(5, 3, (0..15).map(|num| num * num - 7).collect()).unwrap();
+        let coefficients = [2, -1, 0, 3, 1];
+        let x = Matrix::new(1, 5, coefficients.to_vec()).unwrap();
+        let expected = multiply(&x, &matrix, 1).unwrap().get_numbers().to_vec();
+
+        for threads in 1..=5 {
+            assert_eq!(matrix.combine_rows(&coefficients, threads), Ok(expected.clone()));
+        }
+
+        let floats = Matrix::new(3, 2, vec![0.5, f64::INFINITY, -1.0, 2.0, 4.0, f64::NAN]).unwrap();
+        assert_eq!(floats.combine_rows(&[2.0, 0.0, 0.0], 2), Ok(vec![1.0, f64::INFINITY]));
+        assert_eq!(floats.combine_rows(&[0.0, 0.5, 0.0], 3), Ok(vec![-0.5, 1.0]));
+    }
+
+    #[test]
+    fn combine_rows_validates_lengths() {
+        let matrix = Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+
+        assert_eq!(matrix.combine_rows(&[1, 2, 3], 1).unwrap_err(), "Got 3 coefficients for a matrix with 2 rows");
+        assert!(matrix.combine_rows(&[1, 2], 3).is_err());
+        assert!(matrix.combine_rows(&[1, 2], 0).is_err());
+    }
+
+    #[test]
+    fn pow_matches_chained_multiplies() {
+        // i64, with a power whose elements no longer fit in i32.
+        let a = Matrix::new(3, 3, vec![1i64, 2, 0, -1, 1, 3, 2, 0, 1]).unwrap();
+        let chained = (1..21).fold(a.clone(), |power, _| multiply(&power, &a, 1).unwrap());
+        assert!(chained.get_numbers().iter().any(|&num| i32::try_from(num).is_err()));
+        assert_eq!(a.pow(21, 2), Ok(chained));
+
+        let x = Matrix::new(2, 2, vec![0.9f64, 0.1, 0.4, 0.6]).unwrap();
+        let chained = (1..5).fold(x.clone(), |power, _| multiply(&power, &x, 1).unwrap());
+        let power = x.pow(5, 1).unwrap();
+        for (p, c) in power.get_numbers().iter().zip(chained.get_numbers().iter()) {
+            assert!((p - c).abs() < 1e-15);
+        }
+
+        assert_eq!(a.pow(0, 1), Ok(Matrix::identity(3)));
+        assert_eq!(a.pow(1, 3), Ok(a.clone()));
+        assert_eq!(Matrix::new(2, 2, vec![1i64, 1, 0, 1]).unwrap().pow(16, 2), Matrix::new(2, 2, vec![1, 16, 0, 1]));
+    }
+
+    #[test]
+    fn pow_rejects_non_square_and_bad_threads() {
+        assert_eq!(Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap().pow(2, 1).unwrap_err(),
+                   "Power needs a square matrix, got 2x3");
+        assert!(Matrix::<i32>::identity(2).pow(0, 3).is_err());
+        assert!(Matrix::<i32>::identity(2).pow(3, 0).is_err());
+    }
+
+    #[test]
+    fn sum_times_matrix() {
+        let a = Matrix::new(2, 3, vec![1, 0, 2, -1, 3, 1]).unwrap();
+        let b = Matrix::new(2, 3, vec![0, 1, 1, 2, -1, 0]).unwrap();
+        let c = Matrix::new(3, 2, vec![1, 2, 0, 1, 4, 0]).unwrap();
+
+        let distributed = &multiply(&a, &c, 2).unwrap() + &multiply(&b, &c, 2).unwrap();
+
+        assert_eq!(multiply(&(&a + &b), &c, 2), Ok(Matrix::new(2, 2, vec![13, 3, 5, 4]).unwrap()));
+        assert_eq!(multiply(&a.add(&b).unwrap(), &c, 1).unwrap(), distributed);
+    }
+}
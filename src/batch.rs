@@ -0,0 +1,270 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::multiplication::{run_with_workspace, Config};
+use crate::workspace::Workspace;
+
+/// What [`run_batch`] does when several jobs write the same output file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CollisionPolicy {
+    /// Refuse to run anything, naming the colliding job lines.
+    #[default]
+    Error,
+    /// Rename later outputs to `name-1.ext`, `name-2.ext`, ...
+    Suffix,
+    /// Run only the first job writing each output.
+    Skip,
+}
+
+impl CollisionPolicy {
+    pub fn from_name(name: &str) -> Result<CollisionPolicy, String> {
+        match name {
+            "error" => Ok(CollisionPolicy::Error),
+            "suffix" => Ok(CollisionPolicy::Suffix),
+            "skip" => Ok(CollisionPolicy::Skip),
+            _ => Err(format!("Unknown collision policy '{}', expected one of: error, suffix, skip", name))
+        }
+    }
+}
+
+/// One line of a job file, holding the same arguments as a plain run.
+pub struct Job {
+    /// 1-based line in the job file.
+    pub line: usize,
+    config: Config,
+}
+
+impl Job {
+    pub fn output(&self) -> &str {
+        &self.config.matrix_c_file_name
+    }
+}
+
+/// Parses a job file: every line that isn't blank or a `#` comment is
+/// `[Matrix A] [Matrix B] [Matrix C] [threads] [options]`, as for a plain run.
+pub fn parse_jobs(contents: &str) -> Result<Vec<Job>, String> {
+    contents.lines()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty() && !text.trim_start().starts_with('#'))
+        .map(|(index, text)| {
+            let args = std::iter::once(String::from("mm")).chain(text.split_whitespace().map(String::from));
+            Config::from_iter(args)
+                .map(|config| Job { line: index + 1, config })
+                .map_err(|error| format!("Line {}: {}", index + 1, error))
+        })
+        .collect()
+}
+
+/// Applies `policy` to jobs that write the same file, comparing outputs after
+/// canonicalizing the paths that exist and normalizing the rest lexically.
+pub fn resolve_collisions(jobs: Vec<Job>, policy: CollisionPolicy) -> Result<Vec<Job>, String> {
+    let mut lines_by_output: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for job in &jobs {
+        lines_by_output.entry(resolve(job.output())).or_default().push(job.line);
+    }
+
+    let mut collisions: Vec<&Vec<usize>> = lines_by_output.values().filter(|lines| lines.len() > 1).collect();
+    if collisions.is_empty() {
+        return Ok(jobs)
+    }
+
+    match policy {
+        CollisionPolicy::Error => {
+            collisions.sort();
+            let messages: Vec<String> = collisions.iter()
+                .map(|lines| format!("Jobs on lines {} write the same output",
+                                     lines.iter().map(|line| line.to_string()).collect::<Vec<String>>().join(", ")))
+                .collect();
+            Err(messages.join("\n"))
+        },
+        CollisionPolicy::Skip => {
+            let mut seen = HashSet::new();
+            Ok(jobs.into_iter().filter(|job| seen.insert(resolve(job.output()))).collect())
+        },
+        CollisionPolicy::Suffix => {
+            let mut taken: HashSet<PathBuf> = lines_by_output.into_keys().collect();
+            let mut seen = HashSet::new();
+            let mut jobs = jobs;
+
+            for job in &mut jobs {
+                if seen.insert(resolve(job.output())) {
+                    continue
+                }
+
+                let renamed = (1..)
+                    .map(|suffix| with_suffix(job.output(), suffix))
+                    .find(|renamed| !taken.contains(&resolve(renamed)))
+                    .unwrap();
+
+                taken.insert(resolve(&renamed));
+                job.config.matrix_c_file_name = renamed;
+            }
+
+            Ok(jobs)
+        },
+    }
+}
+
+fn resolve(file_name: &str) -> PathBuf {
+    let path = Path::new(file_name);
+
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical
+    }
+
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if let (Ok(parent), Some(name)) = (fs::canonicalize(parent), path.file_name()) {
+        return parent.join(name)
+    }
+
+    let absolute = std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => { normalized.pop(); },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// `out/c.txt` with suffix 2 becomes `out/c-2.txt`.
+fn with_suffix(file_name: &str, suffix: usize) -> String {
+    let path = Path::new(file_name);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+
+    let name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}-{}.{}", stem, suffix, extension),
+        None => format!("{}-{}", stem, suffix),
+    };
+
+    path.with_file_name(name).to_str().unwrap_or(file_name).to_string()
+}
+
+pub struct BatchConfig {
+    job_file_name: String,
+    on_collision: CollisionPolicy,
+    default_tune_profile: Option<String>,
+}
+
+impl BatchConfig {
+    /// Parses `batch [job file] [--on-collision error|suffix|skip]`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<BatchConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let job_file_name = iterator.next().ok_or_else(|| String::from("Missing job file name"))?;
+        let mut on_collision = CollisionPolicy::default();
+
+        while let Some(option) = iterator.next() {
+            match option.as_str() {
+                "--on-collision" => {
+                    let value = iterator.next().ok_or_else(|| format!("Missing value for {option}"))?;
+                    on_collision = CollisionPolicy::from_name(&value)?;
+                },
+                _ => return Err(format!("Unknown option {option}"))
+            }
+        }
+
+        Ok(BatchConfig { job_file_name, on_collision, default_tune_profile: None })
+    }
+
+    /// Used by every job without its own `--tune-profile`, see [`Config::with_default_tune_profile`].
+    pub fn with_default_tune_profile(mut self, profile: Option<String>) -> BatchConfig {
+        self.default_tune_profile = profile;
+        self
+    }
+}
+
+/// Checks every job and their outputs before running any of them, then runs
+/// them in file order, returning how many ran.
+pub fn run_batch(config: BatchConfig) -> Result<usize, String> {
+    let contents = fs::read_to_string(&config.job_file_name)
+        .map_err(|error| format!("Couldn't open file {}\nerror: {}", config.job_file_name, error))?;
+
+    let jobs = resolve_collisions(parse_jobs(&contents)?, config.on_collision)?;
+
+    let ran = jobs.len();
+    let mut workspace = Workspace::new();
+
+    for job in jobs {
+        let line = job.line;
+        let config = job.config.with_default_tune_profile(config.default_tune_profile.clone());
+        run_with_workspace(config, &mut workspace).map_err(|error| format!("Job on line {} failed: {}", line, error))?;
+    }
+
+    Ok(ran)
+}
+
+#[cfg(test)]
+mod batch_test {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::batch::{parse_jobs, resolve_collisions, run_batch, BatchConfig, CollisionPolicy};
+    use crate::test_support::temp_path;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(temp_path(name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn outputs(contents: &str, policy: CollisionPolicy) -> Result<Vec<(usize, String)>, String> {
+        let jobs = resolve_collisions(parse_jobs(contents)?, policy)?;
+        Ok(jobs.iter().map(|job| (job.line, job.output().to_string())).collect())
+    }
+
+    const JOBS: &str = "a.txt b.txt out/c.txt 1\n# comment\nb.txt a.txt out/d.txt 2\n\na.txt a.txt out/./c.txt 1\na.txt b.txt out/c.txt 1\n";
+
+    #[test]
+    fn collision_policies() {
+        assert_eq!(outputs(JOBS, CollisionPolicy::Error).unwrap_err(), "Jobs on lines 1, 5, 6 write the same output");
+
+        assert_eq!(outputs(JOBS, CollisionPolicy::Skip).unwrap(),
+                   vec![(1, String::from("out/c.txt")), (3, String::from("out/d.txt"))]);
+
+        assert_eq!(outputs(JOBS, CollisionPolicy::Suffix).unwrap(), vec![
+            (1, String::from("out/c.txt")), (3, String::from("out/d.txt")),
+            (5, String::from("out/c-1.txt")), (6, String::from("out/c-2.txt"))]);
+
+        assert_eq!(outputs("a b c 1\na b d 1\n", CollisionPolicy::Error).unwrap().len(), 2);
+        assert_eq!(outputs("a b c x\n", CollisionPolicy::Error).unwrap_err(), "Line 1: Couldn't get number of threads from x");
+    }
+
+    #[test]
+    fn existing_file_referenced_two_ways() {
+        let dir = temp_dir("canonical");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("c.txt"), "").unwrap();
+
+        let (direct, indirect) = (dir.join("c.txt"), dir.join("sub").join("..").join("c.txt"));
+        let jobs = format!("a b {} 1\na b {} 1\n", direct.display(), indirect.display());
+        let result = outputs(&jobs, CollisionPolicy::Error);
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap_err(), "Jobs on lines 1, 2 write the same output");
+    }
+
+    #[test]
+    fn batch_runs_suffixed_jobs() {
+        let dir = temp_dir("run");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("a.txt"), "2\n2\n1 2\n3 4\n").unwrap();
+        fs::write(path("jobs.txt"), format!("{a} {a} {c} 1\n{a} {a} {c} 2\n", a = path("a.txt"), c = path("c.txt"))).unwrap();
+
+        let args = |policy: &str| ["mm", "batch", &path("jobs.txt"), "--on-collision", policy].map(String::from).into_iter();
+        let ran = run_batch(BatchConfig::from_iter(args("suffix")).unwrap());
+        let suffixed = fs::read_to_string(path("c-1.txt"));
+        let refused = run_batch(BatchConfig::from_iter(args("error")).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ran, Ok(2));
+        assert_eq!(suffixed.unwrap(), "2\n2\n7 10\n15 22\n");
+        assert!(refused.is_err());
+        assert!(BatchConfig::from_iter(args("overwrite")).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt::{Display, Formatter};
+use std::ops::{AddAssign, Mul};
+use std::time::{Duration, Instant};
+use crate::human::format_duration;
+use crate::matrix::Matrix;
+use crate::multiplication::{multiply_with_options, Kernel, MultiplyOptions};
+
+/// Coefficient of variation above which a benchmark is reported as noisy.
+pub const NOISY_THRESHOLD: f64 = 0.1;
+
+#[derive(Debug, PartialEq)]
+pub struct BenchmarkStats {
+    pub samples: Vec<Duration>,
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub std_dev: Duration,
+    /// Standard deviation divided by the mean.
+    pub coefficient_of_variation: f64,
+    /// Set when `coefficient_of_variation` exceeds [`NOISY_THRESHOLD`].
+    pub noisy: bool,
+}
+
+impl BenchmarkStats {
+    pub fn from_samples(samples: Vec<Duration>) -> Result<BenchmarkStats, String> {
+        if samples.is_empty() {
+            return Err(String::from("Cannot compute statistics of zero samples"))
+        }
+
+        let mut sorted = samples.clone();
+        sorted.sort();
+
+        let len = sorted.len();
+        let median = if len.is_multiple_of(2) {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2
+        } else {
+            sorted[len / 2]
+        };
+
+        let seconds: Vec<f64> = samples.iter().map(Duration::as_secs_f64).collect();
+        let mean = seconds.iter().sum::<f64>() / len as f64;
+        let variance = seconds.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / len as f64;
+        let std_dev = variance.sqrt();
+
+        let coefficient_of_variation = if mean > 0.0 { std_dev / mean } else { 0.0 };
+
+        Ok(BenchmarkStats {
+            min: sorted[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            std_dev: Duration::from_secs_f64(std_dev),
+            coefficient_of_variation,
+            noisy: coefficient_of_variation > NOISY_THRESHOLD,
+            samples,
+        })
+    }
+}
+
+impl Display for BenchmarkStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "runs: {}", self.samples.len())?;
+        writeln!(f, "min: {}", format_duration(self.min))?;
+        writeln!(f, "median: {}", format_duration(self.median))?;
+        writeln!(f, "mean: {}", format_duration(self.mean))?;
+        write!(f, "stddev: {} ({:.1}%)", format_duration(self.std_dev), self.coefficient_of_variation * 100.0)?;
+        if self.noisy {
+            write!(f, "\nwarning: high variance between runs, the machine may have been busy")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `warmup` untimed multiplications to fault in the inputs, then times `reps` more.
+pub fn benchmark_stable<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, options: &MultiplyOptions<T>,
+                           warmup: usize, reps: usize) -> Result<BenchmarkStats, String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    if reps == 0 {
+        return Err(String::from("Number of repetitions must be higher than 0"))
+    }
+
+    for _ in 0..warmup {
+        multiply_with_options(matrix_a, matrix_b, options)?;
+    }
+
+    let mut samples = Vec::with_capacity(reps);
+
+    for _ in 0..reps {
+        let start = Instant::now();
+        multiply_with_options(matrix_a, matrix_b, options)?;
+        samples.push(start.elapsed());
+    }
+
+    BenchmarkStats::from_samples(samples)
+}
+
+pub struct BenchConfig {
+    size: usize,
+    num_of_threads: usize,
+    warmup: usize,
+    reps: usize
+}
+
+impl BenchConfig {
+    /// Parses `bench [size] [number of threads] [warmup runs] [timed runs]`, the
+    /// last two defaulting to 1 and 5.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<BenchConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let mut number = |name: &str, default: Option<usize>| match (iterator.next(), default) {
+            (Some(value), _) => value.parse::<usize>()
+                .map_err(|_| format!("Couldn't get {name} from {value}")),
+            (None, Some(default)) => Ok(default),
+            (None, None) => Err(format!("Missing {name}"))
+        };
+
+        let size = number("matrix size", None)?;
+        let num_of_threads = number("number of threads", None)?;
+        let warmup = number("number of warmup runs", Some(1))?;
+        let reps = number("number of timed runs", Some(5))?;
+
+        Ok(BenchConfig { size, num_of_threads, warmup, reps })
+    }
+}
+
+pub fn run_bench(config: BenchConfig) -> Result<BenchmarkStats, String> {
+    let matrix_a = Matrix::gen_random(config.size, config.size, -1.0, 1.0)?;
+    let matrix_b = Matrix::gen_random(config.size, config.size, -1.0, 1.0)?;
+
+    benchmark_stable(&matrix_a, &matrix_b, &MultiplyOptions::new(config.num_of_threads),
+                     config.warmup, config.reps)
+}
+
+/// Benchmarks every [`Kernel`] on the same random operands, for `bench-kernels`.
+pub fn run_kernel_comparison(config: BenchConfig) -> Result<Vec<(Kernel, BenchmarkStats)>, String> {
+    let matrix_a = Matrix::gen_random(config.size, config.size, -1.0, 1.0)?;
+    let matrix_b = Matrix::gen_random(config.size, config.size, -1.0, 1.0)?;
+
+    [Kernel::Naive, Kernel::CacheOblivious].into_iter().map(|kernel| {
+        let mut options = MultiplyOptions::new(config.num_of_threads);
+        options.kernel = kernel;
+        Ok((kernel, benchmark_stable(&matrix_a, &matrix_b, &options, config.warmup, config.reps)?))
+    }).collect()
+}
+
+#[cfg(test)]
+mod benchmark_test {
+    use std::time::Duration;
+    use crate::benchmark::{benchmark_stable, run_kernel_comparison, BenchConfig, BenchmarkStats};
+    use crate::multiplication::Kernel;
+    use crate::matrix::Matrix;
+    use crate::multiplication::MultiplyOptions;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&ms| Duration::from_millis(ms)).collect()
+    }
+
+    #[test]
+    fn stats_of_stable_samples() {
+        let stats = BenchmarkStats::from_samples(millis(&[12, 10, 11, 10, 12])).unwrap();
+
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.median, Duration::from_millis(11));
+        assert_eq!(stats.mean, Duration::from_millis(11));
+        assert!((stats.std_dev.as_secs_f64() - 0.000894427).abs() < 1e-8);
+        assert!(!stats.noisy);
+        assert_eq!(stats.to_string(), "runs: 5\nmin: 10 ms\nmedian: 11 ms\nmean: 11 ms\nstddev: 894.43 µs (8.1%)");
+    }
+
+    #[test]
+    fn stats_even_count_median() {
+        let stats = BenchmarkStats::from_samples(millis(&[4, 1, 3, 2])).unwrap();
+
+        assert_eq!(stats.median, Duration::from_micros(2500));
+    }
+
+    #[test]
+    fn stats_flags_high_variance() {
+        let stats = BenchmarkStats::from_samples(millis(&[10, 10, 30, 10])).unwrap();
+
+        assert!(stats.coefficient_of_variation > 0.1);
+        assert!(stats.noisy);
+        assert!(stats.to_string().contains("warning"));
+    }
+
+    #[test]
+    fn stats_of_no_samples() {
+        assert!(BenchmarkStats::from_samples(vec![]).is_err());
+    }
+
+    #[test]
+    fn benchmark_tiny_multiplication() {
+        let matrix_a = Matrix::<i32>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+
+        let stats = benchmark_stable(&matrix_a, &matrix_a, &MultiplyOptions::new(2), 2, 4).unwrap();
+
+        assert_eq!(stats.samples.len(), 4);
+        assert!(benchmark_stable(&matrix_a, &matrix_a, &MultiplyOptions::new(2), 0, 0).is_err());
+    }
+
+    #[test]
+    fn bench_config_defaults() {
+        let args = ["mm", "bench", "64", "4"].iter().map(|arg| arg.to_string());
+
+        let config = BenchConfig::from_iter(args).unwrap();
+
+        assert_eq!((config.size, config.num_of_threads, config.warmup, config.reps), (64, 4, 1, 5));
+    }
+
+    #[test]
+    fn kernel_comparison_covers_every_kernel() {
+        let args = ["mm", "bench-kernels", "40", "2", "0", "1"].iter().map(|arg| arg.to_string());
+
+        let results = run_kernel_comparison(BenchConfig::from_iter(args).unwrap()).unwrap();
+
+        let kernels: Vec<Kernel> = results.iter().map(|(kernel, _)| *kernel).collect();
+        assert_eq!(kernels, vec![Kernel::Naive, Kernel::CacheOblivious]);
+    }
+}
@@ -0,0 +1,142 @@
+use std::ops::{AddAssign, Mul};
+use crate::element::Element;
+use crate::matrix::Matrix;
+use crate::multiplication::multiply;
+use crate::parallel::parallel_over_rows;
+
+/// A block-diagonal operator stored as its square diagonal blocks, so it can be
+/// applied without building the mostly-zero dense matrix.
+#[derive(Debug, PartialEq)]
+pub struct BlockDiagonal<T>(pub Vec<Matrix<T>>);
+
+impl<T> BlockDiagonal<T> {
+    /// Side of the dense operator, the sum of the block sizes.
+    pub fn size(&self) -> usize {
+        self.0.iter().map(|block| block.rows()).sum()
+    }
+
+    fn validate(&self, rows: usize) -> Result<(), String> {
+        let mut start = 0;
+
+        for (index, block) in self.0.iter().enumerate() {
+            if block.rows() != block.columns() {
+                return Err(format!("Block {} must be square, got {}", index, block.shape()))
+            }
+
+            let end = start + block.rows();
+            if end > rows {
+                return Err(format!("Block {} needs rows {}..{} of x, but x has {} rows", index, start, end, rows))
+            }
+            start = end;
+        }
+
+        if start != rows {
+            return Err(format!("Blocks cover {} rows but x has {} rows", start, rows))
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Clone> BlockDiagonal<T>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    /// Multiplies every block by its slice of rows of `x`, spreading the
+    /// blocks over `num_of_threads` threads.
+    pub fn apply(&self, x: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, String> {
+        self.validate(x.rows())?;
+
+        if num_of_threads == 0 || num_of_threads > self.0.len().max(1) {
+            return Err(format!("Num of threads: {} must be between 1 and the number of blocks: {}",
+                               num_of_threads, self.0.len()))
+        }
+
+        let columns = x.columns();
+        let numbers = x.get_numbers();
+
+        let mut starts = Vec::with_capacity(self.0.len());
+        let mut start = 0;
+        for block in &self.0 {
+            starts.push(start);
+            start += block.rows();
+        }
+
+        let results: Vec<Result<Vec<T>, String>> = parallel_over_rows(self.0.len(), num_of_threads, |blocks| {
+            let mut result = Vec::new();
+            for (block, &start) in self.0[blocks.clone()].iter().zip(&starts[blocks]) {
+                let slice = numbers[start * columns..(start + block.rows()) * columns].to_vec();
+                let product = multiply(block, &Matrix::new(block.rows(), columns, slice)?, 1)?;
+                result.extend(product.get_numbers().iter().cloned());
+            }
+            Ok(result)
+        }, |results| results)?;
+
+        let mut result = Vec::with_capacity(x.element_count());
+        for numbers in results {
+            result.append(&mut numbers?);
+        }
+
+        Matrix::new(x.rows(), columns, result)
+    }
+}
+
+impl<T: Element> BlockDiagonal<T> {
+    /// The dense operator, for checking [`BlockDiagonal::apply`] against [`multiply`].
+    pub fn to_dense(&self) -> Matrix<T> {
+        let size = self.size();
+        let mut numbers = vec![T::ZERO; size * size];
+
+        let mut offset = 0;
+        for block in &self.0 {
+            let (n, block_numbers) = (block.rows(), block.get_numbers());
+            for row in 0..n {
+                for column in 0..block.columns().min(size - offset) {
+                    numbers[(offset + row) * size + offset + column] = block_numbers[row * block.columns() + column];
+                }
+            }
+            offset += n;
+        }
+
+        Matrix::new(size, size, numbers).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod block_test {
+    use crate::block::BlockDiagonal;
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+
+    fn blocks() -> BlockDiagonal<i64> {
+        BlockDiagonal(vec![
+            Matrix::new(1, 1, vec![3]).unwrap(),
+            Matrix::new(3, 3, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap(),
+            Matrix::new(2, 2, vec![0, -1, 1, 0]).unwrap(),
+        ])
+    }
+
+    #[test]
+    fn apply_matches_dense_product() {
+        let x = Matrix::new(6, 2, (1..=12).collect()).unwrap();
+
+        let expected = multiply(&blocks().to_dense(), &x, 1).unwrap();
+
+        for num_of_threads in 1..=3 {
+            assert_eq!(blocks().apply(&x, num_of_threads).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn apply_mismatched_dimension() {
+        let x = Matrix::new(5, 1, vec![1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(blocks().apply(&x, 1).unwrap_err(), "Block 2 needs rows 4..6 of x, but x has 5 rows");
+
+        let x = Matrix::new(7, 1, vec![0; 7]).unwrap();
+        assert_eq!(blocks().apply(&x, 1).unwrap_err(), "Blocks cover 6 rows but x has 7 rows");
+
+        let not_square = BlockDiagonal(vec![Matrix::new(1, 2, vec![1, 2]).unwrap()]);
+        assert_eq!(not_square.apply(&x, 1).unwrap_err(), "Block 0 must be square, got 1x2");
+    }
+}
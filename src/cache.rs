@@ -0,0 +1,235 @@
+use std::fs;
+use std::io::{BufRead, Cursor};
+use std::ops::{AddAssign, Mul};
+use std::path::{Path, PathBuf};
+use crate::element::Element;
+use crate::hash::Xxh64;
+use crate::io::{Binary, MatrixFormat};
+use crate::matrix::Matrix;
+use crate::multiplication::{multiply_with_options, Kernel, MultiplyOptions};
+
+/// Extension of cache entries; [`clear_cache`] only touches files with it.
+const CACHE_EXTENSION: &str = "mmcache";
+
+/// First word of every entry, followed by the crate version and kernel.
+const CACHE_MAGIC: &str = "mm-cache";
+
+/// Whether [`multiply_cached`] computed the product or read it from the cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheOutcome {
+    Computed,
+    Loaded,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Multiplies like [`multiply_with_options`], but first looks for the product
+/// in `cache_dir` under a key made of both operands' content hashes, the
+/// element type, the kernel, the inner weights and whether the result is
+/// transposed.
+///
+/// Entries record the crate version and kernel, and one written by another
+/// version, or one that can't be read back, is recomputed and overwritten
+/// instead of failing the call. A loaded product is still checked against
+/// [`MultiplyOptions::abort_if_exceeds`].
+pub fn multiply_cached<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, options: &MultiplyOptions<T>,
+                          cache_dir: &str) -> Result<(Matrix<T>, CacheOutcome), String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: Element + AddAssign<T> + Sync + Send + 'static {
+
+    let path = entry_path(cache_dir, matrix_a, matrix_b, options);
+
+    if let Some(product) = load_entry(&path, options.kernel) {
+        if product.rows() == matrix_a.rows() && product.columns() == matrix_b.columns() {
+            if let Some(bound) = &options.abort_if_exceeds {
+                bound.check(&product)?;
+            }
+            return Ok((product, CacheOutcome::Loaded))
+        }
+    }
+
+    let product = multiply_with_options(matrix_a, matrix_b, options)?;
+
+    store_entry(cache_dir, &path, &product, options.kernel)?;
+
+    Ok((product, CacheOutcome::Computed))
+}
+
+/// Counts the entries in `cache_dir` and their total size.
+pub fn cache_stats(cache_dir: &str) -> Result<CacheStats, String> {
+    let mut stats = CacheStats::default();
+
+    for path in entries(cache_dir)? {
+        stats.entries += 1;
+        stats.bytes += fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+    }
+
+    Ok(stats)
+}
+
+/// Removes every entry in `cache_dir` and returns how many were removed.
+pub fn clear_cache(cache_dir: &str) -> Result<usize, String> {
+    let entries = entries(cache_dir)?;
+
+    for path in &entries {
+        fs::remove_file(path).map_err(|error| format!("Couldn't remove {}: {}", path.display(), error))?;
+    }
+
+    Ok(entries.len())
+}
+
+fn entries(cache_dir: &str) -> Result<Vec<PathBuf>, String> {
+    let read_dir = match fs::read_dir(cache_dir) {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(format!("Couldn't read cache directory {}: {}", cache_dir, error))
+    };
+
+    Ok(read_dir
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == CACHE_EXTENSION))
+        .collect())
+}
+
+fn entry_path<T: Element>(cache_dir: &str, matrix_a: &Matrix<T>, matrix_b: &Matrix<T>,
+                          options: &MultiplyOptions<T>) -> PathBuf {
+    let mut hasher = Xxh64::new(0);
+    hasher.update(&matrix_a.content_hash().to_le_bytes());
+    hasher.update(&matrix_b.content_hash().to_le_bytes());
+    hasher.update(&[T::TAG]);
+    hasher.update(options.kernel.to_string().as_bytes());
+    hasher.update(&[options.transpose_result as u8]);
+
+    if let Some(weights) = &options.inner_weights {
+        let mut bytes = Vec::with_capacity(weights.len() * T::SIZE);
+        for weight in weights.iter() {
+            weight.write_le_bytes(&mut bytes);
+        }
+        hasher.update(&bytes);
+    }
+
+    Path::new(cache_dir).join(format!("{:016x}.{}", hasher.finish(), CACHE_EXTENSION))
+}
+
+fn entry_prelude(kernel: Kernel) -> String {
+    format!("{} {} {}\n", CACHE_MAGIC, env!("CARGO_PKG_VERSION"), kernel)
+}
+
+/// `None` for a missing, stale or unreadable entry.
+fn load_entry<T: Element>(path: &Path, kernel: Kernel) -> Option<Matrix<T>> {
+    let bytes = fs::read(path).ok()?;
+    let mut reader = Cursor::new(bytes);
+
+    let mut prelude = String::new();
+    reader.read_line(&mut prelude).ok()?;
+
+    if prelude != entry_prelude(kernel) {
+        return None
+    }
+
+    Binary.read(reader).ok()
+}
+
+fn store_entry<T: Element>(cache_dir: &str, path: &Path, product: &Matrix<T>, kernel: Kernel) -> Result<(), String> {
+    fs::create_dir_all(cache_dir)
+        .map_err(|error| format!("Couldn't create cache directory {}: {}", cache_dir, error))?;
+
+    let mut bytes = entry_prelude(kernel).into_bytes();
+    Binary.write(product, &mut bytes)?;
+
+    // Written aside and renamed so a concurrent reader never sees half an entry.
+    let scratch = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&scratch, &bytes)
+        .and_then(|_| fs::rename(&scratch, path))
+        .map_err(|error| format!("Couldn't write cache entry {}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod cache_test {
+    use std::fs;
+    use crate::cache::{cache_stats, clear_cache, multiply_cached, CacheOutcome};
+    use crate::matrix::Matrix;
+    use crate::multiplication::{multiply, MagnitudeBound, MultiplyError, MultiplyOptions};
+    use crate::test_support::temp_path;
+
+    #[test]
+    fn second_call_hits_cache() {
+        let dir = temp_path("hit");
+        let matrix_a = Matrix::<f64>::gen_random(4, 3, -1.0, 1.0).unwrap();
+        let matrix_b = Matrix::<f64>::gen_random(3, 5, -1.0, 1.0).unwrap();
+        let options = MultiplyOptions::new(2);
+
+        let (first, first_outcome) = multiply_cached(&matrix_a, &matrix_b, &options, &dir).unwrap();
+        let (second, second_outcome) = multiply_cached(&matrix_a, &matrix_b, &options, &dir).unwrap();
+        let stats = cache_stats(&dir).unwrap();
+        let cleared = clear_cache(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((first_outcome, second_outcome), (CacheOutcome::Computed, CacheOutcome::Loaded));
+        assert_eq!(first, multiply(&matrix_a, &matrix_b, 1).unwrap());
+        assert_eq!(second, first);
+        assert_eq!((stats.entries, cleared), (1, 1));
+    }
+
+    #[test]
+    fn changed_element_misses() {
+        let dir = temp_path("miss");
+        let matrix_a = Matrix::<i64>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+        let changed = Matrix::<i64>::from_vec(vec!["2", "2", "1 2", "3 5"]).unwrap();
+        let options = MultiplyOptions::new(1);
+
+        multiply_cached(&matrix_a, &matrix_a, &options, &dir).unwrap();
+        let (product, outcome) = multiply_cached(&changed, &matrix_a, &options, &dir).unwrap();
+        let entries = cache_stats(&dir).unwrap().entries;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(outcome, CacheOutcome::Computed);
+        assert_eq!(product, multiply(&changed, &matrix_a, 1).unwrap());
+        assert_eq!(entries, 2);
+    }
+
+    #[test]
+    fn corrupted_entry_is_recomputed() {
+        let dir = temp_path("corrupt");
+        let matrix_a = Matrix::<i64>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+        let options = MultiplyOptions::new(1);
+
+        multiply_cached(&matrix_a, &matrix_a, &options, &dir).unwrap();
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let bytes = fs::read(&path).unwrap();
+            fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        }
+
+        let (product, outcome) = multiply_cached(&matrix_a, &matrix_a, &options, &dir).unwrap();
+        let (_, repaired) = multiply_cached(&matrix_a, &matrix_a, &options, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(outcome, CacheOutcome::Computed);
+        assert_eq!(product, multiply(&matrix_a, &matrix_a, 1).unwrap());
+        assert_eq!(repaired, CacheOutcome::Loaded);
+    }
+
+    #[test]
+    fn loaded_product_is_checked_against_bound() {
+        let dir = temp_path("bound");
+        let matrix_a = Matrix::<i64>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+        let mut options = MultiplyOptions::new(1);
+
+        multiply_cached(&matrix_a, &matrix_a, &options, &dir).unwrap();
+        options.abort_if_exceeds = Some(MagnitudeBound::new(20.0));
+        let result = multiply_cached(&matrix_a, &matrix_a, &options, &dir);
+        options.abort_if_exceeds = Some(MagnitudeBound::new(22.0));
+        let (_, outcome) = multiply_cached(&matrix_a, &matrix_a, &options, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let error = MultiplyError::BoundExceeded { row: 1, column: 1, value: 22.0, bound: 20.0 };
+        assert_eq!(result, Err(String::from(error)));
+        assert_eq!(outcome, CacheOutcome::Loaded);
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::{Display, Formatter};
+use crate::io;
+use crate::matrix::Matrix;
+use crate::shape::Shape;
+
+/// Absolute tolerance used by `compare` when none is given.
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// How close two elements must be to count as equal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    /// `|a - b| <= tolerance`.
+    Absolute(f64),
+    /// At most this many representable doubles apart, see [`ulps_distance`].
+    Ulps(u64),
+    /// `|a - b| <= max(absolute, relative * max(|a|, |b|))`, so values near
+    /// zero are compared absolutely and large ones relatively.
+    Mixed { absolute: f64, relative: f64 },
+}
+
+impl Tolerance {
+    pub fn accepts(&self, a: f64, b: f64) -> bool {
+        match *self {
+            Tolerance::Absolute(tolerance) => a == b || (a - b).abs() <= tolerance,
+            Tolerance::Ulps(ulps) => ulps_distance(a, b).is_some_and(|distance| distance <= ulps),
+            Tolerance::Mixed { absolute, relative } =>
+                a == b || (a - b).abs() <= absolute.max(relative * a.abs().max(b.abs())),
+        }
+    }
+}
+
+/// Number of representable doubles between `a` and `b`.
+///
+/// `+0.0` and `-0.0` are 0 apart, and values of opposite sign are measured
+/// through zero. NaN is never close to anything and an infinity is only close
+/// to itself, so those cases give `None` unless the values are identical.
+pub fn ulps_distance(a: f64, b: f64) -> Option<u64> {
+    if a.is_nan() || b.is_nan() {
+        return None
+    }
+
+    if a.is_infinite() || b.is_infinite() {
+        return (a == b).then_some(0)
+    }
+
+    let ordered = |x: f64| {
+        let bits = x.to_bits() as i64;
+        if bits < 0 { i64::MIN - bits } else { bits }
+    };
+
+    Some((ordered(a) as i128 - ordered(b) as i128).unsigned_abs() as u64)
+}
+
+/// Element-wise comparison of two matrices under one [`Tolerance`].
+#[derive(Debug, PartialEq)]
+pub struct ComparisonReport {
+    pub tolerance: Tolerance,
+    /// Set when the shapes differ; no elements are compared then.
+    pub shape_mismatch: Option<(Shape, Shape)>,
+    pub mismatches: usize,
+    /// Row and column of the first element outside the tolerance.
+    pub first_mismatch: Option<(usize, usize)>,
+    pub max_abs_diff: f64,
+    /// Largest [`ulps_distance`], `None` if any pair involved a NaN or unequal infinities.
+    pub max_ulps: Option<u64>,
+}
+
+impl ComparisonReport {
+    pub fn is_match(&self) -> bool {
+        self.shape_mismatch.is_none() && self.mismatches == 0
+    }
+}
+
+impl Display for ComparisonReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some((left, right)) = self.shape_mismatch {
+            return write!(f, "Shapes differ: {} vs {}", left, right)
+        }
+
+        writeln!(f, "mismatches: {}", self.mismatches)?;
+        writeln!(f, "max abs diff: {:e}", self.max_abs_diff)?;
+        match self.max_ulps {
+            Some(ulps) => write!(f, "max ulps: {}", ulps)?,
+            None => write!(f, "max ulps: not finite")?,
+        }
+        if let Some((row, column)) = self.first_mismatch {
+            write!(f, "\nfirst mismatch at ({}, {})", row, column)?;
+        }
+        Ok(())
+    }
+}
+
+impl Matrix<f64> {
+    pub fn compare(&self, other: &Matrix<f64>, tolerance: Tolerance) -> ComparisonReport {
+        let mut report = ComparisonReport {
+            tolerance,
+            shape_mismatch: None,
+            mismatches: 0,
+            first_mismatch: None,
+            max_abs_diff: 0.0,
+            max_ulps: Some(0),
+        };
+
+        if self.shape() != other.shape() {
+            report.shape_mismatch = Some((self.shape(), other.shape()));
+            return report
+        }
+
+        let (left, right) = (self.get_numbers(), other.get_numbers());
+
+        for (index, (&a, &b)) in left.iter().zip(right.iter()).enumerate() {
+            report.max_abs_diff = report.max_abs_diff.max((a - b).abs());
+            report.max_ulps = report.max_ulps.zip(ulps_distance(a, b)).map(|(max, ulps)| max.max(ulps));
+
+            if !tolerance.accepts(a, b) {
+                report.mismatches += 1;
+                report.first_mismatch.get_or_insert((index / self.columns(), index % self.columns()));
+            }
+        }
+
+        report
+    }
+
+    /// Whether the shapes are equal and every element is within `tolerance`;
+    /// [`Tolerance::Mixed`] suits the output of threaded multiplication.
+    pub fn approx_eq(&self, other: &Matrix<f64>, tolerance: Tolerance) -> bool {
+        self.compare(other, tolerance).is_match()
+    }
+}
+
+/// Asserts that two `Matrix<f64>` are [`approx_eq`](Matrix::approx_eq) under
+/// a [`Tolerance`], panicking with both shapes if they differ, or with the
+/// first element outside the tolerance and its delta.
+#[macro_export]
+macro_rules! assert_matrices_close {
+    ($left:expr, $right:expr, $tolerance:expr $(,)?) => {{
+        let left: &$crate::matrix::Matrix<f64> = &$left;
+        let right: &$crate::matrix::Matrix<f64> = &$right;
+        let report = left.compare(right, $tolerance);
+
+        if let Some((left_shape, right_shape)) = report.shape_mismatch {
+            panic!("matrices differ in shape: {} vs {}", left_shape, right_shape);
+        }
+
+        if let Some((row, column)) = report.first_mismatch {
+            let (a, b) = (left[(row, column)], right[(row, column)]);
+            panic!("matrices differ at ({}, {}): {} vs {}, delta {:e}; {} elements outside {:?}",
+                   row, column, a, b, (a - b).abs(), report.mismatches, report.tolerance);
+        }
+    }};
+}
+
+pub struct CompareConfig {
+    left_file_name: String,
+    right_file_name: String,
+    tolerance: Tolerance,
+}
+
+impl CompareConfig {
+    /// Parses `compare [left file] [right file] [--tolerance value | --ulps n]`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<CompareConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let left_file_name = iterator.next().ok_or_else(|| String::from("Missing left matrix file name"))?;
+        let right_file_name = iterator.next().ok_or_else(|| String::from("Missing right matrix file name"))?;
+
+        let mut tolerance = Tolerance::Absolute(DEFAULT_TOLERANCE);
+
+        while let Some(option) = iterator.next() {
+            let value = iterator.next().ok_or_else(|| format!("Missing value for {option}"))?;
+
+            tolerance = match option.as_str() {
+                "--tolerance" => match value.parse::<f64>() {
+                    Ok(tolerance) if tolerance >= 0.0 => Tolerance::Absolute(tolerance),
+                    _ => return Err(format!("Couldn't get tolerance from {value}"))
+                },
+                "--ulps" => Tolerance::Ulps(value.parse::<u64>()
+                    .map_err(|_| format!("Couldn't get ulps from {value}"))?),
+                _ => return Err(format!("Unknown option {option}"))
+            };
+        }
+
+        Ok(CompareConfig { left_file_name, right_file_name, tolerance })
+    }
+}
+
+pub fn run_compare(config: CompareConfig) -> Result<ComparisonReport, String> {
+    let left = io::read_matrix::<f64>(&config.left_file_name, None)?;
+    let right = io::read_matrix::<f64>(&config.right_file_name, None)?;
+
+    Ok(left.compare(&right, config.tolerance))
+}
+
+#[cfg(test)]
+mod compare_test {
+    use crate::compare::{ulps_distance, CompareConfig, Tolerance};
+    use crate::matrix::Matrix;
+
+    #[test]
+    fn mixed_tolerance_is_absolute_near_zero_and_relative_far_from_it() {
+        let tolerance = Tolerance::Mixed { absolute: 1e-12, relative: 1e-9 };
+
+        assert!(tolerance.accepts(0.0, 5e-13));
+        assert!(!tolerance.accepts(0.0, 1e-11));
+        assert!(tolerance.accepts(1e6, 1e6 + 1e-4));
+        assert!(!tolerance.accepts(1e6, 1e6 + 1e-2));
+        assert!(tolerance.accepts(f64::INFINITY, f64::INFINITY) && !tolerance.accepts(f64::NAN, f64::NAN));
+
+        let left = Matrix::new(1, 2, vec![0.0, 1e6]).unwrap();
+        assert!(left.approx_eq(&Matrix::new(1, 2, vec![1e-13, 1e6 + 1e-4]).unwrap(), tolerance));
+        assert!(!left.approx_eq(&Matrix::new(2, 1, vec![0.0, 1e6]).unwrap(), tolerance));
+        crate::assert_matrices_close!(left, Matrix::new(1, 2, vec![1e-13, 1e6 + 1e-4]).unwrap(), tolerance);
+    }
+
+    #[test]
+    #[should_panic(expected = "matrices differ at (1, 0): 3 vs 3.5, delta 5e-1; 1 elements outside Absolute(0.1)")]
+    fn close_assertion_names_the_first_difference() {
+        let left = Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+
+        crate::assert_matrices_close!(left, Matrix::new(2, 2, vec![1.0, 2.0, 3.5, 4.0]).unwrap(), Tolerance::Absolute(0.1));
+    }
+
+    #[test]
+    #[should_panic(expected = "matrices differ in shape: 2x2 vs 1x4")]
+    fn close_assertion_reports_both_shapes() {
+        let left = Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+
+        crate::assert_matrices_close!(&left, &Matrix::new(1, 4, vec![1.0, 2.0, 3.0, 4.0]).unwrap(), Tolerance::Ulps(0));
+    }
+
+    #[test]
+    fn ulps_of_neighbours_and_zeros() {
+        let next = f64::from_bits(1.0f64.to_bits() + 1);
+
+        assert_eq!(ulps_distance(1.0, next), Some(1));
+        assert!(Tolerance::Ulps(1).accepts(1.0, next));
+        assert!(!Tolerance::Ulps(0).accepts(1.0, next));
+        assert!(Tolerance::Ulps(0).accepts(0.0, -0.0));
+        assert_eq!(ulps_distance(f64::from_bits(1), -f64::from_bits(1)), Some(2));
+    }
+
+    #[test]
+    fn ulps_far_apart_and_non_finite() {
+        let far = f64::from_bits(1.0f64.to_bits() + 1_000_000);
+
+        assert!(!Tolerance::Ulps(4).accepts(1.0, far));
+        assert!(!Tolerance::Ulps(u64::MAX).accepts(f64::NAN, f64::NAN));
+        assert!(!Tolerance::Ulps(u64::MAX).accepts(f64::MAX, f64::INFINITY));
+        assert!(Tolerance::Ulps(0).accepts(f64::INFINITY, f64::INFINITY));
+    }
+
+    #[test]
+    fn mixed_magnitudes_need_ulps() {
+        let bump = |x: f64| f64::from_bits(x.to_bits() + 2);
+        let left = Matrix::new(1, 3, vec![1e12, 1.0, 1e-12]).unwrap();
+        let right = Matrix::new(1, 3, left.get_numbers().iter().map(|&x| bump(x)).collect()).unwrap();
+
+        let absolute = left.compare(&right, Tolerance::Absolute(1e-9));
+        assert_eq!((absolute.mismatches, absolute.first_mismatch), (1, Some((0, 0))));
+
+        assert!(left.approx_eq(&right, Tolerance::Ulps(4)));
+        assert!(!left.approx_eq(&Matrix::new(3, 1, vec![0.0; 3]).unwrap(), Tolerance::Ulps(4)));
+    }
+
+    #[test]
+    fn compare_config_options() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().into_iter();
+
+        let config = CompareConfig::from_iter(args(&["mm", "compare", "a.txt", "b.txt", "--ulps", "4"])).unwrap();
+        assert_eq!(config.tolerance, Tolerance::Ulps(4));
+
+        assert!(CompareConfig::from_iter(args(&["mm", "compare", "a.txt", "b.txt", "--ulps", "-1"])).is_err());
+        assert!(CompareConfig::from_iter(args(&["mm", "compare", "a.txt"])).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::{Display, LowerExp};
+use std::str::FromStr;
+use crate::element::{Element, ElementType};
+use crate::io::{self, Format};
+use crate::matrix::Matrix;
+
+pub struct ConvertConfig {
+    input_file_name: String,
+    output_file_name: String,
+    format: Format,
+    canonical: bool,
+}
+
+impl ConvertConfig {
+    /// Parses `convert [input file] [output file] [--format plain|csv|binary|coo] [--canonical]`.
+    /// The format defaults to the one of the output's extension.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<ConvertConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let input_file_name = iterator.next().ok_or_else(|| String::from("Missing input matrix file name"))?;
+        let output_file_name = iterator.next().ok_or_else(|| String::from("Missing output matrix file name"))?;
+
+        let mut format = Format::from_extension(&output_file_name);
+        let mut canonical = false;
+
+        while let Some(option) = iterator.next() {
+            match option.as_str() {
+                "--canonical" => canonical = true,
+                "--format" => {
+                    let value = iterator.next().ok_or_else(|| format!("Missing value for {option}"))?;
+                    format = Format::from_name(&value)?;
+                },
+                _ => return Err(format!("Unknown option {option}"))
+            }
+        }
+
+        if canonical && format != Format::PlainText {
+            return Err(String::from("--canonical needs plain text output"))
+        }
+
+        Ok(ConvertConfig { input_file_name, output_file_name, format, canonical })
+    }
+}
+
+/// Converts the input with [`Matrix::convert_file`], and with `--canonical`
+/// rewrites the output with [`Matrix::canonicalize_file`]. A plain text input
+/// is read as the element type probed from it, any other as f64.
+pub fn run_convert(config: ConvertConfig) -> Result<(), String> {
+    let element_type = match Format::from_extension(&config.input_file_name) {
+        Format::PlainText => io::probe_element_type(&config.input_file_name)?,
+        _ => ElementType::F64,
+    };
+
+    match element_type {
+        ElementType::I64 => convert::<i64>(&config),
+        ElementType::F64 => convert::<f64>(&config),
+    }
+}
+
+fn convert<T: FromStr + Display + LowerExp + Element>(config: &ConvertConfig) -> Result<(), String> {
+    Matrix::<T>::convert_file(&config.input_file_name, &config.output_file_name, config.format)?;
+
+    if config.canonical {
+        Matrix::<T>::canonicalize_file(&config.output_file_name)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod convert_test {
+    use crate::convert::ConvertConfig;
+    use crate::io::Format;
+
+    #[test]
+    fn convert_config_options() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().into_iter();
+
+        let config = ConvertConfig::from_iter(args(&["mm", "convert", "a.csv", "b.bin"])).unwrap();
+        assert_eq!((config.format, config.canonical), (Format::Binary, false));
+
+        let config = ConvertConfig::from_iter(args(&["mm", "convert", "a.csv", "b.out", "--canonical"])).unwrap();
+        assert_eq!((config.format, config.canonical), (Format::PlainText, true));
+
+        assert_eq!(ConvertConfig::from_iter(args(&["mm", "convert", "a.txt", "b.txt", "--format", "csv", "--canonical"]))
+                       .err().as_deref(), Some("--canonical needs plain text output"));
+        assert!(ConvertConfig::from_iter(args(&["mm", "convert", "a.txt", "b.txt", "--format"])).is_err());
+        assert!(ConvertConfig::from_iter(args(&["mm", "convert", "a.txt"])).is_err());
+    }
+}
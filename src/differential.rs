@@ -0,0 +1,256 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+use crate::compare::{Tolerance, DEFAULT_TOLERANCE};
+use crate::matrix::Matrix;
+use crate::multiplication::{multiply_with_options, Kernel, MultiplyError, MultiplyOptions};
+use crate::shape::Shape;
+
+/// Finished blocks each kernel may queue ahead of the comparison.
+pub const BLOCK_QUEUE_DEPTH: usize = 2;
+
+/// Computes a band of rows of A·B, for [`compare_kernels`].
+pub trait BlockKernel: Sync {
+    /// Rows `rows` of A·B, as a `rows.len()`×B.columns() matrix.
+    fn multiply_rows(&self, matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, rows: Range<usize>,
+                     num_of_threads: usize) -> Result<Matrix<f64>, String>;
+}
+
+impl BlockKernel for Kernel {
+    fn multiply_rows(&self, matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, rows: Range<usize>,
+                     num_of_threads: usize) -> Result<Matrix<f64>, String> {
+
+        let columns = matrix_a.columns();
+        let band = Matrix::new(rows.len(), columns, matrix_a.get_numbers()[rows.start * columns..rows.end * columns].to_vec())?;
+
+        let mut options = MultiplyOptions::new(num_of_threads.min(rows.len()));
+        options.kernel = *self;
+
+        Ok(multiply_with_options(&band, matrix_b, &options)?)
+    }
+}
+
+/// Knobs for [`compare_kernels`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KernelComparisonOptions {
+    tolerance: Tolerance,
+    block_rows: usize,
+    num_of_threads: usize,
+}
+
+impl Default for KernelComparisonOptions {
+    fn default() -> KernelComparisonOptions {
+        KernelComparisonOptions { tolerance: Tolerance::Absolute(DEFAULT_TOLERANCE), block_rows: 64, num_of_threads: 1 }
+    }
+}
+
+impl KernelComparisonOptions {
+    pub fn new() -> KernelComparisonOptions {
+        KernelComparisonOptions::default()
+    }
+
+    pub fn tolerance(mut self, tolerance: Tolerance) -> KernelComparisonOptions {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Rows of the output in each compared block, 64 by default.
+    pub fn block_rows(mut self, block_rows: usize) -> KernelComparisonOptions {
+        self.block_rows = block_rows;
+        self
+    }
+
+    /// Threads each kernel uses per block, capped at the block's rows.
+    pub fn num_of_threads(mut self, num_of_threads: usize) -> KernelComparisonOptions {
+        self.num_of_threads = num_of_threads;
+        self
+    }
+}
+
+/// The first element where the kernels disagree, with both of their values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KernelMismatch {
+    pub block: usize,
+    pub row: usize,
+    pub column: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct KernelComparison {
+    pub blocks_compared: usize,
+    pub rows_compared: usize,
+    pub max_abs_diff: f64,
+    /// Set when the comparison stopped early.
+    pub first_mismatch: Option<KernelMismatch>,
+    /// Most blocks computed by either kernel and not yet compared at any one
+    /// time, at most 2·([`BLOCK_QUEUE_DEPTH`] + 2).
+    pub peak_blocks_in_flight: usize,
+}
+
+impl KernelComparison {
+    pub fn is_match(&self) -> bool {
+        self.first_mismatch.is_none()
+    }
+}
+
+/// Runs `kernel_x` and `kernel_y` side by side on A·B, each on its own thread
+/// computing one block of rows after another, and compares the blocks as both
+/// kernels finish them, stopping at the first element outside the tolerance.
+/// Neither kernel gets more than [`BLOCK_QUEUE_DEPTH`] blocks ahead of the
+/// slower one, so memory stays a few blocks rather than two full products.
+pub fn compare_kernels(matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, kernel_x: &dyn BlockKernel,
+                       kernel_y: &dyn BlockKernel, options: &KernelComparisonOptions) -> Result<KernelComparison, String> {
+
+    if matrix_a.columns() != matrix_b.rows() {
+        return Err(MultiplyError::DimensionMismatch { expected: Shape { rows: matrix_a.columns(), cols: matrix_b.columns() },
+            actual: matrix_b.shape() }.into())
+    }
+
+    if options.num_of_threads == 0 {
+        return Err(MultiplyError::NoThreads.into())
+    }
+
+    if options.block_rows == 0 {
+        return Err(String::from("Blocks need at least one row"))
+    }
+
+    let blocks: Vec<Range<usize>> = (0..matrix_a.rows()).step_by(options.block_rows)
+        .map(|start| start..(start + options.block_rows).min(matrix_a.rows()))
+        .collect();
+    let in_flight = AtomicUsize::new(0);
+    let peak = AtomicUsize::new(0);
+
+    let produce = |kernel: &dyn BlockKernel, sender: SyncSender<Result<Matrix<f64>, String>>| {
+        for rows in &blocks {
+            let block = kernel.multiply_rows(matrix_a, matrix_b, rows.clone(), options.num_of_threads);
+            peak.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+
+            if sender.send(block).is_err() {
+                return
+            }
+        }
+    };
+
+    let mut comparison = thread::scope(|scope| {
+        let (x_sender, x_receiver) = sync_channel(BLOCK_QUEUE_DEPTH);
+        let (y_sender, y_receiver) = sync_channel(BLOCK_QUEUE_DEPTH);
+        scope.spawn(|| produce(kernel_x, x_sender));
+        scope.spawn(|| produce(kernel_y, y_sender));
+
+        compare_blocks(&blocks, x_receiver, y_receiver, options.tolerance, &in_flight)
+    })?;
+
+    comparison.peak_blocks_in_flight = peak.load(Ordering::SeqCst);
+    Ok(comparison)
+}
+
+/// Takes one block from each kernel at a time; returning drops the receivers,
+/// which stops both producers.
+fn compare_blocks(blocks: &[Range<usize>], x_receiver: Receiver<Result<Matrix<f64>, String>>,
+                  y_receiver: Receiver<Result<Matrix<f64>, String>>, tolerance: Tolerance,
+                  in_flight: &AtomicUsize) -> Result<KernelComparison, String> {
+
+    let mut comparison = KernelComparison { blocks_compared: 0, rows_compared: 0, max_abs_diff: 0.0,
+        first_mismatch: None, peak_blocks_in_flight: 0 };
+    let disconnected = || String::from("A kernel stopped before finishing its blocks");
+
+    for (block, rows) in blocks.iter().enumerate() {
+        let x = x_receiver.recv().map_err(|_| disconnected())??;
+        let y = y_receiver.recv().map_err(|_| disconnected())??;
+
+        if x.shape() != y.shape() || x.rows() != rows.len() {
+            return Err(format!("Block {} has shape {} from one kernel and {} from the other", block, x.shape(), y.shape()))
+        }
+
+        let columns = x.columns();
+        for (index, (&x, &y)) in x.get_numbers().iter().zip(y.get_numbers().iter()).enumerate() {
+            comparison.max_abs_diff = comparison.max_abs_diff.max((x - y).abs());
+
+            if !tolerance.accepts(x, y) && comparison.first_mismatch.is_none() {
+                comparison.first_mismatch = Some(KernelMismatch { block, row: rows.start + index / columns,
+                    column: index % columns, x, y });
+            }
+        }
+
+        in_flight.fetch_sub(2, Ordering::SeqCst);
+        comparison.blocks_compared += 1;
+        comparison.rows_compared += rows.len();
+
+        if comparison.first_mismatch.is_some() {
+            break
+        }
+    }
+
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod differential_test {
+    use std::ops::Range;
+    use crate::compare::Tolerance;
+    use crate::differential::{compare_kernels, BlockKernel, KernelComparisonOptions, KernelMismatch, BLOCK_QUEUE_DEPTH};
+    use crate::matrix::{Aliasing, Matrix};
+    use crate::multiplication::Kernel;
+
+    /// The naive kernel with one element of the product nudged.
+    struct Faulty {
+        row: usize,
+        column: usize,
+    }
+
+    impl BlockKernel for Faulty {
+        fn multiply_rows(&self, matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, rows: Range<usize>,
+                         num_of_threads: usize) -> Result<Matrix<f64>, String> {
+
+            let mut block = Kernel::Naive.multiply_rows(matrix_a, matrix_b, rows.clone(), num_of_threads)?;
+            if rows.contains(&self.row) {
+                let value = block[(self.row - rows.start, self.column)] + 0.5;
+                block.set(self.row - rows.start, self.column, value, Aliasing::default())?;
+            }
+            Ok(block)
+        }
+    }
+
+    fn operands() -> (Matrix<f64>, Matrix<f64>) {
+        (Matrix::new(50, 20, (0..1000).map(|num| (num % 17) as f64 - 8.0).collect()).unwrap(),
+         Matrix::new(20, 30, (0..600).map(|num| (num % 11) as f64 * 0.25).collect()).unwrap())
+    }
+
+    #[test]
+    fn identical_kernels_agree_everywhere() {
+        let (a, b) = operands();
+        let options = KernelComparisonOptions::new().block_rows(8).num_of_threads(3);
+
+        let comparison = compare_kernels(&a, &b, &Kernel::Naive, &Kernel::CacheOblivious, &options).unwrap();
+
+        assert!(comparison.is_match());
+        assert_eq!((comparison.blocks_compared, comparison.rows_compared), (7, 50));
+        assert!(comparison.peak_blocks_in_flight <= 2 * (BLOCK_QUEUE_DEPTH + 2));
+    }
+
+    #[test]
+    fn injected_error_is_caught_at_its_block() {
+        let (a, b) = operands();
+        let options = KernelComparisonOptions::new().block_rows(10).tolerance(Tolerance::Absolute(0.1));
+        let expected = Kernel::Naive.multiply_rows(&a, &b, 0..50, 1).unwrap()[(23, 7)];
+
+        let comparison = compare_kernels(&a, &b, &Kernel::Naive, &Faulty { row: 23, column: 7 }, &options).unwrap();
+
+        assert_eq!(comparison.first_mismatch, Some(KernelMismatch { block: 2, row: 23, column: 7, x: expected, y: expected + 0.5 }));
+        assert_eq!((comparison.blocks_compared, comparison.rows_compared, comparison.max_abs_diff), (3, 30, 0.5));
+        assert!(!comparison.is_match());
+        assert!(comparison.peak_blocks_in_flight <= 2 * (BLOCK_QUEUE_DEPTH + 2));
+    }
+
+    #[test]
+    fn invalid_comparisons_are_errors() {
+        let (a, b) = operands();
+
+        assert!(compare_kernels(&b, &b, &Kernel::Naive, &Kernel::Naive, &KernelComparisonOptions::new()).is_err());
+        assert!(compare_kernels(&a, &b, &Kernel::Naive, &Kernel::Naive, &KernelComparisonOptions::new().block_rows(0)).is_err());
+        assert!(compare_kernels(&a, &b, &Kernel::Naive, &Kernel::Naive, &KernelComparisonOptions::new().num_of_threads(0)).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::ops::{Add, AddAssign};
+use crate::matrix::Matrix;
+use crate::multiplication::validate;
+use crate::parallel::parallel_over_rows;
+
+/// Dekker's splitting constant, 2^27 + 1.
+const SPLITTER: f64 = 134_217_729.0;
+
+/// An unevaluated sum `hi + lo` with `|lo| <= ulp(hi) / 2`, carrying about 106
+/// bits of significand.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+impl DoubleDouble {
+    /// The exact product `a * b` as a double-double.
+    pub fn from_product(a: f64, b: f64) -> DoubleDouble {
+        let (hi, lo) = two_product(a, b);
+        DoubleDouble { hi, lo }
+    }
+
+    /// Rounds to the nearest f64.
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> DoubleDouble {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (sum, error) = two_sum(self.hi, other.hi);
+        let (hi, lo) = quick_two_sum(sum, error + self.lo + other.lo);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl AddAssign for DoubleDouble {
+    fn add_assign(&mut self, other: DoubleDouble) {
+        *self = *self + other;
+    }
+}
+
+/// Knuth's two-sum: `a + b == sum + error` exactly.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_virtual = sum - a;
+    let a_virtual = sum - b_virtual;
+    (sum, (a - a_virtual) + (b - b_virtual))
+}
+
+/// Two-sum for `|a| >= |b|`.
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    (sum, b - (sum - a))
+}
+
+/// Splits `a` into two halves of 26 significant bits each.
+fn split(a: f64) -> (f64, f64) {
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+/// Dekker's two-product: `a * b == product + error` exactly, barring overflow.
+fn two_product(a: f64, b: f64) -> (f64, f64) {
+    let product = a * b;
+    let ((a_hi, a_lo), (b_hi, b_lo)) = (split(a), split(b));
+    let error = ((a_hi * b_hi - product) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo;
+    (product, error)
+}
+
+/// Like [`multiply`](crate::multiplication::multiply), but every dot product
+/// is accumulated in [`DoubleDouble`] and rounded to f64 once at the end, so
+/// cancellation in long inner dimensions costs far fewer digits.
+pub fn multiply_dd(matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, num_of_threads: usize) -> Result<Matrix<f64>, String> {
+    validate(matrix_a, matrix_b, num_of_threads)?;
+
+    let (rows, n, columns) = (matrix_a.rows(), matrix_a.columns(), matrix_b.columns());
+    let (a_numbers, b_numbers) = (matrix_a.get_numbers(), matrix_b.get_numbers());
+    let (a_numbers, b_numbers) = (a_numbers.as_slice(), b_numbers.as_slice());
+
+    let numbers = parallel_over_rows(rows, num_of_threads, |rows| {
+        let mut result = Vec::with_capacity(rows.len() * columns);
+        for row in rows {
+            for column in 0..columns {
+                let mut sum = DoubleDouble::default();
+                for k in 0..n {
+                    sum += DoubleDouble::from_product(a_numbers[row * n + k], b_numbers[k * columns + column]);
+                }
+                result.push(sum.to_f64());
+            }
+        }
+        result
+    }, |results| results.concat())?;
+
+    Matrix::new(rows, columns, numbers)
+}
+
+#[cfg(test)]
+mod double_double_test {
+    use crate::double_double::{multiply_dd, DoubleDouble};
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+
+    /// Exact dot product of multiples of 2^-20: every term becomes an integer
+    /// count of 2^-40 and the sum is rounded to f64 once.
+    fn exact_dot(a: &[f64], b: &[f64]) -> f64 {
+        let scale = 2f64.powi(20);
+        let fixed = |x: f64| {
+            assert_eq!((x * scale).fract(), 0.0, "{x} is not a multiple of 2^-20");
+            (x * scale) as i128
+        };
+
+        let sum: i128 = a.iter().zip(b).map(|(&x, &y)| fixed(x) * fixed(y)).sum();
+        sum as f64 / (scale * scale)
+    }
+
+    #[test]
+    fn two_product_is_exact() {
+        let x = 1.0 + f64::EPSILON;
+        let product = DoubleDouble::from_product(x, x);
+
+        assert_eq!((product.hi, product.lo), (1.0 + 2.0 * f64::EPSILON, f64::EPSILON * f64::EPSILON));
+    }
+
+    #[test]
+    fn recovers_digits_lost_to_cancellation() {
+        let (big, tiny) = (2f64.powi(25), 2f64.powi(-20));
+        let a = vec![big + tiny, -big, 3.0 * tiny];
+        let b = vec![big - tiny, big, 1.0];
+
+        let expected = exact_dot(&a, &b);
+        let matrix_a = Matrix::new(1, 3, a).unwrap();
+        let matrix_b = Matrix::new(3, 1, b).unwrap();
+
+        let naive = multiply(&matrix_a, &matrix_b, 1).unwrap().get_numbers()[0];
+        let dd = multiply_dd(&matrix_a, &matrix_b, 1).unwrap().get_numbers()[0];
+
+        assert!(((naive - expected) / expected).abs() > 1e-7, "naive {naive} vs {expected}");
+        assert_eq!(dd, expected);
+    }
+
+    #[test]
+    fn benign_inputs_match_naive() {
+        let matrix_a = Matrix::new(4, 3, (0..12).map(|num| num as f64 - 5.5).collect()).unwrap();
+        let matrix_b = Matrix::new(3, 5, (0..15).map(|num| num as f64 * 0.25).collect()).unwrap();
+
+        assert_eq!(multiply_dd(&matrix_a, &matrix_b, 2).unwrap(), multiply(&matrix_a, &matrix_b, 1).unwrap());
+        assert_eq!(multiply_dd(&matrix_a, &matrix_a, 1).unwrap_err(), "Dimension mismatch: expected 3x3, got 4x3");
+    }
+}
@@ -0,0 +1,114 @@
+/// Numeric types that can be stored in a [`Matrix`](crate::matrix::Matrix) and
+/// written in the binary format.
+pub trait Element: Copy + PartialEq {
+    /// Tag stored in binary headers so a file is never read back as the wrong type.
+    const TAG: u8;
+    /// Size of one element in bytes.
+    const SIZE: usize;
+    const ZERO: Self;
+    const ONE: Self;
+    /// Lowest and highest finite values of a float type, `None` for integers.
+    const FLOAT_RANGE: Option<(Self, Self)>;
+
+    /// Lossy conversion used for tolerance checks.
+    fn to_f64(self) -> f64;
+
+    fn write_le_bytes(&self, out: &mut Vec<u8>);
+
+    /// `bytes` must be exactly `SIZE` long.
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_element {
+    ($($t:ty => $tag:expr, $zero:expr, $one:expr, $float_range:expr);*) => {
+        $(
+            impl Element for $t {
+                const TAG: u8 = $tag;
+                const SIZE: usize = std::mem::size_of::<$t>();
+                const ZERO: Self = $zero;
+                const ONE: Self = $one;
+                const FLOAT_RANGE: Option<(Self, Self)> = $float_range;
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                fn write_le_bytes(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn from_le_bytes(bytes: &[u8]) -> Self {
+                    let mut buffer = [0u8; std::mem::size_of::<$t>()];
+                    buffer.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(buffer)
+                }
+            }
+        )*
+    };
+}
+
+impl_element!(i32 => 1, 0, 1, None; i64 => 2, 0, 1, None; u32 => 3, 0, 1, None; u64 => 4, 0, 1, None;
+              f32 => 5, 0.0, 1.0, Some((f32::MIN, f32::MAX)); f64 => 6, 0.0, 1.0, Some((f64::MIN, f64::MAX)));
+
+/// Element types a run can compute in, picked with `--dtype`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ElementType {
+    I64,
+    F64,
+}
+
+impl ElementType {
+    pub fn from_name(name: &str) -> Result<ElementType, String> {
+        match name {
+            "i64" => Ok(ElementType::I64),
+            "f64" => Ok(ElementType::F64),
+            _ => Err(format!("Unknown element type {name}, expected auto, i64 or f64"))
+        }
+    }
+}
+
+impl std::fmt::Display for ElementType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ElementType::I64 => write!(f, "i64"),
+            ElementType::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+/// Integer elements, which support overflow checked and wrapping arithmetic.
+pub trait IntegerElement: Element {
+    fn checked_mul(self, other: Self) -> Option<Self>;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn wrapping_mul(self, other: Self) -> Self;
+
+    fn wrapping_add(self, other: Self) -> Self;
+}
+
+macro_rules! impl_integer_element {
+    ($($t:ty),*) => {
+        $(
+            impl IntegerElement for $t {
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$t>::checked_mul(self, other)
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn wrapping_mul(self, other: Self) -> Self {
+                    <$t>::wrapping_mul(self, other)
+                }
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$t>::wrapping_add(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_element!(i32, i64, u32, u64);
@@ -0,0 +1,149 @@
+//! C ABI over the f64 multiply, for callers such as Python's ctypes.
+//!
+//! Every function returns 0 on success or a negative `MM_ERR_*` code; the
+//! message for the last failure on the calling thread is available from
+//! [`matmul_last_error_message`]. Panics are caught and reported as
+//! [`MM_ERR_PANIC`] rather than unwinding into the caller.
+//!
+//! Build the shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic;
+use std::slice;
+use crate::matrix::Matrix;
+use crate::multiplication::{multiply_with_options, MultiplyError, MultiplyOptions};
+
+pub const MM_OK: i32 = 0;
+pub const MM_ERR_DIMENSION_MISMATCH: i32 = -1;
+pub const MM_ERR_TOO_MANY_THREADS: i32 = -2;
+pub const MM_ERR_WEIGHTS_LENGTH: i32 = -3;
+pub const MM_ERR_NO_THREADS: i32 = -4;
+pub const MM_ERR_TIMED_OUT: i32 = -5;
+pub const MM_ERR_OVERFLOW: i32 = -6;
+pub const MM_ERR_INTERNAL: i32 = -7;
+/// A pointer argument was null.
+pub const MM_ERR_NULL_POINTER: i32 = -8;
+/// A shape argument was unusable, e.g. its element count overflows.
+pub const MM_ERR_INVALID_SHAPE: i32 = -9;
+pub const MM_ERR_PANIC: i32 = -10;
+pub const MM_ERR_CANCELLED: i32 = -11;
+pub const MM_ERR_ALLOCATION_FAILED: i32 = -12;
+pub const MM_ERR_BOUND_EXCEEDED: i32 = -13;
+pub const MM_ERR_LIMIT_EXCEEDED: i32 = -14;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn error_code(error: &MultiplyError) -> i32 {
+    match error {
+        MultiplyError::DimensionMismatch { .. } => MM_ERR_DIMENSION_MISMATCH,
+        MultiplyError::TooManyThreads { .. } => MM_ERR_TOO_MANY_THREADS,
+        MultiplyError::WeightsLength { .. } => MM_ERR_WEIGHTS_LENGTH,
+        MultiplyError::NoThreads => MM_ERR_NO_THREADS,
+        MultiplyError::TimedOut { .. } => MM_ERR_TIMED_OUT,
+        MultiplyError::Cancelled { .. } => MM_ERR_CANCELLED,
+        MultiplyError::Overflow { .. } => MM_ERR_OVERFLOW,
+        MultiplyError::AllocationFailed { .. } => MM_ERR_ALLOCATION_FAILED,
+        MultiplyError::BoundExceeded { .. } => MM_ERR_BOUND_EXCEEDED,
+        MultiplyError::LimitExceeded(_) => MM_ERR_LIMIT_EXCEEDED,
+        MultiplyError::Internal(_) => MM_ERR_INTERNAL,
+    }
+}
+
+fn fail(code: i32, message: String) -> i32 {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+/// Multiplies the row-major `a_rows`x`a_cols` matrix at `a_ptr` by the
+/// `b_rows`x`b_cols` matrix at `b_ptr` on `threads` threads, writing the
+/// `a_rows`x`b_cols` product to `out_ptr`.
+///
+/// # Safety
+///
+/// Non-null pointers must be valid for the element counts their shapes
+/// describe, and `out_ptr` must not overlap either input.
+#[no_mangle]
+pub unsafe extern "C" fn matmul_f64(a_ptr: *const f64, a_rows: usize, a_cols: usize,
+                                    b_ptr: *const f64, b_rows: usize, b_cols: usize,
+                                    out_ptr: *mut f64, threads: usize) -> i32 {
+    if a_ptr.is_null() || b_ptr.is_null() || out_ptr.is_null() {
+        return fail(MM_ERR_NULL_POINTER, String::from("Matrix pointers must not be null"))
+    }
+
+    let (Some(a_len), Some(b_len), Some(out_len)) =
+        (a_rows.checked_mul(a_cols), b_rows.checked_mul(b_cols), a_rows.checked_mul(b_cols)) else {
+        return fail(MM_ERR_INVALID_SHAPE, String::from("Matrix shape overflows the element count"))
+    };
+
+    let (a, b) = (slice::from_raw_parts(a_ptr, a_len), slice::from_raw_parts(b_ptr, b_len));
+    let out = slice::from_raw_parts_mut(out_ptr, out_len);
+
+    let result = panic::catch_unwind(|| {
+        let matrix_a = Matrix::new(a_rows, a_cols, a.to_vec()).map_err(MultiplyError::Internal)?;
+        let matrix_b = Matrix::new(b_rows, b_cols, b.to_vec()).map_err(MultiplyError::Internal)?;
+        multiply_with_options(&matrix_a, &matrix_b, &MultiplyOptions::new(threads))
+    });
+
+    match result {
+        Ok(Ok(product)) => {
+            out.copy_from_slice(&product.get_numbers());
+            LAST_ERROR.with(|last| *last.borrow_mut() = CString::default());
+            MM_OK
+        },
+        Ok(Err(error)) => fail(error_code(&error), error.to_string()),
+        Err(_) => fail(MM_ERR_PANIC, String::from("Multiplication panicked")),
+    }
+}
+
+/// Message for the last error on this thread, empty after a success. The
+/// pointer stays valid until the next call into this module on the same thread.
+#[no_mangle]
+pub extern "C" fn matmul_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod ffi_test {
+    use std::ffi::CStr;
+    use std::ptr;
+    use crate::ffi::{matmul_f64, matmul_last_error_message, MM_ERR_DIMENSION_MISMATCH, MM_ERR_NULL_POINTER, MM_OK};
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(matmul_last_error_message()) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn matches_library_result() {
+        let matrix_a = Matrix::<f64>::gen_random(4, 3, -1.0, 1.0).unwrap();
+        let matrix_b = Matrix::<f64>::gen_random(3, 5, -1.0, 1.0).unwrap();
+        let (a, b) = (matrix_a.get_numbers(), matrix_b.get_numbers());
+        let mut out = vec![0.0; 20];
+
+        let code = unsafe { matmul_f64(a.as_ptr(), 4, 3, b.as_ptr(), 3, 5, out.as_mut_ptr(), 2) };
+
+        assert_eq!(code, MM_OK);
+        assert_eq!(out, *multiply(&matrix_a, &matrix_b, 1).unwrap().get_numbers());
+        assert_eq!(last_error(), "");
+    }
+
+    #[test]
+    fn error_codes_for_bad_arguments() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        let mut out = [0.0; 4];
+
+        let code = unsafe { matmul_f64(a.as_ptr(), 2, 2, a.as_ptr(), 4, 1, out.as_mut_ptr(), 1) };
+        assert_eq!(code, MM_ERR_DIMENSION_MISMATCH);
+        assert_eq!(last_error(), "Dimension mismatch: expected 2x1, got 4x1");
+
+        let code = unsafe { matmul_f64(ptr::null(), 2, 2, a.as_ptr(), 2, 2, out.as_mut_ptr(), 1) };
+        assert_eq!(code, MM_ERR_NULL_POINTER);
+        assert_eq!(out, [0.0; 4]);
+    }
+}
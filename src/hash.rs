@@ -0,0 +1,215 @@
+use crate::element::Element;
+use crate::io;
+use crate::matrix::Matrix;
+
+const PRIME_1: u64 = 0x9E3779B185EBCA87;
+const PRIME_2: u64 = 0xC2B2AE3D27D4EB4F;
+const PRIME_3: u64 = 0x165667B19E3779F9;
+const PRIME_4: u64 = 0x85EBCA77C2B2AE63;
+const PRIME_5: u64 = 0x27D4EB2F165667C5;
+
+/// Seed of [`Matrix::content_hash`]; changing it changes every published hash.
+const CONTENT_HASH_SEED: u64 = 0;
+
+/// Streaming XXH64, written out here so the hash can never change with a dependency.
+pub(crate) struct Xxh64 {
+    accumulators: [u64; 4],
+    buffer: [u8; 32],
+    buffered: usize,
+    total_len: u64,
+    seed: u64,
+}
+
+impl Xxh64 {
+    pub(crate) fn new(seed: u64) -> Xxh64 {
+        Xxh64 {
+            accumulators: [
+                seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+                seed.wrapping_add(PRIME_2),
+                seed,
+                seed.wrapping_sub(PRIME_1),
+            ],
+            buffer: [0; 32],
+            buffered: 0,
+            total_len: 0,
+            seed,
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut bytes: &[u8]) {
+        self.total_len += bytes.len() as u64;
+
+        if self.buffered > 0 {
+            let take = (32 - self.buffered).min(bytes.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&bytes[..take]);
+            self.buffered += take;
+            bytes = &bytes[take..];
+
+            if self.buffered < 32 {
+                return
+            }
+            let buffer = self.buffer;
+            self.consume_stripe(&buffer);
+            self.buffered = 0;
+        }
+
+        let mut stripes = bytes.chunks_exact(32);
+        for stripe in &mut stripes {
+            self.consume_stripe(stripe);
+        }
+
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn consume_stripe(&mut self, stripe: &[u8]) {
+        for (accumulator, lane) in self.accumulators.iter_mut().zip(stripe.chunks_exact(8)) {
+            *accumulator = round(*accumulator, read_u64(lane));
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        let mut hash = if self.total_len >= 32 {
+            let [a, b, c, d] = self.accumulators;
+            let mut hash = a.rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18));
+            for accumulator in self.accumulators {
+                hash = merge_round(hash, accumulator);
+            }
+            hash
+        } else {
+            self.seed.wrapping_add(PRIME_5)
+        };
+
+        hash = hash.wrapping_add(self.total_len);
+
+        let mut rest = &self.buffer[..self.buffered];
+
+        while rest.len() >= 8 {
+            hash ^= round(0, read_u64(&rest[..8]));
+            hash = hash.rotate_left(27).wrapping_mul(PRIME_1).wrapping_add(PRIME_4);
+            rest = &rest[8..];
+        }
+
+        if rest.len() >= 4 {
+            hash ^= (u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64).wrapping_mul(PRIME_1);
+            hash = hash.rotate_left(23).wrapping_mul(PRIME_2).wrapping_add(PRIME_3);
+            rest = &rest[4..];
+        }
+
+        for &byte in rest {
+            hash ^= (byte as u64).wrapping_mul(PRIME_5);
+            hash = hash.rotate_left(11).wrapping_mul(PRIME_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME_3);
+        hash ^ (hash >> 32)
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn round(accumulator: u64, lane: u64) -> u64 {
+    accumulator.wrapping_add(lane.wrapping_mul(PRIME_2)).rotate_left(31).wrapping_mul(PRIME_1)
+}
+
+fn merge_round(hash: u64, accumulator: u64) -> u64 {
+    (hash ^ round(0, accumulator)).wrapping_mul(PRIME_1).wrapping_add(PRIME_4)
+}
+
+impl<T: Element> Matrix<T> {
+    /// XXH64 with seed 0 over rows and columns as little-endian u64 followed by
+    /// every element's little-endian bytes, so equal hashes mean equal bits on
+    /// any platform.
+    ///
+    /// Floats are hashed bitwise: `0.0` and `-0.0` hash differently, and NaNs
+    /// only match when their payloads are identical.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Xxh64::new(CONTENT_HASH_SEED);
+        hasher.update(&(self.rows() as u64).to_le_bytes());
+        hasher.update(&(self.columns() as u64).to_le_bytes());
+
+        let mut bytes = Vec::with_capacity(4096);
+        for num in self.get_numbers().iter() {
+            num.write_le_bytes(&mut bytes);
+            if bytes.len() >= 4096 {
+                hasher.update(&bytes);
+                bytes.clear();
+            }
+        }
+        hasher.update(&bytes);
+
+        hasher.finish()
+    }
+}
+
+/// Reads `file_name` as `f64`, in the format its extension names, and formats
+/// its content hash as 16 hex digits for the `hash` subcommand.
+pub fn run_hash(file_name: &str) -> Result<String, String> {
+    let matrix = io::read_matrix::<f64>(file_name, None)?;
+
+    Ok(format!("{:016x}", matrix.content_hash()))
+}
+
+#[cfg(test)]
+mod hash_test {
+    use std::fs;
+    use crate::hash::{run_hash, Xxh64};
+    use crate::matrix::Matrix;
+    use crate::test_support::temp_path;
+
+    fn xxh64(bytes: &[u8]) -> u64 {
+        let mut hasher = Xxh64::new(0);
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn xxh64_reference_values() {
+        assert_eq!(xxh64(b""), 0xEF46DB3751D8E999);
+        assert_eq!(xxh64(b"a"), 0xD24EC4F1A98C6E5B);
+        assert_eq!(xxh64(b"abc"), 0x44BC2CF5AD770999);
+    }
+
+    #[test]
+    fn xxh64_streaming_matches_one_shot() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let mut hasher = Xxh64::new(0);
+        for chunk in bytes.chunks(7) {
+            hasher.update(chunk);
+        }
+
+        assert_eq!(hasher.finish(), xxh64(&bytes));
+    }
+
+    #[test]
+    fn content_hash_pinned_and_sensitive() {
+        let matrix = Matrix::new(2, 2, vec![1.0, -2.5, 0.0, 1e300]).unwrap();
+
+        assert_eq!(matrix.content_hash(), 0xce51edcddf5042a5);
+
+        assert_ne!(Matrix::new(2, 2, vec![1.0, -2.5, -0.0, 1e300]).unwrap().content_hash(), matrix.content_hash());
+        assert_ne!(Matrix::new(1, 4, vec![1.0, -2.5, 0.0, 1e300]).unwrap().content_hash(), matrix.content_hash());
+    }
+
+    #[test]
+    fn hash_subcommand_matches_library() {
+        let path = &temp_path("matrix.txt");
+        let matrix = Matrix::new(2, 3, vec![0.1, 0.2, 0.3, 4.0, 5.0, 6.0]).unwrap();
+        matrix.to_file(path).unwrap();
+
+        let printed = run_hash(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(printed.unwrap(), format!("{:016x}", matrix.content_hash()));
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+const BYTE_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Rounds to two decimals and drops trailing zeros: 1.50 becomes "1.5".
+fn two_decimals(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn rounded(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Sizes in binary units with up to two decimals, e.g. "1023 B", "1 KiB",
+/// "1.5 MiB". A value that would round up to 1024 of a unit is shown as 1
+/// of the next one instead.
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes)
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while unit + 1 < BYTE_UNITS.len() && rounded(value) >= 1024.0 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{} {}", two_decimals(value), BYTE_UNITS[unit])
+}
+
+/// Durations under a minute with up to two decimals in the largest fitting
+/// unit, e.g. "999 ms", "59.9 s"; longer ones to the whole second, e.g.
+/// "2m 13s", "1h 0m 5s".
+pub fn format_duration(duration: Duration) -> String {
+    let nanos = duration.as_nanos() as f64;
+
+    for (scale, unit) in [(1.0, "ns"), (1e3, "µs"), (1e6, "ms"), (1e9, "s")] {
+        let value = nanos / scale;
+        let limit = if unit == "s" { 60.0 } else { 1000.0 };
+
+        if rounded(value) < limit {
+            return format!("{} {}", two_decimals(value), unit)
+        }
+    }
+
+    let seconds = duration.as_secs_f64().round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours == 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod human_test {
+    use std::time::Duration;
+    use crate::human::{format_bytes, format_duration};
+
+    #[test]
+    fn bytes_across_unit_boundaries() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1 KiB");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(1024 * 1024 - 1), "1 MiB");
+        assert_eq!(format_bytes(1024 * 1024 * 3 / 2), "1.5 MiB");
+        assert_eq!(format_bytes(3_650_722_202), "3.4 GiB");
+        assert_eq!(format_bytes(1_234_567_890), "1.15 GiB");
+        assert_eq!(format_bytes(u64::MAX), "16 EiB");
+    }
+
+    #[test]
+    fn durations_across_unit_boundaries() {
+        assert_eq!(format_duration(Duration::from_nanos(999)), "999 ns");
+        assert_eq!(format_duration(Duration::from_nanos(1500)), "1.5 µs");
+        assert_eq!(format_duration(Duration::from_millis(999)), "999 ms");
+        assert_eq!(format_duration(Duration::from_nanos(999_999_999)), "1 s");
+        assert_eq!(format_duration(Duration::from_millis(59_900)), "59.9 s");
+        assert_eq!(format_duration(Duration::from_millis(59_999)), "1m 0s");
+        assert_eq!(format_duration(Duration::from_secs(133)), "2m 13s");
+        assert_eq!(format_duration(Duration::from_secs(3599)), "59m 59s");
+        assert_eq!(format_duration(Duration::from_secs(3605)), "1h 0m 5s");
+    }
+}
@@ -0,0 +1,201 @@
+use std::ops::{AddAssign, Mul};
+use crate::element::Element;
+use crate::matrix::{Aliasing, Matrix};
+use crate::multiplication::MultiplyError;
+use crate::parallel::parallel_over_rows;
+use crate::shape::Shape;
+
+/// Sum of `a(k) * b(k)` over k, in the same order as the naive kernel, so an
+/// unchanged operand reproduces the element of [`multiply`](crate::multiplication::multiply) exactly.
+fn inner_product<T>(n: usize, a: impl Fn(usize) -> T, b: impl Fn(usize) -> T) -> T
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Element {
+
+    if n == 0 {
+        return T::ZERO
+    }
+
+    let mut sum = &a(0) * &b(0);
+    for k in 1..n {
+        sum += &a(k) * &b(k);
+    }
+    sum
+}
+
+fn check_threads(num_of_threads: usize, elements: usize, what: &str) -> Result<(), String> {
+    if num_of_threads == 0 {
+        return Err(MultiplyError::NoThreads.into())
+    }
+
+    if num_of_threads > elements {
+        return Err(format!("Num of threads: {num_of_threads} cannot be higher than the {elements} {what} to update"))
+    }
+
+    Ok(())
+}
+
+/// Recomputes row `row_index` of C = A·B after that row of A was replaced by
+/// `a_row`, writing it into `c` in place; the other rows are left alone. The
+/// threads split the row's columns. A shared C is copied first under
+/// [`Aliasing::CloneIfShared`], the default.
+pub fn update_product_row<T>(c: &mut Matrix<T>, a_row: &[T], row_index: usize, matrix_b: &Matrix<T>,
+                             num_of_threads: usize, aliasing: Aliasing) -> Result<(), String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Element + Sync + Send {
+
+    let (n, columns) = (matrix_b.rows(), matrix_b.columns());
+
+    if c.columns() != columns {
+        return Err(MultiplyError::DimensionMismatch { expected: Shape { rows: c.rows(), cols: columns },
+            actual: c.shape() }.into())
+    }
+
+    if a_row.len() != n {
+        return Err(format!("Row of A has {} elements, but B has {} rows", a_row.len(), n))
+    }
+
+    if row_index >= c.rows() {
+        return Err(format!("Row index {} is out of bounds for C: {}", row_index, c.shape()))
+    }
+
+    check_threads(num_of_threads, columns, "columns")?;
+
+    let b_numbers = matrix_b.get_numbers();
+    let b_numbers = b_numbers.as_slice();
+
+    let row = parallel_over_rows(columns, num_of_threads, |range| range
+        .map(|column| inner_product(n, |k| a_row[k], |k| b_numbers[k * columns + column]))
+        .collect::<Vec<T>>(), |parts| parts.into_iter().flatten().collect::<Vec<T>>())?;
+
+    c.numbers_mut(aliasing)?[row_index * columns..(row_index + 1) * columns].copy_from_slice(&row);
+    Ok(())
+}
+
+/// Recomputes column `column_index` of C = A·B after that column of B was
+/// replaced by `b_column`, like [`update_product_row`]; the threads split the
+/// rows of A.
+pub fn update_product_column<T>(c: &mut Matrix<T>, b_column: &[T], column_index: usize, matrix_a: &Matrix<T>,
+                                num_of_threads: usize, aliasing: Aliasing) -> Result<(), String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Element + Sync + Send {
+
+    let (rows, n) = (matrix_a.rows(), matrix_a.columns());
+
+    if c.rows() != rows {
+        return Err(MultiplyError::DimensionMismatch { expected: Shape { rows, cols: c.columns() },
+            actual: c.shape() }.into())
+    }
+
+    if b_column.len() != n {
+        return Err(format!("Column of B has {} elements, but A has {} columns", b_column.len(), n))
+    }
+
+    if column_index >= c.columns() {
+        return Err(format!("Column index {} is out of bounds for C: {}", column_index, c.shape()))
+    }
+
+    check_threads(num_of_threads, rows, "rows")?;
+
+    let a_numbers = matrix_a.get_numbers();
+    let a_numbers = a_numbers.as_slice();
+
+    let column = parallel_over_rows(rows, num_of_threads, |range| range
+        .map(|row| inner_product(n, |k| a_numbers[row * n + k], |k| b_column[k]))
+        .collect::<Vec<T>>(), |parts| parts.into_iter().flatten().collect::<Vec<T>>())?;
+
+    let columns = c.columns();
+    let numbers = c.numbers_mut(aliasing)?;
+    for (row, value) in column.into_iter().enumerate() {
+        numbers[row * columns + column_index] = value;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod incremental_test {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::incremental::{update_product_column, update_product_row};
+    use crate::matrix::{Aliasing, Matrix};
+    use crate::multiplication::multiply;
+
+    fn random(rows: usize, columns: usize, rng: &mut StdRng) -> Matrix<f64> {
+        Matrix::new(rows, columns, (0..rows * columns).map(|_| rng.gen_range(-10.0..10.0)).collect()).unwrap()
+    }
+
+    #[test]
+    fn updated_row_matches_full_multiply() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (mut a, b) = (random(6, 5, &mut rng), random(5, 4, &mut rng));
+        let mut c = multiply(&a, &b, 2).unwrap();
+
+        let new_row: Vec<f64> = (0..5).map(|_| rng.gen_range(-10.0..10.0)).collect();
+        for (column, &value) in new_row.iter().enumerate() {
+            a.set(3, column, value, Aliasing::default()).unwrap();
+        }
+
+        let before = c.clone();
+        update_product_row(&mut c, &new_row, 3, &b, 3, Aliasing::default()).unwrap();
+
+        assert_eq!(c, multiply(&a, &b, 1).unwrap());
+        assert_eq!(before[(2, 1)], c[(2, 1)]);
+        assert_ne!(before[(3, 1)], c[(3, 1)]);
+    }
+
+    #[test]
+    fn updated_column_matches_full_multiply() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let (a, mut b) = (random(5, 3, &mut rng), random(3, 4, &mut rng));
+        let mut c = multiply(&a, &b, 1).unwrap();
+
+        let new_column = vec![1.5, -2.0, 0.25];
+        for (row, &value) in new_column.iter().enumerate() {
+            b.set(row, 0, value, Aliasing::default()).unwrap();
+        }
+
+        update_product_column(&mut c, &new_column, 0, &a, 5, Aliasing::default()).unwrap();
+
+        assert_eq!(c, multiply(&a, &b, 1).unwrap());
+    }
+
+    #[test]
+    fn unchanged_row_leaves_product_bitwise_identical() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let (a, b) = (random(4, 7, &mut rng), random(7, 3, &mut rng));
+        let c = multiply(&a, &b, 2).unwrap();
+
+        let mut updated = c.clone();
+        for row in 0..4 {
+            update_product_row(&mut updated, &a.get_numbers()[row * 7..(row + 1) * 7], row, &b, 2,
+                               Aliasing::default()).unwrap();
+        }
+
+        let bits = |matrix: &Matrix<f64>| matrix.get_numbers().iter().map(|num| num.to_bits()).collect::<Vec<u64>>();
+        assert_eq!(bits(&updated), bits(&c));
+    }
+
+    #[test]
+    fn invalid_updates_are_errors() {
+        let (a, b) = (Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap(), Matrix::new(2, 3, vec![1, 0, 2, 0, 1, 1]).unwrap());
+        let mut c = multiply(&a, &b, 1).unwrap();
+        let shared = c.clone();
+
+        assert_eq!(update_product_row(&mut c, &[1, 1], 2, &b, 1, Aliasing::default()).unwrap_err(),
+                   "Row index 2 is out of bounds for C: 2x3");
+        assert_eq!(update_product_row(&mut c, &[1, 1, 1], 0, &b, 1, Aliasing::default()).unwrap_err(),
+                   "Row of A has 3 elements, but B has 2 rows");
+        assert_eq!(update_product_row(&mut c, &[1, 1], 0, &a, 1, Aliasing::default()).unwrap_err(),
+                   "Dimension mismatch: expected 2x2, got 2x3");
+        assert_eq!(update_product_column(&mut c, &[1, 1], 3, &a, 1, Aliasing::default()).unwrap_err(),
+                   "Column index 3 is out of bounds for C: 2x3");
+        assert!(update_product_row(&mut c, &[1, 1], 0, &b, 4, Aliasing::default()).is_err());
+        assert!(update_product_row(&mut c, &[1, 1], 0, &b, 1, Aliasing::ErrorIfShared).is_err());
+
+        update_product_row(&mut c, &[0, 1], 0, &b, 1, Aliasing::default()).unwrap();
+        assert_eq!(c.get_numbers()[..3], [0, 1, 1]);
+        assert_eq!(shared.get_numbers()[..3], [1, 2, 4]);
+    }
+}
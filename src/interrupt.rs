@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use crate::multiplication::{run, Config};
+use crate::write::temp_path;
+
+/// Exit code of a run stopped by Ctrl-C, 128 + SIGINT as shells report it.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static TOKEN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Records one interrupt: sets `token` and returns whether this was a repeat,
+/// in which case the caller should exit immediately.
+fn on_interrupt(token: &AtomicBool, interrupts: &AtomicUsize) -> bool {
+    token.store(true, Ordering::SeqCst);
+    interrupts.fetch_add(1, Ordering::SeqCst) > 0
+}
+
+#[cfg(unix)]
+mod sys {
+    pub const SIGINT: i32 = 2;
+    pub const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        pub fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        pub fn _exit(status: i32) -> !;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_sigint(_: i32) {
+    if let Some(token) = TOKEN.get() {
+        if on_interrupt(token, &INTERRUPTS) {
+            unsafe { sys::_exit(INTERRUPTED_EXIT_CODE) }
+        }
+    }
+}
+
+/// Makes the first Ctrl-C set `token` and a second one exit at once. Only one
+/// token can ever be installed per process.
+#[cfg(unix)]
+pub fn install_handler(token: Arc<AtomicBool>) -> Result<(), String> {
+    TOKEN.set(token).map_err(|_| String::from("An interrupt handler is already installed"))?;
+
+    if unsafe { sys::signal(sys::SIGINT, handle_sigint) } == sys::SIG_ERR {
+        return Err(String::from("Couldn't install the interrupt handler"))
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install_handler(token: Arc<AtomicBool>) -> Result<(), String> {
+    let _ = TOKEN.set(token);
+    Err(String::from("Interrupt handling is only supported on Unix"))
+}
+
+/// Runs `config` so that setting `token` winds the workers down, and returns
+/// the process exit code, 0 on success.
+///
+/// After an interrupt the atomic write's temporary file is removed, as is the
+/// output if it didn't exist before the run, and "Interrupted after N of M
+/// rows" is printed to stderr.
+pub fn run_interruptible(config: Config, token: Arc<AtomicBool>) -> i32 {
+    let output = config.matrix_c_file_name.clone();
+    let existed = Path::new(&output).exists();
+
+    match run(config.with_cancel_token(Arc::clone(&token))) {
+        Ok(_) => 0,
+        Err(error) if token.load(Ordering::SeqCst) => {
+            clean_up(&output, existed);
+            eprintln!("{}", error);
+            INTERRUPTED_EXIT_CODE
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            1
+        }
+    }
+}
+
+fn clean_up(output: &str, existed: bool) {
+    let _ = fs::remove_file(temp_path(output));
+
+    if !existed {
+        let _ = fs::remove_file(output);
+    }
+}
+
+#[cfg(test)]
+mod interrupt_test {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::Arc;
+    use crate::interrupt::{on_interrupt, run_interruptible, INTERRUPTED_EXIT_CODE};
+    use crate::multiplication::Config;
+    use crate::test_support;
+    use crate::write::temp_path;
+
+    fn config(a: &str, c: &str) -> Config {
+        Config::from_iter(["mm", a, a, c, "1"].into_iter().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn second_interrupt_forces_exit() {
+        let (token, interrupts) = (AtomicBool::new(false), AtomicUsize::new(0));
+
+        assert!(!on_interrupt(&token, &interrupts));
+        assert!(token.into_inner());
+        assert!(on_interrupt(&AtomicBool::new(false), &interrupts));
+    }
+
+    #[test]
+    fn interrupted_run_cleans_up() {
+        let (matrix_a, output) = (test_support::temp_path("a.txt"), test_support::temp_path("c.txt"));
+        fs::write(&matrix_a, "2\n2\n1 2\n3 4\n").unwrap();
+        fs::write(temp_path(&output), "partial").unwrap();
+
+        let code = run_interruptible(config(&matrix_a, &output), Arc::new(AtomicBool::new(true)));
+        let leftovers = (Path::new(&temp_path(&output)).exists(), Path::new(&output).exists());
+
+        let completed = run_interruptible(config(&matrix_a, &output), Arc::new(AtomicBool::new(false)));
+        let written = fs::read_to_string(&output);
+
+        fs::remove_file(&matrix_a).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(code, INTERRUPTED_EXIT_CODE);
+        assert_eq!(leftovers, (false, false));
+        assert_eq!((completed, written.unwrap()), (0, String::from("2\n2\n7 10\n15 22\n")));
+    }
+}
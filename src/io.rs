@@ -0,0 +1,784 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use crate::element::{Element, ElementType};
+use crate::human::format_bytes;
+use crate::limits::Limits;
+use crate::matrix::Matrix;
+use crate::parse::ParseOptions;
+use crate::shape::Shape;
+
+const BINARY_MAGIC: &[u8; 4] = b"MMB1";
+
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+/// Bytes before the first element of a [`Binary`] file.
+pub(crate) const BINARY_HEADER_LEN: usize = 21;
+
+/// A way of encoding a matrix in a byte stream.
+///
+/// Adding a new format only requires implementing this trait for the element
+/// types it supports and adding a [`Format`] variant so the facade can pick it.
+pub trait MatrixFormat<T> {
+    fn read<R: BufRead>(&self, reader: R) -> Result<Matrix<T>, String>;
+
+    /// Like [`MatrixFormat::read`], rejecting a matrix over `limits`. Formats
+    /// with a header check the shape it declares before allocating anything.
+    fn read_limited<R: BufRead>(&self, reader: R, limits: &Limits) -> Result<Matrix<T>, String> {
+        let matrix = self.read(reader)?;
+        limits.check_shape(matrix.shape())?;
+
+        Ok(matrix)
+    }
+
+    fn write<W: Write>(&self, matrix: &Matrix<T>, writer: W) -> Result<(), String>;
+}
+
+/// Rows and columns on their own lines, followed by whitespace separated rows.
+/// Lines starting with `#` before the header are skipped as comments.
+pub struct PlainText;
+
+/// Comma separated rows without a header; dimensions come from the data.
+/// Blank lines and lines starting with `#` are skipped.
+pub struct Csv;
+
+/// Magic bytes, element tag, rows and columns as little-endian u64, then the
+/// elements in row-major order.
+pub struct Binary;
+
+/// Coordinate (triplet) text: rows, columns and the number of stored entries
+/// on their own lines, then one `row column value` line per entry.
+///
+/// Elements whose magnitude is at most `zero_tolerance` are not written.
+/// Cells missing from a file read back as zero, and a coordinate given twice
+/// is rejected rather than summed.
+pub struct Coo {
+    pub zero_tolerance: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    PlainText,
+    Csv,
+    Binary,
+    Coo,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "plain" | "txt" => Ok(Format::PlainText),
+            "csv" => Ok(Format::Csv),
+            "binary" | "bin" => Ok(Format::Binary),
+            "coo" => Ok(Format::Coo),
+            _ => Err(format!("Unknown format '{}', expected one of: plain, csv, binary, coo", name))
+        }
+    }
+
+    /// `.csv`, `.bin` and `.coo` select their formats, anything else is plain text.
+    pub fn from_extension(file_name: &str) -> Format {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("csv") => Format::Csv,
+            Some("bin") => Format::Binary,
+            Some("coo") => Format::Coo,
+            _ => Format::PlainText,
+        }
+    }
+}
+
+/// Reads `file_name` within the default [`Limits`], see [`read_matrix_limited`].
+pub fn read_matrix<T>(file_name: &str, format: Option<Format>) -> Result<Matrix<T>, String>
+    where T: FromStr + Display + Element {
+
+    read_matrix_limited(file_name, format, &Limits::default())
+}
+
+/// Reads `file_name` in `format`, or the one its extension selects, failing
+/// on a file or declared shape over `limits`.
+pub fn read_matrix_limited<T>(file_name: &str, format: Option<Format>, limits: &Limits) -> Result<Matrix<T>, String>
+    where T: FromStr + Display + Element {
+
+    match format.unwrap_or_else(|| Format::from_extension(file_name)) {
+        Format::PlainText => read_matrix_with_limits(file_name, &PlainText, limits),
+        Format::Csv => read_matrix_with_limits(file_name, &Csv, limits),
+        Format::Binary => read_matrix_with_limits(file_name, &Binary, limits),
+        Format::Coo => read_matrix_with_limits(file_name, &Coo { zero_tolerance: 0.0 }, limits),
+    }
+}
+
+pub fn write_matrix<T>(matrix: &Matrix<T>, file_name: &str, format: Option<Format>) -> Result<(), String>
+    where T: FromStr + Display + Element {
+
+    match format.unwrap_or_else(|| Format::from_extension(file_name)) {
+        Format::PlainText => write_matrix_with(matrix, file_name, &PlainText),
+        Format::Csv => write_matrix_with(matrix, file_name, &Csv),
+        Format::Binary => write_matrix_with(matrix, file_name, &Binary),
+        Format::Coo => write_matrix_with(matrix, file_name, &Coo { zero_tolerance: 0.0 }),
+    }
+}
+
+pub fn read_matrix_with<T, F: MatrixFormat<T>>(file_name: &str, format: &F) -> Result<Matrix<T>, String> {
+    read_matrix_with_limits(file_name, format, &Limits::default())
+}
+
+pub fn read_matrix_with_limits<T, F: MatrixFormat<T>>(file_name: &str, format: &F, limits: &Limits) -> Result<Matrix<T>, String> {
+    format.read_limited(open_limited(file_name, limits)?, limits)
+}
+
+pub fn write_matrix_with<T, F: MatrixFormat<T>>(matrix: &Matrix<T>, file_name: &str, format: &F) -> Result<(), String> {
+    format
+        .write(matrix, create_file(file_name)?)
+        .map_err(|error| format!("Error writing to file {}: {}", file_name, error))
+}
+
+/// Reads only the rows and columns lines of a plain text matrix file, after
+/// any leading `#` comment lines.
+pub fn read_header(file_name: &str) -> Result<(usize, usize), String> {
+    let mut lines = open_input(file_name)?.lines()
+        .skip_while(|line| line.as_ref().is_ok_and(|line| line.trim_start().starts_with('#')));
+
+    let mut next = |name: &str, missing: &str| match lines.next() {
+        Some(Ok(line)) => ParseOptions::new().parse::<usize>(line.trim())
+            .map_err(|hint| format!("Couldn't parse '{}' as {}{}", line, name, hint)),
+        Some(Err(error)) => Err(format!("Couldn't read matrix: {}", error)),
+        None => Err(String::from(missing))
+    };
+
+    let rows = next("rows num", "File is empty!")?;
+    let columns = next("columns num", "File doesn't have columns row")?;
+
+    Ok((rows, columns))
+}
+
+/// Data rows of a plain text file looked at by [`probe_element_type`].
+pub const PROBE_ROWS: usize = 16;
+
+/// Picks the element type for a plain text matrix file from its first
+/// [`PROBE_ROWS`] data rows: [`ElementType::I64`] if every token is an
+/// integer, [`ElementType::F64`] as soon as one isn't, including integers too
+/// big for i64. A token that isn't a number is an error naming its line and
+/// column.
+pub fn probe_element_type(file_name: &str) -> Result<ElementType, String> {
+    let lines = open_file(file_name)?.lines().enumerate()
+        .skip_while(|(_, line)| line.as_ref().is_ok_and(|line| line.trim_start().starts_with('#')))
+        .skip(2)
+        .take(PROBE_ROWS);
+
+    let mut element_type = ElementType::I64;
+
+    for (index, line) in lines {
+        let line = line.map_err(|error| format!("Couldn't read matrix: {}", error))?;
+
+        for (column, token) in line.split_whitespace().enumerate() {
+            if token.parse::<i64>().is_ok() {
+                continue
+            }
+
+            if token.parse::<f64>().is_err() {
+                return Err(format!("Couldn't parse '{}' on line {}, column {} as a number", token, index + 1, column + 1))
+            }
+
+            element_type = ElementType::F64;
+        }
+    }
+
+    Ok(element_type)
+}
+
+pub(crate) fn open_file(file_name: &str) -> Result<BufReader<File>, String> {
+    match File::open(file_name) {
+        Ok(file) => Ok(BufReader::new(file)),
+        Err(error) => Err(format!("Couldn't open file {}\nerror: {}", file_name, error))
+    }
+}
+
+/// An input file that can't be parsed as a matrix of any format.
+#[derive(Debug, PartialEq)]
+pub enum ReadError {
+    /// Nothing, or only whitespace after an optional byte order mark.
+    EmptyOrBlankFile { path: String, size_bytes: u64 },
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::EmptyOrBlankFile { path, size_bytes: 0 } => write!(f, "File {} is empty (0 B)", path),
+            ReadError::EmptyOrBlankFile { path, size_bytes } =>
+                write!(f, "File {} is blank: its {} are only whitespace or a byte order mark",
+                       path, format_bytes(*size_bytes)),
+        }
+    }
+}
+
+impl From<ReadError> for String {
+    fn from(error: ReadError) -> String {
+        error.to_string()
+    }
+}
+
+/// Returns [`ReadError::EmptyOrBlankFile`] if the file has nothing to parse,
+/// reading only up to its first byte that isn't whitespace. A file that
+/// can't be opened isn't reported here but by the read that follows.
+pub fn find_blank_file(file_name: &str) -> Option<ReadError> {
+    let file = File::open(file_name).ok()?;
+    let size_bytes = file.metadata().ok()?.len();
+    let mut in_bom = true;
+
+    for (position, byte) in BufReader::new(file).bytes().enumerate() {
+        let byte = byte.ok()?;
+        in_bom = in_bom && position < UTF8_BOM.len() && byte == UTF8_BOM[position];
+
+        if !in_bom && !byte.is_ascii_whitespace() {
+            return None
+        }
+    }
+
+    Some(ReadError::EmptyOrBlankFile { path: file_name.to_string(), size_bytes })
+}
+
+/// Opens `file_name` for parsing, failing early on an empty or blank file.
+pub(crate) fn open_input(file_name: &str) -> Result<BufReader<File>, String> {
+    match find_blank_file(file_name) {
+        Some(error) => Err(error.into()),
+        None => open_file(file_name),
+    }
+}
+
+/// Like [`open_input`], first checking the file's size against `limits`.
+pub(crate) fn open_limited(file_name: &str, limits: &Limits) -> Result<BufReader<File>, String> {
+    if let Ok(metadata) = std::fs::metadata(file_name) {
+        limits.check_file_bytes(metadata.len()).map_err(|error| format!("{}: {}", file_name, error))?;
+    }
+
+    open_input(file_name)
+}
+
+pub(crate) fn create_file(file_name: &str) -> Result<BufWriter<File>, String> {
+    match File::create(file_name) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(error) => Err(format!("Error writing to file {}: {}", file_name, error))
+    }
+}
+
+/// Reads and checks a [`Binary`] header, returning the rows and columns it declares.
+pub(crate) fn read_binary_header<T: Element, R: Read>(reader: &mut R) -> Result<(usize, usize), String> {
+    let mut header = [0u8; BINARY_HEADER_LEN];
+
+    if reader.read_exact(&mut header).is_err() {
+        return Err(String::from("Binary header is truncated"))
+    }
+
+    if &header[0..4] != BINARY_MAGIC {
+        return Err(String::from("Missing binary matrix magic bytes"))
+    }
+
+    if header[4] != T::TAG {
+        return Err(format!("Binary file holds element tag {} but {} (tag {}) was requested",
+                           header[4], std::any::type_name::<T>(), T::TAG))
+    }
+
+    let rows = u64::from_le_bytes(header[5..13].try_into().unwrap()) as usize;
+    let columns = u64::from_le_bytes(header[13..21].try_into().unwrap()) as usize;
+
+    Ok((rows, columns))
+}
+
+pub(crate) fn binary_header<T: Element>(rows: usize, columns: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(BINARY_HEADER_LEN);
+    header.extend_from_slice(BINARY_MAGIC);
+    header.push(T::TAG);
+    header.extend_from_slice(&(rows as u64).to_le_bytes());
+    header.extend_from_slice(&(columns as u64).to_le_bytes());
+    header
+}
+
+pub(crate) fn read_to_string<R: BufRead>(mut reader: R) -> Result<String, String> {
+    let mut contents = String::new();
+
+    match reader.read_to_string(&mut contents) {
+        Ok(_) => Ok(contents),
+        Err(error) => Err(format!("Couldn't read matrix: {}", error))
+    }
+}
+
+fn finish<W: Write>(mut writer: W, result: std::io::Result<()>) -> Result<(), String> {
+    result
+        .and_then(|_| writer.flush())
+        .map_err(|error| error.to_string())
+}
+
+/// Writes `matrix` as [`PlainText`], which needs only `Display` elements.
+pub(crate) fn write_plain_text<T: Display>(matrix: &Matrix<T>, file_name: &str) -> Result<(), String> {
+    let mut writer = create_file(file_name)?;
+    let result = write!(writer, "{}", matrix);
+
+    finish(writer, result).map_err(|error| format!("Error writing to file {}: {}", file_name, error))
+}
+
+impl<T: FromStr + Display + Element> MatrixFormat<T> for PlainText {
+    fn read<R: BufRead>(&self, reader: R) -> Result<Matrix<T>, String> {
+        self.read_limited(reader, &Limits::default())
+    }
+
+    fn read_limited<R: BufRead>(&self, reader: R, limits: &Limits) -> Result<Matrix<T>, String> {
+        let contents = read_to_string(reader)?;
+
+        Matrix::from_iterator_with(contents.lines(), &ParseOptions::new().limits(*limits))
+    }
+
+    fn write<W: Write>(&self, matrix: &Matrix<T>, mut writer: W) -> Result<(), String> {
+        let result = write!(writer, "{}", matrix);
+        finish(writer, result)
+    }
+}
+
+impl<T: FromStr + Display + Element> MatrixFormat<T> for Csv {
+    fn read<R: BufRead>(&self, reader: R) -> Result<Matrix<T>, String> {
+        let contents = read_to_string(reader)?;
+
+        let mut numbers = Vec::new();
+        let mut rows = 0;
+        let mut columns = 0;
+
+        for (i, line) in contents.lines().filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#')).enumerate() {
+            let row: Result<Vec<T>, &str> = line
+                .split(',')
+                .map(|num| num.trim().parse::<T>().map_err(|_| num.trim()))
+                .collect();
+
+            match row {
+                Ok(mut parsed) if i == 0 || parsed.len() == columns => {
+                    columns = parsed.len();
+                    numbers.append(&mut parsed);
+                },
+                Ok(parsed) => return Err(format!("Row {} length: {} doesn't match columns: {}",
+                                                 i, parsed.len(), columns)),
+                Err(token) => return Err(format!("Error parsing {} row: '{}' is not a valid {}",
+                                                 i, token, std::any::type_name::<T>())),
+            }
+
+            rows += 1;
+        }
+
+        if rows == 0 {
+            return Err(String::from("File is empty!"))
+        }
+
+        Matrix::new(rows, columns, numbers)
+    }
+
+    fn write<W: Write>(&self, matrix: &Matrix<T>, mut writer: W) -> Result<(), String> {
+        let numbers = matrix.get_numbers();
+
+        let mut result = Ok(());
+
+        for row in numbers.chunks(matrix.columns().max(1)).take(matrix.rows()) {
+            let line: Vec<String> = row.iter().map(|num| num.to_string()).collect();
+            result = writeln!(writer, "{}", line.join(","));
+            if result.is_err() {
+                break;
+            }
+        }
+
+        finish(writer, result)
+    }
+}
+
+impl<T: Element> MatrixFormat<T> for Binary {
+    fn read<R: BufRead>(&self, reader: R) -> Result<Matrix<T>, String> {
+        self.read_limited(reader, &Limits::default())
+    }
+
+    fn read_limited<R: BufRead>(&self, mut reader: R, limits: &Limits) -> Result<Matrix<T>, String> {
+        let (rows, columns) = read_binary_header::<T, _>(&mut reader)?;
+        limits.check_shape(Shape { rows, cols: columns })?;
+
+        let expected_len = rows
+            .checked_mul(columns)
+            .and_then(|count| count.checked_mul(T::SIZE))
+            .ok_or_else(|| format!("Binary dimensions {} are too large", Shape { rows, cols: columns }))?;
+
+        let mut data = Vec::new();
+
+        if let Err(error) = reader.read_to_end(&mut data) {
+            return Err(format!("Couldn't read matrix: {}", error))
+        }
+
+        if data.len() != expected_len {
+            return Err(format!("Binary data length: {} doesn't match rows * columns * {}: {}",
+                               data.len(), T::SIZE, expected_len))
+        }
+
+        let numbers = data
+            .chunks_exact(T::SIZE)
+            .map(T::from_le_bytes)
+            .collect();
+
+        Matrix::new(rows, columns, numbers)
+    }
+
+    fn write<W: Write>(&self, matrix: &Matrix<T>, mut writer: W) -> Result<(), String> {
+        let numbers = matrix.get_numbers();
+
+        let mut bytes = Vec::with_capacity(BINARY_HEADER_LEN + numbers.len() * T::SIZE);
+        bytes.extend_from_slice(&binary_header::<T>(matrix.rows(), matrix.columns()));
+
+        for num in numbers.iter() {
+            num.write_le_bytes(&mut bytes);
+        }
+
+        let result = writer.write_all(&bytes);
+        finish(writer, result)
+    }
+}
+
+impl<T: FromStr + Display + Element> MatrixFormat<T> for Coo {
+    fn read<R: BufRead>(&self, reader: R) -> Result<Matrix<T>, String> {
+        self.read_limited(reader, &Limits::default())
+    }
+
+    fn read_limited<R: BufRead>(&self, reader: R, limits: &Limits) -> Result<Matrix<T>, String> {
+        let contents = read_to_string(reader)?;
+        let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+
+        let mut header = |name: &str| match lines.next() {
+            Some(line) => line.trim().parse::<usize>()
+                .map_err(|_| format!("Couldn't parse '{}' as {}", line, name)),
+            None => Err(format!("Missing {} in COO header", name))
+        };
+
+        let rows = header("rows num")?;
+        let columns = header("columns num")?;
+        let entries = header("entries num")?;
+
+        let shape = Shape { rows, cols: columns };
+        limits.check_shape(shape)?;
+        let len = rows.checked_mul(columns).ok_or_else(|| format!("COO dimensions {} are too large", shape))?;
+
+        let (mut numbers, mut filled) = (Vec::new(), Vec::new());
+        numbers.try_reserve_exact(len)
+            .and_then(|_| filled.try_reserve_exact(len))
+            .map_err(|error| format!("Couldn't allocate a {} matrix: {}", shape, error))?;
+        numbers.resize(len, T::ZERO);
+        filled.resize(len, false);
+        let mut count = 0;
+
+        for line in lines {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            let (row, column, value) = match tokens.as_slice() {
+                [row, column, value] => (row.parse::<usize>(), column.parse::<usize>(), value.parse::<T>()),
+                _ => return Err(format!("Entry {} must be 'row column value', got '{}'", count, line))
+            };
+
+            let (row, column, value) = match (row, column, value) {
+                (Ok(row), Ok(column), Ok(value)) => (row, column, value),
+                _ => return Err(format!("Error parsing entry {}: '{}'", count, line))
+            };
+
+            if row >= rows || column >= columns {
+                return Err(format!("Entry {} at ({}, {}) is outside the {} matrix",
+                                   count, row, column, Shape { rows, cols: columns }))
+            }
+
+            let index = row * columns + column;
+
+            if filled[index] {
+                return Err(format!("Entry {} duplicates coordinate ({}, {})", count, row, column))
+            }
+
+            filled[index] = true;
+            numbers[index] = value;
+            count += 1;
+        }
+
+        if count != entries {
+            return Err(format!("Header declares {} entries but {} were found", entries, count))
+        }
+
+        Matrix::new(rows, columns, numbers)
+    }
+
+    fn write<W: Write>(&self, matrix: &Matrix<T>, writer: W) -> Result<(), String> {
+        self.write_formatted(matrix, writer, T::to_string)
+    }
+}
+
+impl Coo {
+    /// Like [`MatrixFormat::write`], writing each kept value as `format_value` gives it.
+    pub(crate) fn write_formatted<T: Element, W: Write>(&self, matrix: &Matrix<T>, mut writer: W,
+                                                        format_value: impl Fn(&T) -> String) -> Result<(), String> {
+        let numbers = matrix.as_slice();
+        let columns = matrix.columns();
+
+        let kept: Vec<(usize, &T)> = numbers
+            .iter()
+            .enumerate()
+            .filter(|(_, num)| num.to_f64().abs() > self.zero_tolerance || num.to_f64().is_nan())
+            .collect();
+
+        let mut text = format!("{}\n{}\n{}\n", matrix.rows(), columns, kept.len());
+
+        for (index, num) in kept {
+            text += &format!("{} {} {}\n", index / columns, index % columns, format_value(num));
+        }
+
+        let result = writer.write_all(text.as_bytes());
+        finish(writer, result)
+    }
+}
+
+#[cfg(test)]
+mod io_test {
+    use std::fs;
+    use std::io::Cursor;
+    use crate::element::ElementType;
+    use crate::io::{find_blank_file, probe_element_type, read_header, read_matrix, read_matrix_limited, write_matrix, Binary,
+                    Coo, Csv, Format, MatrixFormat, PlainText, ReadError};
+    use crate::limits::Limits;
+    use crate::matrix::Matrix;
+    use crate::test_support::temp_path;
+
+    fn sample() -> Matrix<f64> {
+        Matrix::from_vec(vec!["2", "3", "1.5 2 -3", "4 5.25 6"]).unwrap()
+    }
+
+    #[test]
+    fn extension_detection() {
+        assert_eq!(Format::from_extension("a.csv"), Format::Csv);
+        assert_eq!(Format::from_extension("dir/a.CSV"), Format::Csv);
+        assert_eq!(Format::from_extension("a.bin"), Format::Binary);
+        assert_eq!(Format::from_extension("a.coo"), Format::Coo);
+        assert_eq!(Format::from_extension("a.txt"), Format::PlainText);
+        assert_eq!(Format::from_extension("a"), Format::PlainText);
+    }
+
+    #[test]
+    fn plain_text_round_trip() {
+        let matrix = sample();
+        let mut bytes = Vec::new();
+
+        PlainText.write(&matrix, &mut bytes).unwrap();
+
+        assert_eq!(bytes, matrix.to_string().into_bytes());
+        assert_eq!(PlainText.read(Cursor::new(bytes)).unwrap(), matrix);
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let matrix = sample();
+        let mut bytes = Vec::new();
+
+        Csv.write(&matrix, &mut bytes).unwrap();
+
+        assert_eq!(String::from_utf8(bytes.clone()).unwrap(), "1.5,2,-3\n4,5.25,6\n");
+        assert_eq!(Csv.read(Cursor::new(bytes)).unwrap(), matrix);
+    }
+
+    #[test]
+    fn csv_read_ragged_rows() {
+        let ragged: Result<Matrix<i32>, String> = Csv.read(Cursor::new("1,2\n3\n"));
+        let empty: Result<Matrix<i32>, String> = Csv.read(Cursor::new(""));
+
+        assert!(ragged.is_err());
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let matrix = sample();
+        let mut bytes = Vec::new();
+
+        Binary.write(&matrix, &mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 21 + 6 * 8);
+        assert_eq!(Binary.read(Cursor::new(bytes)).unwrap(), matrix);
+    }
+
+    #[test]
+    fn binary_read_wrong_element_type() {
+        let mut bytes = Vec::new();
+        Binary.write(&sample(), &mut bytes).unwrap();
+
+        let result: Result<Matrix<i32>, String> = Binary.read(Cursor::new(bytes));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn binary_read_truncated_data() {
+        let mut bytes = Vec::new();
+        Binary.write(&sample(), &mut bytes).unwrap();
+        bytes.pop();
+
+        let result: Result<Matrix<f64>, String> = Binary.read(Cursor::new(bytes));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn facade_detects_format_from_extension() {
+        let matrix = sample();
+
+        for name in ["detect.txt", "detect.csv", "detect.bin"] {
+            let path = temp_path(name);
+
+            write_matrix(&matrix, &path, None).unwrap();
+            let result = read_matrix::<f64>(&path, None).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(result, matrix);
+        }
+
+        let path = temp_path("detect_contents.csv");
+        write_matrix(&matrix, &path, None).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(contents, "1.5,2,-3\n4,5.25,6\n");
+    }
+
+    #[test]
+    fn facade_explicit_format_overrides_extension() {
+        let matrix = sample();
+        let path = temp_path("override.txt");
+
+        write_matrix(&matrix, &path, Some(Format::Csv)).unwrap();
+
+        let as_csv = read_matrix::<f64>(&path, Some(Format::Csv));
+        let as_plain = read_matrix::<f64>(&path, None);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(as_csv.unwrap(), matrix);
+        assert!(as_plain.is_err());
+    }
+
+    #[test]
+    fn facade_missing_file() {
+        assert!(read_matrix::<f64>(&temp_path("missing.txt"), None).is_err());
+    }
+
+    #[test]
+    fn coo_round_trip_sparse_integers() {
+        let matrix = Matrix::<i32>::from_vec(vec!["3", "3", "0 0 7", "0 0 0", "-2 0 0"]).unwrap();
+        let mut bytes = Vec::new();
+
+        Coo { zero_tolerance: 0.0 }.write(&matrix, &mut bytes).unwrap();
+
+        assert_eq!(String::from_utf8(bytes.clone()).unwrap(), "3\n3\n2\n0 2 7\n2 0 -2\n");
+        assert_eq!(Coo { zero_tolerance: 0.0 }.read(Cursor::new(bytes)).unwrap(), matrix);
+    }
+
+    #[test]
+    fn coo_drops_noise_below_tolerance() {
+        let noisy = Matrix::new(2, 2, vec![1.5, 1e-14, -3e-13, -2.25]).unwrap();
+        let clean = Matrix::new(2, 2, vec![1.5, 0.0, 0.0, -2.25]).unwrap();
+        let mut bytes = Vec::new();
+
+        Coo { zero_tolerance: 1e-12 }.write(&noisy, &mut bytes).unwrap();
+
+        assert_eq!(Coo { zero_tolerance: 1e-12 }.read(Cursor::new(bytes)).unwrap(), clean);
+    }
+
+    #[test]
+    fn coo_rejects_bad_entries() {
+        let inputs = [
+            "2\n2\n1\n2 0 1\n",
+            "2\n2\n1\n0 5 1\n",
+            "2\n2\n2\n0 0 1\n0 0 2\n",
+            "2\n2\n2\n0 0 1\n",
+            "2\n2\n1\n0 0\n",
+            "2\n2\n1\n0 0 a\n",
+        ];
+
+        for input in inputs {
+            let result: Result<Matrix<i32>, String> = Coo { zero_tolerance: 0.0 }.read(Cursor::new(input));
+
+            assert!(result.is_err(), "{input:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected_before_allocating() {
+        let file = temp_path("oversized.coo");
+        fs::write(&file, "1000000000000\n1000000000000\n1\n0 0 1\n").unwrap();
+        let result = read_matrix::<f64>(&file, None);
+        let tight = read_matrix_limited::<f64>(&file, None, &Limits::new().max_elements(4));
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(result.unwrap_err(), "1000000000000x1000000000000 matrix has 1000000000000 rows, over the max_rows limit \
+                                         of 2147483648; raise it with Limits::max_rows");
+        assert!(tight.unwrap_err().contains("over the max_rows limit of 2147483648"));
+
+        let mut binary = vec![];
+        Binary.write(&Matrix::<f64>::new(3, 2, vec![0.0; 6]).unwrap(), &mut binary).unwrap();
+        let over = Binary.read_limited(Cursor::new(&binary), &Limits::new().max_elements(5));
+        assert_eq!(over.map(|_: Matrix<f64>| ()).unwrap_err(),
+                   "3x2 matrix has 6 elements, over the max_elements limit of 5; raise it with Limits::max_elements \
+                    or --max-elements");
+    }
+
+    #[test]
+    fn coo_corrupt_header_is_an_error() {
+        let unlimited = Limits::new().max_rows(usize::MAX).max_cols(usize::MAX).max_elements(usize::MAX);
+        let read = |input: String| -> Result<Matrix<i32>, String> {
+            Coo { zero_tolerance: 0.0 }.read_limited(Cursor::new(input), &unlimited)
+        };
+
+        assert_eq!(read(format!("{}\n2\n0\n", usize::MAX)).unwrap_err(),
+                   format!("COO dimensions {}x2 are too large", usize::MAX));
+        assert!(read(format!("{}\n2\n0\n", 1usize << 62)).unwrap_err()
+            .starts_with(&format!("Couldn't allocate a {}x2 matrix: ", 1usize << 62)));
+    }
+
+    #[test]
+    fn empty_and_blank_files() {
+        let cases: [(&str, &[u8]); 5] = [("empty", b""), ("whitespace", b" \t \r\n "), ("bom", b"\xEF\xBB\xBF"),
+                                         ("newlines", b"\n\n"), ("bom_then_data", b"\xEF\xBB\xBF\n1\n")];
+        let mut results = vec![];
+
+        for (name, contents) in cases {
+            let file = temp_path(&format!("{name}.txt"));
+            fs::write(&file, contents).unwrap();
+            let error = ReadError::EmptyOrBlankFile { path: file.clone(), size_bytes: contents.len() as u64 };
+            results.push((find_blank_file(&file), error.to_string(), Matrix::<f64>::from_file(&file).unwrap_err(),
+                          read_header(&file).map(|_| ()).unwrap_err()));
+            fs::remove_file(&file).unwrap();
+        }
+
+        let sizes: Vec<Option<u64>> = results.iter()
+            .map(|(found, ..)| found.as_ref().map(|ReadError::EmptyOrBlankFile { size_bytes, .. }| *size_bytes))
+            .collect();
+        assert_eq!(sizes, [Some(0), Some(6), Some(3), Some(2), None]);
+
+        for (_, message, from_file, header) in &results[..4] {
+            assert_eq!((from_file, header), (message, message));
+        }
+        assert!(results[0].1.ends_with("is empty (0 B)"));
+        assert!(results[2].1.ends_with("is blank: its 3 B are only whitespace or a byte order mark"));
+    }
+
+    #[test]
+    fn probes_element_type() {
+        let file = temp_path("probe.txt");
+        let probe = |contents: &str| {
+            fs::write(&file, contents).unwrap();
+            probe_element_type(&file)
+        };
+
+        assert_eq!(probe("# comment\n2\n2\n1 -2\n3 9007199254740993\n"), Ok(ElementType::I64));
+        assert_eq!(probe("1\n2\n1.5 2e3\n"), Ok(ElementType::F64));
+        assert_eq!(probe("2\n2\n1 2\n3 4.0\n"), Ok(ElementType::F64));
+        assert_eq!(probe("1\n1\n99999999999999999999\n"), Ok(ElementType::F64));
+        assert_eq!(probe("2\n2\n1 2\n3 x\n").unwrap_err(), "Couldn't parse 'x' on line 4, column 2 as a number");
+
+        fs::remove_file(&file).unwrap();
+    }
+}
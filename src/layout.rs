@@ -0,0 +1,158 @@
+use crate::matrix::Matrix;
+
+/// Side of the square tiles the layout conversions walk, so both the source
+/// and the destination of each tile stay in cache.
+const TILE: usize = 32;
+
+/// Line written after the plain text header when the data is column-major.
+pub(crate) const COLUMN_MAJOR_MARKER: &str = "column-major";
+
+impl<T: Clone> Matrix<T> {
+    /// The elements in column-major (Fortran) order: `[a11, a21, ..., a12, a22, ...]`.
+    pub fn to_column_major_vec(&self) -> Vec<T> {
+        transpose(self.get_numbers().to_vec(), self.rows(), self.columns())
+    }
+
+    /// Aᵀ, copied tile by tile so large matrices don't thrash the cache.
+    pub fn transpose(&self) -> Matrix<T> {
+        Matrix::new(self.columns(), self.rows(), self.to_column_major_vec()).unwrap()
+    }
+
+    /// `self` above `other`; both need the same number of columns.
+    pub fn vstack(&self, other: &Matrix<T>) -> Result<Matrix<T>, String> {
+        if self.columns() != other.columns() {
+            return Err(format!("Can't vstack {} and {}: the column counts differ", self.shape(), other.shape()))
+        }
+
+        let numbers = [self.get_numbers().as_slice(), other.get_numbers().as_slice()].concat();
+
+        Matrix::new(self.rows() + other.rows(), self.columns(), numbers)
+    }
+
+    /// `self` left of `other`; both need the same number of rows.
+    pub fn hstack(&self, other: &Matrix<T>) -> Result<Matrix<T>, String> {
+        if self.rows() != other.rows() {
+            return Err(format!("Can't hstack {} and {}: the row counts differ", self.shape(), other.shape()))
+        }
+
+        let numbers = self.iter_rows().zip(other.iter_rows()).flat_map(|(left, right)| left.iter().chain(right)).cloned().collect();
+
+        Matrix::new(self.rows(), self.columns() + other.columns(), numbers)
+    }
+}
+
+impl<T> Matrix<T> {
+    /// Builds a matrix from elements stored column after column.
+    pub fn from_column_major(rows: usize, columns: usize, data: Vec<T>) -> Result<Matrix<T>, String> {
+        if data.len() != rows * columns {
+            return Err(format!("Numbers length: {} doesn't match rows * columns: {} * {} = {}",
+                               data.len(), rows, columns, rows * columns))
+        }
+
+        Matrix::new(rows, columns, transpose(data, columns, rows))
+    }
+}
+
+/// Transposes the row-major `rows x columns` `data` tile by tile, moving
+/// rather than cloning the elements.
+pub(crate) fn transpose<T>(data: Vec<T>, rows: usize, columns: usize) -> Vec<T> {
+    let mut source: Vec<Option<T>> = data.into_iter().map(Some).collect();
+    let mut transposed: Vec<Option<T>> = (0..source.len()).map(|_| None).collect();
+
+    for row_tile in (0..rows).step_by(TILE) {
+        for column_tile in (0..columns).step_by(TILE) {
+            for row in row_tile..(row_tile + TILE).min(rows) {
+                for column in column_tile..(column_tile + TILE).min(columns) {
+                    transposed[column * rows + row] = source[row * columns + column].take();
+                }
+            }
+        }
+    }
+
+    transposed.into_iter().map(|num| num.expect("every element is moved exactly once")).collect()
+}
+
+#[cfg(test)]
+mod layout_test {
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+
+    #[test]
+    fn stacking_keeps_each_block_in_place() {
+        let top = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let bottom = Matrix::new(2, 3, vec![7, 8, 9, 10, 11, 12]).unwrap();
+
+        assert_eq!(top.vstack(&bottom), Matrix::new(4, 3, (1..=12).collect()));
+        assert_eq!(top.hstack(&bottom), Matrix::new(2, 6, vec![1, 2, 3, 7, 8, 9, 4, 5, 6, 10, 11, 12]));
+
+        // [A1; A2]·B = [A1·B; A2·B]
+        let b = Matrix::new(3, 2, vec![1, 0, -1, 2, 0, 3]).unwrap();
+        assert_eq!(multiply(&top.vstack(&bottom).unwrap(), &b, 2).unwrap(),
+                   multiply(&top, &b, 1).unwrap().vstack(&multiply(&bottom, &b, 1).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn stacking_with_empty_and_single_rows() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let row = Matrix::new(1, 3, vec![0, 0, 7]).unwrap();
+
+        assert_eq!(matrix.vstack(&Matrix::new(0, 3, vec![]).unwrap()).unwrap(), matrix);
+        assert_eq!(Matrix::new(0, 3, vec![]).unwrap().vstack(&row).unwrap(), row);
+        assert_eq!(row.vstack(&matrix), Matrix::new(3, 3, vec![0, 0, 7, 1, 2, 3, 4, 5, 6]));
+        assert_eq!(matrix.hstack(&Matrix::new(2, 0, vec![]).unwrap()).unwrap(), matrix);
+        assert_eq!(row.hstack(&row), Matrix::new(1, 6, vec![0, 0, 7, 0, 0, 7]));
+    }
+
+    #[test]
+    fn stacking_mismatched_shapes_is_an_error() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let other = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(matrix.hstack(&other).unwrap_err(), "Can't hstack 2x3 and 3x2: the row counts differ");
+        assert_eq!(matrix.vstack(&other).unwrap_err(), "Can't vstack 2x3 and 3x2: the column counts differ");
+    }
+
+    #[test]
+    fn column_major_order_of_known_matrix() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(matrix.to_column_major_vec(), vec![1, 4, 2, 5, 3, 6]);
+        assert_eq!(Matrix::from_column_major(2, 3, vec![1, 4, 2, 5, 3, 6]).unwrap(), matrix);
+    }
+
+    #[test]
+    fn column_major_round_trip_across_tiles() {
+        for (rows, columns) in [(70, 33), (1, 100), (100, 1), (0, 5)] {
+            let matrix = Matrix::<f64>::gen_random(rows, columns, -1.0, 1.0).unwrap();
+
+            let data = matrix.to_column_major_vec();
+
+            assert_eq!(Matrix::from_column_major(rows, columns, data).unwrap(), matrix);
+        }
+    }
+
+    #[test]
+    fn transpose_of_known_matrices() {
+        let matrix = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(matrix.transpose(), Matrix::new(2, 3, vec![1, 3, 5, 2, 4, 6]).unwrap());
+        assert_eq!(Matrix::new(1, 3, vec![1, 2, 3]).unwrap().transpose(), Matrix::new(3, 1, vec![1, 2, 3]).unwrap());
+        assert_eq!(Matrix::new(3, 1, vec![1, 2, 3]).unwrap().transpose(), Matrix::new(1, 3, vec![1, 2, 3]).unwrap());
+    }
+
+    #[test]
+    fn transpose_round_trip_across_tiles() {
+        for (rows, columns) in [(70, 33), (1, 100), (100, 1), (64, 64), (0, 5)] {
+            let matrix = Matrix::<f64>::gen_random(rows, columns, -1.0, 1.0).unwrap();
+            let transposed = matrix.transpose();
+
+            assert_eq!((transposed.rows(), transposed.columns()), (columns, rows));
+            assert_eq!(transposed.transpose(), matrix);
+        }
+    }
+
+    #[test]
+    fn from_column_major_wrong_length() {
+        assert!(Matrix::from_column_major(2, 2, vec![1, 2, 3]).is_err());
+    }
+}
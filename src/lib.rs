@@ -0,0 +1,47 @@
+pub mod arithmetic;
+pub mod batch;
+pub mod benchmark;
+pub mod block;
+pub mod cache;
+pub mod compare;
+pub mod convert;
+pub mod differential;
+pub mod double_double;
+pub mod element;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hash;
+pub mod human;
+pub mod incremental;
+pub mod interrupt;
+pub mod io;
+pub mod layout;
+pub mod limits;
+pub mod linalg;
+pub mod matlab;
+pub mod matrix;
+pub mod multiplication;
+pub mod parallel;
+pub mod parse;
+pub mod pipeline;
+pub mod pipelined;
+pub mod plan;
+pub mod prelude;
+pub mod record;
+pub mod selftest;
+pub mod semiring;
+#[cfg(unix)]
+pub mod serve;
+pub mod shape;
+pub mod similarity;
+pub mod stochastic;
+pub mod streaming;
+#[cfg(test)]
+mod test_support;
+pub mod tune;
+pub mod validate;
+pub mod workspace;
+pub mod write;
+
+pub use crate::matrix::Matrix;
+pub use crate::multiplication::{multiply, MultiplyError, MultiplyOptions};
@@ -0,0 +1,160 @@
+use std::fmt::{Display, Formatter};
+use crate::shape::Shape;
+
+/// Default cap on rows, columns and elements: 2^31, 16 GiB of f64.
+pub const DEFAULT_MAX_ELEMENTS: usize = 1 << 31;
+
+/// Default cap on the size of an input file: 1 TiB.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 1 << 40;
+
+/// Sanity limits on the matrices read and computed, so that a corrupted
+/// header is rejected before anything is allocated for it. The defaults are
+/// far above what fits in memory on most machines; raise them through
+/// [`ParseOptions::limits`](crate::parse::ParseOptions::limits),
+/// [`MultiplyOptions::limits`](crate::multiplication::MultiplyOptions::limits)
+/// or `--max-elements` for larger data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_rows: usize,
+    pub max_cols: usize,
+    pub max_elements: usize,
+    pub max_file_bytes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { max_rows: DEFAULT_MAX_ELEMENTS, max_cols: DEFAULT_MAX_ELEMENTS, max_elements: DEFAULT_MAX_ELEMENTS,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES }
+    }
+}
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    pub fn max_rows(mut self, max_rows: usize) -> Limits {
+        self.max_rows = max_rows;
+        self
+    }
+
+    pub fn max_cols(mut self, max_cols: usize) -> Limits {
+        self.max_cols = max_cols;
+        self
+    }
+
+    pub fn max_elements(mut self, max_elements: usize) -> Limits {
+        self.max_elements = max_elements;
+        self
+    }
+
+    pub fn max_file_bytes(mut self, max_file_bytes: u64) -> Limits {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    pub fn check_shape(&self, shape: Shape) -> Result<(), LimitExceeded> {
+        let elements = shape.rows.checked_mul(shape.cols).map_or(u64::MAX, |elements| elements as u64);
+
+        [(Limit::Rows, shape.rows as u64, self.max_rows as u64),
+         (Limit::Cols, shape.cols as u64, self.max_cols as u64),
+         (Limit::Elements, elements, self.max_elements as u64)]
+            .into_iter()
+            .find(|&(_, value, max)| value > max)
+            .map_or(Ok(()), |(limit, value, max)| Err(LimitExceeded { limit, shape: Some(shape), value, max }))
+    }
+
+    pub fn check_file_bytes(&self, bytes: u64) -> Result<(), LimitExceeded> {
+        if bytes > self.max_file_bytes {
+            return Err(LimitExceeded { limit: Limit::FileBytes, shape: None, value: bytes, max: self.max_file_bytes })
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Rows,
+    Cols,
+    Elements,
+    FileBytes,
+}
+
+impl Limit {
+    fn name(&self) -> &'static str {
+        match self {
+            Limit::Rows => "max_rows",
+            Limit::Cols => "max_cols",
+            Limit::Elements => "max_elements",
+            Limit::FileBytes => "max_file_bytes",
+        }
+    }
+}
+
+/// A matrix or file over one of the [`Limits`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    /// The declared shape, unless it's the file that is too large.
+    pub shape: Option<Shape>,
+    pub value: u64,
+    pub max: u64,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.limit {
+            Limit::Rows => "rows",
+            Limit::Cols => "columns",
+            Limit::Elements => "elements",
+            Limit::FileBytes => "bytes",
+        };
+
+        match self.shape {
+            Some(shape) => write!(f, "{} matrix has {} {}", shape, self.value, unit)?,
+            None => write!(f, "File has {} {}", self.value, unit)?,
+        }
+        write!(f, ", over the {} limit of {}; raise it with Limits::{}", self.limit.name(), self.max, self.limit.name())?;
+        if self.limit == Limit::Elements {
+            write!(f, " or --max-elements")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<LimitExceeded> for String {
+    fn from(error: LimitExceeded) -> String {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod limits_test {
+    use crate::limits::{Limit, LimitExceeded, Limits, DEFAULT_MAX_ELEMENTS};
+    use crate::shape::Shape;
+
+    #[test]
+    fn shapes_within_and_over_the_limits() {
+        let limits = Limits::new();
+
+        assert_eq!(limits.check_shape(Shape { rows: 1 << 16, cols: 1 << 15 }), Ok(()));
+        assert_eq!(limits.check_shape(Shape { rows: 1_000_000, cols: 1_000_000 }), Err(LimitExceeded {
+            limit: Limit::Elements, shape: Some(Shape { rows: 1_000_000, cols: 1_000_000 }),
+            value: 1_000_000_000_000, max: DEFAULT_MAX_ELEMENTS as u64 }));
+        assert_eq!(limits.check_shape(Shape { rows: usize::MAX, cols: 0 }).unwrap_err().limit, Limit::Rows);
+        assert_eq!(limits.max_elements(usize::MAX).check_shape(Shape { rows: 1 << 20, cols: 1 << 20 }), Ok(()));
+    }
+
+    #[test]
+    fn messages_name_the_limit_and_how_to_raise_it() {
+        let limits = Limits::new().max_cols(10).max_file_bytes(100);
+
+        assert_eq!(limits.check_shape(Shape { rows: 2, cols: 11 }).unwrap_err().to_string(),
+                   "2x11 matrix has 11 columns, over the max_cols limit of 10; raise it with Limits::max_cols");
+        assert_eq!(limits.check_file_bytes(101).unwrap_err().to_string(),
+                   "File has 101 bytes, over the max_file_bytes limit of 100; raise it with Limits::max_file_bytes");
+        assert!(Limits::new().max_elements(4).check_shape(Shape { rows: 2, cols: 3 }).unwrap_err().to_string()
+            .ends_with("over the max_elements limit of 4; raise it with Limits::max_elements or --max-elements"));
+    }
+}
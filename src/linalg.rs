@@ -0,0 +1,839 @@
+use std::cmp::Ordering;
+use std::ops::Range;
+use crate::element::Element;
+use crate::matrix::{Aliasing, Matrix};
+use crate::multiplication::multiply;
+
+/// Terms of the Taylor series evaluated after scaling; with the norm scaled
+/// below 1/2 the truncation error is far below f64 precision.
+const EXPM_TAYLOR_TERMS: usize = 18;
+
+/// Largest |Aᵢⱼ - Aⱼᵢ| relative to max|A| that [`Matrix::cholesky`] still
+/// treats as symmetric.
+const SYMMETRY_TOLERANCE: f64 = 1e-10;
+
+/// Pivots in each column no larger than this are rounding noise left over
+/// from a singular matrix: n·ε times the largest magnitude in that column of
+/// A, so scaling a column doesn't change whether A counts as singular.
+fn singular_pivot_tolerances(n: usize, numbers: &[f64]) -> Vec<f64> {
+    (0..n)
+        .map(|column| n as f64 * f64::EPSILON * (0..n).fold(0.0, |max: f64, row| max.max(numbers[row * n + column].abs())))
+        .collect()
+}
+
+/// Row of the largest magnitude in `column` among rows `from..n`.
+fn pivot_row(numbers: &[f64], n: usize, from: usize, column: usize) -> usize {
+    (from..n).max_by(|&i, &j| numbers[i * n + column].abs().total_cmp(&numbers[j * n + column].abs())).unwrap()
+}
+
+/// L, U and the row permutation returned by [`Matrix::lu`].
+pub type LuDecomposition = (Matrix<f64>, Matrix<f64>, Vec<usize>);
+
+/// PA = LU in one n×n buffer: the multipliers of L below the diagonal, its
+/// unit diagonal implied, and U on and above it.
+struct LuFactors {
+    n: usize,
+    lu: Vec<f64>,
+    /// Row of A that ends up in each row of PA.
+    permutation: Vec<usize>,
+    /// Whether P swaps an odd number of rows.
+    odd: bool,
+}
+
+impl LuFactors {
+    /// Gaussian elimination with partial pivoting of the n×n `numbers`.
+    /// Fails on a pivot no larger than n·ε times the largest magnitude in
+    /// its column of A, meaning A is singular.
+    fn new(n: usize, mut lu: Vec<f64>) -> Result<LuFactors, String> {
+        let tolerances = singular_pivot_tolerances(n, &lu);
+        let mut permutation: Vec<usize> = (0..n).collect();
+        let mut odd = false;
+
+        for k in 0..n {
+            let pivot = pivot_row(&lu, n, k, k);
+
+            if lu[pivot * n + k].abs() <= tolerances[k] {
+                return Err(format!("Matrix is singular: the largest pivot in column {} is {:e}, within the tolerance {:e}",
+                                   k, lu[pivot * n + k].abs(), tolerances[k]))
+            }
+
+            if pivot != k {
+                for j in 0..n {
+                    lu.swap(k * n + j, pivot * n + j);
+                }
+                permutation.swap(k, pivot);
+                odd = !odd;
+            }
+
+            let diagonal = lu[k * n + k];
+
+            for i in k + 1..n {
+                let factor = lu[i * n + k] / diagonal;
+                lu[i * n + k] = factor;
+                for j in k + 1..n {
+                    lu[i * n + j] -= factor * lu[k * n + j];
+                }
+            }
+        }
+
+        Ok(LuFactors { n, lu, permutation, odd })
+    }
+
+    fn determinant(&self) -> f64 {
+        let sign = if self.odd { -1.0 } else { 1.0 };
+        (0..self.n).fold(sign, |det, k| det * self.lu[k * self.n + k])
+    }
+
+    /// x with Ax = `b`, by forward substitution with L and back substitution with U.
+    fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let (n, lu) = (self.n, &self.lu);
+        let mut x: Vec<f64> = self.permutation.iter().map(|&row| b[row]).collect();
+
+        for i in 0..n {
+            for k in 0..i {
+                x[i] -= lu[i * n + k] * x[k];
+            }
+        }
+
+        for i in (0..n).rev() {
+            for k in i + 1..n {
+                x[i] -= lu[i * n + k] * x[k];
+            }
+            x[i] /= lu[i * n + i];
+        }
+
+        x
+    }
+}
+
+/// Which norm [`Matrix::norm`] computes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormKind {
+    /// Square root of the sum of squares.
+    Frobenius,
+    /// Largest element magnitude.
+    MaxAbs,
+    /// Largest absolute column sum.
+    One,
+    /// Largest absolute row sum.
+    Infinity,
+}
+
+impl Matrix<f64> {
+    pub fn frobenius_norm(&self) -> f64 {
+        self.norm(NormKind::Frobenius)
+    }
+
+    /// Maximum absolute column sum.
+    pub fn norm_1(&self) -> f64 {
+        self.norm(NormKind::One)
+    }
+
+    /// Replaces every subnormal element with a zero of the same sign and
+    /// returns how many were replaced. Normal numbers and zeros are untouched,
+    /// and a buffer without subnormals is never copied, even if it's shared.
+    pub fn flush_denormals_to_zero(&mut self, aliasing: Aliasing) -> Result<usize, String> {
+        let flushed = self.get_numbers().iter().filter(|num| num.is_subnormal()).count();
+
+        if flushed > 0 {
+            for num in self.numbers_mut(aliasing)?.iter_mut().filter(|num| num.is_subnormal()) {
+                *num = 0.0f64.copysign(*num);
+            }
+        }
+
+        Ok(flushed)
+    }
+
+    /// L, U and the permutation of PA = LU, by Gaussian elimination with
+    /// partial pivoting: L has a unit diagonal, U is upper triangular and row
+    /// i of PA is row `permutation[i]` of A. Fails for a numerically singular
+    /// matrix, one with a pivot no larger than n·ε times the largest magnitude
+    /// in its column of A.
+    pub fn lu(&self) -> Result<LuDecomposition, String> {
+        let n = self.rows();
+
+        if n != self.columns() {
+            return Err(format!("LU decomposition needs a square matrix, got {}", self.shape()))
+        }
+
+        let factors = LuFactors::new(n, self.get_numbers().to_vec())?;
+
+        let (mut lower, mut upper) = (vec![0.0; n * n], vec![0.0; n * n]);
+        for i in 0..n {
+            for j in 0..n {
+                match i.cmp(&j) {
+                    Ordering::Greater => lower[i * n + j] = factors.lu[i * n + j],
+                    Ordering::Equal => (lower[i * n + j], upper[i * n + j]) = (1.0, factors.lu[i * n + j]),
+                    Ordering::Less => upper[i * n + j] = factors.lu[i * n + j],
+                }
+            }
+        }
+
+        Ok((Matrix::new(n, n, lower)?, Matrix::new(n, n, upper)?, factors.permutation))
+    }
+
+    /// Thin Q and R of A = QR for an m×n matrix with m >= n, by Householder
+    /// reflections: Q is m×n with orthonormal columns and R is n×n upper
+    /// triangular. A rank-deficient A still factors, with zeros on the
+    /// diagonal of R.
+    pub fn qr(&self) -> Result<(Matrix<f64>, Matrix<f64>), String> {
+        let (m, n) = (self.rows(), self.columns());
+
+        if m < n {
+            return Err(format!("QR decomposition needs at least as many rows as columns, got {}", self.shape()))
+        }
+
+        let mut reduced = self.get_numbers().to_vec();
+        let mut reflectors: Vec<Vec<f64>> = Vec::with_capacity(n);
+
+        for k in 0..n {
+            let mut v: Vec<f64> = (k..m).map(|i| reduced[i * n + k]).collect();
+            let norm = v.iter().map(|num| num * num).sum::<f64>().sqrt();
+            v[0] += if v[0] >= 0.0 { norm } else { -norm };
+
+            let v_norm = v.iter().map(|num| num * num).sum::<f64>().sqrt();
+            if v_norm != 0.0 {
+                v.iter_mut().for_each(|num| *num /= v_norm);
+                reflect(&mut reduced, n, k, k..n, &v);
+            }
+            reflectors.push(v);
+        }
+
+        let mut q = vec![0.0; m * n];
+        for k in 0..n {
+            q[k * n + k] = 1.0;
+        }
+        for (k, v) in reflectors.iter().enumerate().rev() {
+            reflect(&mut q, n, k, 0..n, v);
+        }
+
+        let mut r = vec![0.0; n * n];
+        for i in 0..n {
+            r[i * n + i..(i + 1) * n].copy_from_slice(&reduced[i * n + i..(i + 1) * n]);
+        }
+
+        Ok((Matrix::new(m, n, q)?, Matrix::new(n, n, r)?))
+    }
+
+    /// Lower triangular L with A = LLᵀ for a symmetric positive definite A.
+    /// Fails if A is not symmetric within [`SYMMETRY_TOLERANCE`] or a pivot comes
+    /// out zero or negative.
+    pub fn cholesky(&self) -> Result<Matrix<f64>, String> {
+        let n = self.rows();
+
+        if n != self.columns() {
+            return Err(format!("Cholesky decomposition needs a square matrix, got {}", self.shape()))
+        }
+
+        let numbers = self.get_numbers();
+        let tolerance = SYMMETRY_TOLERANCE * numbers.iter().fold(0.0, |max: f64, num| max.max(num.abs()));
+
+        for i in 0..n {
+            for j in i + 1..n {
+                if (numbers[i * n + j] - numbers[j * n + i]).abs() > tolerance {
+                    return Err(format!("Matrix is not symmetric: ({}, {}) is {} but ({}, {}) is {}",
+                                       i, j, numbers[i * n + j], j, i, numbers[j * n + i]))
+                }
+            }
+        }
+
+        let mut lower = vec![0.0; n * n];
+
+        for j in 0..n {
+            let pivot = numbers[j * n + j] - (0..j).map(|k| lower[j * n + k] * lower[j * n + k]).sum::<f64>();
+
+            if pivot <= 0.0 || pivot.is_nan() {
+                return Err(format!("Matrix is not positive definite: pivot {} is {:e}", j, pivot))
+            }
+
+            let diagonal = pivot.sqrt();
+            lower[j * n + j] = diagonal;
+
+            for i in j + 1..n {
+                let dot: f64 = (0..j).map(|k| lower[i * n + k] * lower[j * n + k]).sum();
+                lower[i * n + j] = (numbers[i * n + j] - dot) / diagonal;
+            }
+        }
+
+        Matrix::new(n, n, lower)
+    }
+
+    /// X with AX = `rhs` for a square A and an n×k `rhs`, solving every column
+    /// of `rhs` with one LU decomposition. Fails for a numerically singular A,
+    /// see [`Matrix::lu`].
+    pub fn solve(&self, rhs: &Matrix<f64>) -> Result<Matrix<f64>, String> {
+        let n = self.rows();
+
+        if n != self.columns() {
+            return Err(format!("Solve needs a square matrix, got {}", self.shape()))
+        }
+
+        if rhs.rows() != n {
+            return Err(format!("Right-hand side has {} rows, but the matrix has {} columns", rhs.rows(), n))
+        }
+
+        let factors = LuFactors::new(n, self.get_numbers().to_vec())?;
+        let (k, numbers) = (rhs.columns(), rhs.get_numbers());
+        let mut solution = vec![0.0; n * k];
+
+        for column in 0..k {
+            let b: Vec<f64> = (0..n).map(|row| numbers[row * k + column]).collect();
+            for (row, value) in factors.solve(&b).into_iter().enumerate() {
+                solution[row * k + column] = value;
+            }
+        }
+
+        Matrix::new(n, k, solution)
+    }
+
+    /// Number of linearly independent rows, by row reduction with partial
+    /// pivoting; a column whose largest remaining pivot is at most `tolerance`
+    /// in magnitude counts as zero.
+    pub fn rank(&self, tolerance: f64) -> usize {
+        let (m, n) = (self.rows(), self.columns());
+        let mut reduced = self.get_numbers().to_vec();
+        let mut rank = 0;
+
+        for column in 0..n {
+            if rank == m {
+                break
+            }
+
+            let pivot = (rank..m).max_by(|&i, &j| reduced[i * n + column].abs()
+                .total_cmp(&reduced[j * n + column].abs())).unwrap();
+
+            if reduced[pivot * n + column].abs() <= tolerance {
+                continue
+            }
+
+            for j in 0..n {
+                reduced.swap(rank * n + j, pivot * n + j);
+            }
+
+            for i in rank + 1..m {
+                let factor = reduced[i * n + column] / reduced[rank * n + column];
+                for j in column..n {
+                    reduced[i * n + j] -= factor * reduced[rank * n + j];
+                }
+            }
+
+            rank += 1;
+        }
+
+        rank
+    }
+
+    /// A⁻¹ from the LU decomposition, solving for one column of the identity at
+    /// a time. Fails for a numerically singular matrix, see [`Matrix::lu`].
+    pub fn inverse(&self) -> Result<Matrix<f64>, String> {
+        let n = self.rows();
+
+        if n != self.columns() {
+            return Err(format!("Inverse needs a square matrix, got {}", self.shape()))
+        }
+
+        let factors = LuFactors::new(n, self.get_numbers().to_vec())?;
+        let mut inverse = vec![0.0; n * n];
+
+        for column in 0..n {
+            let unit: Vec<f64> = (0..n).map(|row| if row == column { 1.0 } else { 0.0 }).collect();
+            for (row, value) in factors.solve(&unit).into_iter().enumerate() {
+                inverse[row * n + column] = value;
+            }
+        }
+
+        Matrix::new(n, n, inverse)
+    }
+
+    /// Matrix exponential e^A by scaling and squaring with a truncated Taylor
+    /// series, using the threaded multiply for every product.
+    pub fn expm(&self, threads: usize) -> Result<Matrix<f64>, String> {
+        let n = self.rows();
+
+        if n != self.columns() {
+            return Err(format!("Matrix exponential needs a square matrix, got {}", self.shape()))
+        }
+
+        if n == 0 {
+            return Matrix::new(0, 0, vec![])
+        }
+
+        let threads = threads.min(n);
+
+        // An infinite norm would saturate the squaring count and never finish.
+        if let Some(index) = self.as_slice().iter().position(|num| !num.is_finite()) {
+            return Err(format!("Matrix exponential needs finite elements, got {} at ({}, {})",
+                               self.as_slice()[index], index / n, index % n))
+        }
+
+        let norm = self.norm_1();
+        if !norm.is_finite() {
+            return Err(format!("Matrix exponential needs a finite 1-norm, got {}", norm))
+        }
+
+        let squarings = if norm > 0.5 { (norm / 0.5).log2().ceil() as i32 } else { 0 };
+
+        let scaled = self.map(|num| num * 2f64.powi(-squarings));
+
+        let mut result = Matrix::<f64>::identity(n);
+        let mut term = Matrix::identity(n);
+
+        for k in 1..=EXPM_TAYLOR_TERMS {
+            term = multiply(&term, &scaled, threads)?;
+            term = term.map(|num| num / k as f64);
+            result = result.add(&term)?;
+        }
+
+        for _ in 0..squarings {
+            result = multiply(&result, &result, threads)?;
+        }
+
+        Ok(result)
+    }
+}
+
+/// Structural checks, each within an absolute `tolerance` and false for a
+/// matrix with a NaN anywhere, on the diagonal included.
+impl<T: Element> Matrix<T> {
+    /// Whether `predicate(i, j, value)` holds for every element (`i`, `j`),
+    /// none of them NaN.
+    fn all_elements(&self, predicate: impl Fn(usize, usize, f64) -> bool) -> bool {
+        let columns = self.columns();
+        self.get_numbers().iter().enumerate().all(|(index, num)| {
+            !num.to_f64().is_nan() && predicate(index / columns, index % columns, num.to_f64())
+        })
+    }
+
+    /// A = Aᵀ; a non-square matrix is never symmetric.
+    pub fn is_symmetric(&self, tolerance: f64) -> bool {
+        let numbers = self.get_numbers();
+        let n = self.columns();
+        self.is_square() && self.all_elements(|i, j, num| (num - numbers[j * n + i].to_f64()).abs() <= tolerance)
+    }
+
+    /// Every element off the main diagonal is zero; the matrix may be rectangular.
+    pub fn is_diagonal(&self, tolerance: f64) -> bool {
+        self.all_elements(|i, j, num| i == j || num.abs() <= tolerance)
+    }
+
+    /// Every element below the main diagonal is zero; the matrix may be rectangular.
+    pub fn is_upper_triangular(&self, tolerance: f64) -> bool {
+        self.all_elements(|i, j, num| i <= j || num.abs() <= tolerance)
+    }
+
+    /// Square, with ones on the main diagonal and zeros elsewhere.
+    pub fn is_identity(&self, tolerance: f64) -> bool {
+        self.is_square() && self.all_elements(|i, j, num| (num - if i == j { 1.0 } else { 0.0 }).abs() <= tolerance)
+    }
+}
+
+impl<T: Copy + Into<f64>> Matrix<T> {
+    /// The norm selected by `kind`, accumulated in f64; 0 for an empty matrix.
+    pub fn norm(&self, kind: NormKind) -> f64 {
+        let numbers = self.get_numbers();
+        let (rows, columns) = (self.rows(), self.columns());
+        let abs = |index: usize| -> f64 { numbers[index].into().abs() };
+
+        match kind {
+            NormKind::Frobenius => (0..numbers.len()).map(|index| abs(index) * abs(index)).sum::<f64>().sqrt(),
+            NormKind::MaxAbs => (0..numbers.len()).map(abs).fold(0.0, f64::max),
+            NormKind::One => (0..columns)
+                .map(|column| (0..rows).map(|row| abs(row * columns + column)).sum::<f64>())
+                .fold(0.0, f64::max),
+            NormKind::Infinity => (0..rows)
+                .map(|row| (0..columns).map(|column| abs(row * columns + column)).sum::<f64>())
+                .fold(0.0, f64::max),
+        }
+    }
+
+    /// Determinant by LU decomposition with partial pivoting in f64, O(n³);
+    /// a numerically singular matrix gives exactly 0.0, see [`Matrix::lu`].
+    pub fn det(&self) -> Result<f64, String> {
+        if self.rows() != self.columns() {
+            return Err(format!("Determinant needs a square matrix, got {}", self.shape()))
+        }
+
+        let numbers = self.get_numbers().iter().map(|&num| num.into()).collect();
+
+        Ok(LuFactors::new(self.rows(), numbers).map_or(0.0, |factors| factors.determinant()))
+    }
+}
+
+/// Applies I - 2vvᵀ to the rows `from..` and `columns` of the row-major
+/// `numbers`, `n` wide; v is a unit vector, or zero for no reflection.
+fn reflect(numbers: &mut [f64], n: usize, from: usize, columns: Range<usize>, v: &[f64]) {
+    for j in columns {
+        let dot: f64 = v.iter().enumerate().map(|(i, vi)| vi * numbers[(from + i) * n + j]).sum();
+        for (i, vi) in v.iter().enumerate() {
+            numbers[(from + i) * n + j] -= 2.0 * dot * vi;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod linalg_test {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::linalg::NormKind;
+    use crate::matrix::{Aliasing, Matrix};
+    use crate::multiplication::multiply;
+
+    fn assert_close(result: &Matrix<f64>, expected: &Matrix<f64>, tolerance: f64) {
+        assert_eq!(result.rows(), expected.rows());
+        assert_eq!(result.columns(), expected.columns());
+
+        for (i, (a, b)) in result.get_numbers().iter().zip(expected.get_numbers().iter()).enumerate() {
+            assert!((a - b).abs() <= tolerance * b.abs().max(1.0),
+                    "Element {i} differs: {a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn norms_correct() {
+        let matrix = Matrix::new(2, 2, vec![1.0, -2.0, 3.0, 4.0]).unwrap();
+
+        assert_eq!(matrix.norm_1(), 6.0);
+        assert!((matrix.frobenius_norm() - 30f64.sqrt()).abs() < 1e-15);
+    }
+
+    #[test]
+    fn norm_kinds() {
+        let matrix = Matrix::new(2, 3, vec![1.0f32, -7.0, 2.0, -3.0, 4.0, 0.5]).unwrap();
+
+        assert_eq!(matrix.norm(NormKind::Frobenius), 79.25f64.sqrt());
+        assert_eq!(matrix.norm(NormKind::MaxAbs), 7.0);
+        assert_eq!(matrix.norm(NormKind::One), 11.0);
+        assert_eq!(matrix.norm(NormKind::Infinity), 10.0);
+
+        let kinds = [NormKind::Frobenius, NormKind::MaxAbs, NormKind::One, NormKind::Infinity];
+        let identity = Matrix::<f64>::identity(4);
+        assert_eq!(kinds.map(|kind| identity.norm(kind)), [2.0, 1.0, 1.0, 1.0]);
+
+        let zero = Matrix::new(3, 2, vec![0.0; 6]).unwrap();
+        assert_eq!(kinds.map(|kind| zero.norm(kind)), [0.0; 4]);
+        assert_eq!(kinds.map(|kind| Matrix::<f64>::new(0, 0, vec![]).unwrap().norm(kind)), [0.0; 4]);
+    }
+
+    #[test]
+    fn det_small_cases() {
+        assert_eq!(Matrix::new(1, 1, vec![3.5]).unwrap().det(), Ok(3.5));
+        assert_eq!(Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]).unwrap().det(), Ok(-2.0));
+        assert_eq!(Matrix::new(2, 2, vec![1.0f32, 2.0, 3.0, 4.0]).unwrap().det(), Ok(-2.0));
+
+        let det = Matrix::new(3, 3, vec![2.0, -1.0, 0.0, 1.0, 3.0, 2.0, 0.0, 1.0, 4.0]).unwrap().det().unwrap();
+        assert!((det - 24.0).abs() < 1e-12);
+
+        assert_eq!(Matrix::<f64>::identity(7).det(), Ok(1.0));
+        assert_eq!(Matrix::<f64>::identity(0).det(), Ok(1.0));
+        assert_eq!(Matrix::new(2, 3, vec![0.0; 6]).unwrap().det().unwrap_err(), "Determinant needs a square matrix, got 2x3");
+    }
+
+    #[test]
+    fn det_singular_is_exactly_zero() {
+        assert_eq!(Matrix::new(3, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]).unwrap().det(), Ok(0.0));
+        assert_eq!(Matrix::new(2, 2, vec![0.0, 0.0, 1.0, 2.0]).unwrap().det(), Ok(0.0));
+        assert_eq!(Matrix::new(3, 3, vec![0.1, 0.2, 0.3, 0.3, 0.6, 0.9, 1.0, 0.0, 1.0]).unwrap().det(), Ok(0.0));
+    }
+
+    #[test]
+    fn small_but_well_conditioned_is_not_singular() {
+        let matrix = Matrix::new(2, 2, vec![1.0, 0.0, 0.0, 1e-20]).unwrap();
+
+        assert_eq!(matrix.det(), Ok(1e-20));
+        assert_eq!(matrix.inverse().unwrap(), Matrix::new(2, 2, vec![1.0, 0.0, 0.0, 1e20]).unwrap());
+        assert_eq!(matrix.solve(&Matrix::new(2, 1, vec![2.0, 1e-20]).unwrap()).unwrap(), Matrix::new(2, 1, vec![2.0, 1.0]).unwrap());
+
+        let scaled_singular = Matrix::new(2, 2, vec![1.0, 2e-20, 2.0, 4e-20]).unwrap();
+        assert_eq!(scaled_singular.det(), Ok(0.0));
+    }
+
+    #[test]
+    fn det_of_product_is_product_of_dets() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut random = || Matrix::new(6, 6, (0..36).map(|_| rng.gen_range(-2.0..2.0)).collect()).unwrap();
+        let (a, b) = (random(), random());
+
+        let (product, expected) = (multiply(&a, &b, 2).unwrap().det().unwrap(), a.det().unwrap() * b.det().unwrap());
+        assert!((product - expected).abs() <= 1e-10 * expected.abs(), "{product} vs {expected}");
+    }
+
+    #[test]
+    fn inverse_times_matrix_is_identity() {
+        for (n, threads) in [(1, 1), (4, 2), (25, 3), (60, 4)] {
+            let random = Matrix::gen_random(n, n, -1.0, 1.0).unwrap();
+            // Diagonally dominant, so well conditioned.
+            let matrix = random.add(&Matrix::identity(n).map(|num| num * n as f64)).unwrap();
+
+            assert_close(&multiply(&matrix, &matrix.inverse().unwrap(), threads).unwrap(), &Matrix::identity(n), 1e-9);
+        }
+    }
+
+    #[test]
+    fn lu_factors_permuted_matrix() {
+        let matrix = Matrix::new(4, 4, vec![
+            0.0, 2.0, 1.0, -1.0,
+            3.0, 1.0, 0.0, 2.0,
+            -6.0, 4.0, 2.0, 0.5,
+            1.0, 0.0, 5.0, 1.0]).unwrap();
+
+        let (lower, upper, permutation) = matrix.lu().unwrap();
+
+        let mut sorted = permutation.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2, 3]);
+        assert_eq!(permutation[0], 2);
+
+        let permuted = Matrix::new(4, 4, permutation.iter()
+            .flat_map(|&row| matrix.get_numbers()[row * 4..(row + 1) * 4].to_vec()).collect()).unwrap();
+        assert_close(&multiply(&lower, &upper, 2).unwrap(), &permuted, 1e-12);
+
+        for i in 0..4 {
+            assert_eq!(lower[(i, i)], 1.0);
+            for j in i + 1..4 {
+                assert_eq!((lower[(i, j)], upper[(j, i)]), (0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn lu_rejects_non_square_and_singular() {
+        assert_eq!(Matrix::new(2, 3, vec![1.0; 6]).unwrap().lu().unwrap_err(), "LU decomposition needs a square matrix, got 2x3");
+        assert!(Matrix::new(2, 2, vec![1.0, 2.0, 2.0, 4.0]).unwrap().lu().unwrap_err().starts_with("Matrix is singular"));
+    }
+
+    fn check_qr(matrix: &Matrix<f64>) {
+        let (q, r) = matrix.qr().unwrap();
+        let n = matrix.columns();
+
+        assert_eq!((q.rows(), q.columns(), r.rows(), r.columns()), (matrix.rows(), n, n, n));
+        assert_close(&multiply(&q.transpose(), &q, 1).unwrap(), &Matrix::<f64>::identity(n), 1e-12);
+        assert_close(&multiply(&q, &r, 1).unwrap(), matrix, 1e-9);
+
+        for i in 0..n {
+            for j in 0..i {
+                assert_eq!(r[(i, j)], 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn qr_tall_square_and_rank_deficient() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let tall = Matrix::new(10, 3, (0..30).map(|_| rng.gen_range(-10.0..10.0)).collect()).unwrap();
+        check_qr(&tall);
+        check_qr(&Matrix::new(5, 5, (0..25).map(|_| rng.gen_range(-1.0..1.0)).collect()).unwrap());
+        check_qr(&Matrix::new(4, 2, vec![1.0, 2.0, 2.0, 4.0, -1.0, -2.0, 0.0, 0.0]).unwrap());
+        check_qr(&Matrix::new(3, 2, vec![0.0; 6]).unwrap());
+    }
+
+    #[test]
+    fn qr_rejects_wide_matrix() {
+        assert_eq!(Matrix::new(2, 3, vec![1.0; 6]).unwrap().qr().unwrap_err(),
+                   "QR decomposition needs at least as many rows as columns, got 2x3");
+    }
+
+    #[test]
+    fn symmetry_within_and_outside_the_tolerance() {
+        let symmetric = Matrix::new(3, 3, vec![2.0, 1.0, 0.5, 1.0, 3.0, -1.0, 0.5, -1.0, 4.0]).unwrap();
+        let mut nearly = symmetric.clone();
+        nearly.set(2, 0, 0.5 + 1e-9, Aliasing::default()).unwrap();
+
+        assert!(symmetric.is_square() && symmetric.is_symmetric(0.0));
+        assert!(nearly.is_symmetric(1e-8));
+        assert!(!nearly.is_symmetric(1e-10));
+        assert!(!Matrix::new(2, 3, vec![0.0; 6]).unwrap().is_symmetric(1.0));
+        assert!(!Matrix::new(2, 2, vec![1.0, f64::NAN, f64::NAN, 1.0]).unwrap().is_symmetric(1.0));
+        assert!(Matrix::new(2, 2, vec![1, 7, 7, 1]).unwrap().is_symmetric(0.0));
+    }
+
+    #[test]
+    fn diagonal_triangular_and_identity() {
+        let identity = Matrix::new(3, 3, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]).unwrap();
+        let upper = Matrix::new(3, 3, vec![1.0, 2.0, 3.0, 1e-13, 4.0, 5.0, 0.0, 0.0, 6.0]).unwrap();
+
+        assert!(identity.is_identity(0.0) && identity.is_diagonal(0.0) && identity.is_upper_triangular(0.0));
+        assert!(Matrix::new(2, 3, vec![5, 0, 0, 0, -2, 0]).unwrap().is_diagonal(0.0));
+        assert!(!Matrix::new(2, 2, vec![5, 0, 1, 2]).unwrap().is_diagonal(0.5));
+        assert!(!Matrix::new(2, 2, vec![2, 0, 0, 2]).unwrap().is_identity(0.5));
+        assert!(!Matrix::new(1, 2, vec![1.0, 0.0]).unwrap().is_identity(0.0));
+
+        assert!(upper.is_upper_triangular(1e-12));
+        assert!(!upper.is_upper_triangular(1e-14));
+        assert!(!upper.transpose().is_upper_triangular(0.1));
+
+        let (_, r) = Matrix::new(3, 2, vec![3.0, 1.0, 4.0, 1.0, 5.0, 9.0]).unwrap().qr().unwrap();
+        assert!(r.is_upper_triangular(1e-12));
+    }
+
+    #[test]
+    fn nan_fails_every_structural_check() {
+        let on_diagonal = Matrix::new(2, 2, vec![f64::NAN, 0.0, 0.0, 1.0]).unwrap();
+        assert!(!on_diagonal.is_diagonal(1.0));
+        assert!(!on_diagonal.is_upper_triangular(1.0));
+        assert!(!on_diagonal.is_identity(1.0));
+        assert!(!on_diagonal.is_symmetric(1.0));
+
+        let above_diagonal = Matrix::new(2, 2, vec![1.0, f64::NAN, 0.0, 1.0]).unwrap();
+        assert!(!above_diagonal.is_upper_triangular(1.0));
+    }
+
+    #[test]
+    fn cholesky_known_and_gram_matrices() {
+        let matrix = Matrix::new(3, 3, vec![4.0, 12.0, -16.0, 12.0, 37.0, -43.0, -16.0, -43.0, 98.0]).unwrap();
+        let expected = Matrix::new(3, 3, vec![2.0, 0.0, 0.0, 6.0, 1.0, 0.0, -8.0, 5.0, 3.0]).unwrap();
+        assert_close(&matrix.cholesky().unwrap(), &expected, 1e-15);
+
+        let b = Matrix::gen_random(8, 5, -1.0, 1.0).unwrap();
+        let gram = multiply(&b.transpose(), &b, 2).unwrap();
+        let lower = gram.cholesky().unwrap();
+
+        assert_close(&multiply(&lower, &lower.transpose(), 1).unwrap(), &gram, 1e-12);
+        for i in 0..5 {
+            assert!(lower[(i, i)] > 0.0);
+            for j in i + 1..5 {
+                assert_eq!(lower[(i, j)], 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn cholesky_failure_modes() {
+        assert_eq!(Matrix::new(2, 3, vec![1.0; 6]).unwrap().cholesky().unwrap_err(),
+                   "Cholesky decomposition needs a square matrix, got 2x3");
+        assert_eq!(Matrix::new(2, 2, vec![2.0, 1.0, 1.5, 2.0]).unwrap().cholesky().unwrap_err(),
+                   "Matrix is not symmetric: (0, 1) is 1 but (1, 0) is 1.5");
+        assert_eq!(Matrix::new(2, 2, vec![1.0, 2.0, 2.0, 1.0]).unwrap().cholesky().unwrap_err(),
+                   "Matrix is not positive definite: pivot 1 is -3e0");
+        assert!(Matrix::new(2, 2, vec![1.0, 1.0, 1.0 + 1e-14, 1.0]).unwrap().cholesky().unwrap_err()
+            .starts_with("Matrix is not positive definite: pivot 1"));
+    }
+
+    #[test]
+    fn solve_reproduces_right_hand_side() {
+        let mut rng = StdRng::seed_from_u64(13);
+
+        for (n, k) in [(1, 1), (4, 1), (12, 3), (30, 7)] {
+            let a = Matrix::new(n, n, (0..n * n)
+                .map(|index| rng.gen_range(-1.0..1.0) + if index % (n + 1) == 0 { n as f64 } else { 0.0 })
+                .collect()).unwrap();
+            let b = Matrix::new(n, k, (0..n * k).map(|_| rng.gen_range(-10.0..10.0)).collect()).unwrap();
+
+            let x = a.solve(&b).unwrap();
+
+            assert_eq!((x.rows(), x.columns()), (n, k));
+            assert_close(&multiply(&a, &x, 1).unwrap(), &b, 1e-9);
+        }
+    }
+
+    #[test]
+    fn solve_rejects_mismatched_and_singular_systems() {
+        let a = Matrix::new(2, 2, vec![2.0, 1.0, 1.0, 3.0]).unwrap();
+
+        assert_eq!(a.solve(&Matrix::new(3, 1, vec![1.0; 3]).unwrap()).unwrap_err(),
+                   "Right-hand side has 3 rows, but the matrix has 2 columns");
+        assert_eq!(Matrix::new(2, 3, vec![1.0; 6]).unwrap().solve(&Matrix::new(2, 1, vec![1.0; 2]).unwrap()).unwrap_err(),
+                   "Solve needs a square matrix, got 2x3");
+        assert!(Matrix::new(2, 2, vec![1.0, 2.0, 2.0, 4.0]).unwrap().solve(&Matrix::new(2, 1, vec![1.0; 2]).unwrap())
+            .unwrap_err().starts_with("Matrix is singular"));
+    }
+
+    #[test]
+    fn rank_of_rectangular_zero_and_outer_product() {
+        let mut rng = StdRng::seed_from_u64(17);
+
+        assert_eq!(Matrix::new(3, 4, vec![0.0; 12]).unwrap().rank(1e-12), 0);
+        assert_eq!(Matrix::new(0, 3, vec![]).unwrap().rank(1e-12), 0);
+        assert_eq!(Matrix::<f64>::identity(5).rank(1e-12), 5);
+        assert_eq!(Matrix::new(6, 4, (0..24).map(|_| rng.gen_range(-1.0..1.0)).collect()).unwrap().rank(1e-10), 4);
+        assert_eq!(Matrix::new(4, 6, (0..24).map(|_| rng.gen_range(-1.0..1.0)).collect()).unwrap().rank(1e-10), 4);
+
+        let u = Matrix::new(5, 1, vec![1.0, -2.0, 0.5, 3.0, 0.0]).unwrap();
+        let v = Matrix::new(1, 4, vec![2.0, 0.0, -1.0, 7.0]).unwrap();
+        assert_eq!(multiply(&u, &v, 1).unwrap().rank(1e-10), 1);
+
+        let nearly = Matrix::new(2, 2, vec![1.0, 1.0, 1.0, 1.0 + 1e-9]).unwrap();
+        assert_eq!((nearly.rank(1e-12), nearly.rank(1e-6)), (2, 1));
+    }
+
+    #[test]
+    fn inverse_known_and_singular() {
+        let matrix = Matrix::new(2, 2, vec![4.0, 7.0, 2.0, 6.0]).unwrap();
+        assert_close(&matrix.inverse().unwrap(), &Matrix::new(2, 2, vec![0.6, -0.7, -0.2, 0.4]).unwrap(), 1e-15);
+
+        let singular = Matrix::new(3, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]).unwrap();
+        assert!(singular.inverse().unwrap_err().starts_with("Matrix is singular: the largest pivot in column 2 is"));
+        assert_eq!(Matrix::new(2, 2, vec![0.0; 4]).unwrap().inverse().unwrap_err(),
+                   "Matrix is singular: the largest pivot in column 0 is 0e0, within the tolerance 0e0");
+        assert_eq!(Matrix::new(1, 2, vec![1.0, 2.0]).unwrap().inverse().unwrap_err(), "Inverse needs a square matrix, got 1x2");
+    }
+
+    #[test]
+    fn expm_zero_is_identity() {
+        let zero = Matrix::new(3, 3, vec![0.0; 9]).unwrap();
+
+        assert_eq!(zero.expm(2).unwrap(), Matrix::identity(3));
+    }
+
+    #[test]
+    fn expm_diagonal_exponentiates_entries() {
+        let diagonal = Matrix::new(3, 3, vec![
+            1.0, 0.0, 0.0,
+            0.0, -2.0, 0.0,
+            0.0, 0.0, 5.5]).unwrap();
+
+        let expected = Matrix::new(3, 3, vec![
+            1f64.exp(), 0.0, 0.0,
+            0.0, (-2f64).exp(), 0.0,
+            0.0, 0.0, 5.5f64.exp()]).unwrap();
+
+        assert_close(&diagonal.expm(3).unwrap(), &expected, 1e-12);
+    }
+
+    #[test]
+    fn expm_rotation_generator() {
+        let generator = Matrix::new(2, 2, vec![0.0, 1.0, -1.0, 0.0]).unwrap();
+
+        let expected = Matrix::new(2, 2, vec![
+            1f64.cos(), 1f64.sin(),
+            -(1f64.sin()), 1f64.cos()]).unwrap();
+
+        assert_close(&generator.expm(1).unwrap(), &expected, 1e-9);
+    }
+
+    #[test]
+    fn expm_non_square() {
+        let matrix = Matrix::new(2, 3, vec![0.0; 6]).unwrap();
+
+        assert_eq!(matrix.expm(1).unwrap_err(), "Matrix exponential needs a square matrix, got 2x3");
+
+        let infinite = Matrix::new(2, 2, vec![0.0, f64::INFINITY, 1.0, 0.0]).unwrap();
+        assert_eq!(infinite.expm(1).unwrap_err(), "Matrix exponential needs finite elements, got inf at (0, 1)");
+        let nan = Matrix::new(1, 1, vec![f64::NAN]).unwrap();
+        assert_eq!(nan.expm(1).unwrap_err(), "Matrix exponential needs finite elements, got NaN at (0, 0)");
+        let overflowing = Matrix::new(2, 2, vec![f64::MAX, 0.0, f64::MAX, 0.0]).unwrap();
+        assert_eq!(overflowing.expm(1).unwrap_err(), "Matrix exponential needs a finite 1-norm, got inf");
+    }
+
+    #[test]
+    fn flush_denormals_only_touches_subnormals() {
+        let matrix_a = Matrix::<f64>::new(1, 2, vec![1e-160, -1e-160]).unwrap();
+        let matrix_b = Matrix::new(2, 3, vec![1e-160, 0.0, 2.0, 0.0, 1e-160, 0.0]).unwrap();
+
+        let mut product = multiply(&matrix_a, &matrix_b, 1).unwrap();
+        assert!(product.get_numbers()[0].is_subnormal() && product.get_numbers()[1].is_subnormal());
+
+        assert_eq!(product.flush_denormals_to_zero(Aliasing::ErrorIfShared), Ok(2));
+
+        let numbers = product.get_numbers();
+        assert_eq!((numbers[0], numbers[0].is_sign_positive()), (0.0, true));
+        assert_eq!((numbers[1], numbers[1].is_sign_negative()), (0.0, true));
+        assert_eq!(numbers[2], 2e-160);
+
+        let mut normal = Matrix::new(1, 3, vec![f64::MIN_POSITIVE, -0.0, 1.0]).unwrap();
+        let sibling = normal.clone();
+        assert_eq!(normal.flush_denormals_to_zero(Aliasing::ErrorIfShared), Ok(0));
+        assert_eq!(sibling.shared_count(), 2);
+        assert_eq!(normal.get_numbers()[1].to_bits(), (-0.0f64).to_bits());
+    }
+}
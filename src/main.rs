@@ -1,12 +1,301 @@
-mod matrix;
-mod multiplication;
-
 use std::{env, process};
-use multiplication::Config;
-use crate::multiplication::run;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use mm::batch::{run_batch, BatchConfig};
+use mm::benchmark::{run_bench, run_kernel_comparison, BenchConfig};
+use mm::compare::{run_compare, CompareConfig};
+use mm::convert::{run_convert, ConvertConfig};
+use mm::hash::run_hash;
+use mm::interrupt::{install_handler, run_interruptible};
+use mm::multiplication::{Config, TUNE_PROFILE_VAR};
+use mm::record;
+use mm::selftest::{run_selftest, SelfTestConfig};
+#[cfg(unix)]
+use mm::serve::{run_client, run_serve, ClientConfig, ServeConfig};
+use mm::similarity::{run_topk, TopkConfig};
+use mm::tune::{run_tune, TuneConfig};
+use mm::validate::{run_validate, ValidateConfig};
 
 fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("bench") => return bench(),
+        Some("bench-kernels") => return bench_kernels(),
+        Some("validate") => return validate(),
+        Some("tune") => return tune(),
+        Some("hash") => return hash(),
+        Some("compare") => return compare(),
+        Some("convert") => return convert(),
+        Some("batch") => return batch(),
+        Some("verify-record") => return verify_record(),
+        Some("topk") => return topk(),
+        Some("selftest") => return selftest(),
+        #[cfg(unix)]
+        Some("serve") => return serve(),
+        #[cfg(unix)]
+        Some("client") => return client(),
+        _ => {}
+    }
+
     let config = Config::from_iter(env::args())
+        .map(|config| config.with_default_tune_profile(env::var(TUNE_PROFILE_VAR).ok()))
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    let token = Arc::new(AtomicBool::new(false));
+    let _ = install_handler(Arc::clone(&token));
+
+    let code = run_interruptible(config, token);
+    if code == 0 {
+        println!("Success!");
+    }
+    process::exit(code);
+}
+
+fn bench() {
+    let config = BenchConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_bench(config) {
+        Ok(stats) => println!("{}", stats),
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn bench_kernels() {
+    let config = BenchConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_kernel_comparison(config) {
+        Ok(results) => for (kernel, stats) in results {
+            println!("{}:\n{}\n", kernel, stats);
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn validate() {
+    let config = ValidateConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_validate(config) {
+        Ok(report) => {
+            println!("{}", report);
+            if !report.is_valid() {
+                process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn tune() {
+    let config = TuneConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_tune(config) {
+        Ok(result) => for entry in result.entries {
+            println!("up to {}: {} threads, {} kernel", entry.max_size, entry.num_of_threads, entry.kernel);
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn hash() {
+    let file_name = env::args().nth(2).unwrap_or_else(|| {
+        eprintln!("Problem passing arguments:\nMissing file to hash");
+        eprintln!();
+        print_instruction();
+        process::exit(1);
+    });
+
+    match run_hash(&file_name) {
+        Ok(hash) => println!("{}", hash),
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn compare() {
+    let config = CompareConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_compare(config) {
+        Ok(report) => {
+            println!("{}", report);
+            if !report.is_match() {
+                process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn convert() {
+    let config = ConvertConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_convert(config) {
+        Ok(()) => println!("Success!"),
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn batch() {
+    let config = BatchConfig::from_iter(env::args())
+        .map(|config| config.with_default_tune_profile(env::var(TUNE_PROFILE_VAR).ok()))
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_batch(config) {
+        Ok(ran) => println!("Success! Ran {} jobs", ran),
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn verify_record() {
+    let file_name = env::args().nth(2).unwrap_or_else(|| {
+        eprintln!("Problem passing arguments:\nMissing record file");
+        eprintln!();
+        print_instruction();
+        process::exit(1);
+    });
+
+    match record::verify_record(&file_name) {
+        Ok(report) => {
+            println!("{}", report);
+            if !report.is_valid() {
+                process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn topk() {
+    let config = TopkConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_topk(config) {
+        Ok(rows) => println!("Success! Wrote neighbours of {} rows", rows),
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn selftest() {
+    let config = SelfTestConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_selftest(config) {
+        Ok(report) => {
+            println!("{}", report);
+            process::exit(report.exit_code());
+        },
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve() {
+    let config = ServeConfig::from_iter(env::args())
+        .unwrap_or_else(|err| {
+            eprintln!("Problem passing arguments:\n{}", err);
+            eprintln!();
+            print_instruction();
+            process::exit(1);
+        });
+
+    match run_serve(config) {
+        Ok(answered) => println!("Shut down after answering {} requests", answered),
+        Err(error) => {
+            eprintln!("Application error:\n{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn client() {
+    let config = ClientConfig::from_iter(env::args())
         .unwrap_or_else(|err| {
             eprintln!("Problem passing arguments:\n{}", err);
             eprintln!();
@@ -14,8 +303,8 @@ fn main() {
             process::exit(1);
         });
 
-    match run(config) {
-        Ok(_) => println!("Success!"),
+    match run_client(config) {
+        Ok(reply) => println!("{}", reply),
         Err(error) => {
             eprintln!("Application error:\n{}", error);
             process::exit(1);
@@ -24,5 +313,18 @@ fn main() {
 }
 
 fn print_instruction() {
-    eprintln!("Usage:\ncargo run (-r) -- [Left input matrix filename] [Right input matrix filename] [Output matrix filename] [number of threads]")
+    eprintln!("Usage:\ncargo run (-r) -- [Left input matrix filename] [Right input matrix filename] [Output matrix filename] [number of threads|auto (all cores but one, all with MATMUL_GREEDY=1)] [--format plain|csv|binary|coo] [--zero-tolerance value] [--verbose] [--flush-denormals] [--annotate] [--tune-profile filename] [--record record filename] [--dtype auto|i64|f64] [--pipeline] [--abort-if-exceeds value] [--max-elements N]\n\
+               cargo run (-r) -- bench [matrix size] [number of threads] [warmup runs] [timed runs]\n\
+               cargo run (-r) -- bench-kernels [matrix size] [number of threads] [warmup runs] [timed runs]\n\
+               cargo run (-r) -- validate [matrix filename] [--fix fixed matrix filename]\n\
+               cargo run (-r) -- tune [profile filename] [timed runs]\n\
+               cargo run (-r) -- hash [matrix filename]\n\
+               cargo run (-r) -- compare [left matrix filename] [right matrix filename] [--tolerance value | --ulps n]\n\
+               cargo run (-r) -- convert [input matrix filename] [output matrix filename] [--format plain|csv|binary|coo] [--canonical]\n\
+               cargo run (-r) -- batch [job filename] [--on-collision error|suffix|skip]\n\
+               cargo run (-r) -- verify-record [record filename]\n\
+               cargo run (-r) -- topk [matrix filename] [k] [output filename] [number of threads]\n\
+               cargo run (-r) -- selftest [--size N] [--threads T]\n\
+               cargo run (-r) -- serve --socket [socket path] [--load name=matrix filename]...\n\
+               cargo run (-r) -- client --socket [socket path] [JSON request]")
 }
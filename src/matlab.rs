@@ -0,0 +1,111 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use crate::matrix::Matrix;
+
+impl<T: FromStr> Matrix<T> {
+    /// Parses a MATLAB style literal such as `[1 2 3; 4 5 6]`.
+    ///
+    /// Rows are separated by semicolons or newlines, elements by commas or
+    /// whitespace, and the outer brackets are optional. Nested brackets are
+    /// rejected.
+    pub fn from_matlab_str(literal: &str) -> Result<Matrix<T>, String> {
+        let literal = literal.trim();
+
+        let inner = match (literal.strip_prefix('['), literal.ends_with(']')) {
+            (Some(rest), true) => &rest[..rest.len() - 1],
+            (None, false) => literal,
+            _ => return Err(String::from("Unbalanced brackets in MATLAB literal"))
+        };
+
+        if inner.contains(['[', ']']) {
+            return Err(String::from("Nested brackets are not supported in MATLAB literals"))
+        }
+
+        let mut numbers = Vec::new();
+        let mut rows = 0;
+        let mut columns = 0;
+
+        for row in inner.split([';', '\n']).filter(|row| !row.trim().is_empty()) {
+            let mut length = 0;
+
+            for token in row.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty()) {
+                match token.parse::<T>() {
+                    Ok(num) => numbers.push(num),
+                    Err(_) => return Err(format!("Couldn't parse '{}' in row {}", token, rows))
+                }
+                length += 1;
+            }
+
+            if rows == 0 {
+                columns = length;
+            } else if length != columns {
+                return Err(format!("Row {} length: {} doesn't match columns: {}", rows, length, columns))
+            }
+
+            rows += 1;
+        }
+
+        Matrix::new(rows, columns, numbers)
+    }
+}
+
+impl<T: Display> Matrix<T> {
+    /// Formats the matrix as a MATLAB literal, e.g. `[1 2 3; 4 5 6]`.
+    pub fn to_matlab_string(&self) -> String {
+        let numbers = self.get_numbers();
+
+        let rows: Vec<String> = (0..self.rows())
+            .map(|row| numbers[row * self.columns()..(row + 1) * self.columns()]
+                .iter()
+                .map(|num| num.to_string())
+                .collect::<Vec<String>>()
+                .join(" "))
+            .collect();
+
+        format!("[{}]", rows.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod matlab_test {
+    use crate::matrix::Matrix;
+
+    #[test]
+    fn parse_example() {
+        let matrix = Matrix::<i32>::from_matlab_str("[1 2 3; 4 5 6]").unwrap();
+
+        assert_eq!(matrix, Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap());
+    }
+
+    #[test]
+    fn parse_commas_newlines_and_no_brackets() {
+        let expected = Matrix::new(2, 2, vec![1.5, -2.0, 3.0, 4.25]).unwrap();
+
+        assert_eq!(Matrix::<f64>::from_matlab_str("[1.5, -2\n 3, 4.25]").unwrap(), expected);
+        assert_eq!(Matrix::<f64>::from_matlab_str("1.5 -2; 3 4.25;").unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_ragged() {
+        let result = Matrix::<i32>::from_matlab_str("[1 2 3; 4 5]");
+
+        assert_eq!(result.unwrap_err(), "Row 1 length: 2 doesn't match columns: 3");
+    }
+
+    #[test]
+    fn parse_rejects_nested_and_unbalanced_brackets() {
+        assert!(Matrix::<i32>::from_matlab_str("[[1 2]; [3 4]]").is_err());
+        assert!(Matrix::<i32>::from_matlab_str("[1 2; 3 4").is_err());
+        assert!(Matrix::<i32>::from_matlab_str("[1 a]").is_err());
+    }
+
+    #[test]
+    fn matlab_round_trip() {
+        let matrix = Matrix::new(2, 3, vec![0.1, -2.5, 3.0, 1e-7, 5.0, 6.75]).unwrap();
+
+        let literal = matrix.to_matlab_string();
+
+        assert_eq!(literal, "[0.1 -2.5 3; 0.0000001 5 6.75]");
+        assert_eq!(Matrix::<f64>::from_matlab_str(&literal).unwrap(), matrix);
+    }
+}
@@ -1,8 +1,21 @@
-use std::fmt::{Display, Formatter};
-use std::fs;
+use std::any::type_name;
+use std::cell::Cell;
+use std::fmt::{Display, Formatter, LowerExp};
+use std::io::BufRead;
+use std::ops::Index;
 use std::str::FromStr;
 use std::sync::Arc;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::distributions::uniform::SampleUniform;
+use rand::{Rng, SeedableRng};
+use crate::element::Element;
+use crate::io;
+use crate::io::{Coo, Format, PlainText};
+use crate::layout::COLUMN_MAJOR_MARKER;
+use crate::parse::{FileMetadata, ParseError, ParseOptions, ParseOutcome, ParseWarning, PartialParse};
+use crate::shape::{Cols, Rows, Shape};
+use crate::write::WriteOptions;
 
 #[derive(PartialEq, Debug)]
 pub struct Matrix<T> {
@@ -11,17 +24,71 @@ pub struct Matrix<T> {
     numbers: Arc<Vec<T>>
 }
 
+/// What an in-place method does when the elements are shared with another
+/// matrix or an outstanding [`Matrix::get_numbers`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Aliasing {
+    /// Copy the elements first, leaving the other holders untouched.
+    #[default]
+    CloneIfShared,
+    /// Fail instead of copying, for buffers too big to copy by accident.
+    ErrorIfShared,
+}
+
+/// Cheap: the copy shares the elements until one of them is mutated.
+impl<T> Clone for Matrix<T> {
+    fn clone(&self) -> Matrix<T> {
+        Matrix { rows: self.rows, columns: self.columns, numbers: Arc::clone(&self.numbers) }
+    }
+}
+
 impl<T> Matrix<T> {
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    #[deprecated(note = "use `rows` instead")]
     pub fn get_rows(&self) -> usize {
-        return self.rows;
+        self.rows()
     }
 
+    #[deprecated(note = "use `columns` instead")]
     pub fn get_columns(&self) -> usize {
-        return self.columns;
+        self.columns()
+    }
+
+    /// Number of stored elements, `rows * columns`.
+    pub fn element_count(&self) -> usize {
+        self.numbers.len()
+    }
+
+    /// Memory taken by the elements, not counting the matrix itself.
+    pub fn size_in_bytes(&self) -> usize {
+        self.element_count() * std::mem::size_of::<T>()
     }
 
     pub fn get_numbers(&self) -> Arc<Vec<T>> {
-        return Arc::clone(&self.numbers)
+        Arc::clone(&self.numbers)
+    }
+
+    /// The elements in row-major order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.numbers
+    }
+
+    /// Whether an in-place method can mutate the elements without copying them.
+    pub fn is_uniquely_owned(&self) -> bool {
+        self.shared_count() == 1
+    }
+
+    /// Matrices and [`Matrix::get_numbers`] handles holding these elements,
+    /// this one included.
+    pub fn shared_count(&self) -> usize {
+        Arc::strong_count(&self.numbers)
     }
 
     pub fn new(rows: usize, columns: usize, numbers: Vec<T>) -> Result<Matrix<T>, String> {
@@ -34,261 +101,1753 @@ impl<T> Matrix<T> {
 
         Ok(Matrix { rows, columns, numbers })
     }
-}
 
-impl Matrix<f32> {
-    pub fn gen_random(rows: usize, columns: usize, min_val: f32, max_val: f32) -> Result<Matrix<f32>, String> {
-        if min_val >= max_val {
-            return Err(format!("Min_val: {min_val} must be less than max_val: {max_val}"))
+    /// A `rows`×`columns` matrix whose element at (i, j) is `f(i, j)`, with
+    /// `f` called in row-major order.
+    pub fn from_fn(rows: usize, columns: usize, mut f: impl FnMut(usize, usize) -> T) -> Result<Matrix<T>, String> {
+        let len = rows.checked_mul(columns)
+            .ok_or_else(|| format!("{}x{} matrix has more elements than the address space", rows, columns))?;
+        let mut numbers = Vec::new();
+        numbers.try_reserve_exact(len).map_err(|error| format!("Couldn't allocate a {}x{} matrix: {}", rows, columns, error))?;
+
+        for i in 0..rows {
+            for j in 0..columns {
+                numbers.push(f(i, j));
+            }
         }
-        let mut numbers = Vec::with_capacity(rows*columns);
 
-        let mut rng = rand::thread_rng();
+        Ok(Matrix { rows, columns, numbers: Arc::new(numbers) })
+    }
 
-        for _ in 0..rows * columns {
-            numbers.push(rng.gen_range(min_val..max_val));
+    /// Like [`Matrix::new`], but the dimensions are typed so they can't be swapped:
+    ///
+    /// ```
+    /// use mm::matrix::Matrix;
+    /// use mm::shape::{Cols, Rows};
+    ///
+    /// let matrix = Matrix::with_shape(Rows(3), Cols(2), vec![1, 2, 3, 4, 5, 6]).unwrap();
+    /// assert_eq!(matrix.rows(), 3);
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use mm::matrix::Matrix;
+    /// use mm::shape::{Cols, Rows};
+    ///
+    /// let matrix = Matrix::with_shape(Cols(2), Rows(3), vec![1, 2, 3, 4, 5, 6]);
+    /// ```
+    pub fn with_shape(rows: Rows, columns: Cols, numbers: Vec<T>) -> Result<Matrix<T>, String> {
+        Matrix::new(rows.0, columns.0, numbers)
+    }
+
+    /// Rows and columns together; `let (rows, columns) = matrix.shape().into()`
+    /// gives them as a tuple.
+    pub fn shape(&self) -> Shape {
+        Shape { rows: self.rows, cols: self.columns }
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.columns
+    }
+
+    /// Row `i` as a slice, or `None` if it is out of bounds.
+    pub fn row(&self, i: usize) -> Option<&[T]> {
+        (i < self.rows).then(|| &self.numbers[i * self.columns..(i + 1) * self.columns])
+    }
+
+    /// Every row as a slice, from the top; named so as not to clash with
+    /// [`Matrix::rows`], the row count.
+    pub fn iter_rows(&self) -> impl ExactSizeIterator<Item=&[T]> {
+        (0..self.rows).map(|i| &self.numbers[i * self.columns..(i + 1) * self.columns])
+    }
+
+    /// Column `j`, top to bottom, or `None` if it is out of bounds. The
+    /// elements are strided in the row-major buffer, so they're gathered as
+    /// references rather than copied or transposed.
+    pub fn column(&self, j: usize) -> Option<Vec<&T>> {
+        (j < self.columns).then(|| self.numbers.iter().skip(j).step_by(self.columns).collect())
+    }
+
+    /// Every column as by [`Matrix::column`], from the left; named so as not
+    /// to clash with [`Matrix::columns`], the column count.
+    pub fn iter_columns(&self) -> impl ExactSizeIterator<Item=Vec<&T>> {
+        (0..self.columns).map(|j| self.numbers.iter().skip(j).step_by(self.columns).collect())
+    }
+
+    /// A matrix of the same shape with `f` applied to every element.
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Matrix<U> {
+        Matrix { rows: self.rows, columns: self.columns, numbers: Arc::new(self.numbers.iter().map(f).collect()) }
+    }
+
+    /// Like [`Matrix::map`], with `f` also given each element's row and column.
+    pub fn map_indexed<U>(&self, f: impl Fn(usize, usize, &T) -> U) -> Matrix<U> {
+        let columns = self.columns;
+        let numbers = self.numbers.iter().enumerate().map(|(index, num)| f(index / columns, index % columns, num)).collect();
+
+        Matrix { rows: self.rows, columns: self.columns, numbers: Arc::new(numbers) }
+    }
+
+    /// The element at (`row`, `column`), or `None` if that is out of bounds.
+    pub fn get(&self, row: usize, column: usize) -> Option<&T> {
+        if row >= self.rows || column >= self.columns {
+            return None
         }
 
-        let numbers = Arc::new(numbers);
+        self.numbers.get(row * self.columns + column)
+    }
+}
 
-        Ok(Matrix { rows, columns, numbers })
+/// Elements a strided buffer needs: every row but the last padded to `row_stride`.
+fn strided_len(rows: usize, columns: usize, row_stride: usize) -> Result<usize, String> {
+    if row_stride < columns {
+        return Err(format!("Row stride {} is smaller than the {} columns", row_stride, columns))
+    }
+
+    match rows {
+        0 => Ok(0),
+        _ => (rows - 1).checked_mul(row_stride).and_then(|len| len.checked_add(columns))
+            .ok_or_else(|| format!("{} rows with stride {} overflow the address space", rows, row_stride)),
     }
 }
 
+/// `matrix[(row, column)]`, panicking on an index out of bounds; see
+/// [`Matrix::get`] for the checked version.
+impl<T> Index<(usize, usize)> for Matrix<T> {
+    type Output = T;
 
-impl<T: Display> Display for Matrix<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut rows= Vec::with_capacity(self.rows);
-
-        for i in 0..self.rows {
-            let mut row = String::with_capacity(self.columns);
-            for j in 0..self.columns {
-                let mut tmp = self.numbers[i * self.columns + j].to_string();
-                tmp.push(' ');
-                row += tmp.as_str();
-            }
-            row.remove(row.len()-1);
-            rows.push(row + "\n");
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        match self.get(row, column) {
+            Some(element) => element,
+            None => panic!("Index ({row}, {column}) is out of bounds for {}", self.shape()),
         }
+    }
+}
 
-        let rows: String = rows
-            .iter()
-            .flat_map(|row| row.chars())
-            .collect();
+/// One inner `Vec` per row; they all need the length of the first.
+impl<T> TryFrom<Vec<Vec<T>>> for Matrix<T> {
+    type Error = String;
 
-        write!(f, "{}\n{}\n{}", self.rows, self.columns, rows)
+    fn try_from(nested: Vec<Vec<T>>) -> Result<Matrix<T>, String> {
+        let (rows, columns) = (nested.len(), nested.first().map_or(0, Vec::len));
+
+        if let Some((index, row)) = nested.iter().enumerate().find(|(_, row)| row.len() != columns) {
+            return Err(format!("Row {} has {} elements, but row 0 has {}", index, row.len(), columns))
+        }
+
+        let numbers = nested.into_iter().flatten().collect();
+
+        Ok(Matrix { rows, columns, numbers: Arc::new(numbers) })
     }
 }
 
-impl<T: Display> Matrix<T> {
-    pub fn to_file(&self, file_name: &str) -> Result<(), String> {
-        return match fs::write(file_name, self.to_string()) {
-            Ok(_) => Ok(()),
-            Err(error) => Err(format!("Error writing to file {}: {}", file_name, error))
-        };
+impl<T: Element> Matrix<T> {
+    pub fn zeros(rows: impl Into<Rows>, columns: impl Into<Cols>) -> Matrix<T> {
+        let (rows, columns) = (rows.into().0, columns.into().0);
+
+        Matrix { rows, columns, numbers: Arc::new(vec![T::ZERO; rows * columns]) }
+    }
+
+    /// The `n`x`n` identity matrix.
+    pub fn identity(n: usize) -> Matrix<T> {
+        let mut numbers = vec![T::ZERO; n * n];
+
+        for i in 0..n {
+            numbers[i * n + i] = T::ONE;
+        }
+
+        Matrix { rows: n, columns: n, numbers: Arc::new(numbers) }
     }
 }
 
-impl<T: FromStr> Matrix<T> {
-    pub fn from_file(file_name: &str) -> Result<Matrix<T>, String> {
-        let contents = match fs::read_to_string(file_name) {
-            Ok(contents) => contents,
-            Err(error) => return Err(format!("Couldn't open file {}\nerror: {}", file_name, error))
-        };
+impl<T: Clone> Matrix<T> {
+    /// A copy with its own buffer, unlike [`Clone::clone`], which shares it.
+    pub fn deep_clone(&self) -> Matrix<T> {
+        Matrix { rows: self.rows, columns: self.columns, numbers: Arc::new(self.numbers.as_ref().clone()) }
+    }
 
-        return Self::from_iterator(contents.lines());
+    /// Takes the elements out in row-major order, copying them only if
+    /// another matrix or [`Matrix::get_numbers`] handle shares them.
+    pub fn into_vec(self) -> Vec<T> {
+        Arc::try_unwrap(self.numbers).unwrap_or_else(|numbers| numbers.as_ref().clone())
     }
 
-    pub fn from_vec(vector: Vec<&str>) -> Result<Matrix<T>, String> {
-        return Self::from_iterator(vector.into_iter());
+    /// The same elements, in the same row-major order, read as a
+    /// `new_rows`×`new_cols` matrix; the buffer is shared rather than copied.
+    pub fn reshape(&self, new_rows: usize, new_cols: usize) -> Result<Matrix<T>, String> {
+        if new_rows.checked_mul(new_cols) != Some(self.numbers.len()) {
+            return Err(format!("Can't reshape {} ({} elements) to {}x{} ({} elements)", self.shape(), self.numbers.len(),
+                               new_rows, new_cols, new_rows as u128 * new_cols as u128))
+        }
+
+        Ok(Matrix { rows: new_rows, columns: new_cols, numbers: Arc::clone(&self.numbers) })
     }
 
-    fn from_iterator<'a>(mut iterator: impl Iterator<Item=&'a str>) -> Result<Matrix<T>, String> {
-        let rows = match iterator.next() {
-            Some(rows) => {match rows.trim().parse::<usize>() {
-                Ok(parsed) => parsed,
-                Err(_) => return Err(format!("Couldn't parse '{}' as rows num", rows))
-            }}
-            None => return Err(String::from("File is empty!"))
-        };
+    /// Fails if the elements are shared and `aliasing` forbids copying them.
+    fn check_aliasing(&self, aliasing: Aliasing) -> Result<(), String> {
+        if aliasing == Aliasing::ErrorIfShared && !self.is_uniquely_owned() {
+            return Err(format!("Matrix elements are shared by {} holders", self.shared_count()))
+        }
 
-        let columns = match iterator.next() {
-            Some(columns) => {match columns.trim().parse::<usize>() {
-                Ok(parsed) => parsed,
-                Err(_) => return Err(format!("Couldn't parse '{}' as columns num", columns))
-            }}
-            None => return Err(String::from("File doesn't have columns row"))
-        };
+        Ok(())
+    }
+
+    /// The elements for mutation, copied first if shared and `aliasing` allows it.
+    pub(crate) fn numbers_mut(&mut self, aliasing: Aliasing) -> Result<&mut [T], String> {
+        self.check_aliasing(aliasing)?;
+        Ok(Arc::make_mut(&mut self.numbers).as_mut_slice())
+    }
 
-        let mut numbers = Vec::with_capacity(rows * columns);
+    /// Sets the element at (`row`, `column`). A shared buffer is copied first
+    /// under [`Aliasing::CloneIfShared`], the default.
+    pub fn set(&mut self, row: usize, column: usize, value: T, aliasing: Aliasing) -> Result<(), String> {
+        if row >= self.rows || column >= self.columns {
+            return Err(format!("Index ({row}, {column}) is out of bounds for {}", self.shape()))
+        }
 
-        for i in 0..rows {
-            let row = match iterator.next() {
-                Some(row) => row.split_whitespace(),
-                None => return Err(format!("Not enough rows: {i}"))
-            };
+        let columns = self.columns;
+        self.numbers_mut(aliasing)?[row * columns + column] = value;
+        Ok(())
+    }
 
-            let row: Result<Vec<T>, _> = row
-                .into_iter()
-                .map(|num| num.parse::<T>())
-                .collect();
+    /// Swaps rows `i` and `j`. A shared buffer is copied first under
+    /// [`Aliasing::CloneIfShared`], the default, unless `i == j`.
+    pub fn swap_rows(&mut self, i: usize, j: usize, aliasing: Aliasing) -> Result<(), String> {
+        if let Some(row) = [i, j].into_iter().find(|&row| row >= self.rows) {
+            return Err(format!("Row index {} is out of bounds for {}", row, self.shape()))
+        }
 
-            match row {
-                Ok(mut parsed) if parsed.len() == columns => numbers.append(&mut parsed),
-                Ok(parsed) => return Err(format!("Row {} length: {} doesn't match columns: {}",
-                                                 i, parsed.len(), columns)),
-                Err(_) => return Err(format!("Error parsing {} row", i)),
+        if i != j {
+            let columns = self.columns;
+            let (first, second) = (i.min(j), i.max(j));
+            let (above, below) = self.numbers_mut(aliasing)?.split_at_mut(second * columns);
+            above[first * columns..(first + 1) * columns].swap_with_slice(&mut below[..columns]);
+        }
+        Ok(())
+    }
+
+    /// Swaps columns `i` and `j`, copying a shared buffer as [`Matrix::swap_rows`] does.
+    pub fn swap_columns(&mut self, i: usize, j: usize, aliasing: Aliasing) -> Result<(), String> {
+        if let Some(column) = [i, j].into_iter().find(|&column| column >= self.columns) {
+            return Err(format!("Column index {} is out of bounds for {}", column, self.shape()))
+        }
+
+        if i != j {
+            let columns = self.columns;
+            for row in self.numbers_mut(aliasing)?.chunks_mut(columns) {
+                row.swap(i, j);
             }
         }
+        Ok(())
+    }
 
-        let numbers = Arc::new(numbers);
+    /// A new matrix of the columns at `indices`, in the order given; an index
+    /// may repeat.
+    pub fn select_columns(&self, indices: &[usize]) -> Result<Matrix<T>, String> {
+        if let Some(column) = indices.iter().find(|&&column| column >= self.columns) {
+            return Err(format!("Column index {} is out of bounds for {}", column, self.shape()))
+        }
 
-        Ok(Matrix { rows, columns, numbers })
+        let numbers = self.numbers.chunks(self.columns.max(1)).take(self.rows)
+            .flat_map(|row| indices.iter().map(|&column| row[column].clone()))
+            .collect();
+
+        Matrix::new(self.rows, indices.len(), numbers)
     }
-}
 
-#[cfg(test)]
-mod matrix_test {
-    use std::sync::Arc;
-    use crate::matrix::Matrix;
+    /// Copies a `rows`x`columns` matrix out of a buffer whose rows start
+    /// `row_stride` elements apart, ignoring the padding after each row.
+    pub fn from_strided(rows: usize, columns: usize, row_stride: usize, data: &[T]) -> Result<Matrix<T>, String> {
+        let required = strided_len(rows, columns, row_stride)?;
 
-    #[test]
-    fn iter_read_correct_ints() {
-        let contents = vec!["3", "2", " 1 2", "3 4", "5 6"];
+        if data.len() < required {
+            return Err(format!("Buffer of {} elements is too short for {} rows of {} with stride {}, needs {}",
+                               data.len(), rows, columns, row_stride, required))
+        }
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+        let numbers = (0..rows).flat_map(|row| data[row * row_stride..row * row_stride + columns].iter().cloned()).collect();
 
-        match matrix {
-            Ok(matrix) => assert_eq!(matrix,
-                                     Matrix{ rows: 3, columns: 2, numbers: Arc::new(vec![1, 2, 3, 4, 5, 6]) }),
-            Err(_) => assert!(false)
+        Matrix::new(rows, columns, numbers)
+    }
+
+    /// Copies the rows into `out`, `row_stride` elements apart, leaving the
+    /// padding between them untouched; see [`Matrix::from_strided`].
+    pub fn copy_into_strided(&self, out: &mut [T], row_stride: usize) -> Result<(), String> {
+        let required = strided_len(self.rows, self.columns, row_stride)?;
+
+        if out.len() < required {
+            return Err(format!("Buffer of {} elements is too short for {} rows of {} with stride {}, needs {}",
+                               out.len(), self.rows, self.columns, row_stride, required))
+        }
+
+        for (row, numbers) in self.numbers.chunks(self.columns.max(1)).take(self.rows).enumerate() {
+            out[row * row_stride..row * row_stride + self.columns].clone_from_slice(numbers);
         }
+        Ok(())
     }
 
+    /// Sets every element to `value`. A shared buffer is replaced rather than
+    /// copied under [`Aliasing::CloneIfShared`], the default.
+    pub fn fill(&mut self, value: T, aliasing: Aliasing) -> Result<(), String> {
+        self.check_aliasing(aliasing)?;
 
-    #[test]
-    fn iter_read_correct_floats() {
-        let contents = vec!["3", "2", "1.2 2.567", "3.45 4.2", "5.0 6.0"];
+        match Arc::get_mut(&mut self.numbers) {
+            Some(numbers) => numbers.fill(value),
+            None => self.numbers = Arc::new(vec![value; self.numbers.len()]),
+        }
+        Ok(())
+    }
 
-        let matrix = Matrix::<f32>::from_iterator(contents.into_iter());
+    /// Keeps only the first `rows` rows. A shared buffer has only those rows
+    /// copied under [`Aliasing::CloneIfShared`], the default.
+    pub fn truncate_rows(&mut self, rows: usize, aliasing: Aliasing) -> Result<(), String> {
+        if rows > self.rows {
+            return Err(format!("Cannot truncate a matrix with {} rows to {rows} rows", self.rows))
+        }
 
-        match matrix {
-            Ok(matrix) => assert_eq!(matrix,
-                                     Matrix{ rows: 3, columns: 2, numbers: Arc::new(vec![1.2, 2.567, 3.45, 4.2, 5.0, 6.0]) }),
-            Err(_) => assert!(false)
+        self.check_aliasing(aliasing)?;
+
+        let len = rows * self.columns;
+        match Arc::get_mut(&mut self.numbers) {
+            Some(numbers) => numbers.truncate(len),
+            None => self.numbers = Arc::new(self.numbers[..len].to_vec()),
         }
+        self.rows = rows;
+        Ok(())
     }
 
-    #[test]
-    fn iter_read_floats_as_ints() {
-        let contents = vec!["3", "2", "1.2 2.567", "3.45 4.2", "5.0 6.0"];
+    /// Picks `k` distinct rows uniformly at random, deterministically for a given seed.
+    pub fn sample_rows(&self, k: usize, seed: u64) -> Result<Matrix<T>, String> {
+        if k > self.rows {
+            return Err(format!("Cannot sample {k} rows from a matrix with {} rows", self.rows))
+        }
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let indexes = index::sample(&mut rng, self.rows, k);
 
-        assert!(matrix.is_err());
+        Ok(self.select_rows(indexes.into_iter()))
     }
 
-    #[test]
-    fn iter_read_blank() {
-        let contents = vec![""];
+    /// Returns a copy with the rows permuted, deterministically for a given seed.
+    pub fn shuffle_rows(&self, seed: u64) -> Matrix<T> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut indexes: Vec<usize> = (0..self.rows).collect();
+        indexes.shuffle(&mut rng);
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+        self.select_rows(indexes.into_iter())
+    }
 
-        assert!(matrix.is_err());
+    /// Splits into the first `first_part` rows and the remaining ones, keeping their order.
+    pub fn split_rows(&self, first_part: usize) -> Result<(Matrix<T>, Matrix<T>), String> {
+        if first_part > self.rows {
+            return Err(format!("Cannot split {first_part} rows from a matrix with {} rows", self.rows))
+        }
+
+        Ok((self.select_rows(0..first_part), self.select_rows(first_part..self.rows)))
     }
 
-    #[test]
-    fn iter_read_no_columns() {
-        let contents = vec!["2"];
+    fn select_rows(&self, indexes: impl Iterator<Item=usize>) -> Matrix<T> {
+        let mut numbers = Vec::new();
+        let mut rows = 0;
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+        for i in indexes {
+            numbers.extend_from_slice(&self.numbers[i * self.columns..(i + 1) * self.columns]);
+            rows += 1;
+        }
 
-        assert!(matrix.is_err());
+        let numbers = Arc::new(numbers);
+
+        Matrix { rows, columns: self.columns, numbers }
     }
+}
 
-    #[test]
-    fn iter_read_no_matrix() {
-        let contents = vec!["3", "2"];
+impl<T: SampleUniform + PartialOrd + Display + Copy> Matrix<T> {
+    /// Uniformly random matrix with elements in `min_val..max_val`, for both
+    /// float and integer element types. The range is half-open for integers
+    /// too: `gen_random(r, c, 0, 2)` draws only zeros and ones.
+    pub fn gen_random(rows: impl Into<Rows>, columns: impl Into<Cols>, min_val: T, max_val: T) -> Result<Matrix<T>, String> {
+        Self::gen_random_with_rng(rows, columns, min_val, max_val, &mut rand::thread_rng())
+    }
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+    /// Like [`Matrix::gen_random`], but the same `seed` always gives the same
+    /// matrix, so a discrepancy found on random inputs can be reproduced.
+    pub fn gen_random_seeded(rows: impl Into<Rows>, columns: impl Into<Cols>, min_val: T, max_val: T,
+                             seed: u64) -> Result<Matrix<T>, String> {
+        Self::gen_random_with_rng(rows, columns, min_val, max_val, &mut StdRng::seed_from_u64(seed))
+    }
 
-        assert!(matrix.is_err());
+    /// Like [`Matrix::gen_random`], drawing from `rng`, so a seeded or mock
+    /// generator gives the same matrix every time.
+    pub fn gen_random_with_rng(rows: impl Into<Rows>, columns: impl Into<Cols>, min_val: T, max_val: T,
+                               rng: &mut impl Rng) -> Result<Matrix<T>, String> {
+        let (rows, columns) = (rows.into().0, columns.into().0);
+
+        if min_val >= max_val {
+            return Err(format!("Min_val: {min_val} must be less than max_val: {max_val}"))
+        }
+        let len = rows.checked_mul(columns)
+            .ok_or_else(|| format!("{}x{} matrix has more elements than the address space", rows, columns))?;
+        let mut numbers = Vec::with_capacity(len);
+
+        for _ in 0..len {
+            numbers.push(rng.gen_range(min_val..max_val));
+        }
+
+        let numbers = Arc::new(numbers);
+
+        Ok(Matrix { rows, columns, numbers })
     }
 
-    #[test]
-    fn iter_read_wrong_rows() {
-        let contents = vec!["a"];
+    /// Refills the elements with values in `min_val..max_val` drawn from
+    /// `seed`, reusing the buffer unless it is shared and `aliasing` allows a
+    /// copy.
+    pub fn randomize_in_place(&mut self, min_val: T, max_val: T, seed: u64, aliasing: Aliasing) -> Result<(), String> {
+        self.randomize_in_place_with_rng(min_val, max_val, &mut StdRng::seed_from_u64(seed), aliasing)
+    }
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+    /// Like [`Matrix::randomize_in_place`], drawing from `rng`.
+    pub fn randomize_in_place_with_rng(&mut self, min_val: T, max_val: T, rng: &mut impl Rng,
+                                       aliasing: Aliasing) -> Result<(), String> {
+        if min_val >= max_val {
+            return Err(format!("Min_val: {min_val} must be less than max_val: {max_val}"))
+        }
 
-        assert!(matrix.is_err());
+        for number in self.numbers_mut(aliasing)? {
+            *number = rng.gen_range(min_val..max_val);
+        }
+        Ok(())
     }
+}
 
-    #[test]
-    fn iter_read_wrong_columns() {
-        let contents = vec!["2", "a"];
+impl<T: SampleUniform + PartialOrd + Display + Element> Matrix<T> {
+    /// Like [`Matrix::gen_random`], but each element is nonzero only with
+    /// probability `density`, in (0, 1], and exactly zero otherwise.
+    pub fn gen_random_sparse(rows: impl Into<Rows>, columns: impl Into<Cols>, min_val: T, max_val: T,
+                             density: f64) -> Result<Matrix<T>, String> {
+        Self::gen_random_sparse_with_rng(rows, columns, min_val, max_val, density, &mut rand::thread_rng())
+    }
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+    /// Like [`Matrix::gen_random_sparse`], drawing from `rng`: first every
+    /// element as [`Matrix::gen_random_with_rng`] would, then whether each one
+    /// is kept.
+    pub fn gen_random_sparse_with_rng(rows: impl Into<Rows>, columns: impl Into<Cols>, min_val: T, max_val: T,
+                                      density: f64, rng: &mut impl Rng) -> Result<Matrix<T>, String> {
+        if density <= 0.0 || density > 1.0 || density.is_nan() {
+            return Err(format!("Density: {density} must be in (0, 1]"))
+        }
 
-        assert!(matrix.is_err());
+        let mut matrix = Self::gen_random_with_rng(rows, columns, min_val, max_val, rng)?;
+
+        for number in matrix.numbers_mut(Aliasing::ErrorIfShared)? {
+            if !rng.gen_bool(density) {
+                *number = T::ZERO;
+            }
+        }
+
+        Ok(matrix)
     }
+}
 
-    #[test]
-    fn iter_read_incorrect_rows_in_matrix_data() {
-        let contents = vec!["3", "2", "1 2", "3 4"];
+/// `rows`×`columns` samples of N(`mean`, `std_dev`²) by the Box-Muller transform.
+fn normal_samples(rows: usize, columns: usize, mean: f64, std_dev: f64, rng: &mut impl Rng) -> Result<Vec<f64>, String> {
+    if std_dev <= 0.0 || !std_dev.is_finite() || !mean.is_finite() {
+        return Err(format!("Std_dev: {std_dev} must be positive and mean: {mean} finite"))
+    }
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+    let len = rows.checked_mul(columns)
+        .ok_or_else(|| format!("{}x{} matrix has more elements than the address space", rows, columns))?;
+    let mut samples = Vec::new();
+    // Samples come in pairs, so an odd length overshoots by one before the truncate.
+    samples.try_reserve_exact(len.saturating_add(1))
+        .map_err(|error| format!("Couldn't allocate a {}x{} matrix: {}", rows, columns, error))?;
+
+    while samples.len() < len {
+        let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+        let angle = std::f64::consts::TAU * rng.gen::<f64>();
+        samples.extend([radius * angle.cos(), radius * angle.sin()].map(|z| mean + std_dev * z));
+    }
+    samples.truncate(len);
 
-        assert!(matrix.is_err());
+    Ok(samples)
+}
+
+impl Matrix<f64> {
+    /// Random matrix whose elements are drawn from a normal distribution.
+    pub fn gen_random_normal(rows: impl Into<Rows>, columns: impl Into<Cols>, mean: f64, std_dev: f64)
+        -> Result<Matrix<f64>, String> {
+        Self::gen_random_normal_with_rng(rows, columns, mean, std_dev, &mut rand::thread_rng())
     }
 
-    #[test]
-    fn iter_read_incorrect_columns_in_matrix_data() {
-        let contents = vec!["3", "2", "1 2 3", "4 5 6", "7 8 9"];
+    /// Like [`Matrix::gen_random_normal`], drawing from `rng`.
+    pub fn gen_random_normal_with_rng(rows: impl Into<Rows>, columns: impl Into<Cols>, mean: f64, std_dev: f64,
+                                      rng: &mut impl Rng) -> Result<Matrix<f64>, String> {
+        let (rows, columns) = (rows.into().0, columns.into().0);
+        let numbers = normal_samples(rows, columns, mean, std_dev, rng)?;
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+        Ok(Matrix { rows, columns, numbers: Arc::new(numbers) })
+    }
+}
 
-        assert!(matrix.is_err());
+impl Matrix<f32> {
+    /// Like the f64 [`Matrix::gen_random_normal`], rounding each sample to f32.
+    pub fn gen_random_normal(rows: impl Into<Rows>, columns: impl Into<Cols>, mean: f64, std_dev: f64)
+        -> Result<Matrix<f32>, String> {
+        Self::gen_random_normal_with_rng(rows, columns, mean, std_dev, &mut rand::thread_rng())
     }
 
-    #[test]
-    fn iter_read_wrong_data_in_matrix_data() {
-        let contents = vec!["3", "2", "1 2 3", "a 5 6", "7 8 9"];
+    /// Like the f32 [`Matrix::gen_random_normal`], drawing from `rng`.
+    pub fn gen_random_normal_with_rng(rows: impl Into<Rows>, columns: impl Into<Cols>, mean: f64, std_dev: f64,
+                                      rng: &mut impl Rng) -> Result<Matrix<f32>, String> {
+        Ok(Matrix::<f64>::gen_random_normal_with_rng(rows, columns, mean, std_dev, rng)?.map(|&num| num as f32))
+    }
+}
 
-        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+impl<T: Display> Display for Matrix<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let rows: String = self.iter_rows()
+            .map(|row| row.iter().map(T::to_string).collect::<Vec<String>>().join(" ") + "\n")
+            .collect();
 
-        assert!(matrix.is_err());
+        write!(f, "{}\n{}\n{}", self.rows, self.columns, rows)
     }
+}
 
-    #[test]
-    fn to_string_correct() {
-        let matrix = Matrix::<i32>::from_vec(
-            vec!["3", "2", "1 2", "3 4", "5 6"]
-        ).unwrap();
+impl<T: Display> Matrix<T> {
+    /// Elements are written in their shortest form that parses back to the
+    /// same value, so for f64 reading the file gives back the exact bits,
+    /// negative zero included.
+    pub fn to_file(&self, file_name: &str) -> Result<(), String> {
+        io::write_plain_text(self, file_name)
+    }
+}
 
-        let result = matrix.to_string();
+impl<T: FromStr + Display + Element> Matrix<T> {
+    pub fn from_file(file_name: &str) -> Result<Matrix<T>, String> {
+        io::read_matrix_with(file_name, &PlainText)
+    }
 
-        let expected = String::from("3\n2\n1 2\n3 4\n5 6\n");
+    /// Reads a plain text file with the token parsing described by `options`.
+    /// Elements changed under [`OverflowPolicy::SaturateAndWarn`](crate::parse::OverflowPolicy::SaturateAndWarn) are not
+    /// reported, see [`Matrix::from_file_with_warnings`].
+    pub fn from_file_with(file_name: &str, options: &ParseOptions) -> Result<Matrix<T>, String> {
+        let contents = io::read_to_string(io::open_limited(file_name, &options.limits)?)?;
 
-        assert_eq!(expected, result);
+        Matrix::from_iterator_with(contents.lines(), options)
     }
 
-    #[test]
-    fn gen_random_correct_matrix() {
-        let rows = 10;
-        let columns = 10;
-        let min_val = 1.0;
-        let max_val = 2.0;
+    /// Like [`Matrix::from_file_with`], also returning a [`ParseWarning`] for
+    /// every element the [`OverflowPolicy`](crate::parse::OverflowPolicy) saturated or let underflow.
+    pub fn from_file_with_warnings(file_name: &str, options: &ParseOptions) -> Result<(Matrix<T>, Vec<ParseWarning>), String> {
+        let contents = io::read_to_string(io::open_limited(file_name, &options.limits)?)?;
+        let mut warnings = vec![];
 
-        let matrix = Matrix::gen_random(rows, columns, min_val, max_val).unwrap();
+        let matrix = Matrix::from_iterator_collecting(contents.lines(), options, Vec::new(), &mut warnings)?
+            .into_result()?;
 
-        assert_eq!(rows, matrix.rows);
-        assert_eq!(columns, matrix.columns);
+        Ok((matrix, warnings))
+    }
 
-        let numbers = Arc::<Vec<f32>>::into_inner(matrix.numbers).unwrap();
+    /// Like [`Matrix::from_file_with`], also returning the comment lines when
+    /// [`ParseOptions::collect_comments`] is set, so a rewrite can carry them
+    /// over with [`WriteOptions::comments`].
+    pub fn from_file_with_metadata(file_name: &str, options: &ParseOptions) -> Result<(Matrix<T>, FileMetadata), String> {
+        let contents = io::read_to_string(io::open_limited(file_name, &options.limits)?)?;
 
-        for i in numbers {
-            assert!(i >= min_val && i <= max_val);
-        }
+        let matrix = Matrix::from_iterator_with(contents.lines(), options)?;
+        let metadata = if options.collect_comments { FileMetadata::from_text(&contents) } else { FileMetadata::default() };
+
+        Ok((matrix, metadata))
+    }
+
+    /// Like [`Matrix::from_file_with`], but with
+    /// [`ParseOptions::partial_ok`] a failing data row gives back the rows
+    /// before it in a [`ParseOutcome::Partial`] rather than only the error.
+    /// Header errors are always returned as errors.
+    pub fn from_file_partial(file_name: &str, options: &ParseOptions) -> Result<ParseOutcome<T>, String> {
+        let contents = io::read_to_string(io::open_limited(file_name, &options.limits)?)?;
+
+        Matrix::from_iterator_collecting(contents.lines(), options, Vec::new(), &mut Vec::new())
     }
-}
\ No newline at end of file
+}
+
+impl<T: FromStr + Display + LowerExp + Element> Matrix<T> {
+    /// Writes only the elements whose magnitude exceeds `zero_tolerance`, see [`Coo`].
+    #[deprecated(note = "use write_with and WriteOptions::new().format(Format::Coo).zero_tolerance(..)")]
+    pub fn to_file_coo(&self, file_name: &str, zero_tolerance: f64) -> Result<(), String> {
+        let options = WriteOptions::new().format(Format::Coo).zero_tolerance(zero_tolerance);
+        self.write_with(file_name, &options)
+    }
+}
+
+impl<T: FromStr + Display + LowerExp + Element> Matrix<T> {
+    /// Rewrites a plain text file in the formatting of [`Matrix::to_file`],
+    /// dropping extra digits and padding, so that further read and write
+    /// cycles leave it byte for byte the same. Comment lines are kept, those
+    /// between the data rows moved up with the rest above the header. The
+    /// rewrite is atomic.
+    pub fn canonicalize_file(file_name: &str) -> Result<(), String> {
+        let options = ParseOptions::new().comments(true).collect_comments(true);
+        let (matrix, metadata) = Matrix::<T>::from_file_with_metadata(file_name, &options)?;
+
+        matrix.write_with(file_name, &WriteOptions::new().atomic(true).comments(metadata.comment_lines()))
+    }
+
+    /// Reads `input` in the format of its extension and writes it to `output`
+    /// as `format`. Comment lines of a plain text or CSV input are written at
+    /// the top of a plain text or CSV output, and dropped otherwise.
+    pub fn convert_file(input: &str, output: &str, format: Format) -> Result<(), String> {
+        let matrix = io::read_matrix::<T>(input, None)?;
+
+        let carries_comments = |format| matches!(format, Format::PlainText | Format::Csv);
+        let comments = if carries_comments(Format::from_extension(input)) && carries_comments(format) {
+            FileMetadata::from_text(&io::read_to_string(io::open_input(input)?)?).comment_lines()
+        } else {
+            vec![]
+        };
+
+        matrix.write_with(output, &WriteOptions::new().format(format).comments(comments))
+    }
+}
+
+impl Matrix<f64> {
+    /// Rounds every element to the nearest integer, failing on the first one,
+    /// row by row, that is further than `tolerance` from it or outside i64.
+    pub fn to_integer_matrix(&self, tolerance: f64) -> Result<Matrix<i64>, String> {
+        let mut numbers = Vec::with_capacity(self.numbers.len());
+
+        for (index, &num) in self.numbers.iter().enumerate() {
+            let rounded = num.round();
+
+            if !((num - rounded).abs() <= tolerance && (-(2f64.powi(63))..2f64.powi(63)).contains(&rounded)) {
+                return Err(format!("Element at ({}, {}) is {}, not within {} of an integer",
+                                   index / self.columns, index % self.columns, num, tolerance))
+            }
+            numbers.push(rounded as i64);
+        }
+
+        Matrix::new(self.rows, self.columns, numbers)
+    }
+
+    /// Writes the elements rounded to integers in the plain text format, so
+    /// the file reads back as `Matrix<i64>`; see [`Matrix::to_integer_matrix`].
+    pub fn to_file_as_integers(&self, file_name: &str, tolerance: f64) -> Result<(), String> {
+        self.to_integer_matrix(tolerance)?.to_file(file_name)
+    }
+}
+
+impl<T: FromStr + Display + Element> Matrix<T> {
+    pub fn from_file_coo(file_name: &str) -> Result<Matrix<T>, String> {
+        io::read_matrix_with(file_name, &Coo { zero_tolerance: 0.0 })
+    }
+}
+
+impl<T: FromStr + Element> Matrix<T> {
+    pub fn from_vec(vector: Vec<&str>) -> Result<Matrix<T>, String> {
+        Self::from_iterator(vector.into_iter())
+    }
+
+    /// Like [`Matrix::from_vec`], with the line and token parsing described by `options`.
+    pub fn from_vec_with(vector: Vec<&str>, options: &ParseOptions) -> Result<Matrix<T>, String> {
+        Self::from_iterator_with(vector.into_iter(), options)
+    }
+
+    /// Reads a plain text matrix from any reader, such as an in-memory buffer.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Matrix<T>, String> {
+        Self::from_reader_with(reader, &ParseOptions::new())
+    }
+
+    /// Like [`Matrix::from_reader`], with the parsing described by `options`.
+    pub fn from_reader_with<R: BufRead>(reader: R, options: &ParseOptions) -> Result<Matrix<T>, String> {
+        let contents = io::read_to_string(reader)?;
+
+        Self::from_iterator_with(contents.lines(), options)
+    }
+
+    pub(crate) fn from_iterator<'a>(iterator: impl Iterator<Item=&'a str>) -> Result<Matrix<T>, String> {
+        Self::from_iterator_with(iterator, &ParseOptions::new())
+    }
+
+    pub(crate) fn from_iterator_with<'a>(iterator: impl Iterator<Item=&'a str>, options: &ParseOptions)
+        -> Result<Matrix<T>, String> {
+
+        Self::from_iterator_reusing(iterator, options, Vec::new())
+    }
+
+    /// Like [`Matrix::from_iterator_with`], parsing into `numbers` to reuse its capacity.
+    pub(crate) fn from_iterator_reusing<'a>(iterator: impl Iterator<Item=&'a str>, options: &ParseOptions,
+                                            numbers: Vec<T>) -> Result<Matrix<T>, String> {
+
+        Self::from_iterator_collecting(iterator, options, numbers, &mut Vec::new()).and_then(ParseOutcome::into_result)
+    }
+
+    /// Like [`Matrix::from_iterator_reusing`], adding a [`ParseWarning`] to
+    /// `warnings` for every element changed by the overflow policy, and
+    /// stopping with the rows parsed so far if the options allow a partial
+    /// parse.
+    fn from_iterator_collecting<'a>(iterator: impl Iterator<Item=&'a str>, options: &ParseOptions,
+                                    mut numbers: Vec<T>, warnings: &mut Vec<ParseWarning>)
+        -> Result<ParseOutcome<T>, String> {
+
+        let mut in_header = true;
+        let line_number = Cell::new(0);
+        let mut iterator = iterator.filter(|line| {
+            line_number.set(line_number.get() + 1);
+            let comment = line.trim_start().starts_with('#');
+            in_header &= comment;
+            !(comment && (in_header || options.comments))
+        });
+
+        let (rows, columns) = if options.one_line_header {
+            match iterator.next() {
+                Some(header) => match header.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                    [rows, columns] => match (options.parse::<usize>(rows), options.parse::<usize>(columns)) {
+                        (Ok(rows), Ok(columns)) => (rows, columns),
+                        _ => return Err(format!("Couldn't parse '{}' as rows num and columns num", header))
+                    },
+                    _ => return Err(format!("Couldn't parse '{}' as rows num and columns num", header))
+                },
+                None => return Err(String::from("File is empty!"))
+            }
+        } else {
+            let rows = match iterator.next() {
+                Some(rows) => {match options.parse::<usize>(rows.trim()) {
+                    Ok(parsed) => parsed,
+                    Err(hint) => return Err(format!("Couldn't parse '{}' as rows num{}", rows, hint))
+                }}
+                None => return Err(String::from("File is empty!"))
+            };
+
+            let columns = match iterator.next() {
+                Some(columns) => {match options.parse::<usize>(columns.trim()) {
+                    Ok(parsed) => parsed,
+                    Err(hint) => return Err(format!("Couldn't parse '{}' as columns num{}", columns, hint))
+                }}
+                None => return Err(String::from("File doesn't have columns row"))
+            };
+
+            (rows, columns)
+        };
+
+        let mut iterator = iterator.peekable();
+        let column_major = iterator.next_if(|line| line.trim() == COLUMN_MAJOR_MARKER).is_some();
+
+        let (lines, line_length, name, length_name) = if column_major {
+            (columns, rows, "Column", "rows")
+        } else {
+            (rows, columns, "Row", "columns")
+        };
+
+        options.limits.check_shape(Shape { rows, cols: columns })?;
+
+        numbers.clear();
+        numbers.reserve_exact(rows * columns);
+
+        for i in 0..lines {
+            let row = iterator.next();
+            let line = line_number.get() + usize::from(row.is_none());
+
+            let row = match row {
+                Some(row) => options.split_row(row).map_err(|reason| format!("{} {} {}", name, i, reason)),
+                None => Err(format!("Not enough {}s: {i}", name.to_lowercase()))
+            };
+
+            let row = row.and_then(|row| {
+                let parsed: Result<Vec<T>, (&str, &str)> = row
+                    .into_iter()
+                    .enumerate()
+                    .map(|(column, num)| match options.parse_element::<T>(num) {
+                        Ok((parsed, Some(kind))) => {
+                            warnings.push(ParseWarning { line, column: column + 1, token: num.to_string(), kind });
+                            Ok(parsed)
+                        },
+                        Ok((parsed, None)) => Ok(parsed),
+                        Err(hint) => Err((num, hint)),
+                    })
+                    .collect();
+
+                match parsed {
+                    Ok(parsed) if parsed.len() == line_length => Ok(parsed),
+                    Ok(parsed) => Err(format!("{} {} length: {} doesn't match {}: {}",
+                                              name, i, parsed.len(), length_name, line_length)),
+                    Err((token, hint)) => Err(format!("Error parsing {} {}: '{}' is not a valid {}{}",
+                                                      i, name.to_lowercase(), token, type_name::<T>(), hint)),
+                }
+            });
+
+            match row {
+                Ok(mut parsed) => numbers.append(&mut parsed),
+                Err(message) if options.partial_ok => {
+                    let matrix = if column_major {
+                        Matrix::from_column_major(rows, i, numbers)?
+                    } else {
+                        Matrix { rows: i, columns, numbers: Arc::new(numbers) }
+                    };
+                    return Ok(ParseOutcome::Partial(PartialParse { matrix, error: ParseError { line, message } }))
+                },
+                Err(message) => return Err(message),
+            }
+
+            if let Some(progress) = &options.progress {
+                progress.rows_parsed(i as u64 + 1, lines as u64);
+            }
+        }
+
+        if column_major {
+            return Matrix::from_column_major(rows, columns, numbers).map(ParseOutcome::Complete)
+        }
+
+        let numbers = Arc::new(numbers);
+
+        Ok(ParseOutcome::Complete(Matrix { rows, columns, numbers }))
+    }
+}
+
+#[cfg(test)]
+mod matrix_test {
+    use std::sync::Arc;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::limits::Limits;
+    use crate::io::Format;
+    use crate::parse::{Comment, FileMetadata, OutOfRange, OverflowPolicy, ParseError, ParseOptions, ParseOutcome, ParseWarning,
+                       PartialParse};
+    use crate::matrix::{Aliasing, Matrix};
+    use crate::test_support::temp_path;
+    use crate::write::WriteOptions;
+
+    #[test]
+    fn iter_read_correct_ints() {
+        let contents = vec!["3", "2", " 1 2", "3 4", "5 6"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        match matrix {
+            Ok(matrix) => assert_eq!(matrix,
+                                     Matrix{ rows: 3, columns: 2, numbers: Arc::new(vec![1, 2, 3, 4, 5, 6]) }),
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+
+    #[test]
+    fn iter_read_correct_floats() {
+        let contents = vec!["3", "2", "1.2 2.567", "3.45 4.2", "5.0 6.0"];
+
+        let matrix = Matrix::<f32>::from_iterator(contents.into_iter());
+
+        match matrix {
+            Ok(matrix) => assert_eq!(matrix,
+                                     Matrix{ rows: 3, columns: 2, numbers: Arc::new(vec![1.2, 2.567, 3.45, 4.2, 5.0, 6.0]) }),
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    #[test]
+    fn iter_read_floats_as_ints() {
+        let contents = vec!["3", "2", "1.2 2.567", "3.45 4.2", "5.0 6.0"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_blank() {
+        let contents = vec![""];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_no_columns() {
+        let contents = vec!["2"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_no_matrix() {
+        let contents = vec!["3", "2"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_wrong_rows() {
+        let contents = vec!["a"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_wrong_columns() {
+        let contents = vec!["2", "a"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_incorrect_rows_in_matrix_data() {
+        let contents = vec!["3", "2", "1 2", "3 4"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_incorrect_columns_in_matrix_data() {
+        let contents = vec!["3", "2", "1 2 3", "4 5 6", "7 8 9"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn iter_read_wrong_data_in_matrix_data() {
+        let contents = vec!["3", "2", "1 2 3", "a 5 6", "7 8 9"];
+
+        let matrix = Matrix::<i32>::from_iterator(contents.into_iter());
+
+        assert!(matrix.is_err());
+    }
+
+    #[test]
+    fn to_string_correct() {
+        let matrix = Matrix::<i32>::from_vec(
+            vec!["3", "2", "1 2", "3 4", "5 6"]
+        ).unwrap();
+
+        let result = matrix.to_string();
+
+        let expected = String::from("3\n2\n1 2\n3 4\n5 6\n");
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn gen_random_correct_matrix() {
+        let rows = 10;
+        let columns = 10;
+        let min_val = 1.0;
+        let max_val = 2.0;
+
+        let matrix = Matrix::gen_random(rows, columns, min_val, max_val).unwrap();
+
+        assert_eq!(rows, matrix.rows);
+        assert_eq!(columns, matrix.columns);
+
+        let numbers = matrix.into_vec();
+
+        for i in numbers {
+            assert!(i >= min_val && i <= max_val);
+        }
+    }
+
+    #[test]
+    fn gen_random_with_mock_and_seeded_rng() {
+        let stepped = Matrix::gen_random_with_rng(2, 3, 0i64, 8, &mut StepRng::new(0, 1 << 61)).unwrap();
+        assert_eq!(stepped, Matrix::new(2, 3, vec![0, 1, 2, 3, 4, 5]).unwrap());
+
+        let floats = Matrix::gen_random_with_rng(1, 4, 1.0, 2.0, &mut StepRng::new(0, 1 << 62)).unwrap();
+        assert_eq!(floats, Matrix::new(1, 4, vec![1.0, 1.25, 1.5, 1.75]).unwrap());
+
+        let seeded = |seed| Matrix::gen_random_with_rng(4, 4, -5, 5, &mut StdRng::seed_from_u64(seed)).unwrap();
+        assert_eq!(seeded(3), seeded(3));
+        assert_ne!(seeded(3), seeded(4));
+
+        let mut refilled = Matrix::new(4, 4, vec![0; 16]).unwrap();
+        refilled.randomize_in_place(-5, 5, 3, Aliasing::default()).unwrap();
+        assert_eq!(refilled, seeded(3));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn row_slices() {
+        let matrix = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(matrix.iter_rows().collect::<Vec<&[i32]>>(), vec![&[1, 2][..], &[3, 4], &[5, 6]]);
+        assert_eq!(matrix.iter_rows().len(), matrix.get_rows());
+        assert_eq!((matrix.row(1), matrix.row(3)), (Some(&[3, 4][..]), None));
+        assert_eq!(matrix.iter_rows().map(|row| row.iter().sum::<i32>()).collect::<Vec<i32>>(), vec![3, 7, 11]);
+
+        let single = Matrix::new(1, 4, vec![0.5, 1.5, 2.5, 3.5]).unwrap();
+        assert_eq!(single.iter_rows().collect::<Vec<&[f64]>>(), vec![&[0.5, 1.5, 2.5, 3.5][..]]);
+        assert_eq!(single.row(0), Some(&[0.5, 1.5, 2.5, 3.5][..]));
+
+        let no_columns = Matrix::<i32>::new(2, 0, vec![]).unwrap();
+        assert_eq!(no_columns.iter_rows().len(), 2);
+        assert_eq!(no_columns.row(1), Some(&[][..]));
+    }
+
+    #[test]
+    fn to_file_needs_only_display() {
+        struct Label(&'static str);
+
+        impl std::fmt::Display for Label {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "<{}>", self.0)
+            }
+        }
+
+        let file = temp_path("display_only.txt");
+        Matrix::new(1, 2, vec![Label("a"), Label("b")]).unwrap().to_file(&file).unwrap();
+        let written = std::fs::read_to_string(&file);
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(written.unwrap(), "1\n2\n<a> <b>\n");
+    }
+
+    #[test]
+    fn clone_shares_and_deep_clone_copies() {
+        let original = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        let cheap = original.clone();
+        assert_eq!((&cheap, original.shared_count()), (&original, 2));
+        assert_eq!(cheap.as_slice().as_ptr(), original.as_slice().as_ptr());
+
+        let deep = original.deep_clone();
+        assert_eq!((&deep, deep.shared_count(), original.shared_count()), (&original, 1, 2));
+        assert_ne!(deep.as_slice().as_ptr(), original.as_slice().as_ptr());
+    }
+
+    #[test]
+    fn into_vec_copies_only_shared_elements() {
+        let matrix = Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+        let address = matrix.as_slice().as_ptr();
+        assert_eq!(matrix.as_slice(), &[1, 2, 3, 4]);
+
+        let numbers = matrix.into_vec();
+        assert_eq!((numbers.as_ptr(), numbers), (address, vec![1, 2, 3, 4]));
+
+        let matrix = Matrix::new(1, 3, vec![5, 6, 7]).unwrap();
+        let shared = matrix.clone();
+        let numbers = matrix.into_vec();
+        assert_ne!(numbers.as_ptr(), shared.as_slice().as_ptr());
+        assert_eq!((numbers.as_slice(), shared.shared_count()), (shared.as_slice(), 1));
+    }
+
+    #[test]
+    fn try_from_nested_vecs() {
+        let matrix = Matrix::try_from(vec![vec![1, 2], vec![3, 4], vec![5, 6]]).unwrap();
+        assert_eq!(matrix, Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap());
+
+        assert_eq!(Matrix::try_from(vec![vec![1, 2], vec![3, 4], vec![5]]).unwrap_err(),
+                   "Row 2 has 1 elements, but row 0 has 2");
+        assert_eq!(Matrix::try_from(vec![vec![1], vec![2, 3]]).unwrap_err(), "Row 1 has 2 elements, but row 0 has 1");
+
+        let empty = Matrix::<i32>::try_from(Vec::new()).unwrap();
+        assert_eq!((empty.rows(), empty.columns()), (0, 0));
+
+        let no_columns = Matrix::<i32>::try_from(vec![vec![], vec![], vec![]]).unwrap();
+        assert_eq!((no_columns.rows(), no_columns.columns()), (3, 0));
+    }
+
+    #[test]
+    fn from_fn_fills_in_row_major_order() {
+        let matrix = Matrix::from_fn(2, 3, |i, j| (i * 10 + j) as i32).unwrap();
+        assert_eq!(matrix, Matrix::new(2, 3, vec![0, 1, 2, 10, 11, 12]).unwrap());
+
+        let identity = Matrix::from_fn(4, 4, |i, j| if i == j { 1.0 } else { 0.0 }).unwrap();
+        assert_eq!(identity, Matrix::<f64>::identity(4));
+
+        let mut calls = Vec::new();
+        Matrix::from_fn(2, 2, |i, j| calls.push((i, j))).unwrap();
+        assert_eq!(calls, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+
+        assert_eq!(Matrix::from_fn(usize::MAX, 2, |_, _| 0u8).unwrap_err(),
+                   format!("{}x2 matrix has more elements than the address space", usize::MAX));
+    }
+
+    #[test]
+    fn map_converts_and_transforms_elements() {
+        let matrix = Matrix::new(2, 3, vec![1, -2, 3, -4, 5, -6]).unwrap();
+
+        let floats: Matrix<f64> = matrix.map(|&num| num as f64 / 2.0);
+        assert_eq!(floats, Matrix::new(2, 3, vec![0.5, -1.0, 1.5, -2.0, 2.5, -3.0]).unwrap());
+        assert_eq!(matrix.map(|num: &i32| num.abs()), Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap());
+        assert_eq!(Matrix::<i32>::new(0, 4, vec![]).unwrap().map(|&num| num as f64).columns(), 4);
+    }
+
+    #[test]
+    fn map_indexed_sees_positions() {
+        let matrix = Matrix::new(3, 3, (1..=9).collect::<Vec<i32>>()).unwrap();
+
+        let off_diagonal = matrix.map_indexed(|row, column, &num| if row == column { 0 } else { num });
+        assert_eq!(off_diagonal, Matrix::new(3, 3, vec![0, 2, 3, 4, 0, 6, 7, 8, 0]).unwrap());
+
+        let positions = Matrix::new(2, 3, vec![(); 6]).unwrap().map_indexed(|row, column, _| (row, column));
+        assert_eq!(positions.row(1), Some(&[(1, 0), (1, 1), (1, 2)][..]));
+    }
+
+    #[test]
+    fn reshape_keeps_row_major_order() {
+        let matrix = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        let wide = matrix.reshape(2, 3).unwrap();
+        assert_eq!(wide, Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap());
+        assert_eq!(wide.row(1), Some(&[4, 5, 6][..]));
+
+        let flat = matrix.reshape(1, 6).unwrap();
+        assert_eq!((flat.rows(), flat.columns()), (1, 6));
+        assert_eq!(flat.row(0), Some(&[1, 2, 3, 4, 5, 6][..]));
+        assert_eq!(matrix.shared_count(), 3);
+
+        assert_eq!(matrix.reshape(4, 2).unwrap_err(), "Can't reshape 3x2 (6 elements) to 4x2 (8 elements)");
+        assert!(matrix.reshape(usize::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn column_views() {
+        let matrix = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(matrix.iter_columns().collect::<Vec<Vec<&i32>>>(), vec![vec![&1, &3, &5], vec![&2, &4, &6]]);
+        assert_eq!((matrix.column(1), matrix.column(2)), (Some(vec![&2, &4, &6]), None));
+        assert_eq!(matrix.iter_columns().len(), 2);
+
+        let single = Matrix::new(1, 3, vec![7, 8, 9]).unwrap();
+        assert_eq!(single.iter_columns().collect::<Vec<Vec<&i32>>>(), vec![vec![&7], vec![&8], vec![&9]]);
+
+        let no_rows = Matrix::<i32>::new(0, 2, vec![]).unwrap();
+        assert_eq!(no_rows.column(1), Some(vec![]));
+    }
+
+    fn rows_of(matrix: &Matrix<i32>) -> Vec<Vec<i32>> {
+        matrix.numbers.chunks(matrix.columns).map(|row| row.to_vec()).collect()
+    }
+
+    fn numbered_rows(rows: usize) -> Matrix<i32> {
+        let numbers = (0..rows as i32 * 2).collect();
+
+        Matrix::new(rows, 2, numbers).unwrap()
+    }
+
+    #[test]
+    fn sample_rows_distinct_subset() {
+        let matrix = numbered_rows(20);
+
+        let sample = matrix.sample_rows(7, 42).unwrap();
+
+        assert_eq!(sample.rows, 7);
+        assert_eq!(sample.columns, 2);
+
+        let original = rows_of(&matrix);
+        let mut sampled = rows_of(&sample);
+        for row in &sampled {
+            assert!(original.contains(row));
+        }
+
+        sampled.sort();
+        sampled.dedup();
+        assert_eq!(sampled.len(), 7);
+
+        assert_eq!(sample, matrix.sample_rows(7, 42).unwrap());
+    }
+
+    #[test]
+    fn sample_rows_too_many() {
+        let matrix = numbered_rows(3);
+
+        assert!(matrix.sample_rows(4, 1).is_err());
+        assert_eq!(matrix.sample_rows(0, 1).unwrap().rows, 0);
+    }
+
+    #[test]
+    fn shuffle_rows_reproducible_permutation() {
+        let matrix = numbered_rows(50);
+
+        let shuffled = matrix.shuffle_rows(7);
+
+        assert_eq!(shuffled, matrix.shuffle_rows(7));
+        assert_ne!(shuffled, matrix);
+
+        let mut sorted = rows_of(&shuffled);
+        sorted.sort();
+        assert_eq!(sorted, rows_of(&matrix));
+    }
+
+    #[test]
+    fn split_rows_preserves_order() {
+        let matrix = numbered_rows(5);
+
+        let (first, second) = matrix.split_rows(2).unwrap();
+
+        assert_eq!(first.rows + second.rows, matrix.rows);
+        assert_eq!(first, Matrix::new(2, 2, vec![0, 1, 2, 3]).unwrap());
+        assert_eq!(second, Matrix::new(3, 2, vec![4, 5, 6, 7, 8, 9]).unwrap());
+
+        assert!(matrix.split_rows(6).is_err());
+    }
+
+    #[test]
+    fn typed_shape_constructors() {
+        use crate::shape::{Cols, Rows, Shape};
+
+        let matrix = Matrix::with_shape(Rows(3), Cols(2), vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(matrix, Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap());
+        assert!(Matrix::with_shape(Rows(2), Cols(2), vec![1, 2, 3]).is_err());
+
+        let zeros = Matrix::<i32>::zeros(Rows(2), Cols(3));
+        assert_eq!(zeros.shape(), Shape { rows: zeros.rows(), cols: zeros.columns() });
+        assert_eq!(zeros.shape(), Shape { rows: 2, cols: 3 });
+
+        let random = Matrix::gen_random(Rows(4), Cols(1), 0.0, 1.0).unwrap();
+        assert_eq!(random.shape().to_string(), "4x1");
+    }
+
+    #[test]
+    fn gen_random_seeded_is_reproducible() {
+        let seeded = |seed| Matrix::<f64>::gen_random_seeded(6, 5, -1.0, 1.0, seed).unwrap();
+        let bits = |matrix: &Matrix<f64>| matrix.numbers.iter().map(|num| num.to_bits()).collect::<Vec<u64>>();
+
+        assert_eq!(bits(&seeded(42)), bits(&seeded(42)));
+        assert_ne!(bits(&seeded(42)), bits(&seeded(43)));
+        assert_eq!(Matrix::gen_random_seeded(3, 3, 0, 1000, 7), Matrix::gen_random_seeded(3, 3, 0, 1000, 7));
+        assert!(Matrix::gen_random_seeded(1, 1, 1.0, 1.0, 0).is_err());
+    }
+
+    #[test]
+    fn gen_random_normal_matches_mean_and_std_dev() {
+        let matrix = Matrix::<f64>::gen_random_normal(1000, 1000, 3.0, 2.0).unwrap();
+        assert_eq!((matrix.rows, matrix.columns, matrix.numbers.len()), (1000, 1000, 1_000_000));
+
+        let mean = matrix.numbers.iter().sum::<f64>() / 1e6;
+        let std_dev = (matrix.numbers.iter().map(|num| (num - mean).powi(2)).sum::<f64>() / 1e6).sqrt();
+        assert!((mean - 3.0).abs() < 0.05, "mean {mean}");
+        assert!((std_dev - 2.0).abs() < 0.05, "std_dev {std_dev}");
+
+        let floats = Matrix::<f32>::gen_random_normal(3, 7, -1.0, 0.5).unwrap();
+        assert_eq!((floats.rows, floats.columns, floats.numbers.len()), (3, 7, 21));
+
+        for std_dev in [0.0, -1.0, f64::NAN] {
+            assert_eq!(Matrix::<f64>::gen_random_normal(2, 2, 0.0, std_dev).unwrap_err(),
+                       format!("Std_dev: {std_dev} must be positive and mean: 0 finite"));
+        }
+        assert!(Matrix::<f32>::gen_random_normal(2, 2, f64::INFINITY, 1.0).is_err());
+    }
+
+    #[test]
+    fn gen_random_normal_with_mock_and_seeded_rng() {
+        // A generator stuck at zero puts every Box-Muller radius at zero, so each sample is the mean.
+        let constant = Matrix::<f64>::gen_random_normal_with_rng(2, 3, 1.5, 4.0, &mut StepRng::new(0, 0)).unwrap();
+        assert_eq!(constant, Matrix::new(2, 3, vec![1.5; 6]).unwrap());
+
+        let seeded = |seed| Matrix::<f32>::gen_random_normal_with_rng(3, 3, 0.0, 1.0, &mut StdRng::seed_from_u64(seed)).unwrap();
+        assert_eq!(seeded(7), seeded(7));
+        assert_ne!(seeded(7), seeded(8));
+
+        assert_eq!(Matrix::<f64>::gen_random_normal_with_rng(usize::MAX, 2, 0.0, 1.0, &mut StepRng::new(0, 0)).unwrap_err(),
+                   format!("{}x2 matrix has more elements than the address space", usize::MAX));
+    }
+
+    #[test]
+    fn gen_random_sparse_density() {
+        let dense = Matrix::gen_random_sparse(50, 40, 1.0, 2.0, 1.0).unwrap();
+        assert_eq!((dense.rows, dense.columns), (50, 40));
+        assert!(dense.numbers.iter().all(|&num| (1.0..2.0).contains(&num)));
+
+        let sparse = Matrix::<i32>::gen_random_sparse(500, 500, 1, 100, 0.01).unwrap();
+        let nonzeros = sparse.numbers.iter().filter(|&&num| num != 0).count();
+        assert!((2000..=3000).contains(&nonzeros), "{nonzeros} nonzeros");
+        assert!(sparse.numbers.iter().all(|&num| num == 0 || (1..100).contains(&num)));
+
+        for density in [0.0, -0.5, 1.5, f64::NAN] {
+            assert_eq!(Matrix::gen_random_sparse(2, 2, 0.0, 1.0, density).unwrap_err(),
+                       format!("Density: {density} must be in (0, 1]"));
+        }
+        assert!(Matrix::gen_random_sparse(2, 2, 3, 3, 0.5).is_err());
+    }
+
+    #[test]
+    fn gen_random_sparse_with_mock_and_seeded_rng() {
+        // Values 1..=6 are drawn first; the keep draws then continue at 6 << 61, and
+        // only draws below half the range keep an element at density 0.5.
+        let stepped = Matrix::gen_random_sparse_with_rng(2, 3, 1i64, 9, 0.5, &mut StepRng::new(0, 1 << 61)).unwrap();
+        assert_eq!(stepped, Matrix::new(2, 3, vec![0, 0, 3, 4, 5, 6]).unwrap());
+
+        let seeded = |seed| Matrix::gen_random_sparse_with_rng(6, 6, -5.0, 5.0, 0.3, &mut StdRng::seed_from_u64(seed)).unwrap();
+        assert_eq!(seeded(3), seeded(3));
+        assert_ne!(seeded(3), seeded(4));
+
+        assert_eq!(Matrix::gen_random_sparse_with_rng(usize::MAX, 2, 0, 1, 0.5, &mut StepRng::new(0, 0)).unwrap_err(),
+                   format!("{}x2 matrix has more elements than the address space", usize::MAX));
+    }
+
+    #[test]
+    fn gen_random_integers() {
+        let matrix = Matrix::<i64>::gen_random(20, 30, i64::MIN, i64::MIN + 10).unwrap();
+        assert_eq!((matrix.rows, matrix.columns), (20, 30));
+        assert!(matrix.numbers.iter().all(|&i| (i64::MIN..i64::MIN + 10).contains(&i)));
+
+        let matrix = Matrix::<u64>::gen_random(5, 5, u64::MAX - 3, u64::MAX).unwrap();
+        assert!(matrix.numbers.iter().all(|i| (u64::MAX - 3..u64::MAX).contains(i)));
+
+        assert!(Matrix::<u64>::gen_random(2, 2, 5, 5).is_err());
+    }
+
+    fn assert_random_within<T>(min_val: T, max_val: T)
+        where
+            T: rand::distributions::uniform::SampleUniform + PartialOrd + std::fmt::Display + std::fmt::Debug + Copy {
+
+        let matrix = Matrix::gen_random(7, 13, min_val, max_val).unwrap();
+
+        assert_eq!((matrix.rows, matrix.columns, matrix.numbers.len()), (7, 13, 91));
+        assert!(matrix.numbers.iter().all(|&num| min_val <= num && num < max_val), "{:?}", matrix.numbers);
+        assert!(Matrix::gen_random(2, 2, max_val, min_val).is_err());
+    }
+
+    #[test]
+    fn gen_random_every_element_type() {
+        assert_random_within(-3i32, 3);
+        assert_random_within(i64::MAX - 5, i64::MAX);
+        assert_random_within(-0.5f32, 0.5);
+        assert_random_within(1e300f64, 2e300);
+
+        let bits = Matrix::<i32>::gen_random(1, 1000, 0, 2).unwrap();
+        assert!(bits.numbers.contains(&0) && bits.numbers.contains(&1) && !bits.numbers.contains(&2));
+    }
+
+    #[test]
+    fn randomize_in_place_reuses_buffer() {
+        let mut matrix = Matrix::<f64>::zeros(4, 5);
+        let buffer = matrix.numbers.as_ptr();
+
+        matrix.randomize_in_place(-1.0, 1.0, 42, Aliasing::ErrorIfShared).unwrap();
+        let first = matrix.numbers.to_vec();
+        matrix.randomize_in_place(-1.0, 1.0, 42, Aliasing::ErrorIfShared).unwrap();
+
+        assert_eq!(matrix.numbers.as_ptr(), buffer);
+        assert_eq!(*matrix.numbers, first);
+        assert!(first.iter().all(|i| (-1.0..1.0).contains(i)) && first.iter().any(|&i| i != 0.0));
+
+        let mut integers = Matrix::<i64>::zeros(3, 3);
+        integers.randomize_in_place(-5, 5, 42, Aliasing::default()).unwrap();
+        assert!(integers.numbers.iter().all(|i| (-5..5).contains(i)));
+        assert!(integers.randomize_in_place(5, 5, 42, Aliasing::default()).is_err());
+
+        let sibling = integers.clone();
+        assert_eq!(integers.randomize_in_place(-5, 5, 1, Aliasing::ErrorIfShared).unwrap_err(),
+                   "Matrix elements are shared by 2 holders");
+        assert_eq!(sibling, integers);
+    }
+
+    #[test]
+    fn parse_error_names_token_and_type() {
+        let result = Matrix::<u64>::from_vec(vec!["1", "2", "3 -4"]);
+
+        assert_eq!(result.unwrap_err(), "Error parsing 0 row: '-4' is not a valid u64");
+
+        let too_big = format!("{}0", i64::MAX);
+        let result = Matrix::<i64>::from_vec(vec!["1", "1", &too_big]);
+
+        assert!(result.unwrap_err().contains("is not a valid i64"));
+    }
+
+    #[test]
+    fn i64_extremes_round_trip() {
+        let matrix = Matrix::new(2, 2, vec![i64::MIN, i64::MAX, -1, 0]).unwrap();
+
+        let read_back = Matrix::<i64>::from_vec(matrix.to_string().lines().collect()).unwrap();
+
+        assert_eq!(read_back, matrix);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn shape_accessors_agree() {
+        let matrix = Matrix::<i64>::zeros(3, 2);
+
+        assert_eq!((matrix.rows(), matrix.columns()), (matrix.get_rows(), matrix.get_columns()));
+        assert_eq!(<(usize, usize)>::from(matrix.shape()), (3, 2));
+        assert_eq!(matrix.element_count(), 6);
+        assert_eq!(matrix.size_in_bytes(), 48);
+    }
+
+    #[test]
+    fn underscores_and_lenient_integers() {
+        let contents = ["1_0", "1", "1_000", "2.0", "-3", "4", "5", "6", "7", "8", "9", "10"];
+
+        let strict = Matrix::<i32>::from_iterator(contents.into_iter());
+        assert_eq!(strict.unwrap_err(), "Error parsing 1 row: '2.0' is not a valid i32 \
+                                         (integral float, enable the lenient_integers parse option to accept it)");
+
+        let lenient = Matrix::<i32>::from_vec_with(contents.to_vec(), &ParseOptions::new().lenient_integers(true));
+        assert_eq!(lenient.unwrap().get_numbers()[..3], [1000, 2, -3]);
+
+        let scientific = Matrix::<i32>::from_vec(vec!["1", "1", "1e3"]);
+        assert!(scientific.unwrap_err().ends_with("(scientific notation isn't accepted here, write the number out in full)"));
+    }
+
+    #[test]
+    fn embedded_literal_with_comments_and_tabs() {
+        let contents = vec!["# weights", "2 3", "1\t2\t3", "# second row", "4\t 5\t6"];
+        let options = ParseOptions::new().comments(true).one_line_header(true).delimiter(Some('\t'));
+        let expected = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(Matrix::<i32>::from_vec_with(contents.clone(), &options), Ok(expected.clone()));
+        assert_eq!(Matrix::<i32>::from_vec(contents.clone()).unwrap_err(), "Couldn't parse '2 3' as rows num");
+        assert!(Matrix::<i32>::from_vec_with(contents.clone(), &options.clone().comments(false)).is_err());
+
+        let trailing = vec!["2 3", "1\t2\t3\t", "4\t5\t6"];
+        assert_eq!(Matrix::<i32>::from_vec_with(trailing, &options).unwrap_err(), "Row 0 ends with the delimiter '\\t'");
+    }
+
+    #[test]
+    fn constructors_agree_under_options() {
+        let file = &temp_path("constructors.txt");
+
+        for options in [ParseOptions::new(), ParseOptions::new().comments(true).delimiter(Some(',')).lenient_integers(true)] {
+            let contents = match options.delimiter {
+                Some(_) => "# m\n2\n2\n1, 2.0\n# x\n3,4\n",
+                None => "# m\n2\n2\n1 2\n3 4\n",
+            };
+            std::fs::write(file, contents).unwrap();
+
+            let from_vec = Matrix::<i64>::from_vec_with(contents.lines().collect(), &options);
+            let from_iterator = Matrix::<i64>::from_iterator_with(contents.lines(), &options);
+            let from_reader = Matrix::<i64>::from_reader_with(std::io::Cursor::new(contents), &options);
+            let from_file = Matrix::<i64>::from_file_with(file, &options);
+
+            let expected = Ok(Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap());
+            assert_eq!([from_vec, from_iterator, from_reader, from_file], [(); 4].map(|_| expected.clone()));
+        }
+
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(Matrix::<i64>::from_reader(std::io::Cursor::new("1\n1\n7\n")), Matrix::from_vec(vec!["1", "1", "7"]));
+    }
+
+    /// Runs `mutate` on an unshared matrix and on one with a sibling under
+    /// both policies, checking whether the sibling kept the original elements.
+    fn check_aliasing(mutate: impl Fn(&mut Matrix<i32>, Aliasing) -> Result<(), String>, expected: &Matrix<i32>) {
+        let original = || Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        for aliasing in [Aliasing::CloneIfShared, Aliasing::ErrorIfShared] {
+            let mut unshared = original();
+            assert_eq!(mutate(&mut unshared, aliasing), Ok(()));
+            assert_eq!((&unshared, unshared.is_uniquely_owned()), (expected, true));
+        }
+
+        let mut shared = original();
+        let sibling = shared.clone();
+        assert_eq!(mutate(&mut shared, Aliasing::ErrorIfShared), Err(String::from("Matrix elements are shared by 2 holders")));
+        assert_eq!((&shared, shared.shared_count()), (&original(), 2));
+
+        assert_eq!(mutate(&mut shared, Aliasing::CloneIfShared), Ok(()));
+        assert_eq!((&shared, shared.shared_count(), sibling.shared_count()), (expected, 1, 1));
+        assert_eq!(sibling, original());
+    }
+
+    #[test]
+    fn set_respects_aliasing() {
+        check_aliasing(|matrix, aliasing| matrix.set(2, 1, 60, aliasing),
+                       &Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 60]).unwrap());
+
+        let mut matrix = Matrix::new(1, 1, vec![1]).unwrap();
+        assert_eq!(matrix.set(1, 0, 2, Aliasing::default()).unwrap_err(), "Index (1, 0) is out of bounds for 1x1");
+    }
+
+    #[test]
+    fn swaps_respect_aliasing() {
+        check_aliasing(|matrix, aliasing| matrix.swap_rows(0, 2, aliasing),
+                       &Matrix::new(3, 2, vec![5, 6, 3, 4, 1, 2]).unwrap());
+        check_aliasing(|matrix, aliasing| matrix.swap_columns(1, 0, aliasing),
+                       &Matrix::new(3, 2, vec![2, 1, 4, 3, 6, 5]).unwrap());
+    }
+
+    #[test]
+    fn swapping_with_itself_or_out_of_range() {
+        let original = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let mut matrix = original.clone();
+
+        matrix.swap_rows(1, 1, Aliasing::ErrorIfShared).unwrap();
+        matrix.swap_columns(0, 0, Aliasing::ErrorIfShared).unwrap();
+        assert_eq!((&matrix, matrix.shared_count()), (&original, 2));
+
+        assert_eq!(matrix.swap_rows(0, 3, Aliasing::default()).unwrap_err(), "Row index 3 is out of bounds for 3x2");
+        assert_eq!(matrix.swap_columns(2, 0, Aliasing::default()).unwrap_err(), "Column index 2 is out of bounds for 3x2");
+        assert_eq!(matrix, original);
+    }
+
+    #[test]
+    fn get_and_index() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!((matrix.get(0, 2), matrix.get(1, 0), matrix[(1, 2)]), (Some(&3), Some(&4), 6));
+        assert_eq!((matrix.get(2, 0), matrix.get(0, 3), matrix.get(usize::MAX, usize::MAX)), (None, None, None));
+        assert_eq!(Matrix::<i32>::new(0, 0, vec![]).unwrap().get(0, 0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Index (0, 3) is out of bounds for 2x3")]
+    fn index_out_of_bounds_panics_with_shape() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let _ = matrix[(0, 3)];
+    }
+
+    #[test]
+    fn select_columns_in_given_order() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(matrix.select_columns(&[2, 0, 2]), Matrix::new(2, 3, vec![3, 1, 3, 6, 4, 6]));
+        assert_eq!(matrix.select_columns(&[]), Matrix::new(2, 0, vec![]));
+        assert_eq!(matrix.select_columns(&[1, 3]).unwrap_err(), "Column index 3 is out of bounds for 2x3");
+    }
+
+    #[test]
+    fn fill_respects_aliasing() {
+        check_aliasing(|matrix, aliasing| matrix.fill(7, aliasing), &Matrix::new(3, 2, vec![7; 6]).unwrap());
+    }
+
+    #[test]
+    fn truncate_rows_respects_aliasing() {
+        check_aliasing(|matrix, aliasing| matrix.truncate_rows(1, aliasing), &Matrix::new(1, 2, vec![1, 2]).unwrap());
+
+        let mut matrix = Matrix::new(1, 1, vec![1]).unwrap();
+        assert_eq!(matrix.truncate_rows(2, Aliasing::default()).unwrap_err(), "Cannot truncate a matrix with 1 rows to 2 rows");
+    }
+
+    #[test]
+    fn f64_write_read_round_trip_is_exact() {
+        let file = &temp_path("round_trip.txt");
+
+        let numbers = vec![-0.0, 0.0, f64::MAX, f64::MIN, f64::MIN_POSITIVE, -f64::MIN_POSITIVE, 1e-300, 1e300,
+                           0.1 + 0.2, 1.0 / 3.0, -2.5e-7, 2f64.sqrt()];
+        let matrix = Matrix::new(3, 4, numbers.clone()).unwrap();
+
+        matrix.to_file(file).unwrap();
+        let read = Matrix::<f64>::from_file(file).unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        let bits = |numbers: &[f64]| numbers.iter().map(|num| num.to_bits()).collect::<Vec<u64>>();
+        assert_eq!(bits(&read.get_numbers()), bits(&numbers));
+    }
+
+    #[test]
+    fn leading_comments_survive_conversion_to_csv_and_back() {
+        let (plain, csv, back) = (temp_path("comments.txt"), temp_path("comments.csv"),
+                                  temp_path("comments_back.txt"));
+        let header = "# exported by the lab pipeline\n# run 42\n#   seed=7\n";
+        std::fs::write(&plain, format!("{header}2\n2\n1 2\n3 4\n")).unwrap();
+
+        Matrix::<i64>::convert_file(&plain, &csv, Format::Csv).unwrap();
+        Matrix::<i64>::convert_file(&csv, &back, Format::PlainText).unwrap();
+        let (csv_text, back_text) = (std::fs::read_to_string(&csv).unwrap(), std::fs::read_to_string(&back).unwrap());
+
+        for file in [&plain, &csv, &back] {
+            std::fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(csv_text, format!("{header}1,2\n3,4\n"));
+        assert_eq!(back_text, format!("{header}2\n2\n1 2\n3 4\n"));
+    }
+
+    #[test]
+    fn interleaved_comments_are_collected_and_moved_to_the_top() {
+        let file = temp_path("interleaved.txt");
+        std::fs::write(&file, "# provenance\n2\n2\n1 2\n  # checked by hand\n3 4\n").unwrap();
+
+        let options = ParseOptions::new().comments(true).collect_comments(true);
+        let (matrix, metadata) = Matrix::<i64>::from_file_with_metadata(&file, &options).unwrap();
+        let (_, uncollected) = Matrix::<i64>::from_file_with_metadata(&file, &ParseOptions::new().comments(true)).unwrap();
+
+        matrix.write_with(file.as_str(), &WriteOptions::new().comments(metadata.comment_lines())).unwrap();
+        let rewritten = std::fs::read_to_string(&file).unwrap();
+        std::fs::write(&file, "# provenance\n2\n2\n1 2\n  # checked by hand\n3 4\n").unwrap();
+        Matrix::<i64>::canonicalize_file(&file).unwrap();
+        let canonical = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(metadata.comments, vec![Comment { line: 1, text: String::from("# provenance") },
+                                           Comment { line: 5, text: String::from("# checked by hand") }]);
+        assert_eq!(uncollected, FileMetadata::default());
+        assert_eq!(rewritten, "# provenance\n# checked by hand\n2\n2\n1 2\n3 4\n");
+        assert_eq!(canonical, rewritten);
+    }
+
+    #[test]
+    fn canonicalized_file_is_byte_stable() {
+        let file = &temp_path("canonical.txt");
+
+        std::fs::write(file, "# from the old exporter\n2\n 2\n1.000000 2.50  \n-0.0e0 0.30000000000000004\n").unwrap();
+
+        Matrix::<f64>::canonicalize_file(file).unwrap();
+        let canonical = std::fs::read_to_string(file).unwrap();
+
+        Matrix::<f64>::canonicalize_file(file).unwrap();
+        let recanonicalized = std::fs::read_to_string(file).unwrap();
+        Matrix::<f64>::from_file(file).unwrap().to_file(file).unwrap();
+        let rewritten = std::fs::read_to_string(file).unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(canonical, "# from the old exporter\n2\n2\n1 2.5\n-0 0.30000000000000004\n");
+        assert_eq!(recanonicalized, canonical);
+        assert_eq!(rewritten, "2\n2\n1 2.5\n-0 0.30000000000000004\n");
+    }
+
+    #[test]
+    fn integral_floats_write_as_integers() {
+        let file = &temp_path("integers.txt");
+        let matrix = Matrix::new(2, 3, vec![3.0, -0.0, 1e15, -7.000000001, 2.0, 0.999999999]).unwrap();
+
+        matrix.to_file_as_integers(file, 1e-6).unwrap();
+        let text = std::fs::read_to_string(file).unwrap();
+        let read = Matrix::<i64>::from_file(file).unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        assert!(!text.contains('.'));
+        assert_eq!(read, Matrix::new(2, 3, vec![3, 0, 1_000_000_000_000_000, -7, 2, 1]).unwrap());
+        assert_eq!(matrix.to_integer_matrix(1e-6), Ok(read));
+    }
+
+    #[test]
+    fn non_integral_float_names_its_position() {
+        let file = &temp_path("not_integers.txt");
+        let matrix = Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 2.5]).unwrap();
+
+        assert_eq!(matrix.to_file_as_integers(file, 1e-9).unwrap_err(), "Element at (1, 1) is 2.5, not within 0.000000001 of an integer");
+        assert!(!std::path::Path::new(file).exists());
+        assert!(Matrix::new(1, 2, vec![1.0, f64::NAN]).unwrap().to_integer_matrix(0.5).is_err());
+        assert!(Matrix::new(1, 1, vec![1e19]).unwrap().to_integer_matrix(0.5).is_err());
+    }
+
+    #[test]
+    fn overflowing_literals_follow_the_policy() {
+        let file = &temp_path("overflow.txt");
+        std::fs::write(file, "# exported\n2\n2\n1 1e999\n1e-999 -2\n").unwrap();
+
+        let read = |overflow| Matrix::<f64>::from_file_with_warnings(file, &ParseOptions::new().overflow(overflow));
+        let strict = read(OverflowPolicy::Error);
+        let (saturated, warnings) = read(OverflowPolicy::SaturateAndWarn).unwrap();
+        let (allowed, allowed_warnings) = read(OverflowPolicy::Allow).unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(strict.unwrap_err(), "Error parsing 0 row: '1e999' is not a valid f64 (overflows to infinity, rejected by OverflowPolicy::Error)");
+        assert_eq!(saturated, Matrix::new(2, 2, vec![1.0, f64::MAX, 0.0, -2.0]).unwrap());
+        assert_eq!(warnings, vec![
+            ParseWarning { line: 4, column: 2, token: String::from("1e999"), kind: OutOfRange::Overflow },
+            ParseWarning { line: 5, column: 1, token: String::from("1e-999"), kind: OutOfRange::Underflow },
+        ]);
+        assert_eq!(warnings[1].to_string(), "line 5, column 1: '1e-999' underflows to zero");
+        assert_eq!(allowed, Matrix::new(2, 2, vec![1.0, f64::INFINITY, 0.0, -2.0]).unwrap());
+        assert!(allowed_warnings.is_empty());
+    }
+
+    #[test]
+    fn partial_parse_keeps_rows_before_the_broken_one() {
+        let file = &temp_path("partial.txt");
+        std::fs::write(file, "# corrupted copy\n5\n2\n1 2\n3 4\n5 6\n7 x\n9 10\n").unwrap();
+
+        let partial = Matrix::<i32>::from_file_partial(file, &ParseOptions::new().partial_ok(true));
+        let strict = Matrix::<i32>::from_file_partial(file, &ParseOptions::new());
+        let plain = Matrix::<i32>::from_file_with(file, &ParseOptions::new().partial_ok(true));
+        std::fs::remove_file(file).unwrap();
+
+        let message = String::from("Error parsing 3 row: 'x' is not a valid i32");
+        assert_eq!(partial, Ok(ParseOutcome::Partial(PartialParse {
+            matrix: Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap(),
+            error: ParseError { line: 7, message: message.clone() },
+        })));
+        assert_eq!(strict, Err(message.clone()));
+        assert_eq!(plain, Err(message));
+
+        let short = Matrix::<i32>::from_vec_with(vec!["3", "2", "1 2"], &ParseOptions::new());
+        assert_eq!(short.unwrap_err(), "Not enough rows: 1");
+    }
+
+    #[test]
+    fn partial_parse_of_complete_and_truncated_input() {
+        let file = &temp_path("truncated.txt");
+        let options = ParseOptions::new().partial_ok(true);
+
+        std::fs::write(file, "2\n2\n1 2\n3 4\n").unwrap();
+        let complete = Matrix::<i32>::from_file_partial(file, &options);
+        std::fs::write(file, "2\n3\ncolumn-major\n1 2\n").unwrap();
+        let truncated = Matrix::<i32>::from_file_partial(file, &options);
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(complete, Ok(ParseOutcome::Complete(Matrix::new(2, 2, vec![1, 2, 3, 4]).unwrap())));
+        match truncated.unwrap() {
+            ParseOutcome::Partial(partial) => {
+                assert_eq!(partial.matrix, Matrix::new(2, 1, vec![1, 2]).unwrap());
+                assert_eq!(partial.error.to_string(), "line 5: Not enough columns: 1");
+            },
+            outcome => panic!("expected a partial parse, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn strided_buffers_round_trip() {
+        let padded = vec![1, 2, 3, -1, -1, 4, 5, 6, -1, -1, 7, 8, 9];
+        let matrix = Matrix::from_strided(3, 3, 5, &padded).unwrap();
+
+        assert_eq!(matrix, Matrix::new(3, 3, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap());
+
+        let mut out = vec![-1; 13];
+        matrix.copy_into_strided(&mut out, 5).unwrap();
+        assert_eq!(out, padded);
+
+        let dense = vec![1.5, 2.5, 3.5, 4.5, 5.5, 6.5];
+        assert_eq!(Matrix::from_strided(2, 3, 3, &dense), Matrix::new(2, 3, dense.clone()));
+        assert_eq!(Matrix::<i32>::from_strided(0, 3, 4, &[]), Matrix::new(0, 3, vec![]));
+    }
+
+    #[test]
+    fn strided_buffers_are_validated() {
+        let matrix = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(Matrix::from_strided(3, 2, 4, &[0; 9]).unwrap_err(),
+                   "Buffer of 9 elements is too short for 3 rows of 2 with stride 4, needs 10");
+        assert_eq!(Matrix::from_strided(2, 3, 2, &[0; 9]).unwrap_err(), "Row stride 2 is smaller than the 3 columns");
+        assert_eq!(matrix.copy_into_strided(&mut [0; 7], 5).unwrap_err(),
+                   "Buffer of 7 elements is too short for 2 rows of 3 with stride 5, needs 8");
+    }
+
+    #[test]
+    fn numbers_handle_counts_as_shared() {
+        let matrix = Matrix::new(1, 2, vec![1, 2]).unwrap();
+        assert!(matrix.is_uniquely_owned());
+
+        let numbers = matrix.get_numbers();
+        assert_eq!((matrix.is_uniquely_owned(), matrix.shared_count()), (false, 2));
+
+        drop(numbers);
+        assert!(matrix.is_uniquely_owned());
+    }
+
+    #[test]
+    fn header_over_the_element_limit_is_rejected_before_the_data() {
+        let contents = ["100000", "100000", "this row would not parse"];
+
+        let error = Matrix::<f64>::from_iterator(contents.into_iter()).unwrap_err();
+
+        assert_eq!(error, "100000x100000 matrix has 10000000000 elements, over the max_elements limit of 2147483648; \
+                           raise it with Limits::max_elements or --max-elements");
+    }
+
+    #[test]
+    fn raised_limits_accept_what_lower_ones_reject() {
+        let path = &temp_path("limits.txt");
+        std::fs::write(path, "2\n3\n1 2 3\n4 5 6\n").unwrap();
+
+        let tight = Matrix::<i64>::from_file_with(path, &ParseOptions::new().limits(Limits::new().max_elements(5)));
+        let raised = Matrix::<i64>::from_file_with(path, &ParseOptions::new().limits(Limits::new().max_elements(6)));
+        let small_files = Matrix::<i64>::from_file_with(path, &ParseOptions::new().limits(Limits::new().max_file_bytes(8)));
+        std::fs::remove_file(path).unwrap();
+
+        assert!(tight.unwrap_err().contains("over the max_elements limit of 5"));
+        assert_eq!(raised, Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(small_files.unwrap_err(), format!("{}: File has 16 bytes, over the max_file_bytes limit of 8; \
+                                                      raise it with Limits::max_file_bytes", path));
+    }
+}
@@ -1,148 +1,916 @@
-use std::ops::{AddAssign, Mul};
-use std::sync::{Arc, Mutex};
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::ops::{AddAssign, Mul, Range};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
 use std::thread;
+use std::time::Duration;
+use crate::element::{Element, ElementType, IntegerElement};
+use crate::human::format_bytes;
+use crate::io::Format;
+use crate::limits::{LimitExceeded, Limits};
 use crate::matrix::Matrix;
+use crate::parallel::{assemble_into, parallel_over_rows, run_units, ResultAssembler, RowPartition};
+use crate::pipelined;
+use crate::plan::Plan;
+use crate::shape::{mismatch_message, Shape};
+use crate::workspace::{Buffers, Workspace};
+
+/// Largest subproblem dimension the cache-oblivious kernel hands to the scalar loop.
+const CACHE_OBLIVIOUS_BASE: usize = 32;
+
+/// Inner loop used by [`multiply_with_options`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Kernel {
+    /// Row by row dot products, the original kernel.
+    #[default]
+    Naive,
+    /// Recursively halves the largest dimension so subproblems fit in cache
+    /// at every level, without a tuned block size.
+    CacheOblivious,
+}
+
+impl Kernel {
+    pub fn from_name(name: &str) -> Result<Kernel, String> {
+        match name {
+            "naive" => Ok(Kernel::Naive),
+            "cache-oblivious" => Ok(Kernel::CacheOblivious),
+            _ => Err(format!("Unknown kernel {name}, expected naive or cache-oblivious"))
+        }
+    }
+}
+
+impl Display for Kernel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kernel::Naive => write!(f, "naive"),
+            Kernel::CacheOblivious => write!(f, "cache-oblivious"),
+        }
+    }
+}
+
+/// Knobs for [`multiply_with_options`]; [`MultiplyOptions::new`] gives the
+/// behaviour of plain [`multiply`].
+#[derive(Clone, Debug)]
+pub struct MultiplyOptions<T = f64> {
+    pub num_of_threads: usize,
+    /// Wall-clock limit after which the workers are asked to stop; a zero
+    /// timeout stops them before the first row.
+    pub timeout: Option<Duration>,
+    /// Diagonal W folded into the inner loop, computing A·W·B without building W.
+    pub inner_weights: Option<Arc<Vec<T>>>,
+    pub kernel: Kernel,
+    /// Flag another thread can set to stop the workers early, see [`multiply_cancellable`].
+    pub cancel_token: Option<Arc<AtomicBool>>,
+    /// Returns (A·B)ᵀ instead, see [`multiply_transpose_result`]. Always uses
+    /// the naive kernel.
+    pub transpose_result: bool,
+    /// Stops the workers with [`MultiplyError::BoundExceeded`] as soon as a
+    /// finished output element is larger in magnitude. Always uses the naive
+    /// kernel.
+    pub abort_if_exceeds: Option<MagnitudeBound<T>>,
+    /// Largest output allowed, checked before anything is allocated for it.
+    pub limits: Limits,
+}
+
+impl<T> MultiplyOptions<T> {
+    pub fn new(num_of_threads: usize) -> MultiplyOptions<T> {
+        MultiplyOptions { num_of_threads, timeout: None, inner_weights: None, kernel: Kernel::Naive, cancel_token: None,
+            transpose_result: false, abort_if_exceeds: None, limits: Limits::default() }
+    }
+
+    /// The kernel that actually runs: [`Kernel::CacheOblivious`] falls back to
+    /// [`Kernel::Naive`] for a transposed or bounded product.
+    pub fn effective_kernel(&self) -> Kernel {
+        match self.kernel {
+            Kernel::CacheOblivious if !self.transpose_result && self.abort_if_exceeds.is_none() => Kernel::CacheOblivious,
+            _ => Kernel::Naive,
+        }
+    }
+}
+
+/// Largest magnitude allowed in the output, see [`MultiplyOptions::abort_if_exceeds`].
+#[derive(Clone, Copy, Debug)]
+pub struct MagnitudeBound<T> {
+    pub bound: f64,
+    to_f64: fn(&T) -> f64,
+}
+
+impl<T: Element> MagnitudeBound<T> {
+    pub fn new(bound: f64) -> MagnitudeBound<T> {
+        MagnitudeBound { bound, to_f64: |num| num.to_f64() }
+    }
+}
+
+impl<T> MagnitudeBound<T> {
+    /// The element as f64 if its magnitude is over the bound.
+    fn exceeded_by(&self, num: &T) -> Option<f64> {
+        let value = (self.to_f64)(num);
+        (value.abs() > self.bound).then_some(value)
+    }
+
+    /// Fails with [`MultiplyError::BoundExceeded`] for the first element of an
+    /// already computed `matrix`, in row-major order, whose magnitude is over the bound.
+    pub(crate) fn check(&self, matrix: &Matrix<T>) -> Result<(), MultiplyError> {
+        match matrix.as_slice().iter().enumerate().find_map(|(i, num)| self.exceeded_by(num).map(|value| (i, value))) {
+            Some((i, value)) => Err(MultiplyError::BoundExceeded {
+                row: i / matrix.columns(), column: i % matrix.columns(), value, bound: self.bound }),
+            None => Ok(())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MultiplyError {
+    /// B's expected shape given A, and the shape it actually has.
+    DimensionMismatch { expected: Shape, actual: Shape },
+    TooManyThreads { num_of_threads: usize, rows: usize },
+    /// The inner weights don't have one entry per column of A.
+    WeightsLength { expected: usize, actual: usize },
+    NoThreads,
+    /// The timeout was hit; `rows_completed` output rows had been finished by then.
+    TimedOut { rows_completed: usize, total_rows: usize },
+    /// The cancel token was set; `rows_completed` output rows had been finished by then.
+    Cancelled { rows_completed: usize, total_rows: usize },
+    /// A checked integer product overflowed while computing this output element.
+    Overflow { row: usize, column: usize },
+    /// The elements of a `shape` result couldn't be allocated.
+    AllocationFailed { requested_bytes: u64, shape: Shape },
+    /// Output element (`row`, `column`) came out as `value`, over the
+    /// [`MultiplyOptions::abort_if_exceeds`] bound.
+    BoundExceeded { row: usize, column: usize, value: f64, bound: f64 },
+    /// The output is over [`MultiplyOptions::limits`].
+    LimitExceeded(LimitExceeded),
+    Internal(String),
+}
+
+impl Display for MultiplyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultiplyError::DimensionMismatch { expected, actual } =>
+                write!(f, "{}", mismatch_message(*expected, *actual)),
+            MultiplyError::TooManyThreads { num_of_threads, rows } =>
+                write!(f, "Num of threads: {num_of_threads} cannot be higher than Matrix A rows: {rows}"),
+            MultiplyError::WeightsLength { expected, actual } =>
+                write!(f, "Weights length: {actual} doesn't match inner dimension: {expected}"),
+            MultiplyError::NoThreads =>
+                write!(f, "Num of threads: 0 must be higher than 0"),
+            MultiplyError::TimedOut { rows_completed, total_rows } =>
+                write!(f, "Timed out after completing {rows_completed} of {total_rows} rows"),
+            MultiplyError::Cancelled { rows_completed, total_rows } =>
+                write!(f, "Interrupted after {rows_completed} of {total_rows} rows"),
+            MultiplyError::Overflow { row, column } =>
+                write!(f, "Overflow while computing element ({row}, {column})"),
+            MultiplyError::AllocationFailed { requested_bytes, shape } =>
+                write!(f, "Not enough memory for the {shape} result: couldn't allocate {}", format_bytes(*requested_bytes)),
+            MultiplyError::BoundExceeded { row, column, value, bound } =>
+                write!(f, "Element ({row}, {column}) is {value}, over the magnitude bound {bound}"),
+            MultiplyError::LimitExceeded(error) => write!(f, "Result is too large: {}", error),
+            MultiplyError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MultiplyError {}
+
+impl From<MultiplyError> for String {
+    fn from(error: MultiplyError) -> String {
+        error.to_string()
+    }
+}
 
 pub fn multiply<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, String>
     where
         for<'a> &'a T: Mul<Output=T>,
         T: AddAssign<T> + Sync + Send + 'static {
 
-    if matrix_a.get_columns() != matrix_b.get_rows() {
-        return Err(format!("A columns: {} and B rows: {} don't match!",
-                           matrix_a.get_columns(), matrix_b.get_rows()));
-    }
+    Ok(multiply_with_options(matrix_a, matrix_b, &MultiplyOptions::new(num_of_threads))?)
+}
+
+/// Computes (A·B)ᵀ in the same single pass as [`multiply`]: the workers split
+/// the columns of B instead of the rows of A, so each one produces whole rows
+/// of the transposed result and nothing is transposed afterwards.
+pub fn multiply_transpose_result<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
 
-    let rows = matrix_a.get_rows();
+    let mut options = MultiplyOptions::new(num_of_threads);
+    options.transpose_result = true;
 
-    let columns = matrix_b.get_columns();
+    Ok(multiply_with_options(matrix_a, matrix_b, &options)?)
+}
+
+/// Computes A·W·B where W is the diagonal matrix of `weights`, folding each
+/// weight into the inner product instead of materializing W or a scaled B.
+pub fn multiply_weighted<T>(matrix_a: &Matrix<T>, weights: &[T], matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Clone + Sync + Send + 'static {
+
+    let mut options = MultiplyOptions::new(num_of_threads);
+    options.inner_weights = Some(Arc::new(weights.to_vec()));
+
+    Ok(multiply_with_options(matrix_a, matrix_b, &options)?)
+}
+
+/// Computes [X | 1]·W, the product with a ones-column appended to X, without
+/// copying X: the last row of W is the bias and starts every accumulator.
+pub fn multiply_affine<T>(x: &Matrix<T>, w: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Clone + Sync + Send + 'static {
+
+    let (rows, n, columns) = (x.rows(), x.columns(), w.columns());
+
+    if w.rows() != n + 1 {
+        return Err(MultiplyError::DimensionMismatch {
+            expected: Shape { rows: n + 1, cols: columns },
+            actual: w.shape() }.into())
+    }
 
     if num_of_threads > rows {
-        return Err(format!(
-            "Num of threads: {num_of_threads} cannot be higher than Matrix A rows: {rows}"))
+        return Err(MultiplyError::TooManyThreads { num_of_threads, rows }.into())
     }
 
     if num_of_threads == 0 {
-        return Err(format!(
-            "Num of threads: {num_of_threads} must be higher than 0"))
+        return Err(MultiplyError::NoThreads.into())
+    }
+
+    let (x_numbers, w_numbers) = (x.as_slice(), w.as_slice());
+    let bias = &w_numbers[n * columns..];
+
+    let numbers = parallel_over_rows(rows, num_of_threads, |rows| {
+        let mut result = Vec::with_capacity(rows.len() * columns);
+        for row in rows {
+            for column in 0..columns {
+                let mut sum = bias[column].clone();
+                for k in 0..n {
+                    sum += &x_numbers[row * n + k] * &w_numbers[k * columns + column];
+                }
+                result.push(sum);
+            }
+        }
+        result
+    }, |results| results.into_iter().flatten().collect())?;
+
+    Matrix::new(rows, columns, numbers)
+}
+
+/// Computes only the columns of A·B listed in `column_indices`, in that order
+/// and repeated where an index is, reading them straight from B instead of
+/// copying them out first as [`Matrix::select_columns`] would.
+pub fn multiply_selected_columns<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, column_indices: &[usize],
+                                    num_of_threads: usize) -> Result<Matrix<T>, String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    validate(matrix_a, matrix_b, num_of_threads)?;
+
+    let (rows, n, columns) = (matrix_a.rows(), matrix_a.columns(), matrix_b.columns());
+
+    if let Some(column) = column_indices.iter().find(|&&column| column >= columns) {
+        return Err(format!("Column index {} is out of bounds for B: {}", column, matrix_b.shape()))
     }
 
-    let results_from_threads = match multiply_in_parallel(matrix_a, matrix_b, num_of_threads) {
-        Ok(results) => results,
-        Err(error) => return Err(error)
+    let (a_numbers, b_numbers) = (matrix_a.as_slice(), matrix_b.as_slice());
+
+    let numbers = parallel_over_rows(rows, num_of_threads, |rows| {
+        let mut result = Vec::with_capacity(rows.len() * column_indices.len());
+        for row in rows {
+            for &column in column_indices {
+                let mut sum = &a_numbers[row * n] * &b_numbers[column];
+                for k in 1..n {
+                    sum += &a_numbers[row * n + k] * &b_numbers[k * columns + column];
+                }
+                result.push(sum);
+            }
+        }
+        result
+    }, |results| results.into_iter().flatten().collect())?;
+
+    Matrix::new(rows, column_indices.len(), numbers)
+}
+
+pub fn multiply_with_options<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, options: &MultiplyOptions<T>) -> Result<Matrix<T>, MultiplyError>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    multiply_with_buffers(matrix_a, matrix_b, options, &mut Buffers::default())
+}
+
+/// Like [`multiply_with_options`], computing into the output and scratch
+/// buffers of `buffers`; the output is moved into the result.
+pub(crate) fn multiply_with_buffers<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, options: &MultiplyOptions<T>,
+                                       buffers: &mut Buffers<T>) -> Result<Matrix<T>, MultiplyError>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    validate(matrix_a, matrix_b, options.num_of_threads)?;
+
+    options.limits.check_shape(Shape { rows: matrix_a.rows(), cols: matrix_b.columns() })
+        .map_err(MultiplyError::LimitExceeded)?;
+
+    if let Some(weights) = &options.inner_weights {
+        if weights.len() != matrix_a.columns() {
+            return Err(MultiplyError::WeightsLength { expected: matrix_a.columns(), actual: weights.len() })
+        }
+    }
+
+    let rows = matrix_a.rows();
+
+    let columns = matrix_b.columns();
+
+    let num_of_threads = options.num_of_threads;
+
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let rows_completed = Arc::new(AtomicUsize::new(0));
+
+    let monitor = match options.timeout {
+        // A zero timeout has run out before the first row, with no race against a monitor thread.
+        Some(timeout) if timeout.is_zero() => {
+            timed_out.store(true, Ordering::SeqCst);
+            None
+        },
+        timeout => timeout.map(|timeout| spawn_timeout_monitor(timeout, Arc::clone(&timed_out))),
     };
 
-    if let Ok(mutex) = Arc::try_unwrap(results_from_threads) {
-        if let Ok(vector) = mutex.into_inner() {
-            let vector: Vec<T> = vector.into_iter().flat_map(|vec| vec).collect();
-            Ok(Matrix::new(rows, columns, vector).unwrap())
+    let exceeded = OnceLock::new();
+    let cancelled = Cancellation { timed_out: &timed_out, token: options.cancel_token.as_deref(), exceeded: &exceeded };
+
+    let numbers = match options.effective_kernel() {
+        Kernel::CacheOblivious => {
+            let input = CacheObliviousInput {
+                a: matrix_a.as_slice(),
+                b: matrix_b.as_slice(),
+                weights: options.inner_weights.as_ref().map(|weights| weights.as_slice()),
+                n: matrix_a.columns(),
+                columns,
+                shape: Shape { rows, cols: columns },
+                cancelled: &cancelled,
+                rows_completed: &rows_completed,
+            };
+            multiply_cache_oblivious(&input, 0..rows, num_of_threads)
+        },
+        Kernel::Naive => multiply_in_parallel(matrix_a, matrix_b, options, &cancelled, &rows_completed, buffers),
+    };
+
+    if let Some((done, handle)) = monitor {
+        drop(done);
+        let _ = handle.join();
+    }
+
+    let numbers = numbers?;
+
+    if let (Some(&(row, column, value)), Some(bound)) = (exceeded.get(), &options.abort_if_exceeds) {
+        return Err(MultiplyError::BoundExceeded { row, column, value, bound: bound.bound })
+    }
+
+    let rows_completed = rows_completed.load(Ordering::SeqCst);
+    let (result_rows, result_columns) = if options.transpose_result { (columns, rows) } else { (rows, columns) };
+
+    if rows_completed < result_rows {
+        return Err(if timed_out.load(Ordering::SeqCst) {
+            MultiplyError::TimedOut { rows_completed, total_rows: result_rows }
         } else {
-            Err(String::from("Error acquiring mutex in main thread"))
-        }
-    } else {
-        Err(String::from("Error unwrapping results in main thread"))
+            MultiplyError::Cancelled { rows_completed, total_rows: result_rows }
+        })
     }
+
+    Ok(Matrix::new(result_rows, result_columns, numbers).unwrap())
 }
 
-fn multiply_in_parallel<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<Arc<Mutex<Vec<Vec<T>>>>, String>
+/// Plain product that stops early with [`MultiplyError::Cancelled`] once
+/// `token` is set, for example from a signal handler.
+pub fn multiply_cancellable<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize,
+                               token: &Arc<AtomicBool>) -> Result<Matrix<T>, MultiplyError>
     where
         for<'a> &'a T: Mul<Output=T>,
         T: AddAssign<T> + Sync + Send + 'static {
 
-    let rows = matrix_a.get_rows();
-    let columns = matrix_b.get_columns();
-    let n = matrix_a.get_columns();
+    let mut options = MultiplyOptions::new(num_of_threads);
+    options.cancel_token = Some(Arc::clone(token));
 
-    let rows_for_threads = generate_indexes_for_threads(num_of_threads, rows);
+    multiply_with_options(matrix_a, matrix_b, &options)
+}
 
-    let results_from_threads: Arc<Mutex<Vec<Vec<T>>>> =
-        Arc::new(Mutex::new(Vec::with_capacity(num_of_threads)));
+/// Stop signals checked by the workers between rows.
+struct Cancellation<'a> {
+    timed_out: &'a AtomicBool,
+    token: Option<&'a AtomicBool>,
+    /// The first element found over the magnitude bound, as (row, column, value).
+    exceeded: &'a OnceLock<(usize, usize, f64)>,
+}
 
-    for _ in 0..num_of_threads {
-        results_from_threads.lock().unwrap().push(vec![])
+impl Cancellation<'_> {
+    fn is_set(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed) || self.token.is_some_and(|token| token.load(Ordering::Relaxed))
+            || self.exceeded.get().is_some()
+    }
+}
+
+/// An empty buffer with room for exactly `len` elements of a `shape` result,
+/// failing with [`MultiplyError::AllocationFailed`] instead of aborting.
+pub(crate) fn try_buffer<T>(len: usize, shape: Shape) -> Result<Vec<T>, MultiplyError> {
+    let mut buffer = Vec::new();
+    try_reserve(&mut buffer, len, shape)?;
+    Ok(buffer)
+}
+
+/// Empties `buffer` and makes room for `len` elements, reusing its capacity.
+fn try_reserve<T>(buffer: &mut Vec<T>, len: usize, shape: Shape) -> Result<(), MultiplyError> {
+    buffer.clear();
+    buffer.try_reserve_exact(len).map_err(|_| MultiplyError::AllocationFailed {
+        requested_bytes: (len as u64).saturating_mul(std::mem::size_of::<T>() as u64), shape })
+}
+
+/// The library functions stay strict about more threads than rows, so callers
+/// notice a thread count that can't be used; [`run`] clamps it instead.
+pub(crate) fn validate<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<(), MultiplyError> {
+    if matrix_a.columns() != matrix_b.rows() {
+        return Err(MultiplyError::DimensionMismatch {
+            expected: Shape { rows: matrix_a.columns(), cols: matrix_b.columns() },
+            actual: matrix_b.shape() });
     }
 
-    let a_numbers = matrix_a.get_numbers();
-    let b_numbers = matrix_b.get_numbers();
+    let rows = matrix_a.rows();
 
-    let mut handles = Vec::with_capacity(num_of_threads);
+    if num_of_threads > rows {
+        return Err(MultiplyError::TooManyThreads { num_of_threads, rows })
+    }
+
+    if num_of_threads == 0 {
+        return Err(MultiplyError::NoThreads)
+    }
+
+    Ok(())
+}
+
+/// Integer product that fails with [`MultiplyError::Overflow`] naming the first
+/// element whose computation overflowed, instead of panicking or wrapping.
+pub fn multiply_checked<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, MultiplyError>
+    where T: IntegerElement + Sync + Send {
 
-    for i in 0..num_of_threads {
-        let a_numbers = Arc::clone(&a_numbers);
-        let b_numbers = Arc::clone(&b_numbers);
+    multiply_integers(matrix_a, matrix_b, num_of_threads, true)
+}
+
+/// Integer product with two's complement wrapping on overflow.
+pub fn multiply_wrapping<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize) -> Result<Matrix<T>, MultiplyError>
+    where T: IntegerElement + Sync + Send {
+
+    multiply_integers(matrix_a, matrix_b, num_of_threads, false)
+}
 
-        let start_row = rows_for_threads[i];
-        let end_row = rows_for_threads[i+1];
+fn multiply_integers<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize, checked: bool) -> Result<Matrix<T>, MultiplyError>
+    where T: IntegerElement + Sync + Send {
 
-        let results_from_threads = Arc::clone(&results_from_threads);
+    validate(matrix_a, matrix_b, num_of_threads)?;
 
-        let handle = thread::spawn(move || {
-            let mut result = Vec::with_capacity((end_row - start_row) * columns);
-            for row in start_row..end_row {
-                for column in 0..columns {
-                    let mut sum = &a_numbers[row * n] * &b_numbers[column];
-                    for k in 1..n {
-                        sum += &a_numbers[row * n + k] * &b_numbers[k * columns + column];
-                    }
-                    result.push(sum);
+    let rows = matrix_a.rows();
+    let columns = matrix_b.columns();
+    let n = matrix_a.columns();
+
+    let (a_numbers, b_numbers) = (matrix_a.as_slice(), matrix_b.as_slice());
+
+    let shape = Shape { rows, cols: columns };
+
+    let numbers = parallel_over_rows(rows, num_of_threads, |rows| {
+        let mut result = try_buffer(rows.len() * columns, shape)?;
+        for row in rows {
+            for column in 0..columns {
+                let mut sum = T::ZERO;
+                for k in 0..n {
+                    let (a, b) = (a_numbers[row * n + k], b_numbers[k * columns + column]);
+                    sum = if checked {
+                        a.checked_mul(b)
+                            .and_then(|product| sum.checked_add(product))
+                            .ok_or(MultiplyError::Overflow { row, column })?
+                    } else {
+                        sum.wrapping_add(a.wrapping_mul(b))
+                    };
                 }
+                result.push(sum);
             }
-            let mut results = results_from_threads.lock().expect(
-                format!("Error acquiring mutex lock for thread {i}").as_str());
-            results[i] = result;
-        });
+        }
+        Ok(result)
+    }, |results| concat_results(results, rows * columns, shape)).map_err(MultiplyError::Internal)??;
+
+    Ok(Matrix::new(rows, columns, numbers).unwrap())
+}
+
+/// Flips `cancelled` once `timeout` elapses, unless the returned sender is
+/// dropped first to signal that the work is done.
+fn spawn_timeout_monitor(timeout: Duration, cancelled: Arc<AtomicBool>) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+    let (done, wait) = mpsc::channel::<()>();
+
+    let handle = thread::spawn(move || {
+        if let Err(mpsc::RecvTimeoutError::Timeout) = wait.recv_timeout(timeout) {
+            cancelled.store(true, Ordering::SeqCst);
+        }
+    });
+
+    (done, handle)
+}
+
+/// Splits the output rows between the threads; with `transpose_result` the
+/// output is (A·B)ᵀ, whose rows are the columns of A·B.
+fn multiply_in_parallel<T>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, options: &MultiplyOptions<T>,
+                           cancelled: &Cancellation, rows_completed: &AtomicUsize, buffers: &mut Buffers<T>)
+    -> Result<Vec<T>, MultiplyError>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    let weights = options.inner_weights.as_ref().map(|weights| weights.as_slice());
+    let (transpose_result, bound) = (options.transpose_result, options.abort_if_exceeds.as_ref());
+
+    let columns = matrix_b.columns();
+    let n = matrix_a.columns();
+
+    let (a_numbers, b_numbers) = (matrix_a.as_slice(), matrix_b.as_slice());
+
+    let (output_rows, output_columns) = if transpose_result {
+        (columns, matrix_a.rows())
+    } else {
+        (matrix_a.rows(), columns)
+    };
+
+    let shape = Shape { rows: output_rows, cols: output_columns };
+    let Buffers { output, scratch, .. } = buffers;
+    let scratch = &*scratch;
+
+    let partition = RowPartition::new(shape, options.num_of_threads);
+
+    let results = run_units(&partition, options.num_of_threads, |unit| {
+        let mut result = scratch.lock().map(|mut bands| bands.pop()).ok().flatten().unwrap_or_default();
+        try_reserve(&mut result, unit.rows.len() * unit.columns.len(), shape)?;
+        for output_row in unit.rows.clone() {
+            if cancelled.is_set() {
+                break;
+            }
+            for output_column in unit.columns.clone() {
+                let (row, column) = if transpose_result { (output_column, output_row) } else { (output_row, output_column) };
+                let sum = match weights {
+                    None => {
+                        let mut sum = &a_numbers[row * n] * &b_numbers[column];
+                        for k in 1..n {
+                            sum += &a_numbers[row * n + k] * &b_numbers[k * columns + column];
+                        }
+                        sum
+                    },
+                    Some(weights) => {
+                        let mut sum = &(&a_numbers[row * n] * &weights[0]) * &b_numbers[column];
+                        for k in 1..n {
+                            sum += &(&a_numbers[row * n + k] * &weights[k]) * &b_numbers[k * columns + column];
+                        }
+                        sum
+                    },
+                };
+                if let Some(value) = bound.and_then(|bound| bound.exceeded_by(&sum)) {
+                    let _ = cancelled.exceeded.set((row, column, value));
+                }
+                result.push(sum);
+            }
+            rows_completed.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(result)
+    }).map_err(MultiplyError::Internal)?;
+
+    let mut parts = results.into_iter().collect::<Result<Vec<Vec<T>>, MultiplyError>>()?;
+
+    // A worker stopped early, so some rows are missing and the caller reports why.
+    if partition.units().iter().zip(&parts).any(|(unit, part)| part.len() != unit.rows.len() * unit.columns.len()) {
+        return Ok(Vec::new())
+    }
+
+    assemble_parts_into(&partition, &mut parts, output, scratch)?;
+    Ok(std::mem::take(output))
+}
+
+/// Places the parts of a result into `numbers` with [`assemble_into`], handing
+/// the emptied parts back to `scratch` for the next product.
+fn assemble_parts_into<T>(assembler: &impl ResultAssembler, parts: &mut [Vec<T>], numbers: &mut Vec<T>,
+                          scratch: &Mutex<Vec<Vec<T>>>) -> Result<(), MultiplyError> {
+    let shape = assembler.shape();
+    try_reserve(numbers, shape.rows * shape.cols, shape)?;
+    assemble_into(assembler, parts, numbers);
+
+    if let Ok(mut bands) = scratch.lock() {
+        bands.extend(parts.iter_mut().map(std::mem::take));
+    }
+    Ok(())
+}
+
+/// Joins the per-thread parts of a `shape` result into one buffer of `len`
+/// elements, or returns the first error.
+fn concat_results<T>(results: Vec<Result<Vec<T>, MultiplyError>>, len: usize, shape: Shape) -> Result<Vec<T>, MultiplyError> {
+    let mut numbers = Vec::new();
+    concat_results_into(results, &mut numbers, len, shape, &Mutex::new(vec![]))?;
+    Ok(numbers)
+}
+
+/// Like [`concat_results`], joining into `numbers` and handing the emptied
+/// parts back to `scratch` for the next product.
+fn concat_results_into<T>(results: Vec<Result<Vec<T>, MultiplyError>>, numbers: &mut Vec<T>, len: usize, shape: Shape,
+                          scratch: &Mutex<Vec<Vec<T>>>) -> Result<(), MultiplyError> {
+    try_reserve(numbers, len, shape)?;
+    for result in results {
+        let mut part = result?;
+        numbers.append(&mut part);
+        if let Ok(mut parts) = scratch.lock() {
+            parts.push(part);
+        }
+    }
+    Ok(())
+}
+
+struct CacheObliviousInput<'a, T> {
+    a: &'a [T],
+    b: &'a [T],
+    weights: Option<&'a [T]>,
+    n: usize,
+    columns: usize,
+    /// Shape of the whole result, for allocation errors.
+    shape: Shape,
+    cancelled: &'a Cancellation<'a>,
+    rows_completed: &'a AtomicUsize,
+}
+
+impl<T> CacheObliviousInput<'_, T>
+    where
+        for<'a> &'a T: Mul<Output=T> {
+
+    fn term(&self, row: usize, k: usize, column: usize) -> T {
+        let (a, b) = (&self.a[row * self.n + k], &self.b[k * self.columns + column]);
+        match self.weights {
+            None => a * b,
+            Some(weights) => &(a * &weights[k]) * b,
+        }
+    }
+}
+
+/// Computes the output rows in `rows`, handing the two halves of the row range
+/// to separate threads until every thread has a band of its own.
+fn multiply_cache_oblivious<T>(input: &CacheObliviousInput<T>, rows: Range<usize>, num_of_threads: usize)
+    -> Result<Vec<T>, MultiplyError>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send {
+
+    if num_of_threads > 1 && rows.len() > 1 {
+        let left_threads = num_of_threads / 2;
+        let middle = rows.start + rows.len() * left_threads / num_of_threads;
 
-        handles.push(handle);
+        return thread::scope(|scope| {
+            let left = scope.spawn(|| multiply_cache_oblivious(input, rows.start..middle, left_threads));
+            let right = multiply_cache_oblivious(input, middle..rows.end, num_of_threads - left_threads);
+
+            let mut left = left.join().unwrap_or_else(
+                |err| Err(MultiplyError::Internal(format!("Error joining thread, error:\n{err:?}"))))?;
+            left.append(&mut right?);
+            Ok(left)
+        })
     }
 
-    for (thread_num,handle) in handles.into_iter().enumerate() {
-        if let Err(err) = handle.join() {
-            return Err(format!("Error joining thread {thread_num}, error:\n{err:?}"))
+    // The first inner term initializes the band, the recursion accumulates the rest
+    // in increasing k, so every element is summed in the same order as the naive kernel.
+    let mut band = try_buffer(rows.len() * input.columns, input.shape)?;
+    for row in rows.clone() {
+        for column in 0..input.columns {
+            band.push(input.term(row, 0, column));
         }
     }
 
-    Ok(results_from_threads)
+    // Inner terms still to add to each row of the band; a row is finished, and
+    // counted, once its count reaches zero.
+    let terms_per_row = input.columns * input.n.saturating_sub(1);
+    let mut remaining = vec![terms_per_row; rows.len()];
+
+    let finished = recurse_cache_oblivious(input, &mut band, &mut remaining, rows.start, rows.clone(), 0..input.columns,
+                                           1..input.n);
+    if finished && terms_per_row == 0 {
+        input.rows_completed.fetch_add(rows.len(), Ordering::Relaxed);
+    }
+
+    Ok(band)
 }
 
-fn generate_indexes_for_threads(num_of_threads: usize, rows: usize) -> Vec<usize> {
+/// Adds the inner terms in `inner` to the given block of `band`, whose first
+/// row is output row `band_start`, counting every row whose `remaining` terms
+/// run out as completed. Returns false if cancelled.
+fn recurse_cache_oblivious<T>(input: &CacheObliviousInput<T>, band: &mut [T], remaining: &mut [usize], band_start: usize,
+                              rows: Range<usize>, columns: Range<usize>, inner: Range<usize>) -> bool
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> {
+
+    let (m, p, q) = (rows.len(), columns.len(), inner.len());
+
+    if m.max(p).max(q) <= CACHE_OBLIVIOUS_BASE {
+        if input.cancelled.is_set() {
+            return false
+        }
+        for row in rows {
+            for column in columns.clone() {
+                let c = &mut band[(row - band_start) * input.columns + column];
+                for k in inner.clone() {
+                    *c += input.term(row, k, column);
+                }
+            }
+
+            let left = &mut remaining[row - band_start];
+            *left -= p * q;
+            if *left == 0 && p * q > 0 {
+                input.rows_completed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        return true
+    }
+
+    if m >= p && m >= q {
+        let middle = rows.start + m / 2;
+        recurse_cache_oblivious(input, band, remaining, band_start, rows.start..middle, columns.clone(), inner.clone())
+            && recurse_cache_oblivious(input, band, remaining, band_start, middle..rows.end, columns, inner)
+    } else if p >= q {
+        let middle = columns.start + p / 2;
+        recurse_cache_oblivious(input, band, remaining, band_start, rows.clone(), columns.start..middle, inner.clone())
+            && recurse_cache_oblivious(input, band, remaining, band_start, rows, middle..columns.end, inner)
+    } else {
+        let middle = inner.start + q / 2;
+        recurse_cache_oblivious(input, band, remaining, band_start, rows.clone(), columns.clone(), inner.start..middle)
+            && recurse_cache_oblivious(input, band, remaining, band_start, rows, columns, middle..inner.end)
+    }
+}
+
+/// Multiplies `matrix_a` by `matrix_b` and returns the a-priori bound on the
+/// normwise relative rounding error of the result.
+///
+/// Every element satisfies |C - fl(C)| <= gamma_n * (|A|·|B|) with
+/// gamma_n = n·u / (1 - n·u), n the inner dimension and u the unit roundoff.
+/// The returned bound is gamma_n * max(|A|·|B|) / max|fl(C)|, infinite when the
+/// product is zero but |A|·|B| isn't.
+pub fn multiply_with_error_bound(matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, num_of_threads: usize)
+    -> Result<(Matrix<f64>, f64), String> {
+
+    let product = multiply(matrix_a, matrix_b, num_of_threads)?;
+    let bound = relative_error_bound(matrix_a, matrix_b, &product, &MultiplyOptions::new(num_of_threads))?;
+
+    Ok((product, bound))
+}
+
+/// The bound of [`multiply_with_error_bound`] for an already computed
+/// `product` of A and B. |A|·|B| is computed with `options`, so it honours
+/// the same kernel, cancel token and timeout, but never aborts on a magnitude
+/// bound, which it may exceed even where the product doesn't.
+pub fn relative_error_bound(matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, product: &Matrix<f64>,
+                            options: &MultiplyOptions<f64>) -> Result<f64, MultiplyError> {
+    let absolute_product = multiply_absolute(matrix_a, matrix_b, &MultiplyOptions { abort_if_exceeds: None, ..options.clone() })?;
+
+    let n = matrix_a.columns() as f64;
+    let unit_roundoff = f64::EPSILON / 2.0;
+
+    let gamma = if n * unit_roundoff < 1.0 {
+        n * unit_roundoff / (1.0 - n * unit_roundoff)
+    } else {
+        f64::INFINITY
+    };
+
+    let max_absolute = absolute_product.as_slice().iter().fold(0.0, |max: f64, num| max.max(*num));
+    let max_product = product.as_slice().iter().fold(0.0, |max: f64, num| max.max(num.abs()));
+
+    let bound = if max_absolute == 0.0 {
+        0.0
+    } else if max_product == 0.0 {
+        f64::INFINITY
+    } else {
+        gamma * max_absolute / max_product
+    };
+
+    Ok(bound)
+}
+
+/// |A|·|B| computed with the same threaded kernel.
+fn multiply_absolute(matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, options: &MultiplyOptions<f64>)
+    -> Result<Matrix<f64>, MultiplyError> {
+    let absolute = |matrix: &Matrix<f64>| matrix.map(|num| num.abs());
+
+    multiply_with_options(&absolute(matrix_a), &absolute(matrix_b), options)
+}
+
+/// Splits `0..rows` into contiguous ranges, one per thread, whose lengths
+/// differ by at most one with the longer ranges first: 7 rows over 3 threads
+/// are 3/2/2. With more threads than rows only `rows` one-row ranges are
+/// returned, so no range is ever empty.
+pub(crate) fn partition_rows(num_of_threads: usize, rows: usize) -> Vec<Range<usize>> {
+    let num_of_threads = num_of_threads.min(rows);
+
+    if num_of_threads == 0 {
+        return vec![]
+    }
+
     let rows_per_thread = rows / num_of_threads;
     let mut rest: usize = rows % num_of_threads;
 
-    let mut rows_for_threads = Vec::with_capacity(num_of_threads+1);
+    let mut ranges = Vec::with_capacity(num_of_threads);
 
     let mut current = 0;
 
-    rows_for_threads.push(0);
-
     for _ in 0..num_of_threads {
+        let start = current;
         current += rows_per_thread;
         if rest > 0 {
             current += 1;
             rest -= 1;
         }
-        rows_for_threads.push(current);
+        ranges.push(start..current);
     }
 
-    rows_for_threads
+    ranges
+}
+
+/// Whether `ranges` are non-empty, in order and cover `0..rows` exactly once.
+pub(crate) fn covers_rows_exactly(ranges: &[Range<usize>], rows: usize) -> bool {
+    ranges.iter().try_fold(0, |next, range| (range.start == next && range.end > range.start).then_some(range.end))
+        == Some(rows)
+}
+
+/// What [`run_with_stats`] actually did, after resolving defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiplyStats {
+    pub num_of_threads: usize,
+    pub kernel: Kernel,
+    /// Subnormal results replaced by zero, always 0 without `--flush-denormals`.
+    pub denormals_flushed: usize,
+    /// Largest absolute value in the output.
+    pub max_magnitude: f64,
 }
 
+/// Validates the whole configuration up front, reporting every problem at
+/// once, and then executes the resulting [`Plan`].
 pub fn run(config: Config) -> Result<(), String> {
-    let matrix_a = Matrix::<f64>::from_file(config.matrix_a_file_name.as_str())?;
-    let matrix_b = Matrix::<f64>::from_file(config.matrix_b_file_name.as_str())?;
+    run_with_stats(config).map(|_| ())
+}
 
-    let matrix_c = multiply(&matrix_a, &matrix_b, config.num_of_threads)?;
+pub fn run_with_stats(config: Config) -> Result<MultiplyStats, String> {
+    run_with_workspace(config, &mut Workspace::new())
+}
+
+/// Like [`run_with_stats`], reusing the buffers of `workspace` and leaving
+/// this run's in it for the next one.
+pub fn run_with_workspace(config: Config, workspace: &mut Workspace) -> Result<MultiplyStats, String> {
+    let plan = Plan::from_config(&config).map_err(|errors| {
+        errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+    })?;
+
+    if config.pipeline {
+        return pipelined::execute(&plan)
+    }
 
-    return matrix_c.to_file(config.matrix_c_file_name.as_str())
+    plan.execute_with(workspace)
 }
 
+/// Environment variable naming a tune profile, overridden by `--tune-profile`,
+/// see [`Config::with_default_tune_profile`].
+pub const TUNE_PROFILE_VAR: &str = "MM_TUNE_PROFILE";
+
+/// Environment variable that, set to `1`, lets `auto` use every core.
+pub const GREEDY_VAR: &str = "MATMUL_GREEDY";
+
+#[derive(Clone, Debug)]
 pub struct Config {
-    matrix_a_file_name: String,
-    matrix_b_file_name: String,
-    matrix_c_file_name: String,
-    num_of_threads: usize
+    pub(crate) matrix_a_file_name: String,
+    pub(crate) matrix_b_file_name: String,
+    pub(crate) matrix_c_file_name: String,
+    /// `None` when given as `auto`, to be picked from the tune profile.
+    pub(crate) num_of_threads: Option<usize>,
+    pub(crate) output_format: Format,
+    pub(crate) zero_tolerance: Option<f64>,
+    pub(crate) verbose: bool,
+    pub(crate) tune_profile: Option<String>,
+    /// Read from [`GREEDY_VAR`]; only matters when threads are `auto`.
+    pub(crate) greedy: bool,
+    pub(crate) flush_denormals: bool,
+    pub(crate) annotate: bool,
+    /// Overlap parsing, computing and writing, see [`run_pipelined`](crate::pipelined::run_pipelined).
+    pub(crate) pipeline: bool,
+    /// `None` for `--dtype auto`, probed from the input files.
+    pub(crate) element_type: Option<ElementType>,
+    /// Where to write a [`RunRecord`](crate::record::RunRecord) of the run.
+    pub(crate) record: Option<String>,
+    /// Fail instead of writing an output with a larger element, see
+    /// [`MultiplyOptions::abort_if_exceeds`].
+    pub(crate) abort_if_exceeds: Option<f64>,
+    /// `--max-elements` overrides the element limit on the inputs and output.
+    pub(crate) limits: Limits,
+    /// Set by [`Config::with_cancel_token`], never from the command line.
+    pub(crate) cancel_token: Option<Arc<AtomicBool>>
 }
 
 impl Config {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<Config, String> {
         iterator.next();
 
@@ -162,21 +930,145 @@ impl Config {
         };
 
         let num_of_threads = match iterator.next() {
+            Some(auto) if auto == "auto" => None,
             Some(string_num_of_threads) => match string_num_of_threads.parse::<usize>() {
-                Ok(num_of_threads) => num_of_threads,
+                Ok(num_of_threads) => Some(num_of_threads),
                 Err(_) => return Err(format!("Couldn't get number of threads from {string_num_of_threads}"))
             },
             None => return Err(String::from("Missing number of threads"))
         };
 
-        Ok(Config{ matrix_a_file_name, matrix_b_file_name, matrix_c_file_name, num_of_threads })
+        let mut output_format = Format::PlainText;
+        let mut zero_tolerance = None;
+        let mut verbose = false;
+        let mut flush_denormals = false;
+        let mut annotate = false;
+        let mut record = None;
+        let mut element_type = None;
+        let mut pipeline = false;
+        let mut abort_if_exceeds = None;
+        let mut limits = Limits::default();
+        let mut tune_profile = None;
+        let greedy = env::var(GREEDY_VAR).is_ok_and(|value| value == "1");
+
+        while let Some(option) = iterator.next() {
+            match option.as_str() {
+                "--verbose" => verbose = true,
+                "--flush-denormals" => flush_denormals = true,
+                "--annotate" => annotate = true,
+                "--pipeline" => pipeline = true,
+                "--tune-profile" => tune_profile = Some(option_value(&mut iterator, &option)?),
+                "--record" => record = Some(option_value(&mut iterator, &option)?),
+                "--dtype" => element_type = match option_value(&mut iterator, &option)?.as_str() {
+                    "auto" => None,
+                    name => Some(ElementType::from_name(name)?),
+                },
+                "--format" => output_format = Format::from_name(&option_value(&mut iterator, &option)?)?,
+                "--abort-if-exceeds" => {
+                    let value = option_value(&mut iterator, &option)?;
+                    abort_if_exceeds = match value.parse::<f64>() {
+                        Ok(bound) if bound >= 0.0 => Some(bound),
+                        _ => return Err(format!("Couldn't get magnitude bound from {value}"))
+                    };
+                },
+                "--max-elements" => {
+                    let value = option_value(&mut iterator, &option)?;
+                    limits = match value.parse::<usize>() {
+                        Ok(max_elements) if max_elements > 0 => limits.max_elements(max_elements),
+                        _ => return Err(format!("Couldn't get element limit from {value}"))
+                    };
+                },
+                "--zero-tolerance" => {
+                    let value = option_value(&mut iterator, &option)?;
+                    zero_tolerance = match value.parse::<f64>() {
+                        Ok(tolerance) if tolerance >= 0.0 => Some(tolerance),
+                        _ => return Err(format!("Couldn't get zero tolerance from {value}"))
+                    };
+                },
+                _ => return Err(format!("Unknown option {option}"))
+            }
+        }
+
+        if annotate && output_format != Format::PlainText {
+            return Err(String::from("--annotate needs plain text output"))
+        }
+
+        if pipeline && (annotate || flush_denormals || record.is_some() || zero_tolerance.is_some()
+            || !matches!(output_format, Format::PlainText | Format::Csv)) {
+            return Err(String::from("--pipeline needs plain text or CSV output without --annotate, --flush-denormals, --record \
+                                     or --zero-tolerance"))
+        }
+
+        Ok(Config{ matrix_a_file_name, matrix_b_file_name, matrix_c_file_name, num_of_threads,
+            output_format, zero_tolerance, verbose, tune_profile, greedy, flush_denormals, annotate, pipeline,
+            element_type, record, abort_if_exceeds, limits, cancel_token: None })
+    }
+}
+
+impl Config {
+    /// Lets `token` stop the multiplication early, with [`MultiplyError::Cancelled`].
+    pub fn with_cancel_token(mut self, token: Arc<AtomicBool>) -> Config {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// Uses `profile`, usually read from [`TUNE_PROFILE_VAR`], unless
+    /// `--tune-profile` was given.
+    pub fn with_default_tune_profile(mut self, profile: Option<String>) -> Config {
+        self.tune_profile = self.tune_profile.or(profile);
+        self
+    }
+}
+
+fn option_value(iterator: &mut impl Iterator<Item=String>, option: &str) -> Result<String, String> {
+    match iterator.next() {
+        Some(value) => Ok(value),
+        None => Err(format!("Missing value for {option}"))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::shape::Shape;
+    use std::time::{Duration, Instant};
     use crate::matrix::Matrix;
-    use crate::multiplication::multiply;
+    use crate::compare::Tolerance;
+    use crate::io::Format;
+    use crate::limits::{LimitExceeded, Limits};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::multiplication::{multiply, multiply_absolute, multiply_affine, multiply_cancellable, multiply_checked,
+                                multiply_selected_columns, multiply_transpose_result, multiply_with_error_bound, multiply_weighted, multiply_with_options,
+                                multiply_wrapping, partition_rows, try_buffer, Config, Kernel, MagnitudeBound,
+                                MultiplyError, MultiplyOptions, covers_rows_exactly};
+    use crate::test_support::temp_path;
+
+    #[test]
+    fn partition_pins_distributions() {
+        assert_eq!(partition_rows(3, 7), vec![0..3, 3..5, 5..7]);
+        assert_eq!(partition_rows(5, 5), vec![0..1, 1..2, 2..3, 3..4, 4..5]);
+        assert_eq!(partition_rows(4, 2), vec![0..1, 1..2]);
+        assert_eq!(partition_rows(1, 6), vec![0..6]);
+        assert_eq!(partition_rows(3, 0), vec![]);
+    }
+
+    #[test]
+    fn partition_covers_rows_evenly() {
+        for rows in 1..200 {
+            for threads in 1..64 {
+                let ranges = partition_rows(threads, rows);
+                let lengths: Vec<usize> = ranges.iter().map(|range| range.len()).collect();
+
+                assert!(covers_rows_exactly(&ranges, rows), "{rows} rows, {threads} threads: {ranges:?}");
+                assert_eq!(ranges.len(), threads.min(rows));
+                assert!(lengths.iter().max().unwrap() - lengths.iter().min().unwrap() <= 1);
+            }
+        }
+
+        assert!(!covers_rows_exactly(&[0..2, 3..4], 4));
+        assert!(!covers_rows_exactly(&[0..2, 2..2, 2..4], 4));
+        assert!(!covers_rows_exactly(&[0..2, 1..4], 4));
+    }
 
     #[test]
     fn multiplication_correct() {
@@ -225,6 +1117,20 @@ mod test {
 
             assert_eq!(result, expected);
         }
+
+        let floats_a = Matrix::<f64>::gen_random(37, 23, -1.0, 1.0).unwrap();
+        let floats_b = Matrix::<f64>::gen_random(23, 19, -1.0, 1.0).unwrap();
+        let sequential = multiply(&floats_a, &floats_b, 1).unwrap();
+
+        for num_of_threads in [2, 5, 37] {
+            for kernel in [Kernel::Naive, Kernel::CacheOblivious] {
+                let mut options = MultiplyOptions::new(num_of_threads);
+                options.kernel = kernel;
+                let result = multiply_with_options(&floats_a, &floats_b, &options).unwrap();
+
+                crate::assert_matrices_close!(result, sequential, Tolerance::Mixed { absolute: 1e-12, relative: 1e-12 });
+            }
+        }
     }
 
     #[test]
@@ -242,6 +1148,550 @@ mod test {
         }
     }
 
+    #[test]
+    fn multiplication_generous_timeout_completes() {
+        let matrix_a = Matrix::<i32>::from_vec(
+            vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+
+        let matrix_b = Matrix::<i32>::from_vec(
+            vec!["2", "4", "7 8 9 10", "11 12 13 14"]).unwrap();
+
+        let mut options = MultiplyOptions::new(2);
+        options.timeout = Some(Duration::from_secs(60));
+
+        let result = multiply_with_options(&matrix_a, &matrix_b, &options).unwrap();
+
+        assert_eq!(result, multiply(&matrix_a, &matrix_b, 2).unwrap());
+    }
+
+    #[test]
+    fn multiplication_zero_timeout() {
+        let size = 300;
+        let matrix_a = Matrix::new(size, size, vec![1.5f64; size * size]).unwrap();
+        let matrix_b = Matrix::new(size, size, vec![0.5f64; size * size]).unwrap();
+
+        for kernel in [Kernel::Naive, Kernel::CacheOblivious] {
+            let mut options = MultiplyOptions::new(2);
+            options.timeout = Some(Duration::ZERO);
+            options.kernel = kernel;
+
+            assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options).unwrap_err(),
+                       MultiplyError::TimedOut { rows_completed: 0, total_rows: size }, "{kernel:?}");
+        }
+    }
+
+    #[test]
+    fn multiplication_short_timeout_stops_workers() {
+        let size = 1000;
+        let matrix_a = Matrix::new(size, size, vec![1.5f64; size * size]).unwrap();
+        let matrix_b = Matrix::new(size, size, vec![0.5f64; size * size]).unwrap();
+
+        for kernel in [Kernel::Naive, Kernel::CacheOblivious] {
+            let mut options = MultiplyOptions::new(2);
+            options.timeout = Some(Duration::from_millis(20));
+            options.kernel = kernel;
+
+            let started = Instant::now();
+            let error = multiply_with_options(&matrix_a, &matrix_b, &options).unwrap_err();
+            let elapsed = started.elapsed();
+
+            match error {
+                MultiplyError::TimedOut { rows_completed, total_rows } => {
+                    assert_eq!(total_rows, size, "{kernel:?}");
+                    assert!(rows_completed < total_rows, "{kernel:?} finished {rows_completed} rows");
+                },
+                error => panic!("{kernel:?} failed with {error:?} instead of timing out")
+            }
+            assert!(elapsed < Duration::from_secs(1), "{kernel:?} took {elapsed:?} to time out");
+        }
+    }
+
+    #[test]
+    fn multiplication_error_messages_unchanged() {
+        let matrix_a = Matrix::<i32>::from_vec(
+            vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+
+        assert_eq!(multiply(&matrix_a, &matrix_a, 1).unwrap_err(),
+                   "Dimension mismatch: expected 2x2, got 3x2");
+        assert_eq!(String::from(MultiplyError::TimedOut { rows_completed: 1, total_rows: 3 }),
+                   "Timed out after completing 1 of 3 rows");
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item=String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.into_iter()
+    }
+
+    #[test]
+    fn config_defaults_to_plain_text() {
+        let config = Config::from_iter(args(&["mm", "a.txt", "b.txt", "c.txt", "2"])).unwrap();
+
+        assert_eq!(config.num_of_threads, Some(2));
+        assert_eq!(config.output_format, Format::PlainText);
+        assert_eq!(config.zero_tolerance, None);
+    }
+
+    #[test]
+    fn config_coo_format() {
+        let config = Config::from_iter(args(
+            &["mm", "a.txt", "b.txt", "c.coo", "2", "--format", "coo", "--zero-tolerance", "1e-9"])).unwrap();
+
+        assert_eq!(config.output_format, Format::Coo);
+        assert_eq!(config.zero_tolerance, Some(1e-9));
+    }
+
+    #[test]
+    fn config_tune_profile_option_beats_the_default() {
+        let default = Some(String::from("env.profile"));
+
+        let config = Config::from_iter(args(&["mm", "a", "b", "c", "auto"])).unwrap();
+        assert_eq!(config.tune_profile, None);
+        assert_eq!(config.with_default_tune_profile(default.clone()).tune_profile.as_deref(), Some("env.profile"));
+
+        let config = Config::from_iter(args(&["mm", "a", "b", "c", "auto", "--tune-profile", "cli.profile"])).unwrap();
+        assert_eq!(config.with_default_tune_profile(default).tune_profile.as_deref(), Some("cli.profile"));
+    }
+
+    #[test]
+    fn config_incorrect_options() {
+        let incorrect = [
+            vec!["mm", "a", "b", "c", "2", "--format"],
+            vec!["mm", "a", "b", "c", "2", "--format", "xml"],
+            vec!["mm", "a", "b", "c", "2", "--zero-tolerance", "-1"],
+            vec!["mm", "a", "b", "c", "2", "--unknown"],
+        ];
+
+        for arguments in incorrect {
+            assert!(Config::from_iter(args(&arguments)).is_err());
+        }
+    }
+
+    /// Dot product accurate to about one rounding, using exact products and
+    /// compensated summation.
+    fn accurate_dot(a: &[f64], b: &[f64]) -> f64 {
+        let mut sum = 0.0;
+        let mut compensation = 0.0;
+
+        for (x, y) in a.iter().zip(b) {
+            let product = x * y;
+            compensation += x.mul_add(*y, -product);
 
+            let total = sum + product;
+            compensation += if sum.abs() >= product.abs() {
+                (sum - total) + product
+            } else {
+                (product - total) + sum
+            };
+            sum = total;
+        }
 
+        sum + compensation
+    }
+
+    #[test]
+    fn error_bound_tiny_for_well_scaled() {
+        let matrix_a = Matrix::<f64>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+
+        let (product, bound) = multiply_with_error_bound(&matrix_a, &matrix_a, 2).unwrap();
+
+        assert_eq!(product, multiply(&matrix_a, &matrix_a, 2).unwrap());
+        assert!(bound > 0.0 && bound < 1e-15);
+    }
+
+    #[test]
+    fn error_bound_holds_in_random_trials() {
+        let mut rng = StdRng::seed_from_u64(213);
+
+        for _ in 0..20 {
+            let (rows, n, columns) = (rng.gen_range(1..8), rng.gen_range(1..200), rng.gen_range(1..8));
+            let a: Vec<f64> = (0..rows * n).map(|_| rng.gen_range(-1e3..1e3)).collect();
+            let b: Vec<f64> = (0..n * columns).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+            let matrix_a = Matrix::new(rows, n, a.clone()).unwrap();
+            let matrix_b = Matrix::new(n, columns, b.clone()).unwrap();
+
+            let (product, bound) = multiply_with_error_bound(&matrix_a, &matrix_b, 1).unwrap();
+
+            let mut max_error: f64 = 0.0;
+            let mut max_reference: f64 = 0.0;
+            for i in 0..rows {
+                for j in 0..columns {
+                    let column: Vec<f64> = (0..n).map(|k| b[k * columns + j]).collect();
+                    let reference = accurate_dot(&a[i * n..(i + 1) * n], &column);
+                    max_error = max_error.max((product[(i, j)] - reference).abs());
+                    max_reference = max_reference.max(reference.abs());
+                }
+            }
+
+            assert!(max_error / max_reference <= bound, "{} > {}", max_error / max_reference, bound);
+        }
+    }
+
+    #[test]
+    fn absolute_product_correct() {
+        let matrix_a = Matrix::<f64>::from_vec(vec!["2", "2", "1 -2", "-3 4"]).unwrap();
+        let matrix_b = Matrix::<f64>::from_vec(vec!["2", "1", "-5", "6"]).unwrap();
+
+        let expected = Matrix::<f64>::from_vec(vec!["2", "1", "17", "39"]).unwrap();
+
+        assert_eq!(multiply_absolute(&matrix_a, &matrix_b, &MultiplyOptions::new(2)).unwrap(), expected);
+    }
+
+    #[test]
+    fn config_verbose_flag() {
+        let config = Config::from_iter(args(&["mm", "a", "b", "c", "1", "--verbose"])).unwrap();
+
+        assert!(config.verbose);
+    }
+
+    #[test]
+    fn checked_multiplication_matches_plain() {
+        let matrix_a = Matrix::<i64>::from_vec(
+            vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+
+        let matrix_b = Matrix::<i64>::from_vec(
+            vec!["2", "4", "-7 8 9 10", "11 12 13 -14"]).unwrap();
+
+        let expected = multiply(&matrix_a, &matrix_b, 1).unwrap();
+
+        for num_of_threads in 1..=3 {
+            assert_eq!(multiply_checked(&matrix_a, &matrix_b, num_of_threads).unwrap(), expected);
+            assert_eq!(multiply_wrapping(&matrix_a, &matrix_b, num_of_threads).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn checked_multiplication_overflow() {
+        let matrix_a = Matrix::<i64>::new(2, 2, vec![1, 1, i64::MAX, 1]).unwrap();
+        let matrix_b = Matrix::<i64>::new(2, 1, vec![1, 1]).unwrap();
+
+        assert_eq!(multiply_checked(&matrix_a, &matrix_b, 2),
+                   Err(MultiplyError::Overflow { row: 1, column: 0 }));
+        assert_eq!(multiply_wrapping(&matrix_a, &matrix_b, 2).unwrap(),
+                   Matrix::new(2, 1, vec![2, i64::MIN]).unwrap());
+    }
+
+    #[test]
+    fn u64_extremes_parse_multiply_write() {
+        let max = u64::MAX.to_string();
+        let almost_max = (u64::MAX - 1).to_string();
+        let row_a = format!("{} {}", max, almost_max);
+
+        let matrix_a = Matrix::<u64>::from_vec(vec!["1", "2", &row_a]).unwrap();
+        let identity = Matrix::<u64>::from_vec(vec!["2", "2", "1 0", "0 1"]).unwrap();
+        let doubling = Matrix::<u64>::from_vec(vec!["2", "2", "2 0", "0 2"]).unwrap();
+
+        let result = multiply_checked(&matrix_a, &identity, 1).unwrap();
+        assert_eq!(result, matrix_a);
+
+        assert_eq!(multiply_checked(&matrix_a, &doubling, 1),
+                   Err(MultiplyError::Overflow { row: 0, column: 0 }));
+
+        let path = &temp_path("u64_extremes.txt");
+
+        result.to_file(path).unwrap();
+        let read_back = Matrix::<u64>::from_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(read_back.unwrap(), matrix_a);
+    }
+
+    #[test]
+    fn weighted_matches_explicit_diagonal() {
+        let matrix_a = Matrix::<f64>::from_vec(vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+        let matrix_b = Matrix::<f64>::from_vec(vec!["2", "3", "7 8 9", "10 11 12"]).unwrap();
+        let weights = [0.5, -3.0];
+
+        let expected = multiply(&multiply(&matrix_a, &Matrix::from_diagonal(weights.to_vec()), 1).unwrap(), &matrix_b, 1).unwrap();
+
+        for num_of_threads in 1..=3 {
+            assert_eq!(multiply_weighted(&matrix_a, &weights, &matrix_b, num_of_threads).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn weighted_all_ones_matches_plain() {
+        let matrix_a = Matrix::<f64>::from_vec(vec!["2", "3", "0.1 0.2 0.3", "1.5 -2.5 3.25"]).unwrap();
+        let matrix_b = Matrix::<f64>::from_vec(vec!["3", "1", "0.7", "1e-3", "-9"]).unwrap();
+
+        let mut options = MultiplyOptions::new(2);
+        options.inner_weights = Some(std::sync::Arc::new(vec![1.0; 3]));
+
+        assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options).unwrap(),
+                   multiply(&matrix_a, &matrix_b, 2).unwrap());
+    }
+
+    #[test]
+    fn weighted_wrong_length() {
+        let matrix_a = Matrix::<i32>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+
+        assert_eq!(multiply_weighted(&matrix_a, &[1, 2, 3], &matrix_a, 1).unwrap_err(),
+                   "Weights length: 3 doesn't match inner dimension: 2");
+    }
+
+    #[test]
+    fn cache_oblivious_matches_naive_for_awkward_shapes() {
+        let shapes = [(1, 1009, 3), (1009, 1, 7), (5, 1009, 1), (1009, 2, 1009), (37, 53, 41), (64, 64, 64)];
+
+        for (seed, (rows, n, columns)) in shapes.into_iter().enumerate() {
+            let matrix_a = Matrix::<f64>::gen_random(rows, n, -1.0, 1.0).unwrap();
+            let matrix_b = Matrix::<f64>::gen_random(n, columns, -1.0, 1.0).unwrap();
+            let expected = multiply(&matrix_a, &matrix_b, 1).unwrap();
+
+            let mut options = MultiplyOptions::new(1 + seed % rows.min(4));
+            options.kernel = Kernel::CacheOblivious;
+
+            assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options).unwrap(), expected,
+                       "{rows}x{n} by {n}x{columns}");
+        }
+    }
+
+    #[test]
+    fn cache_oblivious_with_weights_and_integers() {
+        let matrix_a = Matrix::<i64>::from_vec(vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+        let matrix_b = Matrix::<i64>::from_vec(vec!["2", "3", "7 8 9", "10 11 12"]).unwrap();
+
+        let mut options = MultiplyOptions::new(3);
+        options.kernel = Kernel::CacheOblivious;
+        options.inner_weights = Some(std::sync::Arc::new(vec![2, -1]));
+
+        assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options).unwrap(),
+                   multiply_weighted(&matrix_a, &[2, -1], &matrix_b, 1).unwrap());
+    }
+
+    fn with_ones_column(x: &Matrix<i64>) -> Matrix<i64> {
+        let numbers = x.get_numbers().chunks(x.columns()).flat_map(|row| row.iter().copied().chain([1])).collect();
+
+        Matrix::new(x.rows(), x.columns() + 1, numbers).unwrap()
+    }
+
+    #[test]
+    fn affine_matches_explicit_ones_column() {
+        let x = Matrix::<i64>::from_vec(vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+        let w = Matrix::<i64>::from_vec(vec!["3", "2", "7 8", "9 10", "-1 100"]).unwrap();
+
+        let expected = multiply(&with_ones_column(&x), &w, 1).unwrap();
+
+        for num_of_threads in 1..=3 {
+            assert_eq!(multiply_affine(&x, &w, num_of_threads).unwrap(), expected);
+        }
+
+        let single_row = Matrix::<i64>::from_vec(vec!["1", "2", "-3 5"]).unwrap();
+        assert_eq!(multiply_affine(&single_row, &w, 1).unwrap(), multiply(&with_ones_column(&single_row), &w, 1).unwrap());
+    }
+
+    #[test]
+    fn affine_incorrect_dimensions() {
+        let x = Matrix::<i64>::from_vec(vec!["2", "2", "1 2", "3 4"]).unwrap();
+        let w = Matrix::<i64>::from_vec(vec!["2", "1", "1", "2"]).unwrap();
+
+        assert_eq!(multiply_affine(&x, &w, 1).unwrap_err(), "Dimension mismatch: expected 3x1, got 2x1");
+        let w = Matrix::<i64>::from_vec(vec!["3", "1", "1", "2", "3"]).unwrap();
+        assert_eq!(multiply_affine(&x, &w, 3).unwrap_err(),
+                   "Num of threads: 3 cannot be higher than Matrix A rows: 2");
+    }
+
+    #[test]
+    fn cancel_token_stops_workers() {
+        let matrix_a = Matrix::<i64>::from_vec(vec!["3", "2", "1 2", "3 4", "5 6"]).unwrap();
+        let token = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        assert_eq!(multiply_cancellable(&matrix_a, &Matrix::zeros(2, 2), 3, &token).unwrap(), Matrix::zeros(3, 2));
+
+        token.store(true, std::sync::atomic::Ordering::SeqCst);
+        let error = multiply_cancellable(&matrix_a, &Matrix::zeros(2, 2), 3, &token).unwrap_err();
+        assert_eq!(error, MultiplyError::Cancelled { rows_completed: 0, total_rows: 3 });
+        assert_eq!(error.to_string(), "Interrupted after 0 of 3 rows");
+    }
+
+    /// An integer whose products count down on the current thread, setting a
+    /// cancel token once the count runs out.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Tripwire(i64);
+
+    thread_local! {
+        static TRIPWIRE: std::cell::RefCell<Option<(usize, std::sync::Arc<std::sync::atomic::AtomicBool>)>> =
+            const { std::cell::RefCell::new(None) };
+    }
+
+    impl std::ops::Mul for &Tripwire {
+        type Output = Tripwire;
+
+        fn mul(self, other: &Tripwire) -> Tripwire {
+            TRIPWIRE.with(|tripwire| if let Some((products, token)) = tripwire.borrow_mut().as_mut() {
+                *products = products.saturating_sub(1);
+                if *products == 0 {
+                    token.store(true, std::sync::atomic::Ordering::SeqCst);
+                }
+            });
+            Tripwire(self.0 * other.0)
+        }
+    }
+
+    impl std::ops::AddAssign for Tripwire {
+        fn add_assign(&mut self, other: Tripwire) {
+            self.0 += other.0;
+        }
+    }
+
+    #[test]
+    fn cache_oblivious_counts_finished_rows() {
+        let (rows, n, columns) = (96, 3, 2);
+        let matrix_a = Matrix::new(rows, n, vec![Tripwire(1); rows * n]).unwrap();
+        let matrix_b = Matrix::new(n, columns, vec![Tripwire(1); n * columns]).unwrap();
+        let token = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let mut options = MultiplyOptions::new(1);
+        options.kernel = Kernel::CacheOblivious;
+        options.cancel_token = Some(std::sync::Arc::clone(&token));
+
+        // The band starts with the first inner term of every element, then the
+        // recursion halves the rows down to 24-row blocks of 2 columns and 2
+        // terms each; the token is set once the first block is done.
+        TRIPWIRE.with(|tripwire| *tripwire.borrow_mut() = Some((rows * columns + 24 * columns * 2, token)));
+        let error = multiply_with_options(&matrix_a, &matrix_b, &options).unwrap_err();
+        TRIPWIRE.with(|tripwire| *tripwire.borrow_mut() = None);
+
+        assert_eq!(error, MultiplyError::Cancelled { rows_completed: 24, total_rows: rows });
+    }
+
+    #[test]
+    fn transpose_result_matches_transposed_product() {
+        let transposed = |matrix: Matrix<i64>| Matrix::from_column_major(matrix.columns(), matrix.rows(),
+                                                                          matrix.get_numbers().to_vec()).unwrap();
+
+        for (rows, n, columns) in [(2, 3, 5), (5, 3, 2), (4, 1, 7), (1, 6, 3)] {
+            let matrix_a = Matrix::new(rows, n, (0..(rows * n) as i64).map(|num| num * 3 - 4).collect()).unwrap();
+            let matrix_b = Matrix::new(n, columns, (0..(n * columns) as i64).map(|num| 7 - num).collect()).unwrap();
+            let expected = transposed(multiply(&matrix_a, &matrix_b, 1).unwrap());
+
+            for num_of_threads in 1..=rows {
+                assert_eq!(multiply_transpose_result(&matrix_a, &matrix_b, num_of_threads).unwrap(), expected);
+            }
+
+            let mut options = MultiplyOptions::new(rows);
+            options.transpose_result = true;
+            options.kernel = Kernel::CacheOblivious;
+            assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn transpose_result_counts_output_rows() {
+        let matrix_a = Matrix::new(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+
+        let mut options = MultiplyOptions::new(2);
+        options.transpose_result = true;
+        options.inner_weights = Some(std::sync::Arc::new(vec![2.0, 0.5]));
+        options.cancel_token = Some(std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true)));
+
+        let error = multiply_with_options(&matrix_a, &Matrix::zeros(2, 4), &options).unwrap_err();
+        assert_eq!(error, MultiplyError::Cancelled { rows_completed: 0, total_rows: 4 });
+
+        options.cancel_token = None;
+        let matrix_b = Matrix::new(2, 4, vec![1.0, 0.0, 2.0, -1.0, 0.0, 1.0, 3.0, 0.5]).unwrap();
+        let expected = multiply_weighted(&matrix_a, &[2.0, 0.5], &matrix_b, 1).unwrap();
+        assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options).unwrap(),
+                   Matrix::from_column_major(4, 3, expected.get_numbers().to_vec()).unwrap());
+    }
+
+    #[test]
+    fn magnitude_bound_names_offending_element() {
+        // Only row 3 of A has large entries, and B's column 1 is the only one that doesn't cancel them.
+        let mut a = vec![1.0; 12];
+        a[6..8].copy_from_slice(&[50.0, 50.0]);
+        let matrix_a = Matrix::new(6, 2, a).unwrap();
+        let matrix_b = Matrix::new(2, 3, vec![1.0, 2.0, 1.0, -1.0, 1.0, -1.0]).unwrap();
+
+        for (threads, kernel) in [(1, Kernel::Naive), (3, Kernel::CacheOblivious)] {
+            let mut options = MultiplyOptions::new(threads);
+            options.kernel = kernel;
+            options.abort_if_exceeds = Some(MagnitudeBound::new(100.0));
+
+            let error = multiply_with_options(&matrix_a, &matrix_b, &options).unwrap_err();
+            assert_eq!(error, MultiplyError::BoundExceeded { row: 3, column: 1, value: 150.0, bound: 100.0 });
+            assert_eq!(error.to_string(), "Element (3, 1) is 150, over the magnitude bound 100");
+
+            options.abort_if_exceeds = Some(MagnitudeBound::new(150.0));
+            assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options), Ok(multiply(&matrix_a, &matrix_b, 1).unwrap()));
+        }
+
+        let mut options = MultiplyOptions::new(2);
+        options.abort_if_exceeds = Some(MagnitudeBound::new(5.0));
+        let result = multiply_with_options(&Matrix::new(2, 1, vec![-1, 9]).unwrap(), &Matrix::new(1, 1, vec![-1]).unwrap(), &options);
+        assert!(matches!(result, Err(MultiplyError::BoundExceeded { row: 1, column: 0, value: -9.0, .. })));
+    }
+
+    #[test]
+    fn output_over_the_limits_is_rejected_before_computing() {
+        let (matrix_a, matrix_b) = (Matrix::new(4, 1, vec![1, 2, 3, 4]).unwrap(), Matrix::new(1, 4, vec![1, 1, 1, 1]).unwrap());
+        let mut options = MultiplyOptions::new(2);
+        options.limits = Limits::new().max_elements(15);
+
+        let error = multiply_with_options(&matrix_a, &matrix_b, &options).unwrap_err();
+        assert!(matches!(error, MultiplyError::LimitExceeded(LimitExceeded { value: 16, max: 15, .. })));
+        assert!(error.to_string().starts_with("Result is too large: 4x4 matrix has 16 elements"));
+
+        options.limits = options.limits.max_elements(16);
+        assert_eq!(multiply_with_options(&matrix_a, &matrix_b, &options), Ok(multiply(&matrix_a, &matrix_b, 1).unwrap()));
+    }
+
+    #[test]
+    fn multiplies_matrices_built_from_fn() {
+        let a = Matrix::from_fn(3, 4, |i, j| (i * 10 + j) as i32).unwrap();
+        let b = Matrix::from_fn(4, 2, |i, j| i as i32 - j as i32).unwrap();
+        let expected = Matrix::from_fn(3, 2, |i, j| (0..4).map(|k| (i * 10 + k) as i32 * (k as i32 - j as i32)).sum()).unwrap();
+
+        for threads in [1, 2, 3] {
+            assert_eq!(multiply(&a, &b, threads), Ok(expected.clone()));
+        }
+        assert_eq!(expected, Matrix::new(3, 2, vec![14, 8, 74, 28, 134, 48]).unwrap());
+    }
+
+    #[test]
+    fn identity_is_neutral() {
+        let ints = Matrix::new(4, 3, (0..12).map(|num| num * 7 - 30).collect::<Vec<i32>>()).unwrap();
+        let floats = Matrix::<f32>::gen_random(5, 4, -10.0, 10.0).unwrap();
+        let doubles = Matrix::<f64>::gen_random(3, 6, -10.0, 10.0).unwrap();
+
+        for threads in [1, 2, 3] {
+            assert_eq!(multiply(&ints, &Matrix::identity(3), threads).as_ref(), Ok(&ints));
+            assert_eq!(multiply(&Matrix::identity(4), &ints, threads).as_ref(), Ok(&ints));
+            assert_eq!(multiply(&floats, &Matrix::identity(4), threads).as_ref(), Ok(&floats));
+            assert_eq!(multiply(&doubles, &Matrix::identity(6), threads).as_ref(), Ok(&doubles));
+        }
+
+        assert_eq!(Matrix::<i32>::identity(2), Matrix::new(2, 2, vec![1, 0, 0, 1]).unwrap());
+    }
+
+    #[test]
+    fn selected_columns_match_full_product() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let matrix_a = Matrix::new(5, 3, (0..15).map(|_| rng.gen_range(-5.0..5.0)).collect()).unwrap();
+        let matrix_b = Matrix::new(3, 4, (0..12).map(|_| rng.gen_range(-5.0..5.0)).collect()).unwrap();
+        let full = multiply(&matrix_a, &matrix_b, 2).unwrap();
+
+        assert_eq!(multiply_selected_columns(&matrix_a, &matrix_b, &[0, 1, 2, 3], 2), Ok(full.clone()));
+        for indices in [vec![3, 1], vec![2, 2, 0]] {
+            let expected = full.select_columns(&indices).unwrap();
+            assert_eq!(multiply_selected_columns(&matrix_a, &matrix_b, &indices, 3).unwrap(), expected);
+            assert_eq!(multiply(&matrix_a, &matrix_b.select_columns(&indices).unwrap(), 1).unwrap(), expected);
+        }
+
+        let selected = multiply_selected_columns(&matrix_a, &matrix_b, &[1, 1], 1).unwrap();
+        assert_eq!((selected[(4, 0)], selected[(4, 1)]), (full[(4, 1)], full[(4, 1)]));
+        assert_eq!(multiply_selected_columns(&matrix_a, &matrix_b, &[0, 4], 1).unwrap_err(),
+                   "Column index 4 is out of bounds for B: 3x4");
+    }
+
+    #[test]
+    fn oversized_result_is_an_error() {
+        let shape = Shape { rows: usize::MAX / 4, cols: 2 };
+        let error = try_buffer::<f64>(usize::MAX / 2, shape).unwrap_err();
+
+        assert_eq!(error, MultiplyError::AllocationFailed { requested_bytes: u64::MAX, shape });
+        assert_eq!(error.to_string(), format!("Not enough memory for the {} result: couldn't allocate 16 EiB", shape));
+
+        assert_eq!(try_buffer::<f64>(6, shape).unwrap().capacity(), 6);
+    }
 }
@@ -0,0 +1,415 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crate::multiplication::{covers_rows_exactly, partition_rows};
+use crate::shape::Shape;
+
+/// Runs `worker` on up to `num_of_threads` scoped threads, each given a
+/// disjoint, contiguous slice of `0..rows`, and hands the results to `combine`
+/// in row order.
+///
+/// Rows are split as evenly as possible, see [`partition_rows`]; with more
+/// threads than rows only one thread per row is started. A panicking worker is reported as an error
+/// naming its thread instead of tearing down the caller.
+pub(crate) fn parallel_over_rows<R, Out>(rows: usize, num_of_threads: usize,
+                                         worker: impl Fn(Range<usize>) -> R + Sync,
+                                         combine: impl FnOnce(Vec<R>) -> Out) -> Result<Out, String>
+    where R: Send {
+
+    parallel_over_rows_indexed(rows, num_of_threads, |_, rows| worker(rows), combine)
+}
+
+/// Like [`parallel_over_rows`], also passing `worker` the index of its thread,
+/// counted from 0 in row order.
+pub(crate) fn parallel_over_rows_indexed<R, Out>(rows: usize, num_of_threads: usize,
+                                                 worker: impl Fn(usize, Range<usize>) -> R + Sync,
+                                                 combine: impl FnOnce(Vec<R>) -> Out) -> Result<Out, String>
+    where R: Send {
+
+    let ranges = partition_rows(num_of_threads.max(1), rows);
+    debug_assert!(covers_rows_exactly(&ranges, rows), "{ranges:?} don't cover {rows} rows exactly once");
+    let worker = &worker;
+
+    let results: Result<Vec<R>, String> = thread::scope(|scope| {
+        let handles: Vec<_> = ranges.into_iter()
+            .enumerate()
+            .map(|(thread_num, rows)| scope.spawn(move || worker(thread_num, rows)))
+            .collect();
+
+        join_all(handles)
+    });
+
+    Ok(combine(results?))
+}
+
+/// Joins every one of `handles` before reporting the first that panicked, so
+/// no panicked thread is left for the enclosing scope to re-raise.
+fn join_all<R>(handles: Vec<thread::ScopedJoinHandle<'_, R>>) -> Result<Vec<R>, String> {
+    let joined: Vec<thread::Result<R>> = handles.into_iter().map(|handle| handle.join()).collect();
+
+    joined.into_iter().enumerate().map(|(thread_num, result)| result.map_err(
+        |err| format!("Error joining thread {thread_num}, error:\n{err:?}"))).collect()
+}
+
+/// A block of the output computed as one piece of work.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct WorkUnit {
+    pub(crate) rows: Range<usize>,
+    pub(crate) columns: Range<usize>,
+}
+
+/// How a partitioning strategy splits a `shape` output into [`WorkUnit`]s,
+/// and where each unit's elements, row-major within the unit, belong in the
+/// row-major output. Units are handed back by index, so [`assemble_into`]
+/// places them correctly whichever thread ran them and in whatever order
+/// they finished.
+pub(crate) trait ResultAssembler: Sync {
+    fn shape(&self) -> Shape;
+
+    fn units(&self) -> &[WorkUnit];
+
+    /// Whether an idle thread takes the next unit, rather than thread `t`
+    /// running units `t`, `t + threads`, and so on.
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+
+    /// Output index ranges unit `index` fills, one per row of the unit.
+    fn destination(&self, index: usize) -> Vec<Range<usize>> {
+        let unit = &self.units()[index];
+        let columns = self.shape().cols;
+
+        unit.rows.clone().map(|row| row * columns + unit.columns.start..row * columns + unit.columns.end).collect()
+    }
+}
+
+/// One band of whole rows per thread, split as by [`partition_rows`].
+pub(crate) struct RowPartition {
+    shape: Shape,
+    units: Vec<WorkUnit>,
+}
+
+impl RowPartition {
+    pub(crate) fn new(shape: Shape, num_of_threads: usize) -> RowPartition {
+        let units = partition_rows(num_of_threads.max(1), shape.rows).into_iter()
+            .map(|rows| WorkUnit { rows, columns: 0..shape.cols })
+            .collect();
+
+        RowPartition { shape, units }
+    }
+}
+
+impl ResultAssembler for RowPartition {
+    fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    fn units(&self) -> &[WorkUnit] {
+        &self.units
+    }
+}
+
+/// One band of whole columns per thread. This and the strategies below
+/// have no caller outside the tests yet.
+#[cfg(test)]
+pub(crate) struct ColumnPartition {
+    shape: Shape,
+    units: Vec<WorkUnit>,
+}
+
+#[cfg(test)]
+impl ColumnPartition {
+    pub(crate) fn new(shape: Shape, num_of_threads: usize) -> ColumnPartition {
+        let units = partition_rows(num_of_threads.max(1), shape.cols).into_iter()
+            .map(|columns| WorkUnit { rows: 0..shape.rows, columns })
+            .collect();
+
+        ColumnPartition { shape, units }
+    }
+}
+
+#[cfg(test)]
+impl ResultAssembler for ColumnPartition {
+    fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    fn units(&self) -> &[WorkUnit] {
+        &self.units
+    }
+}
+
+/// `tile_rows`×`tile_columns` tiles, smaller at the bottom and right
+/// edges, listed row of tiles by row of tiles.
+#[cfg(test)]
+pub(crate) struct TilePartition {
+    shape: Shape,
+    units: Vec<WorkUnit>,
+}
+
+#[cfg(test)]
+impl TilePartition {
+    /// Tile sides are at least 1.
+    pub(crate) fn new(shape: Shape, tile_rows: usize, tile_columns: usize) -> TilePartition {
+        let bands = |len: usize, side: usize| (0..len).step_by(side.max(1)).map(move |start| start..(start + side.max(1)).min(len));
+        let units = bands(shape.rows, tile_rows)
+            .flat_map(|rows| bands(shape.cols, tile_columns).map(move |columns| WorkUnit { rows: rows.clone(), columns }))
+            .collect();
+
+        TilePartition { shape, units }
+    }
+}
+
+#[cfg(test)]
+impl ResultAssembler for TilePartition {
+    fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    fn units(&self) -> &[WorkUnit] {
+        &self.units
+    }
+}
+
+/// Chunks of `chunk_rows` whole rows, taken by whichever thread is idle.
+#[cfg(test)]
+pub(crate) struct DynamicRowChunks {
+    shape: Shape,
+    units: Vec<WorkUnit>,
+}
+
+#[cfg(test)]
+impl DynamicRowChunks {
+    /// `chunk_rows` is at least 1.
+    pub(crate) fn new(shape: Shape, chunk_rows: usize) -> DynamicRowChunks {
+        let chunk_rows = chunk_rows.max(1);
+        let units = (0..shape.rows).step_by(chunk_rows)
+            .map(|start| WorkUnit { rows: start..(start + chunk_rows).min(shape.rows), columns: 0..shape.cols })
+            .collect();
+
+        DynamicRowChunks { shape, units }
+    }
+}
+
+#[cfg(test)]
+impl ResultAssembler for DynamicRowChunks {
+    fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    fn units(&self) -> &[WorkUnit] {
+        &self.units
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+}
+
+/// Runs `worker` on every unit of `assembler` on up to `num_of_threads`
+/// scoped threads and returns the results indexed by unit, not by thread or
+/// completion order. A panicking worker is reported as an error naming its
+/// thread, as in [`parallel_over_rows`].
+pub(crate) fn run_units<R: Send>(assembler: &impl ResultAssembler, num_of_threads: usize,
+                                 worker: impl Fn(&WorkUnit) -> R + Sync) -> Result<Vec<R>, String> {
+
+    let units = assembler.units();
+    let threads = num_of_threads.max(1).min(units.len());
+    let next = AtomicUsize::new(0);
+    let (worker, next) = (&worker, &next);
+
+    let finished: Result<Vec<Vec<(usize, R)>>, String> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread_num| scope.spawn(move || {
+                let mut finished = vec![];
+                if assembler.is_dynamic() {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= units.len() {
+                            break
+                        }
+                        finished.push((index, worker(&units[index])));
+                    }
+                } else {
+                    for index in (thread_num..units.len()).step_by(threads) {
+                        finished.push((index, worker(&units[index])));
+                    }
+                }
+                finished
+            }))
+            .collect();
+
+        join_all(handles)
+    });
+
+    let mut slots: Vec<Option<R>> = (0..units.len()).map(|_| None).collect();
+    for (index, result) in finished?.into_iter().flatten() {
+        debug_assert!(slots[index].is_none(), "unit {index} ran twice");
+        slots[index] = Some(result);
+    }
+    debug_assert!(slots.iter().all(Option::is_some), "a unit never ran");
+
+    Ok(slots.into_iter().flatten().collect())
+}
+
+/// Appends the elements of `parts`, indexed by unit, to `output` in row-major
+/// order, leaving each part empty so its allocation can be reused. Panics if
+/// a part doesn't fit its unit or the units don't cover the output exactly
+/// once, in release builds too.
+pub(crate) fn assemble_into<T>(assembler: &impl ResultAssembler, parts: &mut [Vec<T>], output: &mut Vec<T>) {
+    let mut destinations: Vec<(Range<usize>, usize)> = (0..parts.len())
+        .flat_map(|index| assembler.destination(index).into_iter().map(move |range| (range, index)))
+        .collect();
+    destinations.sort_by_key(|(range, _)| range.start);
+
+    assert!(parts.iter().enumerate().all(|(index, part)| part.len() == assembler.units()[index].rows.len()
+        * assembler.units()[index].columns.len()), "a part doesn't match the size of its unit");
+    assert_eq!(destinations.iter().try_fold(0, |next, (range, _)| (range.start == next).then_some(range.end)),
+               Some(assembler.shape().rows * assembler.shape().cols),
+               "the units don't write every output index exactly once");
+
+    let mut drains: Vec<_> = parts.iter_mut().map(|part| part.drain(..)).collect();
+    for (range, index) in destinations {
+        output.extend(drains[index].by_ref().take(range.len()));
+    }
+}
+
+#[cfg(test)]
+mod parallel_test {
+    use std::sync::{Condvar, Mutex};
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+    use crate::parallel::{assemble_into, parallel_over_rows, parallel_over_rows_indexed, run_units, ColumnPartition, DynamicRowChunks, ResultAssembler,
+                          RowPartition, TilePartition, WorkUnit};
+    use crate::shape::Shape;
+
+    fn operands() -> (Matrix<i64>, Matrix<i64>) {
+        (Matrix::new(7, 3, (0..21).map(|num| num % 5 - 2).collect()).unwrap(),
+         Matrix::new(3, 5, (0..15).map(|num| num * 3 % 7 - 3).collect()).unwrap())
+    }
+
+    /// Runs every unit of A·B on its own thread, holding each back until the
+    /// units before it in `order` have finished, and returns the assembled
+    /// output with the order the units actually finished in.
+    fn assembled_in_order(assembler: &impl ResultAssembler, order: &[usize]) -> (Vec<i64>, Vec<usize>) {
+        let (a, b) = operands();
+        let (a_numbers, b_numbers) = (a.get_numbers(), b.get_numbers());
+        let (finished, turn) = (Mutex::new(vec![]), Condvar::new());
+
+        let mut parts = run_units(assembler, assembler.units().len(), |unit: &WorkUnit| {
+            let index = assembler.units().iter().position(|other| other == unit).unwrap();
+            let position = order.iter().position(|&other| other == index).unwrap();
+
+            let part = unit.rows.clone()
+                .flat_map(|row| unit.columns.clone().map(move |column| (row, column)))
+                .map(|(row, column)| (0..3).map(|k| a_numbers[row * 3 + k] * b_numbers[k * 5 + column]).sum())
+                .collect::<Vec<i64>>();
+
+            let mut done = turn.wait_while(finished.lock().unwrap(), |done| done.len() < position).unwrap();
+            done.push(index);
+            turn.notify_all();
+            part
+        }).unwrap();
+
+        let mut output = vec![];
+        assemble_into(assembler, &mut parts, &mut output);
+        assert!(parts.iter().all(Vec::is_empty));
+
+        (output, finished.into_inner().unwrap())
+    }
+
+    fn check_orders(assembler: &impl ResultAssembler) {
+        let (a, b) = operands();
+        let expected = multiply(&a, &b, 1).unwrap().get_numbers().to_vec();
+        let units = assembler.units().len();
+
+        let reverse: Vec<usize> = (0..units).rev().collect();
+        let interleaved: Vec<usize> = (1..units).step_by(2).chain((0..units).step_by(2).rev()).collect();
+
+        for order in [reverse, interleaved] {
+            let (output, finished) = assembled_in_order(assembler, &order);
+            assert_eq!(finished, order);
+            assert_eq!(output, expected, "units finished in {order:?}");
+        }
+    }
+
+    #[test]
+    fn every_strategy_assembles_out_of_order_units() {
+        let shape = Shape { rows: 7, cols: 5 };
+
+        check_orders(&RowPartition::new(shape, 4));
+        check_orders(&ColumnPartition::new(shape, 3));
+        check_orders(&TilePartition::new(shape, 3, 2));
+        check_orders(&DynamicRowChunks::new(shape, 2));
+
+        assert_eq!(TilePartition::new(shape, 3, 2).units().len(), 9);
+        assert_eq!(DynamicRowChunks::new(shape, 2).units().last(), Some(&WorkUnit { rows: 6..7, columns: 0..5 }));
+    }
+
+    struct Overlapping(Vec<WorkUnit>);
+
+    impl ResultAssembler for Overlapping {
+        fn shape(&self) -> Shape {
+            Shape { rows: 2, cols: 2 }
+        }
+
+        fn units(&self) -> &[WorkUnit] {
+            &self.0
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "exactly once")]
+    fn overlapping_units_are_caught() {
+        let assembler = Overlapping(vec![WorkUnit { rows: 0..2, columns: 0..1 }, WorkUnit { rows: 1..2, columns: 0..2 }]);
+
+        assemble_into(&assembler, &mut [vec![1, 2], vec![3, 4]], &mut vec![]);
+    }
+
+    #[test]
+    fn ranges_cover_rows_in_order() {
+        let ranges = parallel_over_rows(10, 3, |rows| rows, |ranges| ranges).unwrap();
+
+        assert_eq!(ranges, vec![0..4, 4..7, 7..10]);
+        assert_eq!(parallel_over_rows(10, 3, |rows| rows.sum::<usize>(), |sums| sums.iter().sum::<usize>()),
+                   Ok(45));
+    }
+
+    #[test]
+    fn workers_get_their_thread_index() {
+        let indexed = parallel_over_rows_indexed(10, 3, |thread_num, rows| (thread_num, rows), |parts| parts).unwrap();
+
+        assert_eq!(indexed, vec![(0, 0..4), (1, 4..7), (2, 7..10)]);
+    }
+
+    #[test]
+    fn no_empty_ranges_with_more_threads_than_rows() {
+        let ranges = parallel_over_rows(2, 4, |rows| rows, |ranges| ranges).unwrap();
+        assert_eq!(ranges, vec![0..1, 1..2]);
+
+        let collected = parallel_over_rows(0, 2, |rows| rows.collect::<Vec<usize>>(), |parts| parts.concat());
+        assert_eq!(collected, Ok(vec![]));
+    }
+
+    #[test]
+    fn worker_panic_becomes_error() {
+        let result = parallel_over_rows(4, 2, |rows| {
+            if rows.start > 0 {
+                panic!("worker failed")
+            }
+            rows.len()
+        }, |lengths| lengths);
+
+        assert!(result.unwrap_err().starts_with("Error joining thread 1, error:"));
+    }
+
+    #[test]
+    fn every_worker_panicking_becomes_error() {
+        let result = parallel_over_rows(4, 4, |_| -> usize { panic!("worker failed") }, |lengths| lengths);
+        assert!(result.unwrap_err().starts_with("Error joining thread 0, error:"));
+
+        let assembler = RowPartition::new(Shape { rows: 4, cols: 2 }, 4);
+        let result = run_units(&assembler, 4, |_: &WorkUnit| -> Vec<i32> { panic!("worker failed") });
+        assert!(result.unwrap_err().starts_with("Error joining thread 0, error:"));
+    }
+}
@@ -0,0 +1,447 @@
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::element::Element;
+use crate::limits::Limits;
+use crate::matrix::Matrix;
+
+/// Called with (rows parsed, total rows from the header) every `every` rows
+/// and after the last one, so a long parse can be told apart from a hang.
+#[derive(Clone)]
+pub struct ProgressHook {
+    every: u64,
+    callback: Arc<dyn Fn(u64, u64) + Send + Sync>,
+}
+
+impl ProgressHook {
+    /// `every` is at least 1.
+    pub fn new(every: u64, callback: impl Fn(u64, u64) + Send + Sync + 'static) -> ProgressHook {
+        ProgressHook { every: every.max(1), callback: Arc::new(callback) }
+    }
+
+    /// Reports `parsed` rows of `total` on every multiple of `every` and at the end.
+    pub(crate) fn rows_parsed(&self, parsed: u64, total: u64) {
+        if parsed.is_multiple_of(self.every) || parsed == total {
+            (self.callback)(parsed, total)
+        }
+    }
+}
+
+impl Debug for ProgressHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ProgressHook {{ every: {} }}", self.every)
+    }
+}
+
+/// Hooks are equal only if they share the callback.
+impl PartialEq for ProgressHook {
+    fn eq(&self, other: &ProgressHook) -> bool {
+        self.every == other.every && Arc::ptr_eq(&self.callback, &other.callback)
+    }
+}
+
+/// What happens to a finite float literal that overflows the element type to
+/// infinity, like `1e999`, or a non-zero one that underflows to zero, like
+/// `1e-999`. Only f32 and f64 elements are checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverflowPolicy {
+    /// Reject the token.
+    Error,
+    /// Replace an overflow with the largest finite value of its sign, keep an
+    /// underflow at zero, and record a [`ParseWarning`] for either.
+    SaturateAndWarn,
+    /// Keep the infinity or zero the standard parser gives.
+    #[default]
+    Allow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutOfRange {
+    Overflow,
+    Underflow,
+}
+
+/// A token changed under [`OverflowPolicy::SaturateAndWarn`]; `line` counts
+/// every line of the input from 1 and `column` the tokens of that line from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseWarning {
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub kind: OutOfRange,
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            OutOfRange::Overflow => write!(f, "line {}, column {}: '{}' overflows, saturated to the largest finite value",
+                                           self.line, self.column, self.token),
+            OutOfRange::Underflow => write!(f, "line {}, column {}: '{}' underflows to zero",
+                                            self.line, self.column, self.token),
+        }
+    }
+}
+
+/// A data row that couldn't be parsed; `line` counts every line of the input
+/// from 1, one past the end if the input ran out of rows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// The rows, or columns of a column-major file, parsed before `error`.
+#[derive(Debug, PartialEq)]
+pub struct PartialParse<T> {
+    pub matrix: Matrix<T>,
+    pub error: ParseError,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseOutcome<T> {
+    Complete(Matrix<T>),
+    /// Only with [`ParseOptions::partial_ok`].
+    Partial(PartialParse<T>),
+}
+
+impl<T> ParseOutcome<T> {
+    /// The complete matrix, or the error message of a partial parse.
+    pub fn into_result(self) -> Result<Matrix<T>, String> {
+        match self {
+            ParseOutcome::Complete(matrix) => Ok(matrix),
+            ParseOutcome::Partial(partial) => Err(partial.error.message),
+        }
+    }
+}
+
+/// A `#` comment line of a text matrix, as written; `line` counts every line
+/// of the input from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub line: usize,
+    pub text: String,
+}
+
+/// What a text matrix file holds besides the matrix, see
+/// [`Matrix::from_file_with_metadata`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileMetadata {
+    /// Every comment line, leading or between the data rows, in file order.
+    pub comments: Vec<Comment>,
+}
+
+impl FileMetadata {
+    /// Collects the comment lines of `contents`.
+    pub(crate) fn from_text(contents: &str) -> FileMetadata {
+        let comments = contents.lines().enumerate()
+            .filter(|(_, line)| line.trim_start().starts_with('#'))
+            .map(|(index, line)| Comment { line: index + 1, text: line.trim().to_string() })
+            .collect();
+
+        FileMetadata { comments }
+    }
+
+    /// The comment lines, for [`WriteOptions::comments`](crate::write::WriteOptions::comments).
+    pub fn comment_lines(&self) -> Vec<String> {
+        self.comments.iter().map(|comment| comment.text.clone()).collect()
+    }
+}
+
+/// How a text matrix is split into lines and tokens, and how the tokens are
+/// turned into numbers.
+///
+/// Underscores between digits, as in `1_000`, are always accepted in both the
+/// header and the data, and so are `#` comment lines before the header.
+/// Everything else is strict unless opted into here.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParseOptions {
+    lenient_integers: bool,
+    pub(crate) comments: bool,
+    pub(crate) one_line_header: bool,
+    pub(crate) delimiter: Option<char>,
+    pub(crate) progress: Option<ProgressHook>,
+    overflow: OverflowPolicy,
+    pub(crate) partial_ok: bool,
+    pub(crate) limits: Limits,
+    pub(crate) collect_comments: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        ParseOptions::default()
+    }
+
+    /// Accept exactly integral floats without an exponent, such as `5.0`, where
+    /// an integer is expected. `5.5` and `1e3` are still rejected.
+    pub fn lenient_integers(mut self, lenient_integers: bool) -> ParseOptions {
+        self.lenient_integers = lenient_integers;
+        self
+    }
+
+    /// Skip `#` comment lines between the data rows too.
+    pub fn comments(mut self, comments: bool) -> ParseOptions {
+        self.comments = comments;
+        self
+    }
+
+    /// Read the header as `rows columns` on a single line.
+    pub fn one_line_header(mut self, one_line_header: bool) -> ParseOptions {
+        self.one_line_header = one_line_header;
+        self
+    }
+
+    /// Split rows on `delimiter` instead of any whitespace. Whitespace around
+    /// each element is trimmed, and an empty element, including one left by a
+    /// delimiter ending the row, is an error.
+    pub fn delimiter(mut self, delimiter: Option<char>) -> ParseOptions {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Report progress through the data rows, or columns of a column-major
+    /// file, to `progress`.
+    pub fn progress(mut self, progress: Option<ProgressHook>) -> ParseOptions {
+        self.progress = progress;
+        self
+    }
+
+    /// What to do with float literals out of the element type's range.
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> ParseOptions {
+        self.overflow = overflow;
+        self
+    }
+
+    /// On a data row that fails to parse, keep the rows before it, see
+    /// [`Matrix::from_file_partial`]. Readers that can only return a complete
+    /// matrix still fail with the error.
+    pub fn partial_ok(mut self, partial_ok: bool) -> ParseOptions {
+        self.partial_ok = partial_ok;
+        self
+    }
+
+    /// Keep the comment lines the parser skips, see [`Matrix::from_file_with_metadata`].
+    pub fn collect_comments(mut self, collect_comments: bool) -> ParseOptions {
+        self.collect_comments = collect_comments;
+        self
+    }
+
+    /// Reject headers, and files, over `limits` before reading any data.
+    pub fn limits(mut self, limits: Limits) -> ParseOptions {
+        self.limits = limits;
+        self
+    }
+
+    /// Splits a data row into its tokens, or says why the row is malformed.
+    pub(crate) fn split_row<'a>(&self, row: &'a str) -> Result<Vec<&'a str>, String> {
+        let delimiter = match self.delimiter {
+            None => return Ok(row.split_whitespace().collect()),
+            Some(_) if row.trim().is_empty() => return Ok(vec![]),
+            Some(delimiter) => delimiter,
+        };
+
+        let tokens: Vec<&str> = row.split(delimiter).map(str::trim).collect();
+
+        match tokens.iter().position(|token| token.is_empty()) {
+            Some(position) if position + 1 == tokens.len() => Err(format!("ends with the delimiter {:?}", delimiter)),
+            Some(position) => Err(format!("has an empty element at position {}", position)),
+            None => Ok(tokens),
+        }
+    }
+
+    /// Parses `token`, returning a hint to append to the error when it fails.
+    pub(crate) fn parse<T: FromStr>(&self, token: &str) -> Result<T, &'static str> {
+        let token = strip_digit_separators(token);
+
+        if let Ok(num) = token.parse::<T>() {
+            return Ok(num)
+        }
+
+        let integral = integral_part(&token).and_then(|integral| integral.parse::<T>().ok());
+
+        match integral {
+            Some(num) if self.lenient_integers => Ok(num),
+            Some(_) => Err(" (integral float, enable the lenient_integers parse option to accept it)"),
+            None if token.contains(['e', 'E']) && token.parse::<f64>().is_ok() =>
+                Err(" (scientific notation isn't accepted here, write the number out in full)"),
+            None => Err(""),
+        }
+    }
+
+    /// Like [`ParseOptions::parse`] for a matrix element, applying the
+    /// [`OverflowPolicy`] and saying when the token was out of range.
+    pub(crate) fn parse_element<T: FromStr + Element>(&self, token: &str) -> Result<(T, Option<OutOfRange>), &'static str> {
+        let num = self.parse::<T>(token)?;
+
+        match (out_of_range(&strip_digit_separators(token), num), self.overflow) {
+            (None, _) | (Some(_), OverflowPolicy::Allow) => Ok((num, None)),
+            (Some(OutOfRange::Overflow), OverflowPolicy::Error) => Err(" (overflows to infinity, rejected by OverflowPolicy::Error)"),
+            (Some(OutOfRange::Underflow), OverflowPolicy::Error) => Err(" (underflows to zero, rejected by OverflowPolicy::Error)"),
+            (Some(OutOfRange::Overflow), OverflowPolicy::SaturateAndWarn) => {
+                let (lowest, highest) = T::FLOAT_RANGE.ok_or("")?;
+                let saturated = if num.to_f64() < 0.0 { lowest } else { highest };
+                Ok((saturated, Some(OutOfRange::Overflow)))
+            },
+            (Some(OutOfRange::Underflow), OverflowPolicy::SaturateAndWarn) => Ok((num, Some(OutOfRange::Underflow))),
+        }
+    }
+}
+
+/// Whether the float literal `token`, parsed as `num`, left the range of its
+/// float type; integer types are never out of range.
+fn out_of_range<T: Element>(token: &str, num: T) -> Option<OutOfRange> {
+    T::FLOAT_RANGE?;
+    let value = num.to_f64();
+
+    let lowercase = token.to_ascii_lowercase();
+    let mantissa = lowercase.split('e').next().unwrap_or_default();
+
+    if value.is_infinite() && !lowercase.contains("inf") {
+        Some(OutOfRange::Overflow)
+    } else if value == 0.0 && mantissa.contains(|c: char| ('1'..='9').contains(&c)) {
+        Some(OutOfRange::Underflow)
+    } else {
+        None
+    }
+}
+
+/// Removes underscores that sit between two digits, leaving any other
+/// underscore in place so the token still fails to parse.
+fn strip_digit_separators(token: &str) -> Cow<'_, str> {
+    if !token.contains('_') {
+        return Cow::Borrowed(token)
+    }
+
+    let chars: Vec<char> = token.chars().collect();
+
+    Cow::Owned(chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| c != '_' || !(i > 0 && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(char::is_ascii_digit)))
+        .map(|(_, c)| c)
+        .collect())
+}
+
+/// `-5.00` gives `-5`; anything with a fraction, an exponent or no digits gives `None`.
+fn integral_part(token: &str) -> Option<&str> {
+    let (integer, fraction) = token.split_once('.')?;
+    let digits = integer.strip_prefix(['-', '+']).unwrap_or(integer);
+
+    let is_integral = !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c == '0');
+
+    is_integral.then_some(integer)
+}
+
+#[cfg(test)]
+mod parse_test {
+    use std::sync::{Arc, Mutex};
+    use crate::matrix::Matrix;
+    use crate::parse::{OutOfRange, OverflowPolicy, ParseOptions, ProgressHook};
+
+    #[test]
+    fn progress_hook_reports_every_n_rows_and_the_end() {
+        let mut contents = vec![String::from("10"), String::from("2")];
+        contents.extend((0..10).map(|row| format!("{} {}", row, row * 2)));
+        let contents: Vec<&str> = contents.iter().map(String::as_str).collect();
+
+        let calls = Arc::new(Mutex::new(vec![]));
+        let recorded = Arc::clone(&calls);
+        let progress = ProgressHook::new(3, move |parsed, total| recorded.lock().unwrap().push((parsed, total)));
+
+        let with_hook = Matrix::<i32>::from_vec_with(contents.clone(), &ParseOptions::new().progress(Some(progress)));
+
+        assert_eq!(*calls.lock().unwrap(), vec![(3, 10), (6, 10), (9, 10), (10, 10)]);
+        assert_eq!(with_hook, Matrix::from_vec(contents));
+    }
+
+    #[test]
+    fn progress_hook_on_exact_multiple_and_column_major() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let recorded = Arc::clone(&calls);
+        let options = ParseOptions::new().progress(Some(ProgressHook::new(2, move |parsed, total| {
+            recorded.lock().unwrap().push((parsed, total))
+        })));
+
+        Matrix::<i32>::from_vec_with(vec!["4", "1", "1", "2", "3", "4"], &options).unwrap();
+        Matrix::<i32>::from_vec_with(vec!["1", "3", "column-major", "1", "2", "3"], &options).unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), vec![(2, 4), (4, 4), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn overflow_policies() {
+        let policy = |overflow| ParseOptions::new().overflow(overflow);
+
+        assert!(policy(OverflowPolicy::Error).parse_element::<f64>("1e999").unwrap_err().contains("overflows to infinity"));
+        assert!(policy(OverflowPolicy::Error).parse_element::<f64>("1e-999").unwrap_err().contains("underflows to zero"));
+        assert_eq!(policy(OverflowPolicy::SaturateAndWarn).parse_element::<f64>("1e999"),
+                   Ok((f64::MAX, Some(OutOfRange::Overflow))));
+        assert_eq!(policy(OverflowPolicy::SaturateAndWarn).parse_element::<f32>("-1e39"),
+                   Ok((f32::MIN, Some(OutOfRange::Overflow))));
+        assert_eq!(policy(OverflowPolicy::SaturateAndWarn).parse_element::<f64>("1e-999"),
+                   Ok((0.0, Some(OutOfRange::Underflow))));
+        assert_eq!(policy(OverflowPolicy::Allow).parse_element::<f64>("1e999"), Ok((f64::INFINITY, None)));
+        assert_eq!(ParseOptions::new().parse_element::<f64>("-1e999"), Ok((f64::NEG_INFINITY, None)));
+
+        for token in ["inf", "-infinity", "0.0", "0e999", "1e300", "4.9e-324"] {
+            assert_eq!(policy(OverflowPolicy::Error).parse_element::<f64>(token).map(|(_, warning)| warning), Ok(None));
+        }
+        assert_eq!(policy(OverflowPolicy::Error).parse_element::<i64>("9223372036854775807").map(|(_, w)| w), Ok(None));
+    }
+
+    #[test]
+    fn underscores_between_digits() {
+        let strict = ParseOptions::new();
+
+        assert_eq!(strict.parse::<i32>("1_000"), Ok(1000));
+        assert_eq!(strict.parse::<f64>("1_000.5"), Ok(1000.5));
+        assert!(strict.parse::<i32>("_1").is_err());
+        assert!(strict.parse::<i32>("1_").is_err());
+        assert!(strict.parse::<i32>("1__0").is_err());
+    }
+
+    #[test]
+    fn strict_integers() {
+        let strict = ParseOptions::new();
+
+        assert!(strict.parse::<i32>("5.0").unwrap_err().contains("lenient_integers"));
+        assert!(strict.parse::<i32>("1e3").unwrap_err().contains("scientific notation"));
+        assert_eq!(strict.parse::<i32>("5.5"), Err(""));
+        assert_eq!(strict.parse::<i32>("x"), Err(""));
+    }
+
+    #[test]
+    fn split_rows_on_delimiter() {
+        let whitespace = ParseOptions::new();
+        let tabs = ParseOptions::new().delimiter(Some('\t'));
+
+        assert_eq!(whitespace.split_row(" 1\t2  3 "), Ok(vec!["1", "2", "3"]));
+        assert_eq!(tabs.split_row("1 \t 2\t3"), Ok(vec!["1", "2", "3"]));
+        assert_eq!(tabs.split_row("  "), Ok(vec![]));
+        assert_eq!(tabs.split_row("1\t\t3").unwrap_err(), "has an empty element at position 1");
+        assert_eq!(tabs.split_row("1\t2\t").unwrap_err(), "ends with the delimiter '\\t'");
+        assert_eq!(ParseOptions::new().delimiter(Some(',')).split_row(",1,2").unwrap_err(), "has an empty element at position 0");
+    }
+
+    #[test]
+    fn lenient_integers() {
+        let lenient = ParseOptions::new().lenient_integers(true);
+
+        assert_eq!(lenient.parse::<i32>("5.0"), Ok(5));
+        assert_eq!(lenient.parse::<i64>("-1_000.000"), Ok(-1000));
+        assert_eq!(lenient.parse::<u32>("7."), Ok(7));
+        assert!(lenient.parse::<i32>("5.5").is_err());
+        assert!(lenient.parse::<i32>("1e3").is_err());
+        assert!(lenient.parse::<i32>(".0").is_err());
+        assert!(lenient.parse::<u32>("-3.0").is_err());
+    }
+}
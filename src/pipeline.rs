@@ -0,0 +1,160 @@
+use std::ops::{AddAssign, Mul};
+use crate::layout::transpose;
+use crate::matrix::Matrix;
+use crate::multiplication::multiply;
+use crate::shape::{mismatch_message, Shape};
+
+enum Step<'a, T> {
+    Transpose,
+    Multiply(&'a Matrix<T>),
+    Add(&'a Matrix<T>),
+}
+
+impl<T> Step<'_, T> {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Transpose => "transpose",
+            Step::Multiply(_) => "multiply",
+            Step::Add(_) => "add",
+        }
+    }
+}
+
+/// A chain of operations whose shapes are checked as each step is added, from
+/// the dimensions alone, so a bad step fails before anything is computed.
+///
+/// `Pipeline::new(&a).transpose().multiply(&b)?.add(&c)?.run(threads)`
+pub struct Pipeline<'a, T> {
+    input: &'a Matrix<T>,
+    steps: Vec<Step<'a, T>>,
+    shape: Shape,
+}
+
+impl<'a, T> Pipeline<'a, T> {
+    pub fn new(input: &'a Matrix<T>) -> Pipeline<'a, T> {
+        Pipeline { input, steps: vec![], shape: input.shape() }
+    }
+
+    /// Shape of the result `run` will return.
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn transpose(mut self) -> Pipeline<'a, T> {
+        self.shape = Shape { rows: self.shape.cols, cols: self.shape.rows };
+        self.steps.push(Step::Transpose);
+        self
+    }
+
+    pub fn multiply(mut self, other: &'a Matrix<T>) -> Result<Pipeline<'a, T>, String> {
+        if self.shape.cols != other.rows() {
+            let expected = Shape { rows: self.shape.cols, cols: other.columns() };
+            return Err(self.step_error("multiply", mismatch_message(expected, other.shape())))
+        }
+
+        self.shape = Shape { rows: self.shape.rows, cols: other.columns() };
+        self.steps.push(Step::Multiply(other));
+        Ok(self)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, other: &'a Matrix<T>) -> Result<Pipeline<'a, T>, String> {
+        if self.shape != other.shape() {
+            return Err(self.step_error("add", mismatch_message(self.shape, other.shape())))
+        }
+
+        self.steps.push(Step::Add(other));
+        Ok(self)
+    }
+
+    fn step_error(&self, name: &str, message: String) -> String {
+        format!("Step {} ({}): {}", self.steps.len(), name, message)
+    }
+}
+
+impl<T: Clone> Pipeline<'_, T>
+    where
+        for<'b> &'b T: Mul<Output=T>,
+        T: AddAssign<T> + Sync + Send + 'static {
+
+    /// Executes the planned steps in order. Transposes and additions work in
+    /// place on the intermediate result; products use up to `num_of_threads`
+    /// threads, capped at the rows of their left operand.
+    pub fn run(self, num_of_threads: usize) -> Result<Matrix<T>, String> {
+        let mut current = Matrix::new(self.input.rows(), self.input.columns(), self.input.get_numbers().to_vec())?;
+
+        for (index, step) in self.steps.iter().enumerate() {
+            let (rows, columns) = (current.rows(), current.columns());
+
+            current = match step {
+                Step::Transpose => Matrix::new(columns, rows, transpose(current.into_vec(), rows, columns))?,
+                Step::Multiply(other) => multiply(&current, other, num_of_threads.min(rows).max(1))
+                    .map_err(|error| format!("Step {} ({}): {}", index, step.name(), error))?,
+                Step::Add(other) => {
+                    let mut numbers = current.into_vec();
+                    for (num, other) in numbers.iter_mut().zip(other.get_numbers().iter()) {
+                        *num += other.clone();
+                    }
+                    Matrix::new(rows, columns, numbers)?
+                },
+            };
+        }
+
+        Ok(current)
+    }
+}
+
+#[cfg(test)]
+mod pipeline_test {
+    use std::ops::Mul;
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+    use crate::pipeline::Pipeline;
+
+    /// An element that can't be computed with, so any arithmetic fails the test.
+    #[derive(Clone, Debug, PartialEq)]
+    struct Untouchable(i64);
+
+    impl Mul for &Untouchable {
+        type Output = Untouchable;
+
+        fn mul(self, _: &Untouchable) -> Untouchable {
+            panic!("computed a product")
+        }
+    }
+
+    fn transposed(matrix: &Matrix<i64>) -> Matrix<i64> {
+        Matrix::from_column_major(matrix.columns(), matrix.rows(), matrix.get_numbers().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn matches_composed_library_calls() {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let b = Matrix::new(2, 4, (0..8).collect()).unwrap();
+        let c = Matrix::new(3, 4, (0..12).map(|num| num * 10).collect()).unwrap();
+
+        let pipeline = Pipeline::new(&a).transpose().multiply(&b).unwrap().add(&c).unwrap();
+        assert_eq!(pipeline.shape().to_string(), "3x4");
+
+        let product = multiply(&transposed(&a), &b, 1).unwrap();
+        let expected: Vec<i64> = product.get_numbers().iter().zip(c.get_numbers().iter()).map(|(x, y)| x + y).collect();
+
+        assert_eq!(pipeline.run(8).unwrap(), Matrix::new(3, 4, expected).unwrap());
+    }
+
+    #[test]
+    fn invalid_middle_step_fails_before_computing() {
+        let a = Matrix::new(2, 3, (1..=6).map(Untouchable).collect()).unwrap();
+        let b = Matrix::new(2, 4, (0..8).map(Untouchable).collect()).unwrap();
+
+        let error = Pipeline::new(&a).transpose().multiply(&b).unwrap().add(&a).err().unwrap();
+        assert_eq!(error, "Step 2 (add): Dimension mismatch: expected 3x4, got 2x3");
+
+        let error = Pipeline::new(&a).multiply(&b).err().unwrap();
+        assert_eq!(error, "Step 0 (multiply): Dimension mismatch: expected 3x4, got 2x4");
+
+        // The inputs are only borrowed, so a failed pipeline leaves them as they were.
+        assert_eq!(a, Matrix::new(2, 3, (1..=6).map(Untouchable).collect()).unwrap());
+        assert_eq!(b.shared_count(), 1);
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt::{Display, LowerExp};
+use std::fs;
+use std::io::Write;
+use std::ops::{AddAssign, Mul};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use crate::element::{Element, ElementType};
+use crate::io;
+use crate::matrix::Matrix;
+use crate::multiplication::{multiply_with_options, Config, MagnitudeBound, MultiplyError, MultiplyOptions, MultiplyStats};
+use crate::plan::{max_magnitude, read_input, Plan};
+use crate::write::{temp_path, RowRenderer, WriteOptions};
+
+/// Rendered row blocks that may wait for the writer before computing stalls.
+pub(crate) const PIPELINE_DEPTH: usize = 4;
+
+/// Output rows computed, rendered and sent to the writer at a time.
+pub(crate) const BLOCK_ROWS: usize = 64;
+
+/// Like [`run`](crate::multiplication::run), but overlapping the phases: A and
+/// B are parsed on separate threads, and each block of output rows is written
+/// by a writer thread while later blocks are computed. At most
+/// [`PIPELINE_DEPTH`] blocks are queued, so a slow disk stalls the computation
+/// instead of letting the output pile up in memory.
+///
+/// The output is identical to a sequential run. It is written through a
+/// temporary file, so a failed run never leaves part of it behind.
+pub fn run_pipelined(config: Config) -> Result<MultiplyStats, String> {
+    let plan = Plan::from_config(&config).map_err(|errors| {
+        errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+    })?;
+
+    execute(&plan)
+}
+
+pub(crate) fn execute(plan: &Plan) -> Result<MultiplyStats, String> {
+    match plan.element_type {
+        ElementType::F64 => execute_as::<f64>(plan),
+        ElementType::I64 => execute_as::<i64>(plan),
+    }
+}
+
+fn execute_as<T>(plan: &Plan) -> Result<MultiplyStats, String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: FromStr + Display + LowerExp + Element + AddAssign<T> + Sync + Send + 'static {
+
+    let config = &plan.config;
+
+    let (matrix_a, matrix_b) = thread::scope(|scope| {
+        let matrix_b = scope.spawn(|| read_input::<T>(&config.matrix_b_file_name, config.verbose, config.limits));
+        let matrix_a = read_input::<T>(&config.matrix_a_file_name, config.verbose, config.limits);
+        let matrix_b = matrix_b.join()
+            .unwrap_or_else(|err| Err(format!("Error joining parser thread, error:\n{err:?}")));
+        matrix_a.and_then(|matrix_a| Ok((matrix_a, matrix_b?)))
+    })?;
+
+    let mut options = MultiplyOptions::new(plan.num_of_threads);
+    options.kernel = plan.kernel;
+    options.limits = config.limits;
+    options.cancel_token = config.cancel_token.clone();
+    options.abort_if_exceeds = config.abort_if_exceeds.map(MagnitudeBound::new);
+
+    if config.verbose {
+        println!("Threads: {} ({})", plan.num_of_threads, plan.thread_source);
+        println!("Element type: {}", plan.element_type);
+    }
+
+    let write_options = WriteOptions::new().format(config.output_format);
+    let (output, temp) = (&config.matrix_c_file_name, temp_path(&config.matrix_c_file_name));
+
+    let result = io::create_file(&temp)
+        .and_then(|file| multiply_to_writer(&matrix_a, &matrix_b, &options, BLOCK_ROWS, &write_options, file,
+                                            &AtomicUsize::new(0)))
+        .and_then(|(_, max_magnitude)| fs::rename(&temp, output)
+            .map(|_| max_magnitude)
+            .map_err(|error| format!("Error writing to file {}: {}", output, error)));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    Ok(MultiplyStats { num_of_threads: plan.num_of_threads, kernel: options.effective_kernel(), denormals_flushed: 0,
+        max_magnitude: result? })
+}
+
+/// Computes A·B `block_rows` output rows at a time and streams them to
+/// `writer` from a separate thread, returning the writer and the largest
+/// output magnitude once everything is written. `rows_computed` counts the
+/// output rows handed to the writer.
+pub(crate) fn multiply_to_writer<T, W>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, options: &MultiplyOptions<T>,
+                                       block_rows: usize, write_options: &WriteOptions, writer: W,
+                                       rows_computed: &AtomicUsize) -> Result<(W, f64), String>
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: Display + LowerExp + Element + AddAssign<T> + Sync + Send + 'static,
+        W: Write + Send {
+
+    let renderer = RowRenderer::new(write_options)?;
+
+    if block_rows == 0 {
+        return Err(String::from("Block rows: 0 must be higher than 0"))
+    }
+
+    let (rows, n, columns) = (matrix_a.rows(), matrix_a.columns(), matrix_b.columns());
+    let a_numbers = matrix_a.get_numbers();
+    let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(PIPELINE_DEPTH);
+    let mut max = 0.0f64;
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || {
+            let mut writer = writer;
+            for block in receiver {
+                writer.write_all(&block).map_err(|error| format!("Error writing output: {}", error))?;
+            }
+            writer.flush().map_err(|error| format!("Error writing output: {}", error))?;
+            Ok(writer)
+        });
+
+        let computed = (|| {
+            // A failed send means the writer has stopped; its error is reported below.
+            if sender.send(renderer.header(rows, columns).into_bytes()).is_err() {
+                return Ok(())
+            }
+
+            for start in (0..rows).step_by(block_rows) {
+                let end = (start + block_rows).min(rows);
+                let block = Matrix::new(end - start, n, a_numbers[start * n..end * n].to_vec())?;
+
+                let mut block_options = options.clone();
+                block_options.num_of_threads = options.num_of_threads.min(end - start);
+
+                let product = multiply_with_options(&block, matrix_b, &block_options).map_err(|error| match error {
+                    MultiplyError::Cancelled { rows_completed, .. } =>
+                        MultiplyError::Cancelled { rows_completed: start + rows_completed, total_rows: rows },
+                    MultiplyError::TimedOut { rows_completed, .. } =>
+                        MultiplyError::TimedOut { rows_completed: start + rows_completed, total_rows: rows },
+                    MultiplyError::BoundExceeded { row, column, value, bound } =>
+                        MultiplyError::BoundExceeded { row: start + row, column, value, bound },
+                    error => error,
+                })?;
+
+                rows_computed.fetch_add(end - start, Ordering::SeqCst);
+                max = max.max(max_magnitude(&product.get_numbers()));
+
+                if sender.send(renderer.rows(&product.get_numbers(), end - start, columns).into_bytes()).is_err() {
+                    return Ok(())
+                }
+            }
+
+            Ok(())
+        })();
+
+        drop(sender);
+
+        let written = writer.join()
+            .unwrap_or_else(|err| Err(format!("Error joining writer thread, error:\n{err:?}")));
+        computed.and(written).map(|writer| (writer, max))
+    })
+}
+
+#[cfg(test)]
+mod pipelined_test {
+    use std::fs;
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::matrix::Matrix;
+    use crate::multiplication::{run, Config, MultiplyOptions};
+    use crate::pipelined::{multiply_to_writer, PIPELINE_DEPTH};
+    use crate::test_support::temp_path;
+    use crate::write::WriteOptions;
+
+    fn random(rows: usize, columns: usize, seed: u64) -> Matrix<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Matrix::new(rows, columns, (0..rows * columns).map(|_| rng.gen_range(-10.0..10.0)).collect()).unwrap()
+    }
+
+    fn config(args: &[&str]) -> Config {
+        Config::from_iter(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn pipelined_output_matches_sequential() {
+        let (matrix_a, matrix_b) = (temp_path("a.txt"), temp_path("b.txt"));
+        let (sequential, pipelined) = (temp_path("sequential.txt"), temp_path("pipelined.txt"));
+        random(150, 40, 1).to_file(&matrix_a).unwrap();
+        random(40, 30, 2).to_file(&matrix_b).unwrap();
+
+        let mut outputs = vec![];
+        for format in ["plain", "csv"] {
+            run(config(&["mm", &matrix_a, &matrix_b, &sequential, "3", "--format", format])).unwrap();
+            run(config(&["mm", &matrix_a, &matrix_b, &pipelined, "3", "--format", format, "--pipeline"])).unwrap();
+            outputs.push((fs::read_to_string(&sequential).unwrap(), fs::read_to_string(&pipelined).unwrap()));
+        }
+
+        for file in [&matrix_a, &matrix_b, &sequential, &pipelined] {
+            fs::remove_file(file).unwrap();
+        }
+
+        for (sequential, pipelined) in outputs {
+            assert!(sequential.len() > 10_000);
+            assert_eq!(sequential, pipelined);
+        }
+    }
+
+    #[test]
+    fn pipeline_rejects_settings_it_would_ignore() {
+        let parse = |extra: &[&str]| {
+            let args = ["mm", "a", "b", "c", "1", "--pipeline"].iter().chain(extra);
+            Config::from_iter(args.map(|arg| arg.to_string())).err()
+        };
+
+        for extra in [&["--zero-tolerance", "1e-9"][..], &["--format", "coo"], &["--annotate"]] {
+            assert_eq!(parse(extra).as_deref(),
+                       Some("--pipeline needs plain text or CSV output without --annotate, --flush-denormals, --record \
+                             or --zero-tolerance"), "{extra:?}");
+        }
+        assert_eq!(parse(&["--format", "csv"]), None);
+    }
+
+    /// Sleeps on every write and records how far computation got ahead of it.
+    struct SlowWriter<'a> {
+        rows_computed: &'a AtomicUsize,
+        rows_written: usize,
+        max_lead: usize,
+    }
+
+    impl Write for SlowWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            thread::sleep(Duration::from_millis(2));
+            self.rows_written += buf.iter().filter(|&&byte| byte == b'\n').count();
+            self.max_lead = self.max_lead.max(self.rows_computed.load(Ordering::SeqCst).saturating_sub(self.rows_written));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn slow_writer_stalls_computation() {
+        let (matrix_a, matrix_b) = (random(400, 3, 3), random(3, 2, 4));
+        let rows_computed = AtomicUsize::new(0);
+        let writer = SlowWriter { rows_computed: &rows_computed, rows_written: 0, max_lead: 0 };
+
+        let (writer, _) = multiply_to_writer(&matrix_a, &matrix_b, &MultiplyOptions::new(1), 2, &WriteOptions::new(),
+                                             writer, &rows_computed).unwrap();
+
+        // The header's two lines count as written rows, and one block can be in
+        // hand between the channel and the writer on each side.
+        assert_eq!(writer.rows_written, 402);
+        assert!(writer.max_lead <= (PIPELINE_DEPTH + 2) * 2, "computation ran {} rows ahead", writer.max_lead);
+    }
+}
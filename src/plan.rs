@@ -0,0 +1,692 @@
+use std::fmt::{Display, Formatter, LowerExp};
+use std::fs;
+use std::io::Read;
+use std::ops::{AddAssign, Mul};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Instant;
+use crate::element::{Element, ElementType};
+use crate::io::{self, ReadError};
+use crate::limits::{LimitExceeded, Limits};
+use crate::matrix::{Aliasing, Matrix};
+use crate::multiplication::{multiply_with_buffers, relative_error_bound, Config, Kernel, MagnitudeBound,
+                            MultiplyOptions, MultiplyStats, GREEDY_VAR};
+use crate::parse::{ParseOptions, ProgressHook};
+use crate::record::{FileRecord, RunRecord};
+use crate::shape::{mismatch_message, Shape};
+use crate::tune::TuneResult;
+use crate::workspace::{Buffers, Workspace};
+use crate::write::WriteOptions;
+
+/// Rows between the parsing progress lines printed with `--verbose`.
+const VERBOSE_PROGRESS_ROWS: u64 = 1_000_000;
+
+/// Bytes between the reading progress lines printed with `--verbose`.
+const VERBOSE_PROGRESS_BYTES: u64 = 64 << 20;
+
+/// Passes reads through, calling `progress` with the bytes read so far once
+/// every `every` bytes and once at the end of the input.
+struct ProgressReader<R, F> {
+    inner: R,
+    bytes_read: u64,
+    next_report: u64,
+    every: u64,
+    finished: bool,
+    progress: F,
+}
+
+impl<R, F: FnMut(u64)> ProgressReader<R, F> {
+    /// `every` is at least 1.
+    fn new(inner: R, every: u64, progress: F) -> ProgressReader<R, F> {
+        let every = every.max(1);
+        ProgressReader { inner, bytes_read: 0, next_report: every, every, finished: false, progress }
+    }
+}
+
+impl<R: Read, F: FnMut(u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.bytes_read += len as u64;
+
+        if len == 0 && !buf.is_empty() && !self.finished {
+            self.finished = true;
+            (self.progress)(self.bytes_read);
+        } else if self.bytes_read >= self.next_report {
+            self.next_report = (self.bytes_read / self.every + 1) * self.every;
+            (self.progress)(self.bytes_read);
+        }
+
+        Ok(len)
+    }
+}
+
+/// Reads a plain text input within `limits`, printing how far parsing got when `verbose`.
+pub(crate) fn read_input<T: FromStr + Display + Element>(file_name: &str, verbose: bool, limits: Limits) -> Result<Matrix<T>, String> {
+    read_input_reusing(file_name, verbose, limits, &mut String::new(), Vec::new())
+}
+
+/// Like [`read_input`], reading the file into `text` and parsing into `numbers`.
+fn read_input_reusing<T: FromStr + Display + Element>(file_name: &str, verbose: bool, limits: Limits, text: &mut String,
+                                            numbers: Vec<T>) -> Result<Matrix<T>, String> {
+
+    let mut options = ParseOptions::new().limits(limits);
+
+    if verbose {
+        let name = file_name.to_string();
+        options = options.progress(Some(ProgressHook::new(VERBOSE_PROGRESS_ROWS, move |parsed, total| {
+            println!("Parsed {} of {} rows of {} ({:.0}%)", parsed, total, name, 100.0 * parsed as f64 / total as f64);
+        })));
+    }
+
+    text.clear();
+    let mut reader = io::open_limited(file_name, &limits)?;
+
+    let read = if verbose {
+        let (name, size) = (file_name.to_string(), fs::metadata(file_name).map(|metadata| metadata.len()).ok());
+        ProgressReader::new(reader, VERBOSE_PROGRESS_BYTES, move |read| match size {
+            Some(size) if size > 0 => println!("Read {} of {} bytes of {} ({:.0}%)", read, size, name,
+                                               100.0 * read as f64 / size as f64),
+            _ => println!("Read {} bytes of {}", read, name),
+        }).read_to_string(text)
+    } else {
+        reader.read_to_string(text)
+    };
+    read.map_err(|error| format!("Couldn't read matrix: {}", error))?;
+
+    Matrix::from_iterator_reusing(text.lines(), &options, numbers)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PlanError {
+    UnreadableInput { file_name: String, reason: String },
+    InvalidHeader { file_name: String, reason: String },
+    /// A token in the rows probed for `--dtype auto` isn't a number.
+    InvalidData { file_name: String, reason: String },
+    /// B's expected shape given A's header, and the shape its header declares.
+    DimensionMismatch { expected: Shape, actual: Shape },
+    UnwritableOutput { file_name: String, reason: String },
+    InvalidThreadCount { num_of_threads: usize, rows: Option<usize> },
+    InvalidTuneProfile { file_name: String, reason: String },
+    BlankInput(ReadError),
+    /// The input's header or size is over the configured [`Limits`].
+    InputTooLarge { file_name: String, error: LimitExceeded },
+    /// A·B would be over the configured [`Limits`].
+    OutputTooLarge(LimitExceeded),
+}
+
+impl Display for PlanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanError::UnreadableInput { file_name, reason } =>
+                write!(f, "Input file {} can't be read: {}", file_name, reason),
+            PlanError::InvalidHeader { file_name, reason } =>
+                write!(f, "Input file {} has an invalid header: {}", file_name, reason),
+            PlanError::InvalidData { file_name, reason } =>
+                write!(f, "Input file {} has invalid data: {}", file_name, reason),
+            PlanError::DimensionMismatch { expected, actual } =>
+                write!(f, "{}", mismatch_message(*expected, *actual)),
+            PlanError::UnwritableOutput { file_name, reason } =>
+                write!(f, "Output file {} can't be written: {}", file_name, reason),
+            PlanError::InvalidThreadCount { num_of_threads, rows: None } =>
+                write!(f, "Num of threads: {num_of_threads} must be higher than 0"),
+            PlanError::InvalidThreadCount { num_of_threads, rows: Some(rows) } =>
+                write!(f, "Num of threads: {num_of_threads} cannot be higher than Matrix A rows: {rows}"),
+            PlanError::InvalidTuneProfile { file_name, reason } =>
+                write!(f, "Tune profile {} can't be used: {}", file_name, reason),
+            PlanError::BlankInput(error) =>
+                write!(f, "{}. Check that the step that should have generated it actually ran", error),
+            PlanError::InputTooLarge { file_name, error } =>
+                write!(f, "Input file {} is too large: {}", file_name, error),
+            PlanError::OutputTooLarge(error) =>
+                write!(f, "Result is too large: {}", error),
+        }
+    }
+}
+
+/// Why a plan uses the thread count it does, printed with `--verbose`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThreadSource {
+    /// Given on the command line.
+    Explicit,
+    /// Taken from the tune profile.
+    Tuned,
+    /// `auto` with [`GREEDY_VAR`] set: every available core.
+    Greedy,
+    /// `auto`: every available core but one, so the machine stays usable.
+    Polite,
+}
+
+impl Display for ThreadSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThreadSource::Explicit => write!(f, "given explicitly"),
+            ThreadSource::Tuned => write!(f, "from the tune profile"),
+            ThreadSource::Greedy => write!(f, "every core, {}=1", GREEDY_VAR),
+            ThreadSource::Polite => write!(f, "one core left free, set {}=1 to use all", GREEDY_VAR),
+        }
+    }
+}
+
+/// A [`Config`] whose inputs, output location and thread count have been
+/// checked, together with the operand shapes read from the file headers and
+/// the defaults resolved from the tune profile.
+#[derive(Debug)]
+pub struct Plan {
+    pub(crate) config: Config,
+    a_shape: (usize, usize),
+    b_shape: (usize, usize),
+    pub(crate) num_of_threads: usize,
+    pub(crate) thread_source: ThreadSource,
+    pub(crate) kernel: Kernel,
+    pub(crate) element_type: ElementType,
+}
+
+impl Plan {
+    /// Checks everything that can be known before parsing the matrices and
+    /// returns all problems found rather than only the first.
+    pub fn from_config(config: &Config) -> Result<Plan, Vec<PlanError>> {
+        let mut errors = Vec::new();
+
+        let a_shape = check_input(&config.matrix_a_file_name, &config.limits, &mut errors);
+        let b_shape = check_input(&config.matrix_b_file_name, &config.limits, &mut errors);
+
+        if let (Some((a_rows, a_columns)), Some((b_rows, b_columns))) = (a_shape, b_shape) {
+            if a_columns != b_rows {
+                errors.push(PlanError::DimensionMismatch {
+                    expected: Shape { rows: a_columns, cols: b_columns },
+                    actual: Shape { rows: b_rows, cols: b_columns } });
+            } else if let Err(error) = config.limits.check_shape(Shape { rows: a_rows, cols: b_columns }) {
+                errors.push(PlanError::OutputTooLarge(error));
+            }
+        }
+
+        if let Err(reason) = check_output(&config.matrix_c_file_name) {
+            errors.push(PlanError::UnwritableOutput {
+                file_name: config.matrix_c_file_name.clone(), reason });
+        }
+
+        let profile = config.tune_profile.as_ref().and_then(|file_name| match TuneResult::load(file_name) {
+            Ok(profile) => Some(profile),
+            Err(reason) => {
+                errors.push(PlanError::InvalidTuneProfile { file_name: file_name.clone(), reason });
+                None
+            }
+        });
+
+        let tuned = match (&profile, a_shape, b_shape) {
+            (Some(profile), Some((rows, n)), Some((_, columns))) => profile.lookup(rows.max(n).max(columns)),
+            _ => None,
+        };
+
+        let kernel = tuned.map_or(Kernel::Naive, |entry| entry.kernel);
+
+        let rows = a_shape.map_or(1, |(rows, _)| rows);
+        let (num_of_threads, thread_source) = match (config.num_of_threads, tuned) {
+            (None, Some(entry)) => (entry.num_of_threads.min(rows).max(1), ThreadSource::Tuned),
+            (explicit, _) => resolve_thread_count(explicit, config.greedy, available_threads(), rows),
+        };
+
+        if num_of_threads == 0 {
+            errors.push(PlanError::InvalidThreadCount { num_of_threads, rows: None });
+        }
+
+        // Both operands are read as one type, so a single float file makes it f64.
+        let element_type = config.element_type.unwrap_or_else(|| {
+            let probed: Vec<ElementType> = [(&config.matrix_a_file_name, a_shape), (&config.matrix_b_file_name, b_shape)]
+                .into_iter()
+                .filter(|(_, shape)| shape.is_some())
+                .filter_map(|(file_name, _)| match io::probe_element_type(file_name) {
+                    Ok(element_type) => Some(element_type),
+                    Err(reason) => {
+                        errors.push(PlanError::InvalidData { file_name: file_name.clone(), reason });
+                        None
+                    }
+                })
+                .collect();
+
+            if probed.contains(&ElementType::F64) { ElementType::F64 } else { ElementType::I64 }
+        });
+
+        match (a_shape, b_shape) {
+            (Some(a_shape), Some(b_shape)) if errors.is_empty() =>
+                Ok(Plan { config: config.clone(), a_shape, b_shape, num_of_threads, thread_source, kernel, element_type }),
+            _ => Err(errors)
+        }
+    }
+
+    pub fn output_shape(&self) -> (usize, usize) {
+        (self.a_shape.0, self.b_shape.1)
+    }
+
+    pub fn execute(&self) -> Result<MultiplyStats, String> {
+        self.execute_with(&mut Workspace::new())
+    }
+
+    /// Like [`Plan::execute`], reusing the buffers of `workspace`.
+    pub fn execute_with(&self, workspace: &mut Workspace) -> Result<MultiplyStats, String> {
+        let text = &mut workspace.text;
+
+        match self.element_type {
+            ElementType::F64 => self.execute_as::<f64>(text, &mut workspace.f64, |matrix_a, matrix_b, options, buffers| {
+                let matrix_c = multiply_with_buffers(matrix_a, matrix_b, options, buffers)?;
+
+                if self.config.verbose {
+                    println!("Relative error bound: {:e}", relative_error_bound(matrix_a, matrix_b, &matrix_c, options)?);
+                }
+                Ok(matrix_c)
+            }, |matrix_c| matrix_c.flush_denormals_to_zero(Aliasing::default())),
+            ElementType::I64 => self.execute_as::<i64>(text, &mut workspace.i64,
+                |matrix_a, matrix_b, options, buffers| Ok(multiply_with_buffers(matrix_a, matrix_b, options, buffers)?),
+                |_| Ok(0)),
+        }
+    }
+
+    /// Reads, multiplies and writes as `T` using `text` and `buffers`, with
+    /// `multiply` computing the product and `flush_denormals` handling
+    /// `--flush-denormals`. The buffers are handed back once the output is written.
+    fn execute_as<T>(&self, text: &mut String, buffers: &mut Buffers<T>,
+                     multiply: impl FnOnce(&Matrix<T>, &Matrix<T>, &MultiplyOptions<T>, &mut Buffers<T>) -> Result<Matrix<T>, String>,
+                     flush_denormals: impl FnOnce(&mut Matrix<T>) -> Result<usize, String>) -> Result<MultiplyStats, String>
+        where
+            for<'a> &'a T: Mul<Output=T>,
+            T: FromStr + Display + LowerExp + Element + AddAssign<T> + Sync + Send + 'static {
+
+        let config = &self.config;
+        let start = Instant::now();
+
+        let matrix_a = read_input_reusing(&config.matrix_a_file_name, config.verbose, config.limits, text,
+                                          std::mem::take(&mut buffers.a))?;
+        let matrix_b = read_input_reusing(&config.matrix_b_file_name, config.verbose, config.limits, text,
+                                          std::mem::take(&mut buffers.b))?;
+
+        let mut options = MultiplyOptions::new(self.num_of_threads);
+        options.kernel = self.kernel;
+        options.limits = config.limits;
+        options.cancel_token = config.cancel_token.clone();
+        options.abort_if_exceeds = config.abort_if_exceeds.map(MagnitudeBound::new);
+
+        if config.verbose {
+            println!("Threads: {} ({})", self.num_of_threads, self.thread_source);
+            println!("Element type: {}", self.element_type);
+        }
+
+        let mut matrix_c = multiply(&matrix_a, &matrix_b, &options, buffers)?;
+
+        let denormals_flushed = if config.flush_denormals { flush_denormals(&mut matrix_c)? } else { 0 };
+        let max_magnitude = max_magnitude(&matrix_c.get_numbers());
+
+        if config.verbose && config.flush_denormals {
+            println!("Denormal results flushed to zero: {}", denormals_flushed);
+        }
+
+        let mut write_options = WriteOptions::new().format(config.output_format);
+
+        if let Some(zero_tolerance) = config.zero_tolerance {
+            write_options = write_options.zero_tolerance(zero_tolerance);
+        }
+
+        if config.annotate {
+            write_options = write_options.annotate(true)
+                .sources(vec![config.matrix_a_file_name.clone(), config.matrix_b_file_name.clone()]);
+        }
+
+        // Interruptible runs write through a temporary file, so a late signal
+        // can never leave half an output behind.
+        if config.cancel_token.is_some() {
+            write_options = write_options.atomic(true);
+        }
+
+        matrix_c.write_with(config.matrix_c_file_name.as_str(), &write_options)?;
+
+        if let Some(record) = &config.record {
+            let (rows, columns) = self.output_shape();
+            RunRecord {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                element_type: self.element_type.to_string(),
+                kernel: options.effective_kernel(),
+                num_of_threads: self.num_of_threads,
+                wall_time: start.elapsed(),
+                matrix_a: FileRecord::new(&config.matrix_a_file_name, self.a_shape.0, self.a_shape.1)?,
+                matrix_b: FileRecord::new(&config.matrix_b_file_name, self.b_shape.0, self.b_shape.1)?,
+                output: FileRecord::new(&config.matrix_c_file_name, rows, columns)?,
+            }.save(record)?;
+        }
+
+        (buffers.a, buffers.b, buffers.output) = (matrix_a.into_vec(), matrix_b.into_vec(), matrix_c.into_vec());
+
+        Ok(MultiplyStats { num_of_threads: self.num_of_threads, kernel: options.effective_kernel(), denormals_flushed, max_magnitude })
+    }
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Picks the thread count for a multiplication of `rows` rows: an explicit
+/// count always wins; otherwise `auto` uses every one of the `available`
+/// cores when `greedy`, and all but one when not.
+///
+/// Either way the count is clamped to the rows, since a thread without a row
+/// has nothing to do, so 1xN products run on any thread count. Only an
+/// explicit 0 is left alone, to be reported.
+pub(crate) fn resolve_thread_count(explicit: Option<usize>, greedy: bool, available: usize,
+                                   rows: usize) -> (usize, ThreadSource) {
+    let (num_of_threads, source) = match explicit {
+        Some(0) => return (0, ThreadSource::Explicit),
+        Some(num_of_threads) => (num_of_threads, ThreadSource::Explicit),
+        None if greedy => (available, ThreadSource::Greedy),
+        None => (available.saturating_sub(1), ThreadSource::Polite),
+    };
+
+    (num_of_threads.min(rows).max(1), source)
+}
+
+/// Largest absolute value among `numbers`, 0 if there are none.
+pub(crate) fn max_magnitude<T: Element>(numbers: &[T]) -> f64 {
+    numbers.iter().map(|num| num.to_f64().abs()).fold(0.0, f64::max)
+}
+
+fn check_input(file_name: &str, limits: &Limits, errors: &mut Vec<PlanError>) -> Option<(usize, usize)> {
+    if let Err(error) = fs::File::open(file_name) {
+        errors.push(PlanError::UnreadableInput {
+            file_name: file_name.to_string(), reason: error.to_string() });
+        return None
+    }
+
+    if let Some(error) = io::find_blank_file(file_name) {
+        errors.push(PlanError::BlankInput(error));
+        return None
+    }
+
+    let too_large = |error| PlanError::InputTooLarge { file_name: file_name.to_string(), error };
+
+    if let Err(error) = fs::metadata(file_name).map_or(Ok(()), |metadata| limits.check_file_bytes(metadata.len())) {
+        errors.push(too_large(error));
+        return None
+    }
+
+    match io::read_header(file_name) {
+        Ok((rows, cols)) => match limits.check_shape(Shape { rows, cols }) {
+            Ok(()) => Some((rows, cols)),
+            Err(error) => {
+                errors.push(too_large(error));
+                None
+            }
+        },
+        Err(reason) => {
+            errors.push(PlanError::InvalidHeader { file_name: file_name.to_string(), reason });
+            None
+        }
+    }
+}
+
+/// Probes the output directory by creating and removing a scratch file.
+fn check_output(file_name: &str) -> Result<(), String> {
+    let directory = match Path::new(file_name).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    if !directory.is_dir() {
+        return Err(format!("directory {} doesn't exist", directory.display()))
+    }
+
+    let probe = directory.join(format!(".mm_write_probe_{}", std::process::id()));
+
+    match fs::write(&probe, b"") {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(())
+        },
+        Err(error) => Err(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod plan_test {
+    use std::fs;
+    use std::io::Read;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::limits::{Limit, LimitExceeded};
+    use crate::matrix::Matrix;
+    use crate::multiplication::{multiply, run, run_with_stats, Config};
+    use crate::plan::{resolve_thread_count, Plan, PlanError, ProgressReader, ThreadSource};
+    use crate::shape::Shape;
+    use crate::test_support::temp_path;
+
+    fn config(args: &[&str]) -> Config {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Config::from_iter(args.into_iter()).unwrap()
+    }
+
+    #[test]
+    fn progress_reader_reports_bytes_while_reading() {
+        let mut reports = vec![];
+        let mut reader = ProgressReader::new(&b"0123456789"[..], 4, |read| reports.push(read));
+
+        let mut contents = vec![];
+        let mut chunk = [0u8; 3];
+        loop {
+            match reader.read(&mut chunk).unwrap() {
+                0 => break,
+                len => contents.extend_from_slice(&chunk[..len]),
+            }
+        }
+        // Reading past the end again doesn't report the end twice.
+        assert_eq!(reader.read(&mut chunk).unwrap(), 0);
+
+        assert_eq!(contents, b"0123456789");
+        assert_eq!(reports, vec![6, 9, 10]);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let bad_header = temp_path("bad_header.txt");
+        fs::write(&bad_header, "x\n2\n1 2\n").unwrap();
+
+        let missing = temp_path("missing.txt");
+        let output = temp_path("no_such_dir/c.txt");
+
+        let errors = Plan::from_config(&config(&["mm", &missing, &bad_header, &output, "1"])).unwrap_err();
+        fs::remove_file(&bad_header).unwrap();
+
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(errors[0], PlanError::UnreadableInput { .. }));
+        assert!(matches!(errors[1], PlanError::InvalidHeader { .. }));
+        assert!(matches!(errors[2], PlanError::UnwritableOutput { .. }));
+    }
+
+    #[test]
+    fn stats_report_max_magnitude_under_bound() {
+        let (matrix_a, matrix_b, output) = (temp_path("bound_a.txt"), temp_path("bound_b.txt"), temp_path("bound_c.txt"));
+        fs::write(&matrix_a, "2\n2\n1 -2\n3 4\n").unwrap();
+        fs::write(&matrix_b, "2\n1\n5\n6\n").unwrap();
+
+        let args = ["mm", &matrix_a, &matrix_b, &output, "2"];
+        let unbounded = run_with_stats(config(&args));
+        let bounded = run_with_stats(config(&[&args[..], &["--abort-if-exceeds", "39"]].concat()));
+        let pipelined = run_with_stats(config(&[&args[..], &["--abort-if-exceeds", "38.5", "--pipeline"]].concat()));
+
+        for file in [&matrix_a, &matrix_b, &output] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!((unbounded.unwrap().max_magnitude, bounded.unwrap().max_magnitude), (39.0, 39.0));
+        assert_eq!(pipelined.unwrap_err(), "Element (1, 0) is 39, over the magnitude bound 38.5");
+    }
+
+    #[test]
+    fn verbose_runs_keep_the_cancel_token_and_bound() {
+        let (matrix_a, output) = (temp_path("verbose_a.txt"), temp_path("verbose_c.txt"));
+        fs::write(&matrix_a, "2\n2\n1.5 0\n0 2\n").unwrap();
+
+        let args = ["mm", &matrix_a, &matrix_a, &output, "1", "--verbose"];
+        let stats = run_with_stats(config(&args));
+        let cancelled = run_with_stats(config(&args).with_cancel_token(Arc::new(AtomicBool::new(true))));
+        let bounded = run_with_stats(config(&[&args[..], &["--abort-if-exceeds", "3"]].concat()));
+
+        fs::remove_file(&matrix_a).unwrap();
+        let _ = fs::remove_file(&output);
+
+        assert_eq!(stats.unwrap().max_magnitude, 4.0);
+        assert_eq!(cancelled.unwrap_err(), "Interrupted after 0 of 2 rows");
+        assert_eq!(bounded.unwrap_err(), "Element (1, 1) is 4, over the magnitude bound 3");
+    }
+
+    #[test]
+    fn blank_input_suggests_checking_its_generation() {
+        let (matrix_a, matrix_b, output) = (temp_path("blank_a.txt"), temp_path("blank_b.txt"), temp_path("blank_c.txt"));
+        fs::write(&matrix_a, "\n").unwrap();
+        fs::write(&matrix_b, "1\n1\n2\n").unwrap();
+
+        let result = run(config(&["mm", &matrix_a, &matrix_b, &output, "1"]));
+
+        fs::remove_file(&matrix_a).unwrap();
+        fs::remove_file(&matrix_b).unwrap();
+
+        assert_eq!(result.unwrap_err(), format!("File {} is blank: its 1 B are only whitespace or a byte order mark. \
+                                                 Check that the step that should have generated it actually ran", matrix_a));
+    }
+
+    #[test]
+    fn planner_rejects_results_over_the_element_limit() {
+        let (matrix_a, matrix_b, output) = (temp_path("limit_a.txt"), temp_path("limit_b.txt"), temp_path("limit_c.txt"));
+        fs::write(&matrix_a, "3\n1\n1\n2\n3\n").unwrap();
+        fs::write(&matrix_b, "1\n3\n4 5 6\n").unwrap();
+
+        let args = ["mm", &matrix_a, &matrix_b, &output, "1", "--max-elements"];
+        let tight = Plan::from_config(&config(&[&args[..], &["8"]].concat()));
+        let raised = run(config(&[&args[..], &["9"]].concat()));
+
+        for file in [&matrix_a, &matrix_b, &output] {
+            let _ = fs::remove_file(file);
+        }
+
+        assert_eq!(tight.unwrap_err(), vec![PlanError::OutputTooLarge(LimitExceeded {
+            limit: Limit::Elements, shape: Some(Shape { rows: 3, cols: 3 }), value: 9, max: 8 })]);
+        assert!(raised.is_ok());
+    }
+
+    #[test]
+    fn reports_shape_and_thread_problems_together() {
+        let matrix_a = temp_path("shape_a.txt");
+        let matrix_b = temp_path("shape_b.txt");
+        fs::write(&matrix_a, "2\n3\n1 2 3\n4 5 6\n").unwrap();
+        fs::write(&matrix_b, "2\n2\n1 2\n3 4\n").unwrap();
+
+        let errors = Plan::from_config(&config(&["mm", &matrix_a, &matrix_b, &temp_path("c.txt"), "0"])).unwrap_err();
+        fs::remove_file(&matrix_a).unwrap();
+        fs::remove_file(&matrix_b).unwrap();
+
+        assert_eq!(errors, vec![
+            PlanError::DimensionMismatch { expected: Shape { rows: 3, cols: 2 }, actual: Shape { rows: 2, cols: 2 } },
+            PlanError::InvalidThreadCount { num_of_threads: 0, rows: None },
+        ]);
+    }
+
+    #[test]
+    fn valid_plan_matches_direct_multiplication() {
+        let matrix_a = temp_path("valid_a.txt");
+        let matrix_b = temp_path("valid_b.txt");
+        let output = temp_path("valid_c.txt");
+        fs::write(&matrix_a, "3\n2\n1 2\n3 4\n5 6\n").unwrap();
+        fs::write(&matrix_b, "2\n4\n7 8 9 10\n11 12 13 14\n").unwrap();
+
+        let plan = Plan::from_config(&config(&["mm", &matrix_a, &matrix_b, &output, "2"])).unwrap();
+        assert_eq!(plan.output_shape(), (3, 4));
+
+        run(config(&["mm", &matrix_a, &matrix_b, &output, "2"])).unwrap();
+
+        let expected = multiply(&Matrix::<f64>::from_file(&matrix_a).unwrap(),
+                                &Matrix::<f64>::from_file(&matrix_b).unwrap(), 2).unwrap();
+        let written = fs::read_to_string(&output).unwrap();
+
+        for file in [&matrix_a, &matrix_b, &output] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(written, expected.to_string());
+    }
+
+    #[test]
+    fn flush_denormals_is_opt_in() {
+        let (matrix_a, matrix_b, output) = (temp_path("tiny_a.txt"), temp_path("tiny_b.txt"), temp_path("tiny_c.txt"));
+        fs::write(&matrix_a, "1\n2\n1e-160 -1e-160\n").unwrap();
+        fs::write(&matrix_b, "2\n2\n1e-160 0\n0 1e-160\n").unwrap();
+
+        let stats = run_with_stats(config(&["mm", &matrix_a, &matrix_b, &output, "1"])).unwrap();
+        let default_output = fs::read_to_string(&output).unwrap();
+
+        let flushed = run_with_stats(config(&["mm", &matrix_a, &matrix_b, &output, "1", "--flush-denormals"])).unwrap();
+        let flushed_output = fs::read_to_string(&output).unwrap();
+
+        let expected = multiply(&Matrix::<f64>::from_file(&matrix_a).unwrap(),
+                                &Matrix::<f64>::from_file(&matrix_b).unwrap(), 1).unwrap();
+
+        for file in [&matrix_a, &matrix_b, &output] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(stats.denormals_flushed, 0);
+        assert_eq!(default_output, expected.to_string());
+        assert_eq!(flushed.denormals_flushed, 2);
+        assert_eq!(flushed_output, "1\n2\n0 -0\n");
+    }
+
+    #[test]
+    fn thread_count_precedence() {
+        for greedy in [false, true] {
+            assert_eq!(resolve_thread_count(Some(3), greedy, 8, 100), (3, ThreadSource::Explicit));
+            assert_eq!(resolve_thread_count(Some(12), greedy, 8, 10), (10, ThreadSource::Explicit));
+            assert_eq!(resolve_thread_count(Some(8), greedy, 8, 1), (1, ThreadSource::Explicit));
+            assert_eq!(resolve_thread_count(Some(0), greedy, 8, 10), (0, ThreadSource::Explicit));
+        }
+
+        assert_eq!(resolve_thread_count(None, true, 8, 100), (8, ThreadSource::Greedy));
+        assert_eq!(resolve_thread_count(None, false, 8, 100), (7, ThreadSource::Polite));
+    }
+
+    #[test]
+    fn auto_thread_count_edge_cases() {
+        assert_eq!(resolve_thread_count(None, false, 1, 100), (1, ThreadSource::Polite));
+        assert_eq!(resolve_thread_count(None, true, 1, 100), (1, ThreadSource::Greedy));
+        assert_eq!(resolve_thread_count(None, false, 2, 100), (1, ThreadSource::Polite));
+
+        assert_eq!(resolve_thread_count(None, false, 8, 3), (3, ThreadSource::Polite));
+        assert_eq!(resolve_thread_count(None, true, 8, 3), (3, ThreadSource::Greedy));
+        assert_eq!(resolve_thread_count(None, false, 8, 7), (7, ThreadSource::Polite));
+        assert_eq!(resolve_thread_count(None, true, 8, 1), (1, ThreadSource::Greedy));
+    }
+
+    #[test]
+    fn element_type_is_probed_unless_given() {
+        let (matrix_a, identity, output) = (temp_path("dtype_a.txt"), temp_path("dtype_i.txt"), temp_path("dtype_c.txt"));
+        let (floats, float_output) = (temp_path("dtype_f.txt"), temp_path("dtype_fc.txt"));
+        fs::write(&matrix_a, "1\n2\n9007199254740993 -3\n").unwrap();
+        fs::write(&identity, "2\n2\n1 0\n0 1\n").unwrap();
+        fs::write(&floats, "2\n2\n0.5 0\n0 1\n").unwrap();
+
+        run(config(&["mm", &matrix_a, &identity, &output, "1"])).unwrap();
+        let exact = fs::read_to_string(&output).unwrap();
+
+        run(config(&["mm", &matrix_a, &identity, &output, "1", "--dtype", "f64"])).unwrap();
+        let rounded = fs::read_to_string(&output).unwrap();
+
+        run(config(&["mm", &identity, &floats, &float_output, "1"])).unwrap();
+        let mixed = fs::read_to_string(&float_output).unwrap();
+
+        let forced = run(config(&["mm", &identity, &floats, &float_output, "1", "--dtype", "i64"]));
+
+        for file in [&matrix_a, &identity, &output, &floats, &float_output] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(exact, "1\n2\n9007199254740993 -3\n");
+        assert_eq!(rounded, "1\n2\n9007199254740992 -3\n");
+        assert_eq!(mixed, "2\n2\n0.5 0\n0 1\n");
+        assert!(forced.is_err());
+        assert!(Config::from_iter(["mm", "a", "b", "c", "1", "--dtype", "u8"].map(String::from).into_iter()).is_err());
+    }
+}
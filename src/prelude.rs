@@ -0,0 +1,27 @@
+//! The types and functions most programs need, for a single glob import:
+//!
+//! ```
+//! use mm::prelude::*;
+//!
+//! let path = std::env::temp_dir().join(format!("mm_prelude_doc_{}.txt", std::process::id()));
+//! let path = path.to_str().unwrap();
+//!
+//! let a = Matrix::new(2, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+//! a.write_with(path, &WriteOptions::new()).unwrap();
+//! let a = Matrix::<f64>::from_file_with(path, &ParseOptions::new()).unwrap();
+//! std::fs::remove_file(path).unwrap();
+//!
+//! let product = multiply_with_options(&a, &a.transpose(), &MultiplyOptions::new(2)).unwrap();
+//! assert_eq!(product.shape(), Shape { rows: 2, cols: 2 });
+//! ```
+//!
+//! Everything else keeps its module path.
+
+pub use crate::element::Element;
+pub use crate::io::Format;
+pub use crate::limits::Limits;
+pub use crate::matrix::{Aliasing, Matrix};
+pub use crate::multiplication::{multiply, multiply_with_options, Kernel, MultiplyError, MultiplyOptions};
+pub use crate::parse::ParseOptions;
+pub use crate::shape::Shape;
+pub use crate::write::WriteOptions;
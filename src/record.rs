@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::time::Duration;
+use crate::hash::Xxh64;
+use crate::multiplication::Kernel;
+
+/// First line of every record file.
+const RECORD_HEADER: &str = "# mm run record";
+
+/// A file a run read or wrote, with the XXH64 of its bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileRecord {
+    pub path: String,
+    pub rows: usize,
+    pub columns: usize,
+    pub hash: u64,
+}
+
+impl FileRecord {
+    /// Hashes the file as it is now on disk.
+    pub fn new(path: &str, rows: usize, columns: usize) -> Result<FileRecord, String> {
+        Ok(FileRecord { path: path.to_string(), rows, columns, hash: file_hash(path)? })
+    }
+}
+
+/// What a run did, written next to its output with `--record` and checked
+/// again with `verify-record`.
+///
+/// Saved as TOML-compatible `key = value` lines, with the files under the
+/// `a.`, `b.` and `output.` prefixes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunRecord {
+    pub version: String,
+    pub element_type: String,
+    pub kernel: Kernel,
+    pub num_of_threads: usize,
+    pub wall_time: Duration,
+    pub matrix_a: FileRecord,
+    pub matrix_b: FileRecord,
+    pub output: FileRecord,
+}
+
+impl RunRecord {
+    pub fn save(&self, file_name: &str) -> Result<(), String> {
+        let mut contents = format!("{}\n", RECORD_HEADER);
+        contents += &format!("version = {}\n", quote(&self.version));
+        contents += &format!("element_type = {}\n", quote(&self.element_type));
+        contents += &format!("kernel = {}\n", quote(&self.kernel.to_string()));
+        contents += &format!("threads = {}\n", self.num_of_threads);
+        contents += &format!("wall_time_seconds = {}\n", self.wall_time.as_secs_f64());
+
+        for (prefix, file) in [("a", &self.matrix_a), ("b", &self.matrix_b), ("output", &self.output)] {
+            contents += &format!("{prefix}.path = {}\n", quote(&file.path));
+            contents += &format!("{prefix}.rows = {}\n", file.rows);
+            contents += &format!("{prefix}.columns = {}\n", file.columns);
+            contents += &format!("{prefix}.hash = \"{:016x}\"\n", file.hash);
+        }
+
+        fs::write(file_name, contents).map_err(|error| format!("Error writing to file {}: {}", file_name, error))
+    }
+
+    pub fn load(file_name: &str) -> Result<RunRecord, String> {
+        let contents = fs::read_to_string(file_name)
+            .map_err(|error| format!("Couldn't open file {}\nerror: {}", file_name, error))?;
+
+        RunRecord::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<RunRecord, String> {
+        let mut values = BTreeMap::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("Invalid record line {}: '{}'", index + 1, line))?;
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+
+        let value = |key: &str| values.get(key).ok_or_else(|| format!("Record is missing {key}"));
+        let string = |key: &str| value(key).and_then(|value| unquote(value).ok_or_else(|| format!("Record {key} isn't a string")));
+        let number = |key: &str| value(key).and_then(|value| value.parse::<usize>()
+            .map_err(|_| format!("Record {key} isn't a number")));
+
+        let file = |prefix: &str| -> Result<FileRecord, String> {
+            let hash = string(&format!("{prefix}.hash"))?;
+            Ok(FileRecord {
+                path: string(&format!("{prefix}.path"))?,
+                rows: number(&format!("{prefix}.rows"))?,
+                columns: number(&format!("{prefix}.columns"))?,
+                hash: u64::from_str_radix(&hash, 16).map_err(|_| format!("Record {prefix}.hash isn't a hash"))?,
+            })
+        };
+
+        let duration = |key: &str| value(key).and_then(|value| value.parse::<f64>().ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| format!("Record {key} isn't a duration")));
+
+        Ok(RunRecord {
+            version: string("version")?,
+            element_type: string("element_type")?,
+            kernel: Kernel::from_name(&string("kernel")?)?,
+            num_of_threads: number("threads")?,
+            wall_time: duration("wall_time_seconds")?,
+            matrix_a: file("a")?,
+            matrix_b: file("b")?,
+            output: file("output")?,
+        })
+    }
+}
+
+/// XXH64 of the file's bytes.
+pub fn file_hash(path: &str) -> Result<u64, String> {
+    let bytes = fs::read(path).map_err(|error| format!("Couldn't open file {}\nerror: {}", path, error))?;
+
+    let mut hasher = Xxh64::new(0);
+    hasher.update(&bytes);
+    Ok(hasher.finish())
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => result.push(chars.next()?),
+            '"' => return None,
+            char => result.push(char),
+        }
+    }
+
+    Some(result)
+}
+
+/// Files whose current contents no longer match a [`RunRecord`].
+#[derive(Debug, PartialEq)]
+pub struct VerifyReport {
+    /// One message per changed or unreadable file, in a, b, output order.
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Display for VerifyReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_valid() {
+            return write!(f, "All recorded files are unchanged")
+        }
+
+        write!(f, "{}", self.problems.join("\n"))
+    }
+}
+
+/// Re-hashes every file named in the record at `file_name`.
+pub fn verify_record(file_name: &str) -> Result<VerifyReport, String> {
+    let record = RunRecord::load(file_name)?;
+
+    let problems = [&record.matrix_a, &record.matrix_b, &record.output].into_iter()
+        .filter_map(|file| match file_hash(&file.path) {
+            Ok(hash) if hash == file.hash => None,
+            Ok(hash) => Some(format!("{} changed: hash {:016x}, recorded {:016x}", file.path, hash, file.hash)),
+            Err(_) => Some(format!("{} can't be read", file.path)),
+        })
+        .collect();
+
+    Ok(VerifyReport { problems })
+}
+
+#[cfg(test)]
+mod record_test {
+    use std::fs;
+    use crate::hash::Xxh64;
+    use crate::multiplication::{run, Config, Kernel};
+    use crate::record::{verify_record, RunRecord};
+    use crate::test_support::temp_path;
+
+    fn xxh64(bytes: &[u8]) -> u64 {
+        let mut hasher = Xxh64::new(0);
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn run_writes_verifiable_record() {
+        let (matrix_a, matrix_b) = (temp_path("a \"quoted\".txt"), temp_path("b.txt"));
+        let (output, record) = (temp_path("c.txt"), temp_path("c.meta.toml"));
+        fs::write(&matrix_a, "2\n3\n1 2 3\n4 5 6\n").unwrap();
+        fs::write(&matrix_b, "3\n1\n1\n0\n-1\n").unwrap();
+
+        let args = ["mm", &matrix_a, &matrix_b, &output, "2", "--record", &record].map(String::from);
+        run(Config::from_iter(args.into_iter()).unwrap()).unwrap();
+
+        let saved = RunRecord::load(&record).unwrap();
+        let hashes = [&matrix_a, &matrix_b, &output].map(|file| xxh64(&fs::read(file).unwrap()));
+        let valid = verify_record(&record).unwrap();
+
+        fs::write(&output, "2\n1\n-2\n-3\n").unwrap();
+        let tampered = verify_record(&record).unwrap();
+
+        for file in [&matrix_a, &matrix_b, &output, &record] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!([saved.matrix_a.hash, saved.matrix_b.hash, saved.output.hash], hashes);
+        assert_eq!((saved.matrix_a.path.as_str(), saved.output.rows, saved.output.columns), (matrix_a.as_str(), 2, 1));
+        assert_eq!((saved.kernel, saved.num_of_threads, saved.element_type.as_str()), (Kernel::Naive, 2, "i64"));
+        assert_eq!(saved.version, env!("CARGO_PKG_VERSION"));
+
+        assert!(valid.is_valid(), "{}", valid);
+        assert_eq!(tampered.problems.len(), 1);
+        assert!(tampered.problems[0].starts_with(&format!("{} changed", output)));
+    }
+
+    #[test]
+    fn record_round_trips_and_rejects_garbage() {
+        let record = temp_path("round_trip.toml");
+        let output = temp_path("round_trip_c.txt");
+        fs::write(&output, "1\n1\n5\n").unwrap();
+
+        let file = crate::record::FileRecord::new(&output, 1, 1).unwrap();
+        let original = RunRecord {
+            version: String::from("1.2.3"), element_type: String::from("f64"), kernel: Kernel::CacheOblivious,
+            num_of_threads: 3, wall_time: std::time::Duration::from_millis(1500),
+            matrix_a: file.clone(), matrix_b: file.clone(), output: file,
+        };
+        original.save(&record).unwrap();
+        let loaded = RunRecord::load(&record);
+
+        fs::write(&record, "# mm run record\nversion = 1.2.3\n").unwrap();
+        let garbage = RunRecord::load(&record);
+
+        fs::remove_file(&record).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(loaded, Ok(original));
+        assert_eq!(garbage.unwrap_err(), "Record version isn't a string");
+    }
+}
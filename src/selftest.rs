@@ -0,0 +1,242 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use crate::io::{read_matrix, write_matrix, Format};
+use crate::matrix::{Aliasing, Matrix};
+use crate::multiplication::{multiply, multiply_with_options, Kernel, MultiplyOptions};
+
+/// Seed of the generated operands, so a failure reproduces on every run.
+pub const SELFTEST_SEED: u64 = 2024;
+
+/// Rounds of the Freivalds check; each misses a wrong product with
+/// probability at most 1/2.
+const FREIVALDS_ROUNDS: u64 = 10;
+
+/// Arguments of the `selftest` subcommand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelfTestConfig {
+    pub size: usize,
+    pub num_of_threads: usize,
+}
+
+impl SelfTestConfig {
+    /// Parses `selftest [--size N] [--threads T]`, defaulting to 64 and 4.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<SelfTestConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let mut config = SelfTestConfig { size: 64, num_of_threads: 4 };
+
+        while let Some(option) = iterator.next() {
+            let value = iterator.next().ok_or_else(|| format!("Missing value for {option}"))?;
+            let number = value.parse::<usize>().ok().filter(|&number| number > 0);
+            match option.as_str() {
+                "--size" => config.size = number.ok_or_else(|| format!("Couldn't get matrix size from {value}"))?,
+                "--threads" => config.num_of_threads = number
+                    .ok_or_else(|| format!("Couldn't get number of threads from {value}"))?,
+                _ => return Err(format!("Unknown option {option}"))
+            }
+        }
+
+        if config.num_of_threads > config.size {
+            return Err(format!("Num of threads: {} cannot be higher than the matrix size: {}",
+                               config.num_of_threads, config.size))
+        }
+
+        Ok(config)
+    }
+}
+
+/// Seeded operands shared by every check.
+pub struct SelfTestInputs {
+    pub config: SelfTestConfig,
+    /// Small integers, so every kernel and thread count must agree exactly.
+    pub integers: (Matrix<i64>, Matrix<i64>),
+    pub floats: (Matrix<f64>, Matrix<f64>),
+}
+
+impl SelfTestInputs {
+    pub fn new(config: SelfTestConfig) -> Result<SelfTestInputs, String> {
+        let n = config.size;
+        let seeded_i64 = |seed| Matrix::<i64>::gen_random_seeded(n, n, -100, 100, seed);
+        let seeded_f64 = |seed| Matrix::<f64>::gen_random_seeded(n, n, -1.0, 1.0, seed);
+
+        Ok(SelfTestInputs {
+            config,
+            integers: (seeded_i64(SELFTEST_SEED)?, seeded_i64(SELFTEST_SEED + 1)?),
+            floats: (seeded_f64(SELFTEST_SEED + 2)?, seeded_f64(SELFTEST_SEED + 3)?),
+        })
+    }
+}
+
+type CheckFn = dyn Fn(&SelfTestInputs) -> Result<(), String>;
+
+/// A named check of the self-test; features register theirs in [`default_checks`].
+pub struct SelfCheck {
+    pub name: String,
+    pub check: Box<CheckFn>,
+}
+
+impl SelfCheck {
+    pub fn new(name: &str, check: impl Fn(&SelfTestInputs) -> Result<(), String> + 'static) -> SelfCheck {
+        SelfCheck { name: name.to_string(), check: Box::new(check) }
+    }
+}
+
+/// The checks run by `selftest`: threaded and alternative kernels against the
+/// sequential one, Freivalds on the product, and a round trip through every
+/// file format.
+pub fn default_checks() -> Vec<SelfCheck> {
+    let mut checks = vec![];
+
+    for kernel in [Kernel::Naive, Kernel::CacheOblivious] {
+        checks.push(SelfCheck::new(&format!("threaded {kernel} kernel matches sequential"), move |inputs| {
+            let (a, b) = &inputs.integers;
+            let mut options = MultiplyOptions::new(inputs.config.num_of_threads);
+            options.kernel = kernel;
+            expect_equal(&multiply_with_options(a, b, &options)?, &multiply(a, b, 1)?)
+        }));
+    }
+
+    checks.push(SelfCheck::new("Freivalds check of the threaded product", |inputs| {
+        let (a, b) = &inputs.integers;
+        freivalds(a, b, &multiply(a, b, inputs.config.num_of_threads)?)
+    }));
+
+    for format in [Format::PlainText, Format::Csv, Format::Binary, Format::Coo] {
+        checks.push(SelfCheck::new(&format!("{format:?} round trip"), move |inputs| round_trip(inputs, format)));
+    }
+
+    checks
+}
+
+fn expect_equal<T: PartialEq>(result: &Matrix<T>, expected: &Matrix<T>) -> Result<(), String> {
+    if result.shape() != expected.shape() {
+        return Err(format!("Result is {}, expected {}", result.shape(), expected.shape()))
+    }
+
+    match result.get_numbers().iter().zip(expected.get_numbers().iter()).position(|(a, b)| a != b) {
+        Some(index) => Err(format!("Results differ at ({}, {})", index / result.columns(), index % result.columns())),
+        None => Ok(())
+    }
+}
+
+/// Checks A(Bx) = Cx for random 0/1 vectors x, without recomputing A·B.
+fn freivalds(a: &Matrix<i64>, b: &Matrix<i64>, c: &Matrix<i64>) -> Result<(), String> {
+    for round in 0..FREIVALDS_ROUNDS {
+        let mut x = Matrix::new(b.columns(), 1, vec![0; b.columns()])?;
+        x.randomize_in_place(0, 2, SELFTEST_SEED + round, Aliasing::default())?;
+
+        if multiply(a, &multiply(b, &x, 1)?, 1)? != multiply(c, &x, 1)? {
+            return Err(format!("A(Bx) differs from Cx in round {}", round + 1))
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the float operand to a temp file and reads it back bit for bit.
+fn round_trip(inputs: &SelfTestInputs, format: Format) -> Result<(), String> {
+    let path = std::env::temp_dir().join(format!("mm_selftest_{}_{:?}", std::process::id(), format));
+    let path = path.to_str().ok_or_else(|| String::from("Temp dir is not valid UTF-8"))?;
+
+    let matrix = &inputs.floats.0;
+    let read = write_matrix(matrix, path, Some(format)).and_then(|_| read_matrix::<f64>(path, Some(format)));
+    let _ = fs::remove_file(path);
+
+    let bits = |matrix: &Matrix<f64>| matrix.get_numbers().iter().map(|num| num.to_bits()).collect::<Vec<u64>>();
+    let read = read?;
+    if read.shape() != matrix.shape() || bits(&read) != bits(matrix) {
+        return Err(format!("Reading back the {:?} file gave a different matrix", format))
+    }
+
+    Ok(())
+}
+
+/// Outcome of every check, in the order they ran.
+#[derive(Debug, PartialEq)]
+pub struct SelfTestReport {
+    pub results: Vec<(String, Result<(), String>)>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// 0 when every check passed, 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.passed() { 0 } else { 1 }
+    }
+}
+
+impl Display for SelfTestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, result) in &self.results {
+            match result {
+                Ok(()) => writeln!(f, "PASS {}", name)?,
+                Err(error) => writeln!(f, "FAIL {}: {}", name, error)?,
+            }
+        }
+
+        let passed = self.results.iter().filter(|(_, result)| result.is_ok()).count();
+        write!(f, "{} of {} checks passed", passed, self.results.len())
+    }
+}
+
+/// Runs each check on the same inputs; a failing check doesn't stop the rest.
+pub fn run_checks(config: SelfTestConfig, checks: &[SelfCheck]) -> Result<SelfTestReport, String> {
+    let inputs = SelfTestInputs::new(config)?;
+
+    let results = checks.iter().map(|check| (check.name.clone(), (check.check)(&inputs))).collect();
+
+    Ok(SelfTestReport { results })
+}
+
+pub fn run_selftest(config: SelfTestConfig) -> Result<SelfTestReport, String> {
+    run_checks(config, &default_checks())
+}
+
+#[cfg(test)]
+mod selftest_test {
+    use crate::selftest::{default_checks, run_checks, run_selftest, SelfCheck, SelfTestConfig};
+
+    fn config(args: &[&str]) -> Result<SelfTestConfig, String> {
+        SelfTestConfig::from_iter(["mm", "selftest"].iter().chain(args).map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn default_build_passes_every_check() {
+        let report = run_selftest(config(&["--size", "24", "--threads", "3"]).unwrap()).unwrap();
+
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.exit_code(), 0);
+        assert_eq!(report.results.len(), 7);
+        assert!(report.to_string().ends_with("7 of 7 checks passed"));
+    }
+
+    #[test]
+    fn broken_check_flips_exit_code() {
+        let mut checks = default_checks();
+        checks.insert(1, SelfCheck::new("deliberately broken", |inputs| {
+            Err(format!("size was {}", inputs.config.size))
+        }));
+
+        let report = run_checks(config(&["--size", "8"]).unwrap(), &checks).unwrap();
+        let summary = report.to_string();
+
+        assert_eq!(report.exit_code(), 1);
+        assert!(summary.contains("FAIL deliberately broken: size was 8\n"));
+        assert!(summary.contains("PASS Freivalds check of the threaded product\n"));
+        assert!(summary.ends_with("7 of 8 checks passed"));
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(config(&[]), Ok(SelfTestConfig { size: 64, num_of_threads: 4 }));
+        assert_eq!(config(&["--threads", "2", "--size", "10"]), Ok(SelfTestConfig { size: 10, num_of_threads: 2 }));
+        assert_eq!(config(&["--size", "0"]).unwrap_err(), "Couldn't get matrix size from 0");
+        assert_eq!(config(&["--size", "2"]).unwrap_err(), "Num of threads: 4 cannot be higher than the matrix size: 2");
+        assert!(config(&["--kernel", "naive"]).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::ops::{AddAssign, Mul};
+use crate::element::Element;
+use crate::matrix::Matrix;
+use crate::multiplication::{validate, MultiplyError};
+use crate::parallel::parallel_over_rows;
+
+/// The two operations of an inner product: entry (i, j) of the product is
+/// `add` over k of `mul(a[i][k], b[k][j])`, starting from `zero`.
+pub trait Semiring<T> {
+    /// Identity of `add`.
+    fn zero(&self) -> T;
+
+    fn add(&self, sum: T, product: T) -> T;
+
+    fn mul(&self, a: &T, b: &T) -> T;
+}
+
+/// Ordinary sum of products. [`multiply`](crate::multiplication::multiply)
+/// keeps its specialized kernels, this is for code generic over semirings.
+pub struct StandardSemiring;
+
+impl<T> Semiring<T> for StandardSemiring
+    where
+        for<'a> &'a T: Mul<Output=T>,
+        T: AddAssign<T> + Element {
+
+    fn zero(&self) -> T {
+        T::ZERO
+    }
+
+    fn add(&self, mut sum: T, product: T) -> T {
+        sum += product;
+        sum
+    }
+
+    fn mul(&self, a: &T, b: &T) -> T {
+        a * b
+    }
+}
+
+/// Minimum of sums, with infinity meaning "no edge": the k-th power of a
+/// graph's weight matrix holds the shortest paths of at most k edges.
+pub struct TropicalMinPlus;
+
+impl Semiring<f64> for TropicalMinPlus {
+    fn zero(&self) -> f64 {
+        f64::INFINITY
+    }
+
+    fn add(&self, sum: f64, product: f64) -> f64 {
+        sum.min(product)
+    }
+
+    fn mul(&self, a: &f64, b: &f64) -> f64 {
+        a + b
+    }
+}
+
+/// Any of ands: the product of adjacency matrices tells which vertices are
+/// joined by a path through an intermediate one.
+pub struct BooleanOrAnd;
+
+impl Semiring<bool> for BooleanOrAnd {
+    fn zero(&self) -> bool {
+        false
+    }
+
+    fn add(&self, sum: bool, product: bool) -> bool {
+        sum || product
+    }
+
+    fn mul(&self, a: &bool, b: &bool) -> bool {
+        *a && *b
+    }
+}
+
+/// A·B over `semiring`, split between the threads by rows of A like
+/// [`multiply`](crate::multiplication::multiply).
+pub fn multiply_semiring<T, S>(matrix_a: &Matrix<T>, matrix_b: &Matrix<T>, num_of_threads: usize, semiring: &S)
+    -> Result<Matrix<T>, String>
+    where
+        T: Sync + Send,
+        S: Semiring<T> + Sync {
+
+    validate(matrix_a, matrix_b, num_of_threads)?;
+
+    let (rows, n, columns) = (matrix_a.rows(), matrix_a.columns(), matrix_b.columns());
+
+    let a_numbers = matrix_a.get_numbers();
+    let b_numbers = matrix_b.get_numbers();
+    let (a_numbers, b_numbers) = (a_numbers.as_slice(), b_numbers.as_slice());
+
+    let numbers = parallel_over_rows(rows, num_of_threads, |rows| {
+        let mut result = Vec::with_capacity(rows.len() * columns);
+        for row in rows {
+            for column in 0..columns {
+                let mut sum = semiring.zero();
+                for k in 0..n {
+                    sum = semiring.add(sum, semiring.mul(&a_numbers[row * n + k], &b_numbers[k * columns + column]));
+                }
+                result.push(sum);
+            }
+        }
+        result
+    }, |results| results.into_iter().flatten().collect()).map_err(MultiplyError::Internal)?;
+
+    Matrix::new(rows, columns, numbers)
+}
+
+#[cfg(test)]
+mod semiring_test {
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+    use crate::semiring::{multiply_semiring, BooleanOrAnd, StandardSemiring, TropicalMinPlus};
+
+    const INF: f64 = f64::INFINITY;
+
+    #[test]
+    fn min_plus_powers_give_shortest_paths() {
+        // 0 -> 1 (4), 0 -> 2 (1), 2 -> 1 (2), 1 -> 3 (1), 3 -> 0 (7).
+        let weights = Matrix::new(4, 4, vec![
+            0.0, 4.0, 1.0, INF,
+            INF, 0.0, INF, 1.0,
+            INF, 2.0, 0.0, INF,
+            7.0, INF, INF, 0.0,
+        ]).unwrap();
+
+        // W³: paths of up to three edges, enough to join every pair here.
+        let mut distances = weights.clone();
+        for _ in 0..2 {
+            distances = multiply_semiring(&distances, &weights, 2, &TropicalMinPlus).unwrap();
+        }
+
+        assert_eq!(distances, Matrix::new(4, 4, vec![
+            0.0, 3.0, 1.0, 4.0,
+            8.0, 0.0, 9.0, 1.0,
+            10.0, 2.0, 0.0, 3.0,
+            7.0, 10.0, 8.0, 0.0,
+        ]).unwrap());
+    }
+
+    #[test]
+    fn boolean_and_standard_semirings_match_multiply() {
+        let a = Matrix::new(3, 4, vec![1, 0, 0, 2, 0, 0, 0, 0, 3, 1, 0, 1]).unwrap();
+        let b = Matrix::new(4, 2, vec![0, 1, 1, 0, 0, 0, 5, 0]).unwrap();
+        let product = multiply(&a, &b, 2).unwrap();
+
+        let as_bool = |matrix: &Matrix<i64>| Matrix::new(matrix.rows(), matrix.columns(),
+                                                         matrix.get_numbers().iter().map(|&num| num != 0).collect()).unwrap();
+
+        assert_eq!(multiply_semiring(&as_bool(&a), &as_bool(&b), 3, &BooleanOrAnd), Ok(as_bool(&product)));
+        assert_eq!(multiply_semiring(&a, &b, 3, &StandardSemiring), Ok(product));
+        assert!(multiply_semiring(&a, &a, 1, &StandardSemiring).is_err());
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::iter::Peekable;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::str::Chars;
+use std::net::Shutdown;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+use crate::matrix::Matrix;
+use crate::multiplication::multiply;
+use crate::write::WriteOptions;
+
+/// Arguments of the `serve` subcommand.
+pub struct ServeConfig {
+    socket_path: String,
+    /// Matrices loaded once at startup, as (name, file) pairs.
+    matrices: Vec<(String, String)>,
+}
+
+impl ServeConfig {
+    /// Parses `serve --socket [path] [--load name=file]...`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<ServeConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let (mut socket_path, mut matrices) = (None, vec![]);
+
+        while let Some(option) = iterator.next() {
+            let value = iterator.next().ok_or_else(|| format!("Missing value for {option}"))?;
+            match option.as_str() {
+                "--socket" => socket_path = Some(value),
+                "--load" => match value.split_once('=') {
+                    Some((name, file)) if !name.is_empty() && !file.is_empty() =>
+                        matrices.push((name.to_string(), file.to_string())),
+                    _ => return Err(format!("Couldn't get name=file from {value}"))
+                },
+                _ => return Err(format!("Unknown option {option}"))
+            }
+        }
+
+        let socket_path = socket_path.ok_or_else(|| String::from("Missing --socket path"))?;
+
+        Ok(ServeConfig { socket_path, matrices })
+    }
+}
+
+/// Arguments of the `client` subcommand.
+pub struct ClientConfig {
+    socket_path: String,
+    request: String,
+}
+
+impl ClientConfig {
+    /// Parses `client --socket [path] [request]`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<ClientConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        match iterator.next().as_deref() {
+            Some("--socket") => {},
+            Some(option) => return Err(format!("Unknown option {option}")),
+            None => return Err(String::from("Missing --socket path")),
+        }
+
+        let socket_path = iterator.next().ok_or_else(|| String::from("Missing value for --socket"))?;
+        let request = iterator.next().ok_or_else(|| String::from("Missing request"))?;
+
+        Ok(ClientConfig { socket_path, request })
+    }
+}
+
+/// Why a request failed, sent back as the `kind` and `message` of the reply.
+#[derive(Debug, PartialEq)]
+pub enum ServeError {
+    /// Not a JSON object, or a field is missing or has the wrong type.
+    MalformedRequest(String),
+    UnknownOp(String),
+    /// An operand is neither a loaded matrix nor a readable file.
+    Input(String),
+    Multiply(String),
+    Output(String),
+}
+
+impl ServeError {
+    fn kind(&self) -> &'static str {
+        match self {
+            ServeError::MalformedRequest(_) => "malformed_request",
+            ServeError::UnknownOp(_) => "unknown_op",
+            ServeError::Input(_) => "input",
+            ServeError::Multiply(_) => "multiply",
+            ServeError::Output(_) => "output",
+        }
+    }
+}
+
+impl Display for ServeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServeError::MalformedRequest(message) => write!(f, "Malformed request: {message}"),
+            ServeError::UnknownOp(op) => write!(f, "Unknown op {op:?}, expected \"multiply\" or \"shutdown\""),
+            ServeError::Input(message) | ServeError::Multiply(message) | ServeError::Output(message) =>
+                write!(f, "{message}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+/// Just enough JSON for the protocol: one flat object per line, whose values
+/// are strings, numbers, booleans or null.
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{expected}', found '{c}'")),
+            None => Err(format!("expected '{expected}', found the end of the line")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+
+        let mut string = String::new();
+        loop {
+            let c = match self.chars.next().ok_or_else(|| String::from("unterminated string"))? {
+                '"' => return Ok(string),
+                '\\' => match self.chars.next().ok_or_else(|| String::from("unterminated string"))? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\u{hex}"))?
+                    },
+                    c @ ('"' | '\\' | '/') => c,
+                    c => return Err(format!("invalid escape \\{c}"))
+                },
+                c => c,
+            };
+            string.push(c);
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('{' | '[') => Err(String::from("nested objects and arrays are not supported")),
+            Some(_) => {
+                let mut token = String::new();
+                while let Some(c) = self.chars.next_if(|&c| c != ',' && c != '}' && !c.is_whitespace()) {
+                    token.push(c);
+                }
+                match token.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    _ => token.parse().map(Value::Number).map_err(|_| format!("invalid value {token}")),
+                }
+            },
+            None => Err(String::from("expected a value, found the end of the line")),
+        }
+    }
+
+    fn object(mut self) -> Result<HashMap<String, Value>, String> {
+        self.expect('{')?;
+
+        let mut fields = HashMap::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_none() {
+            loop {
+                let key = self.string()?;
+                self.expect(':')?;
+                let value = self.value()?;
+                if fields.insert(key.clone(), value).is_some() {
+                    return Err(format!("duplicate field \"{key}\""))
+                }
+
+                self.skip_whitespace();
+                match self.chars.next() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    Some(c) => return Err(format!("expected ',' or '}}', found '{c}'")),
+                    None => return Err(String::from("unterminated object")),
+                }
+            }
+        }
+
+        self.skip_whitespace();
+        if let Some(c) = self.chars.next() {
+            return Err(format!("unexpected '{c}' after the object"))
+        }
+
+        Ok(fields)
+    }
+}
+
+/// Quotes `string` as a JSON string.
+fn escape(string: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            '\t' => escaped += "\\t",
+            c if c.is_control() => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped + "\""
+}
+
+#[derive(Debug, PartialEq)]
+enum Request {
+    /// Writes `a`·`b` to `out`; the operands are loaded names or file paths.
+    Multiply { a: String, b: String, out: String, threads: usize },
+    Shutdown,
+}
+
+impl Request {
+    fn parse(line: &str) -> Result<Request, ServeError> {
+        let fields = JsonParser { chars: line.chars().peekable() }.object().map_err(ServeError::MalformedRequest)?;
+
+        let string = |name: &str| match fields.get(name) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(_) => Err(ServeError::MalformedRequest(format!("field \"{name}\" must be a string"))),
+            None => Err(ServeError::MalformedRequest(format!("missing field \"{name}\""))),
+        };
+
+        match string("op")?.as_str() {
+            "multiply" => {
+                let threads = match fields.get("threads") {
+                    None => 1,
+                    Some(Value::Number(threads)) if *threads >= 1.0 && threads.fract() == 0.0 => *threads as usize,
+                    Some(_) => return Err(ServeError::MalformedRequest(
+                        String::from("field \"threads\" must be a positive integer"))),
+                };
+                Ok(Request::Multiply { a: string("a")?, b: string("b")?, out: string("out")?, threads })
+            },
+            "shutdown" => Ok(Request::Shutdown),
+            op => Err(ServeError::UnknownOp(op.to_string())),
+        }
+    }
+}
+
+/// State shared by the connections of a running [`Server`].
+struct ServerState {
+    socket_path: String,
+    matrices: HashMap<String, Matrix<f64>>,
+    shutdown: AtomicBool,
+    answered: AtomicUsize,
+    /// A clone of every open connection, so shutdown can stop their reads.
+    connections: Mutex<HashMap<usize, UnixStream>>,
+}
+
+impl ServerState {
+    /// A loaded matrix by name, or else the matrix in the file at that path.
+    fn operand(&self, name: &str) -> Result<Matrix<f64>, ServeError> {
+        match self.matrices.get(name) {
+            Some(matrix) => Ok(matrix.clone()),
+            None => Matrix::from_file(name)
+                .map_err(|error| ServeError::Input(format!("{name} is not a loaded matrix or a readable file: {error}"))),
+        }
+    }
+
+    /// The reply line to `line`, and whether it asked the server to stop.
+    fn answer(&self, line: &str) -> (String, bool) {
+        let start = Instant::now();
+
+        let result = Request::parse(line).and_then(|request| match request {
+            Request::Shutdown => Ok((String::new(), true)),
+            Request::Multiply { a, b, out, threads } => {
+                let (matrix_a, matrix_b) = (self.operand(&a)?, self.operand(&b)?);
+                let loaded = Instant::now();
+
+                let threads = threads.min(matrix_a.rows()).max(1);
+                let product = multiply(&matrix_a, &matrix_b, threads).map_err(ServeError::Multiply)?;
+                let multiplied = Instant::now();
+
+                product.write_with(out.as_str(), &WriteOptions::new().atomic(true)).map_err(ServeError::Output)?;
+
+                let ms = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.0;
+                Ok((format!(",\"rows\":{},\"columns\":{},\"threads\":{},\"load_ms\":{:.3},\"multiply_ms\":{:.3},\"write_ms\":{:.3}",
+                            product.rows(), product.columns(), threads, ms(start, loaded), ms(loaded, multiplied),
+                            ms(multiplied, Instant::now())), false))
+            },
+        });
+
+        self.answered.fetch_add(1, Ordering::SeqCst);
+
+        match result {
+            Ok((details, shutdown)) => (format!("{{\"status\":\"ok\"{details}}}"), shutdown),
+            Err(error) => (format!("{{\"status\":\"error\",\"kind\":\"{}\",\"message\":{}}}", error.kind(),
+                                   escape(&error.to_string())), false),
+        }
+    }
+
+    /// Answers every line of one connection until it closes or asks the server to stop.
+    fn handle(&self, stream: UnixStream) {
+        let Ok(mut writer) = stream.try_clone() else { return };
+
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { return };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (reply, shutdown) = self.answer(&line);
+            if shutdown {
+                self.shutdown.store(true, Ordering::SeqCst);
+            }
+
+            if writeln!(writer, "{reply}").and_then(|_| writer.flush()).is_err() {
+                return
+            }
+
+            if shutdown {
+                // Wakes the accept loop so it notices the flag.
+                let _ = UnixStream::connect(&self.socket_path);
+                return
+            }
+        }
+    }
+}
+
+/// Keeps the matrices named at startup in memory and answers newline-delimited
+/// JSON requests over a Unix socket, each connection on its own thread:
+///
+/// `{"op":"multiply","a":"name_or_path","b":"name_or_path","out":"path","threads":4}`
+/// replies `{"status":"ok","rows":..,"columns":..,"threads":..,"load_ms":..,"multiply_ms":..,"write_ms":..}`,
+/// a failure `{"status":"error","kind":..,"message":..}`, and `{"op":"shutdown"}`
+/// stops the server. Outputs are written atomically.
+pub struct Server {
+    listener: UnixListener,
+    state: ServerState,
+}
+
+impl Server {
+    /// Loads the matrices and binds the socket, replacing a stale socket file.
+    pub fn bind(config: &ServeConfig) -> Result<Server, String> {
+        let mut matrices = HashMap::new();
+        for (name, file) in &config.matrices {
+            let matrix = Matrix::from_file(file).map_err(|error| format!("Couldn't load matrix {name}: {error}"))?;
+            matrices.insert(name.clone(), matrix);
+        }
+
+        let path = &config.socket_path;
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)
+                .map_err(|error| format!("Couldn't remove stale socket {}: {}", path, error))?,
+            Ok(_) => return Err(format!("{} exists and is not a socket", path)),
+            Err(_) => {},
+        }
+
+        let listener = UnixListener::bind(path).map_err(|error| format!("Couldn't bind socket {}: {}", path, error))?;
+
+        let state = ServerState { socket_path: path.clone(), matrices, shutdown: AtomicBool::new(false),
+            answered: AtomicUsize::new(0), connections: Mutex::new(HashMap::new()) };
+
+        Ok(Server { listener, state })
+    }
+
+    /// Serves until a shutdown request, waits for every connection's thread
+    /// to finish, then removes the socket file and returns the number of
+    /// requests answered. A request already being answered still gets its
+    /// reply; idle connections are closed.
+    pub fn run(self) -> Result<usize, String> {
+        let state = &self.state;
+
+        thread::scope(|scope| {
+            for (id, stream) in self.listener.incoming().enumerate() {
+                if state.shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    if let (Ok(clone), Ok(mut connections)) = (stream.try_clone(), state.connections.lock()) {
+                        connections.insert(id, clone);
+                    }
+                    scope.spawn(move || {
+                        state.handle(stream);
+                        if let Ok(mut connections) = state.connections.lock() {
+                            connections.remove(&id);
+                        }
+                    });
+                }
+            }
+
+            if let Ok(connections) = state.connections.lock() {
+                for stream in connections.values() {
+                    let _ = stream.shutdown(Shutdown::Read);
+                }
+            }
+        });
+
+        let _ = fs::remove_file(&self.state.socket_path);
+
+        Ok(self.state.answered.load(Ordering::SeqCst))
+    }
+}
+
+pub fn run_serve(config: ServeConfig) -> Result<usize, String> {
+    Server::bind(&config)?.run()
+}
+
+/// Sends one request line to the server at `socket_path` and returns its reply.
+pub fn send_request(socket_path: &str, request: &str) -> Result<String, String> {
+    let mut stream = UnixStream::connect(socket_path)
+        .map_err(|error| format!("Couldn't connect to {}: {}", socket_path, error))?;
+
+    writeln!(stream, "{}", request.trim()).map_err(|error| format!("Error sending request: {}", error))?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).map_err(|error| format!("Error reading reply: {}", error))?;
+
+    if reply.is_empty() {
+        return Err(String::from("The server closed the connection without replying"))
+    }
+
+    Ok(reply.trim_end().to_string())
+}
+
+pub fn run_client(config: ClientConfig) -> Result<String, String> {
+    send_request(&config.socket_path, &config.request)
+}
+
+#[cfg(test)]
+mod serve_test {
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+    use crate::serve::{send_request, Request, ServeConfig, Server};
+    use crate::test_support::temp_path;
+
+    fn start(name: &str, load: &[(&str, &str)]) -> (String, thread::JoinHandle<Result<usize, String>>) {
+        let socket = temp_path(name);
+        let mut args = vec![String::from("mm"), String::from("serve"), String::from("--socket"), socket.clone()];
+        for (name, file) in load {
+            args.extend([String::from("--load"), format!("{name}={file}")]);
+        }
+
+        let server = Server::bind(&ServeConfig::from_iter(args.into_iter()).unwrap()).unwrap();
+        (socket, thread::spawn(move || server.run()))
+    }
+
+    fn stop(socket: &str, server: thread::JoinHandle<Result<usize, String>>) -> usize {
+        assert_eq!(send_request(socket, r#"{"op": "shutdown"}"#), Ok(String::from(r#"{"status":"ok"}"#)));
+        let answered = server.join().unwrap().unwrap();
+        assert!(fs::metadata(socket).is_err());
+        answered
+    }
+
+    #[test]
+    fn round_trip_multiplies_loaded_and_file_matrices() {
+        let (a_file, b_file, out) = (temp_path("a.txt"), temp_path("b.txt"), temp_path("c.txt"));
+        let a = Matrix::new(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let b = Matrix::new(2, 2, vec![0.5, -1.0, 2.0, 0.0]).unwrap();
+        a.to_file(&a_file).unwrap();
+        b.to_file(&b_file).unwrap();
+
+        let (socket, server) = start("round_trip.sock", &[("weights", &b_file)]);
+        // B is served from memory even after its file is gone.
+        fs::remove_file(&b_file).unwrap();
+
+        let request = format!(r#"{{"op":"multiply","a":"{a_file}","b":"weights","out":"{out}","threads":8}}"#);
+        let reply = send_request(&socket, &request).unwrap();
+        let product = Matrix::<f64>::from_file(&out);
+
+        assert_eq!(stop(&socket, server), 2);
+        fs::remove_file(&a_file).unwrap();
+        fs::remove_file(&out).unwrap();
+
+        assert!(reply.starts_with(r#"{"status":"ok","rows":3,"columns":2,"threads":3,"load_ms":"#), "{reply}");
+        assert_eq!(product, multiply(&a, &b, 1));
+    }
+
+    #[test]
+    fn bad_requests_get_structured_errors() {
+        let (socket, server) = start("errors.sock", &[]);
+
+        let replies: Vec<String> = [
+            r#"{"op":"multiply","a":"x""#,
+            r#"{"op":"multiply","a":"x","b":"y"}"#,
+            r#"{"op":"multiply","a":"x","b":"y","out":"z","threads":0}"#,
+            r#"{"op":"transpose"}"#,
+            r#"{"op":"multiply","a":"missing \"matrix\"","b":"y","out":"z"}"#,
+        ].iter().map(|request| send_request(&socket, request).unwrap()).collect();
+
+        assert_eq!(stop(&socket, server), 6);
+
+        assert_eq!(replies[0], r#"{"status":"error","kind":"malformed_request","message":"Malformed request: unterminated object"}"#);
+        assert_eq!(replies[1], r#"{"status":"error","kind":"malformed_request","message":"Malformed request: missing field \"out\""}"#);
+        assert!(replies[2].contains(r#""message":"Malformed request: field \"threads\" must be a positive integer""#));
+        assert!(replies[3].starts_with(r#"{"status":"error","kind":"unknown_op","message":"Unknown op \"transpose\""#));
+        assert!(replies[4].starts_with(r#"{"status":"error","kind":"input","message":"missing \"matrix\" is not a loaded"#));
+    }
+
+    #[test]
+    fn concurrent_requests_keep_their_outputs() {
+        let b_file = temp_path("concurrent_b.txt");
+        let b = Matrix::new(2, 3, vec![1.0, 0.0, 2.0, -1.0, 3.0, 1.0]).unwrap();
+        b.to_file(&b_file).unwrap();
+
+        let (socket, server) = start("concurrent.sock", &[("b", &b_file)]);
+
+        let clients: Vec<_> = (0..8).map(|i| {
+            let socket = socket.clone();
+            thread::spawn(move || {
+                let (a_file, out) = (temp_path(&format!("concurrent_a{i}.txt")), temp_path(&format!("concurrent_c{i}.txt")));
+                let a = Matrix::new(40, 2, (0..80).map(|k| (k * (i + 1)) as f64).collect()).unwrap();
+                a.to_file(&a_file).unwrap();
+
+                let request = format!(r#"{{"op":"multiply","a":"{a_file}","b":"b","out":"{out}","threads":2}}"#);
+                let reply = send_request(&socket, &request).unwrap();
+                let product = Matrix::<f64>::from_file(&out).unwrap();
+
+                fs::remove_file(&a_file).unwrap();
+                fs::remove_file(&out).unwrap();
+                (reply, a, product)
+            })
+        }).collect();
+
+        for client in clients {
+            let (reply, a, product) = client.join().unwrap();
+            assert!(reply.starts_with(r#"{"status":"ok""#), "{reply}");
+            assert_eq!(product, multiply(&a, &b, 1).unwrap());
+        }
+
+        assert_eq!(stop(&socket, server), 9);
+        fs::remove_file(&b_file).unwrap();
+    }
+
+    #[test]
+    fn shutdown_waits_for_and_closes_open_connections() {
+        let (socket, server) = start("idle.sock", &[]);
+        let idle = UnixStream::connect(&socket).unwrap();
+
+        assert_eq!(stop(&socket, server), 1);
+
+        let mut rest = String::new();
+        assert_eq!(BufReader::new(idle).read_line(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn requests_parse_escapes_and_defaults() {
+        assert_eq!(Request::parse(r#" { "op" : "multiply", "a": "a \\b.txt", "b": "b", "out": "c\/d" } "#),
+                   Ok(Request::Multiply { a: String::from("a \\b.txt"), b: String::from("b"), out: String::from("c/d"),
+                       threads: 1 }));
+        assert!(Request::parse(r#"{"op":"shutdown","extra":[1]}"#).is_err());
+        assert!(Request::parse(r#"{"op":"shutdown","op":"multiply"}"#).is_err());
+    }
+}
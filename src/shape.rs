@@ -0,0 +1,68 @@
+use std::fmt::{Display, Formatter};
+
+/// Number of rows, kept distinct from [`Cols`] so the two can't be swapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rows(pub usize);
+
+/// Number of columns, kept distinct from [`Rows`] so the two can't be swapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cols(pub usize);
+
+impl From<usize> for Rows {
+    fn from(rows: usize) -> Rows {
+        Rows(rows)
+    }
+}
+
+impl From<usize> for Cols {
+    fn from(cols: usize) -> Cols {
+        Cols(cols)
+    }
+}
+
+/// Dimensions of a matrix, displayed as `rowsxcols`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shape {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Shape {
+    pub fn new(rows: Rows, cols: Cols) -> Shape {
+        Shape { rows: rows.0, cols: cols.0 }
+    }
+}
+
+impl From<Shape> for (usize, usize) {
+    fn from(shape: Shape) -> (usize, usize) {
+        (shape.rows, shape.cols)
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.rows, self.cols)
+    }
+}
+
+/// Formats the uniform "expected AxB, got CxD" dimension mismatch message.
+pub fn mismatch_message(expected: Shape, actual: Shape) -> String {
+    format!("Dimension mismatch: expected {}, got {}", expected, actual)
+}
+
+#[cfg(test)]
+mod shape_test {
+    use crate::shape::{mismatch_message, Cols, Rows, Shape};
+
+    #[test]
+    fn shape_display() {
+        assert_eq!(Shape::new(Rows(3), Cols(2)).to_string(), "3x2");
+    }
+
+    #[test]
+    fn mismatch_message_format() {
+        let message = mismatch_message(Shape { rows: 2, cols: 4 }, Shape { rows: 3, cols: 4 });
+
+        assert_eq!(message, "Dimension mismatch: expected 2x4, got 3x4");
+    }
+}
@@ -0,0 +1,191 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt::Write as _;
+use std::fs;
+use crate::io;
+use crate::matrix::Matrix;
+use crate::multiplication::MultiplyError;
+use crate::parallel::parallel_over_rows;
+
+/// A neighbour ordered by score, with the lower index winning a tie, so the
+/// best candidate is the greatest.
+#[derive(PartialEq)]
+struct Candidate {
+    score: f64,
+    index: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl Matrix<f64> {
+    /// For every row i, the `k` rows j ≠ i with the largest entries of A·Aᵀ,
+    /// as (j, score) pairs from the highest score down; equal scores keep the
+    /// lower index first. `k` is clamped to the other rows available.
+    ///
+    /// Each thread takes a band of rows and keeps only a k-sized heap per
+    /// row, so S itself is never stored.
+    pub fn self_similarity_topk(&self, k: usize, threads: usize) -> Result<Vec<Vec<(usize, f64)>>, String> {
+        let (rows, columns) = (self.rows(), self.columns());
+
+        if threads == 0 {
+            return Err(MultiplyError::NoThreads.into())
+        }
+
+        if threads > rows {
+            return Err(MultiplyError::TooManyThreads { num_of_threads: threads, rows }.into())
+        }
+
+        let k = k.min(rows.saturating_sub(1));
+        let numbers = self.get_numbers();
+        let row = |i: usize| &numbers[i * columns..(i + 1) * columns];
+
+        let neighbours = parallel_over_rows(rows, threads, |band| band.map(|i| {
+            let mut best = BinaryHeap::with_capacity(k + 1);
+
+            for j in (0..rows).filter(|&j| j != i) {
+                let score = row(i).iter().zip(row(j)).map(|(a, b)| a * b).sum();
+                best.push(Reverse(Candidate { score, index: j }));
+                if best.len() > k {
+                    best.pop();
+                }
+            }
+
+            best.into_sorted_vec().into_iter().map(|Reverse(candidate)| (candidate.index, candidate.score)).collect()
+        }).collect::<Vec<Vec<(usize, f64)>>>(), |bands| bands.concat())?;
+
+        Ok(neighbours)
+    }
+}
+
+/// Arguments of the `topk` subcommand.
+pub struct TopkConfig {
+    matrix_file_name: String,
+    k: usize,
+    output_file_name: String,
+    num_of_threads: usize,
+}
+
+impl TopkConfig {
+    /// Parses `topk [matrix file] [k] [output file] [number of threads]`, with
+    /// one thread if the count is left out.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<TopkConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let matrix_file_name = iterator.next().ok_or_else(|| String::from("Missing matrix file name"))?;
+        let k = iterator.next().ok_or_else(|| String::from("Missing k"))?;
+        let k = k.parse::<usize>().map_err(|_| format!("Couldn't get k from {k}"))?;
+        let output_file_name = iterator.next().ok_or_else(|| String::from("Missing output file name"))?;
+
+        let num_of_threads = match iterator.next() {
+            Some(threads) => match threads.parse::<usize>() {
+                Ok(threads) if threads > 0 => threads,
+                _ => return Err(format!("Couldn't get number of threads from {threads}"))
+            },
+            None => 1,
+        };
+
+        Ok(TopkConfig { matrix_file_name, k, output_file_name, num_of_threads })
+    }
+}
+
+/// Writes one line per row of the matrix: its neighbours as `index:score`
+/// separated by spaces, best first. Returns the number of rows written.
+pub fn run_topk(config: TopkConfig) -> Result<usize, String> {
+    let matrix = io::read_matrix::<f64>(&config.matrix_file_name, None)?;
+    let threads = config.num_of_threads.min(matrix.rows()).max(1);
+
+    let neighbours = if matrix.rows() == 0 { vec![] } else { matrix.self_similarity_topk(config.k, threads)? };
+
+    let mut contents = String::new();
+    for row in &neighbours {
+        let line: Vec<String> = row.iter().map(|(index, score)| format!("{}:{}", index, score)).collect();
+        let _ = writeln!(contents, "{}", line.join(" "));
+    }
+
+    fs::write(&config.output_file_name, contents)
+        .map_err(|error| format!("Error writing to file {}: {}", config.output_file_name, error))?;
+
+    Ok(neighbours.len())
+}
+
+#[cfg(test)]
+mod similarity_test {
+    use std::fs;
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+    use crate::similarity::{run_topk, TopkConfig};
+    use crate::test_support::temp_path;
+
+    fn sample() -> Matrix<f64> {
+        Matrix::new(5, 3, vec![
+            1.0, 0.0, 2.0,
+            0.5, 1.0, -1.0,
+            3.0, 0.0, 0.0,
+            -1.0, 2.0, 1.0,
+            0.0, 0.0, 1.5,
+        ]).unwrap()
+    }
+
+    #[test]
+    fn topk_matches_materialized_similarity() {
+        let matrix = sample();
+        let similarity = multiply(&matrix, &matrix.transpose(), 1).unwrap();
+
+        for (k, threads) in [(1, 1), (2, 2), (3, 5)] {
+            let topk = matrix.self_similarity_topk(k, threads).unwrap();
+
+            for (i, neighbours) in topk.iter().enumerate() {
+                let mut expected: Vec<(usize, f64)> = (0..5).filter(|&j| j != i).map(|j| (j, similarity[(i, j)])).collect();
+                expected.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                expected.truncate(k);
+
+                assert_eq!(neighbours, &expected, "row {i}, k {k}");
+            }
+        }
+    }
+
+    #[test]
+    fn ties_diagonal_and_large_k() {
+        let matrix = Matrix::new(4, 1, vec![2.0, 1.0, 1.0, 1.0]).unwrap();
+
+        assert_eq!(matrix.self_similarity_topk(2, 1).unwrap(), vec![
+            vec![(1, 2.0), (2, 2.0)],
+            vec![(0, 2.0), (2, 1.0)],
+            vec![(0, 2.0), (1, 1.0)],
+            vec![(0, 2.0), (1, 1.0)],
+        ]);
+        assert_eq!(matrix.self_similarity_topk(10, 2).unwrap()[0], vec![(1, 2.0), (2, 2.0), (3, 2.0)]);
+        assert!(matrix.self_similarity_topk(1, 5).is_err());
+    }
+
+    #[test]
+    fn topk_subcommand_writes_a_line_per_row() {
+        let (matrix, output) = (temp_path("a.txt"), temp_path("topk.txt"));
+        sample().to_file(&matrix).unwrap();
+
+        let args = ["mm", "topk", &matrix, "2", &output, "8"].map(String::from);
+        let written = run_topk(TopkConfig::from_iter(args.into_iter()).unwrap());
+        let contents = fs::read_to_string(&output).unwrap();
+
+        fs::remove_file(&matrix).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(written, Ok(5));
+        assert_eq!(contents.lines().next(), Some("2:3 4:3"));
+        assert_eq!(contents.lines().count(), 5);
+    }
+}
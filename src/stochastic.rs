@@ -0,0 +1,111 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::matrix::Matrix;
+use crate::parallel::parallel_over_rows_indexed;
+use crate::shape::{mismatch_message, Shape};
+
+/// Multiplies in f64 and stores each result in f32 by stochastic rounding:
+/// it is rounded up with probability equal to its distance from the f32 below,
+/// so the rounding is unbiased in expectation.
+///
+/// Thread `i` draws from an RNG seeded with `seed` and `i`, so the output is
+/// reproducible for a fixed seed and thread count.
+pub fn multiply_f64_to_f32_stochastic(matrix_a: &Matrix<f64>, matrix_b: &Matrix<f64>, num_of_threads: usize,
+                                      seed: u64) -> Result<Matrix<f32>, String> {
+
+    if matrix_a.columns() != matrix_b.rows() {
+        return Err(mismatch_message(
+            Shape { rows: matrix_a.columns(), cols: matrix_b.columns() }, matrix_b.shape()))
+    }
+
+    let rows = matrix_a.rows();
+
+    if num_of_threads == 0 || num_of_threads > rows {
+        return Err(format!("Num of threads: {num_of_threads} must be between 1 and Matrix A rows: {rows}"))
+    }
+
+    let (n, columns) = (matrix_a.columns(), matrix_b.columns());
+    let (a_numbers, b_numbers) = (matrix_a.get_numbers(), matrix_b.get_numbers());
+    let (a_numbers, b_numbers) = (a_numbers.as_slice(), b_numbers.as_slice());
+
+    let numbers = parallel_over_rows_indexed(rows, num_of_threads, |thread_num, rows| {
+        let mut rng = StdRng::seed_from_u64(seed ^ (thread_num as u64).wrapping_mul(0x9E3779B97F4A7C15));
+        let mut result = Vec::with_capacity(rows.len() * columns);
+        for row in rows {
+            for column in 0..columns {
+                let sum: f64 = (0..n).map(|k| a_numbers[row * n + k] * b_numbers[k * columns + column]).sum();
+                result.push(round_stochastic(sum, rng.gen::<f64>()));
+            }
+        }
+        result
+    }, |results| results.concat())?;
+
+    Matrix::new(rows, columns, numbers)
+}
+
+/// Picks the f32 above `value` when `draw` (uniform in [0, 1)) falls below the
+/// fractional distance from the f32 below, the f32 below otherwise.
+fn round_stochastic(value: f64, draw: f64) -> f32 {
+    let nearest = value as f32;
+
+    if !nearest.is_finite() || nearest as f64 == value {
+        return nearest
+    }
+
+    let (below, above) = if (nearest as f64) < value {
+        (nearest, nearest.next_up())
+    } else {
+        (nearest.next_down(), nearest)
+    };
+
+    let fraction = (value - below as f64) / (above as f64 - below as f64);
+
+    if draw < fraction { above } else { below }
+}
+
+#[cfg(test)]
+mod stochastic_test {
+    use crate::matrix::Matrix;
+    use crate::stochastic::multiply_f64_to_f32_stochastic;
+
+    #[test]
+    fn reproducible_for_fixed_seed() {
+        let matrix_a = Matrix::<f64>::gen_random(6, 5, -1.0, 1.0).unwrap();
+        let matrix_b = Matrix::<f64>::gen_random(5, 4, -1.0, 1.0).unwrap();
+
+        let first = multiply_f64_to_f32_stochastic(&matrix_a, &matrix_b, 3, 42).unwrap();
+
+        assert_eq!(multiply_f64_to_f32_stochastic(&matrix_a, &matrix_b, 3, 42).unwrap(), first);
+    }
+
+    #[test]
+    fn within_one_ulp_of_f64_product() {
+        let matrix_a = Matrix::<f64>::gen_random(8, 7, -10.0, 10.0).unwrap();
+        let matrix_b = Matrix::<f64>::gen_random(7, 9, -10.0, 10.0).unwrap();
+
+        let exact = crate::multiplication::multiply(&matrix_a, &matrix_b, 1).unwrap();
+        let rounded = multiply_f64_to_f32_stochastic(&matrix_a, &matrix_b, 2, 7).unwrap();
+
+        for (&x, &r) in exact.get_numbers().iter().zip(rounded.get_numbers().iter()) {
+            let nearest = x as f32;
+            assert!(r == nearest || r == nearest.next_up() || r == nearest.next_down(), "{r} from {x}");
+            assert!((r as f64 - x).abs() <= (nearest.next_up() - nearest.next_down()) as f64 / 2.0 * 1.0000001);
+        }
+    }
+
+    #[test]
+    fn mean_over_seeds_is_unbiased() {
+        // A quarter of the way from 1 to the next f32 up; round-to-nearest gives 1.
+        let value = 1.0 + f32::EPSILON as f64 / 4.0;
+        let matrix_a = Matrix::new(1, 1, vec![value]).unwrap();
+        let identity = Matrix::new(1, 1, vec![1.0]).unwrap();
+
+        let seeds = 4000;
+        let mean = (0..seeds)
+            .map(|seed| multiply_f64_to_f32_stochastic(&matrix_a, &identity, 1, seed).unwrap().get_numbers()[0] as f64)
+            .sum::<f64>() / seeds as f64;
+
+        assert_eq!(value as f32, 1.0);
+        assert!((mean - value).abs() < f32::EPSILON as f64 / 20.0, "mean {mean}");
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use crate::element::Element;
+use crate::io::{self, binary_header, read_binary_header, BINARY_HEADER_LEN};
+use crate::matrix::Matrix;
+use crate::multiplication::multiply;
+use crate::shape::{mismatch_message, Shape};
+
+/// Multiplies `matrix_a` by the binary matrix stored at `b_path` without
+/// loading B, writing the product to `out_path` in the binary format.
+///
+/// B is read `panel_cols` columns at a time by seeking to each row's slice of
+/// the panel. Every panel of C is computed with all threads and appended to a
+/// scratch file next to `out_path`, which is then reassembled row by row.
+/// Memory stays bounded by A plus one panel of B and one panel of C.
+pub fn multiply_streaming_b(matrix_a: &Matrix<f64>, b_path: &str, panel_cols: usize, num_of_threads: usize,
+                            out_path: &str) -> Result<(), String> {
+
+    if panel_cols == 0 {
+        return Err(String::from("Panel columns: 0 must be higher than 0"))
+    }
+
+    let mut b_file = io::open_file(b_path)?;
+    let (b_rows, b_columns) = read_binary_header::<f64, _>(&mut b_file)
+        .map_err(|error| format!("Error reading file {}: {}", b_path, error))?;
+
+    if matrix_a.columns() != b_rows {
+        return Err(mismatch_message(
+            Shape { rows: matrix_a.columns(), cols: b_columns },
+            Shape { rows: b_rows, cols: b_columns }))
+    }
+
+    let rows = matrix_a.rows();
+    let panels_path = format!("{}.panels{}", out_path, std::process::id());
+
+    let result = write_panels(matrix_a, &mut b_file, b_rows, b_columns, panel_cols, num_of_threads, &panels_path)
+        .and_then(|_| assemble(&panels_path, rows, b_columns, panel_cols, out_path));
+
+    let _ = fs::remove_file(&panels_path);
+
+    result
+}
+
+/// Computes C panel by panel, storing each as a row-major `rows x width` block.
+fn write_panels(matrix_a: &Matrix<f64>, b_file: &mut BufReader<File>, b_rows: usize, b_columns: usize,
+                panel_cols: usize, num_of_threads: usize, panels_path: &str) -> Result<(), String> {
+
+    let mut panels = io::create_file(panels_path)?;
+
+    for start in (0..b_columns).step_by(panel_cols) {
+        let width = panel_cols.min(b_columns - start);
+        let mut bytes = vec![0u8; width * f64::SIZE];
+        let mut numbers = Vec::with_capacity(b_rows * width);
+
+        for row in 0..b_rows {
+            let offset = BINARY_HEADER_LEN + (row * b_columns + start) * f64::SIZE;
+
+            b_file.seek(SeekFrom::Start(offset as u64))
+                .and_then(|_| b_file.read_exact(&mut bytes))
+                .map_err(|error| format!("Couldn't read panel of B: {}", error))?;
+
+            numbers.extend(bytes.chunks_exact(f64::SIZE).map(<f64 as Element>::from_le_bytes));
+        }
+
+        let panel = multiply(matrix_a, &Matrix::new(b_rows, width, numbers)?, num_of_threads)?;
+
+        let mut out = Vec::with_capacity(panel.get_numbers().len() * f64::SIZE);
+        for num in panel.get_numbers().iter() {
+            num.write_le_bytes(&mut out);
+        }
+
+        panels.write_all(&out)
+            .map_err(|error| format!("Error writing to file {}: {}", panels_path, error))?;
+    }
+
+    panels.flush().map_err(|error| format!("Error writing to file {}: {}", panels_path, error))
+}
+
+/// Stitches one output row at a time together from the slices in every panel.
+fn assemble(panels_path: &str, rows: usize, columns: usize, panel_cols: usize, out_path: &str) -> Result<(), String> {
+    let mut panels = io::open_file(panels_path)?;
+    let mut out = io::create_file(out_path)?;
+
+    let write_error = |error: std::io::Error| format!("Error writing to file {}: {}", out_path, error);
+
+    out.write_all(&binary_header::<f64>(rows, columns)).map_err(write_error)?;
+
+    let mut bytes = vec![0u8; panel_cols * f64::SIZE];
+
+    for row in 0..rows {
+        for start in (0..columns).step_by(panel_cols) {
+            let width = panel_cols.min(columns - start);
+            let offset = (rows * start + row * width) * f64::SIZE;
+
+            panels.seek(SeekFrom::Start(offset as u64))
+                .and_then(|_| panels.read_exact(&mut bytes[..width * f64::SIZE]))
+                .map_err(|error| format!("Couldn't read file {}: {}", panels_path, error))?;
+
+            out.write_all(&bytes[..width * f64::SIZE]).map_err(write_error)?;
+        }
+    }
+
+    out.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod streaming_test {
+    use std::fs;
+    use crate::io::{read_matrix, write_matrix, Format};
+    use crate::matrix::Matrix;
+    use crate::multiplication::multiply;
+    use crate::streaming::multiply_streaming_b;
+    use crate::test_support::temp_path;
+
+    #[test]
+    fn streamed_product_matches_in_memory() {
+        let matrix_a = Matrix::<f64>::gen_random(4, 3, -1.0, 1.0).unwrap();
+        let matrix_b = Matrix::<f64>::gen_random(3, 7, -1.0, 1.0).unwrap();
+        let (b_path, out_path) = (temp_path("b.bin"), temp_path("c.bin"));
+        write_matrix(&matrix_b, &b_path, Some(Format::Binary)).unwrap();
+
+        let expected = multiply(&matrix_a, &matrix_b, 2).unwrap();
+
+        for panel_cols in [1, 3, 7, 10] {
+            multiply_streaming_b(&matrix_a, &b_path, panel_cols, 2, &out_path).unwrap();
+            let result = read_matrix::<f64>(&out_path, Some(Format::Binary)).unwrap();
+
+            assert_eq!(result, expected, "panel_cols {panel_cols}");
+        }
+
+        fs::remove_file(&b_path).unwrap();
+        fs::remove_file(&out_path).unwrap();
+    }
+
+    #[test]
+    fn streaming_rejects_bad_inputs() {
+        let matrix_a = Matrix::<f64>::gen_random(2, 2, -1.0, 1.0).unwrap();
+        let (b_path, out_path) = (temp_path("bad_b.bin"), temp_path("bad_c.bin"));
+        write_matrix(&Matrix::<f64>::gen_random(3, 2, -1.0, 1.0).unwrap(), &b_path, Some(Format::Binary)).unwrap();
+
+        let zero_panel = multiply_streaming_b(&matrix_a, &b_path, 0, 1, &out_path);
+        let mismatch = multiply_streaming_b(&matrix_a, &b_path, 1, 1, &out_path);
+        fs::remove_file(&b_path).unwrap();
+
+        assert!(zero_panel.is_err());
+        assert_eq!(mismatch.unwrap_err(), "Dimension mismatch: expected 2x2, got 3x2");
+        assert!(fs::metadata(&out_path).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+//! Helpers shared by the unit tests.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A path in the temporary directory for the file `name`, unique to the
+/// running test: the test harness names each test's thread after the test, so
+/// two tests using the same `name` at once don't share a file. The name is
+/// hashed to keep socket paths within their length limit.
+pub(crate) fn temp_path(name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    std::thread::current().name().hash(&mut hasher);
+
+    let path = std::env::temp_dir().join(format!("mm_{}_{:016x}_{}", std::process::id(), hasher.finish(), name));
+    path.to_str().unwrap().to_string()
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::thread;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::benchmark::benchmark_stable;
+use crate::matrix::Matrix;
+use crate::multiplication::{Kernel, MultiplyOptions};
+
+/// Square sizes benchmarked by [`autotune`]; each one is the upper end of a bucket.
+pub const TUNE_SIZES: [usize; 3] = [32, 128, 384];
+
+/// The fastest configuration found for matrices up to `max_size` in every dimension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuneEntry {
+    pub max_size: usize,
+    pub num_of_threads: usize,
+    pub kernel: Kernel,
+}
+
+/// Per-size-bucket defaults, saved as `size.<max size>.<key> = <value>` lines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TuneResult {
+    /// Sorted by `max_size`.
+    pub entries: Vec<TuneEntry>,
+}
+
+impl TuneResult {
+    /// The smallest bucket that fits `size`, or the largest bucket when none does.
+    pub fn lookup(&self, size: usize) -> Option<&TuneEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.max_size >= size)
+            .or(self.entries.last())
+    }
+
+    pub fn save(&self, file_name: &str) -> Result<(), String> {
+        let mut contents = String::from("# mm tune profile\n");
+
+        for entry in &self.entries {
+            contents += &format!("size.{}.threads = {}\n", entry.max_size, entry.num_of_threads);
+            contents += &format!("size.{}.kernel = {}\n", entry.max_size, entry.kernel);
+        }
+
+        fs::write(file_name, contents).map_err(|error| format!("Error writing to file {}: {}", file_name, error))
+    }
+
+    pub fn load(file_name: &str) -> Result<TuneResult, String> {
+        let contents = fs::read_to_string(file_name)
+            .map_err(|error| format!("Couldn't open file {}\nerror: {}", file_name, error))?;
+
+        TuneResult::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<TuneResult, String> {
+        let mut buckets: BTreeMap<usize, (Option<usize>, Option<Kernel>)> = BTreeMap::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || format!("Invalid tune profile line {}: '{}'", index + 1, line);
+
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let (key, value) = (key.trim(), value.trim());
+
+            let (max_size, field) = key
+                .strip_prefix("size.")
+                .and_then(|rest| rest.split_once('.'))
+                .ok_or_else(invalid)?;
+            let max_size = max_size.parse::<usize>().map_err(|_| invalid())?;
+
+            let bucket = buckets.entry(max_size).or_default();
+
+            match field {
+                "threads" => bucket.0 = Some(value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(invalid)?),
+                "kernel" => bucket.1 = Some(Kernel::from_name(value).map_err(|_| invalid())?),
+                _ => return Err(invalid())
+            }
+        }
+
+        let entries = buckets.into_iter().map(|(max_size, bucket)| match bucket {
+            (Some(num_of_threads), Some(kernel)) => Ok(TuneEntry { max_size, num_of_threads, kernel }),
+            (None, _) => Err(format!("Tune profile size {max_size} is missing threads")),
+            (_, None) => Err(format!("Tune profile size {max_size} is missing kernel")),
+        }).collect::<Result<Vec<TuneEntry>, String>>()?;
+
+        if entries.is_empty() {
+            return Err(String::from("Tune profile has no entries"))
+        }
+
+        Ok(TuneResult { entries })
+    }
+}
+
+/// Sweeps thread counts and kernels over seeded matrices of every [`TUNE_SIZES`]
+/// size, timing `sample_size` runs of each configuration.
+pub fn autotune(sample_size: usize) -> Result<TuneResult, String> {
+    autotune_sizes(&TUNE_SIZES, sample_size)
+}
+
+pub fn autotune_sizes(sizes: &[usize], sample_size: usize) -> Result<TuneResult, String> {
+    let available = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+
+    let mut entries = Vec::with_capacity(sizes.len());
+
+    for &size in sizes {
+        let random = |rng: &mut StdRng| Matrix::new(size, size, (0..size * size)
+            .map(|_| rng.gen_range(-1.0..1.0))
+            .collect::<Vec<f64>>());
+        let matrix_a = random(&mut rng)?;
+        let matrix_b = random(&mut rng)?;
+
+        let mut thread_counts: Vec<usize> = (0..).map(|power| 1 << power)
+            .take_while(|&threads| threads < available.min(size))
+            .collect();
+        thread_counts.push(available.min(size).max(1));
+
+        let mut best: Option<(TuneEntry, std::time::Duration)> = None;
+
+        for &num_of_threads in &thread_counts {
+            for kernel in [Kernel::Naive, Kernel::CacheOblivious] {
+                let mut options = MultiplyOptions::new(num_of_threads);
+                options.kernel = kernel;
+
+                let median = benchmark_stable(&matrix_a, &matrix_b, &options, 1, sample_size)?.median;
+
+                if best.as_ref().is_none_or(|(_, best_median)| median < *best_median) {
+                    best = Some((TuneEntry { max_size: size, num_of_threads, kernel }, median));
+                }
+            }
+        }
+
+        entries.extend(best.map(|(entry, _)| entry));
+    }
+
+    entries.sort_by_key(|entry| entry.max_size);
+
+    Ok(TuneResult { entries })
+}
+
+pub struct TuneConfig {
+    profile_file_name: String,
+    sample_size: usize,
+}
+
+impl TuneConfig {
+    /// Parses `tune [profile file] [timed runs]`, the latter defaulting to 3.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<TuneConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let profile_file_name = iterator.next().ok_or_else(|| String::from("Missing tune profile file name"))?;
+
+        let sample_size = match iterator.next() {
+            Some(value) => value.parse::<usize>().map_err(|_| format!("Couldn't get number of timed runs from {value}"))?,
+            None => 3,
+        };
+
+        Ok(TuneConfig { profile_file_name, sample_size })
+    }
+}
+
+pub fn run_tune(config: TuneConfig) -> Result<TuneResult, String> {
+    let result = autotune(config.sample_size)?;
+    result.save(&config.profile_file_name)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tune_test {
+    use std::fs;
+    use crate::multiplication::{run_with_stats, Config, Kernel, MultiplyStats};
+    use crate::test_support::temp_path;
+    use crate::tune::{autotune_sizes, TuneEntry, TuneResult};
+
+    #[test]
+    fn tuner_covers_every_bucket() {
+        let result = autotune_sizes(&[24, 8], 1).unwrap();
+
+        let sizes: Vec<usize> = result.entries.iter().map(|entry| entry.max_size).collect();
+        assert_eq!(sizes, vec![8, 24]);
+        assert!(result.entries.iter().all(|entry| (1..=entry.max_size).contains(&entry.num_of_threads)));
+
+        assert_eq!(result.lookup(5).unwrap().max_size, 8);
+        assert_eq!(result.lookup(9).unwrap().max_size, 24);
+        assert_eq!(result.lookup(1000).unwrap().max_size, 24);
+    }
+
+    #[test]
+    fn profile_round_trip() {
+        let path = temp_path("round_trip.txt");
+        let result = TuneResult { entries: vec![
+            TuneEntry { max_size: 32, num_of_threads: 2, kernel: Kernel::Naive },
+            TuneEntry { max_size: 512, num_of_threads: 8, kernel: Kernel::CacheOblivious },
+        ] };
+
+        result.save(&path).unwrap();
+        let loaded = TuneResult::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), result);
+    }
+
+    #[test]
+    fn profile_rejects_bad_lines() {
+        assert!(TuneResult::parse("size.32.threads = 0\nsize.32.kernel = naive\n").is_err());
+        assert!(TuneResult::parse("size.32.threads = 2\n").unwrap_err().contains("missing kernel"));
+        assert!(TuneResult::parse("size.32.colour = blue\n").is_err());
+        assert!(TuneResult::parse("# empty\n").is_err());
+    }
+
+    #[test]
+    fn run_honors_profile() {
+        let (profile, matrix_a, output) = (temp_path("profile.txt"), temp_path("a.txt"), temp_path("c.txt"));
+        fs::write(&profile, "size.1000.threads = 1\nsize.1000.kernel = cache-oblivious\n").unwrap();
+        fs::write(&matrix_a, "4\n4\n1 0 0 0\n0 1 0 0\n0 0 1 0\n0 0 0 1\n").unwrap();
+
+        let args = ["mm", &matrix_a, &matrix_a, &output, "auto", "--tune-profile", &profile];
+        let stats = run_with_stats(Config::from_iter(args.iter().map(|arg| arg.to_string())).unwrap());
+        let bounded_args = [&args[..], &["--abort-if-exceeds", "2"]].concat();
+        let bounded = run_with_stats(Config::from_iter(bounded_args.iter().map(|arg| arg.to_string())).unwrap());
+
+        let written = fs::read_to_string(&output);
+        for file in [&profile, &matrix_a, &output] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!(stats.unwrap(), MultiplyStats { num_of_threads: 1, kernel: Kernel::CacheOblivious, denormals_flushed: 0,
+            max_magnitude: 1.0 });
+        assert_eq!(bounded.unwrap().kernel, Kernel::Naive);
+        assert_eq!(written.unwrap(), "4\n4\n1 0 0 0\n0 1 0 0\n0 0 1 0\n0 0 0 1\n");
+    }
+}
@@ -0,0 +1,295 @@
+use std::any::type_name;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::str::FromStr;
+use crate::element::Element;
+use crate::matrix::Matrix;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProblemKind {
+    BlankLine,
+    TrailingWhitespace,
+    CarriageReturn,
+    Comment,
+    InvalidHeader(String),
+    WrongRowLength { expected: usize, actual: usize },
+    InvalidToken(String),
+    NonFinite(String),
+    MissingRows { expected: usize, actual: usize },
+    TrailingGarbage,
+}
+
+impl ProblemKind {
+    /// Whether `--fix` can rewrite the problem away without touching the data.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, ProblemKind::BlankLine | ProblemKind::TrailingWhitespace
+            | ProblemKind::CarriageReturn | ProblemKind::Comment)
+    }
+}
+
+/// One problem found by [`Matrix::validate_file`], on a 1-based line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub line: usize,
+    pub kind: ProblemKind,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ProblemKind::BlankLine => write!(f, "blank line"),
+            ProblemKind::TrailingWhitespace => write!(f, "trailing whitespace"),
+            ProblemKind::CarriageReturn => write!(f, "CRLF line ending"),
+            ProblemKind::Comment => write!(f, "comment line"),
+            ProblemKind::InvalidHeader(reason) => write!(f, "{}", reason),
+            ProblemKind::WrongRowLength { expected, actual } =>
+                write!(f, "row length: {} doesn't match columns: {}", actual, expected),
+            ProblemKind::InvalidToken(message) => write!(f, "{}", message),
+            ProblemKind::NonFinite(token) => write!(f, "non-finite value '{}'", token),
+            ProblemKind::MissingRows { expected, actual } =>
+                write!(f, "found {} rows but the header declares {}", actual, expected),
+            ProblemKind::TrailingGarbage => write!(f, "data after the last declared row"),
+        }
+    }
+}
+
+/// Every problem in a plain text matrix file, plus the file's data lines with
+/// the recoverable problems cleaned up.
+#[derive(Debug, PartialEq)]
+pub struct ValidationReport {
+    pub problems: Vec<Problem>,
+    cleaned: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn is_fixable(&self) -> bool {
+        self.problems.iter().all(|problem| problem.kind.is_recoverable())
+    }
+
+    /// Writes the cleaned file, refusing if any problem isn't recoverable.
+    pub fn write_fixed(&self, file_name: &str) -> Result<(), String> {
+        let fatal: Vec<String> = self.problems
+            .iter()
+            .filter(|problem| !problem.kind.is_recoverable())
+            .map(|problem| problem.to_string())
+            .collect();
+
+        if !fatal.is_empty() {
+            return Err(format!("Can't fix file, data errors remain:\n{}", fatal.join("\n")))
+        }
+
+        let mut contents = self.cleaned.join("\n");
+        contents.push('\n');
+
+        fs::write(file_name, contents).map_err(|error| format!("Error writing to file {}: {}", file_name, error))
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_valid() {
+            return write!(f, "No problems found")
+        }
+        let problems: Vec<String> = self.problems.iter().map(|problem| problem.to_string()).collect();
+        write!(f, "{}", problems.join("\n"))
+    }
+}
+
+impl<T: FromStr + Element> Matrix<T> {
+    /// Checks a plain text matrix file without stopping at the first problem.
+    ///
+    /// Blank lines, trailing whitespace, CRLF endings and `#` comments after
+    /// the first header line are reported as recoverable; header, row length, token, non-finite value and
+    /// row count problems are data errors.
+    pub fn validate_file(file_name: &str) -> Result<ValidationReport, String> {
+        let contents = fs::read_to_string(file_name)
+            .map_err(|error| format!("Couldn't open file {}\nerror: {}", file_name, error))?;
+
+        let mut lines: Vec<&str> = contents.split('\n').collect();
+        if lines.last() == Some(&"") {
+            lines.pop();
+        }
+
+        let mut problems = Vec::new();
+        let mut cleaned = Vec::new();
+        let mut report = |line: usize, kind: ProblemKind| problems.push(Problem { line, kind });
+
+        let mut header: Vec<Option<usize>> = Vec::new();
+        let mut rows_seen = 0;
+
+        for (index, raw) in lines.iter().enumerate() {
+            let line = index + 1;
+
+            let text = match raw.strip_suffix('\r') {
+                Some(text) => {
+                    report(line, ProblemKind::CarriageReturn);
+                    text
+                },
+                None => raw,
+            };
+
+            if text.trim().is_empty() {
+                report(line, ProblemKind::BlankLine);
+                continue;
+            }
+
+            if text.trim_start().starts_with('#') {
+                if !header.is_empty() {
+                    report(line, ProblemKind::Comment);
+                }
+                continue;
+            }
+
+            let text = text.trim_end();
+            if text.len() != raw.trim_end_matches('\r').len() {
+                report(line, ProblemKind::TrailingWhitespace);
+            }
+
+            cleaned.push(text.to_string());
+
+            if header.len() < 2 {
+                let name = if header.is_empty() { "rows num" } else { "columns num" };
+                match text.trim().parse::<usize>() {
+                    Ok(value) => header.push(Some(value)),
+                    Err(_) => {
+                        report(line, ProblemKind::InvalidHeader(format!("Couldn't parse '{}' as {}", text, name)));
+                        header.push(None);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(rows) = header[0] {
+                if rows_seen >= rows {
+                    report(line, ProblemKind::TrailingGarbage);
+                    continue;
+                }
+            }
+
+            let tokens: Vec<&str> = text.split_whitespace().collect();
+
+            if let Some(columns) = header[1] {
+                if tokens.len() != columns {
+                    report(line, ProblemKind::WrongRowLength { expected: columns, actual: tokens.len() });
+                }
+            }
+
+            for token in tokens {
+                match token.parse::<T>() {
+                    Ok(num) if !num.to_f64().is_finite() => report(line, ProblemKind::NonFinite(token.to_string())),
+                    Ok(_) => {},
+                    Err(_) => report(line, ProblemKind::InvalidToken(
+                        format!("'{}' is not a valid {}", token, type_name::<T>()))),
+                }
+            }
+
+            rows_seen += 1;
+        }
+
+        match header.as_slice() {
+            [Some(rows), _] if rows_seen < *rows =>
+                report(lines.len(), ProblemKind::MissingRows { expected: *rows, actual: rows_seen }),
+            [] | [_] => report(lines.len(), ProblemKind::InvalidHeader(String::from("File doesn't have a full header"))),
+            _ => {}
+        }
+
+        Ok(ValidationReport { problems, cleaned })
+    }
+}
+
+pub struct ValidateConfig {
+    file_name: String,
+    fix_file_name: Option<String>,
+}
+
+impl ValidateConfig {
+    /// Parses `validate [file] [--fix fixed file]`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter(mut iterator: impl Iterator<Item=String>) -> Result<ValidateConfig, String> {
+        iterator.next();
+        iterator.next();
+
+        let file_name = iterator.next().ok_or_else(|| String::from("Missing file to validate"))?;
+
+        let fix_file_name = match iterator.next().as_deref() {
+            Some("--fix") => Some(iterator.next().ok_or_else(|| String::from("Missing value for --fix"))?),
+            Some(other) => return Err(format!("Unknown option: {other}")),
+            None => None,
+        };
+
+        Ok(ValidateConfig { file_name, fix_file_name })
+    }
+}
+
+/// Validates the file as `f64` and, with `--fix`, writes the cleaned copy.
+pub fn run_validate(config: ValidateConfig) -> Result<ValidationReport, String> {
+    let report = Matrix::<f64>::validate_file(&config.file_name)?;
+
+    if let Some(fix_file_name) = config.fix_file_name {
+        report.write_fixed(&fix_file_name)?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod validate_test {
+    use std::fs;
+    use crate::matrix::Matrix;
+    use crate::test_support::temp_path;
+    use crate::validate::{Problem, ProblemKind};
+
+    #[test]
+    fn reports_every_data_problem() {
+        let path = temp_path("three_problems.txt");
+        fs::write(&path, "2\n3\n1 2 x\n4 5\n7 8 9\n").unwrap();
+
+        let report = Matrix::<f64>::validate_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.problems, vec![
+            Problem { line: 3, kind: ProblemKind::InvalidToken(String::from("'x' is not a valid f64")) },
+            Problem { line: 4, kind: ProblemKind::WrongRowLength { expected: 3, actual: 2 } },
+            Problem { line: 5, kind: ProblemKind::TrailingGarbage },
+        ]);
+        assert!(!report.is_fixable());
+        assert!(report.write_fixed(&temp_path("unfixable.txt")).is_err());
+    }
+
+    #[test]
+    fn reports_non_finite_and_missing_rows() {
+        let path = temp_path("non_finite.txt");
+        fs::write(&path, "3\n2\n1 NaN\n").unwrap();
+
+        let report = Matrix::<f64>::validate_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.to_string(),
+                   "line 3: non-finite value 'NaN'\nline 3: found 1 rows but the header declares 3");
+    }
+
+    #[test]
+    fn fixes_recoverable_problems() {
+        let (path, fixed) = (temp_path("messy.txt"), temp_path("fixed.txt"));
+        fs::write(&path, "# generated\r\n2\r\n2  \r\n\r\n1 2\r\n3 4\t\r\n\r\n").unwrap();
+
+        let report = Matrix::<f64>::validate_file(&path).unwrap();
+        assert!(!report.is_valid());
+        assert!(report.is_fixable());
+        report.write_fixed(&fixed).unwrap();
+
+        let matrix = Matrix::<f64>::from_file(&fixed);
+        let revalidated = Matrix::<f64>::validate_file(&fixed).unwrap();
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&fixed).unwrap();
+
+        assert_eq!(matrix.unwrap(), Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]).unwrap());
+        assert!(revalidated.is_valid());
+    }
+}
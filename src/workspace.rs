@@ -0,0 +1,104 @@
+use std::sync::Mutex;
+
+/// The buffers of one element type, see [`Workspace`].
+#[derive(Debug)]
+pub(crate) struct Buffers<T> {
+    pub(crate) a: Vec<T>,
+    pub(crate) b: Vec<T>,
+    pub(crate) output: Vec<T>,
+    /// Bands the threads compute into before they're copied to the output.
+    pub(crate) scratch: Mutex<Vec<Vec<T>>>,
+}
+
+impl<T> Default for Buffers<T> {
+    fn default() -> Buffers<T> {
+        Buffers { a: Vec::new(), b: Vec::new(), output: Vec::new(), scratch: Mutex::new(Vec::new()) }
+    }
+}
+
+/// Buffers kept between runs so that consecutive jobs of the same shape, as
+/// in a long batch, read and multiply without allocating again: the file
+/// text, both operands, the output and the per-thread bands of the naive
+/// kernel. Each buffer grows to the largest shape seen and is otherwise
+/// reused as is; a larger shape simply reallocates it.
+///
+/// Pipelined runs and the cache-oblivious kernel still allocate their own.
+#[derive(Debug, Default)]
+pub struct Workspace {
+    pub(crate) text: String,
+    pub(crate) f64: Buffers<f64>,
+    pub(crate) i64: Buffers<i64>,
+}
+
+impl Workspace {
+    pub fn new() -> Workspace {
+        Workspace::default()
+    }
+}
+
+#[cfg(test)]
+mod workspace_test {
+    use std::fs;
+    use crate::matrix::Matrix;
+    use crate::multiplication::{run, run_with_workspace, Config};
+    use crate::test_support::temp_path;
+    use crate::workspace::Workspace;
+
+    fn config(a: &str, b: &str, c: &str, threads: &str) -> Config {
+        Config::from_iter(["mm", a, b, c, threads].map(String::from).into_iter()).unwrap()
+    }
+
+    #[test]
+    fn same_shape_jobs_reuse_the_output_buffer() {
+        let (a, b, c) = (temp_path("reuse_a.txt"), temp_path("reuse_b.txt"), temp_path("reuse_c.txt"));
+        Matrix::new(30, 20, (0..600).map(|num| num as f64 * 0.5).collect()).unwrap().to_file(&a).unwrap();
+        Matrix::new(20, 10, (0..200).map(|num| num as f64 - 7.0).collect()).unwrap().to_file(&b).unwrap();
+
+        let mut workspace = Workspace::new();
+        run_with_workspace(config(&a, &b, &c, "3"), &mut workspace).unwrap();
+        let (output, operand) = (workspace.f64.output.as_ptr(), workspace.f64.a.as_ptr());
+        let first = fs::read_to_string(&c).unwrap();
+
+        run_with_workspace(config(&a, &b, &c, "3"), &mut workspace).unwrap();
+        let second = fs::read_to_string(&c).unwrap();
+
+        run(config(&a, &b, &c, "3")).unwrap();
+        let fresh = fs::read_to_string(&c).unwrap();
+
+        for file in [&a, &b, &c] {
+            fs::remove_file(file).unwrap();
+        }
+
+        assert_eq!((workspace.f64.output.as_ptr(), workspace.f64.output.len()), (output, 300));
+        assert_eq!(workspace.f64.a.as_ptr(), operand);
+        assert_eq!(first, fresh);
+        assert_eq!(second, fresh);
+    }
+
+    #[test]
+    fn interleaved_shapes_and_types_stay_correct() {
+        let files: Vec<String> = ["a", "b", "c", "d", "out"].iter().map(|name| temp_path(&format!("mixed_{name}.txt"))).collect();
+        let (a, b, c, d, out) = (&files[0], &files[1], &files[2], &files[3], &files[4]);
+        Matrix::new(3, 4, (0..12).map(|num| num as f64 / 3.0).collect()).unwrap().to_file(a).unwrap();
+        Matrix::new(4, 2, (0..8).map(|num| num as f64 - 2.5).collect()).unwrap().to_file(b).unwrap();
+        Matrix::new(6, 6, (0..36).map(|num| num - 18).collect::<Vec<i64>>()).unwrap().to_file(c).unwrap();
+        Matrix::new(6, 5, (0..30).map(|num| num % 7).collect::<Vec<i64>>()).unwrap().to_file(d).unwrap();
+
+        let mut workspace = Workspace::new();
+        let mut outputs = vec![];
+        for (left, right, threads) in [(a, b, "2"), (c, d, "4"), (a, b, "1"), (c, c, "6"), (a, b, "3")] {
+            run_with_workspace(config(left, right, out, threads), &mut workspace).unwrap();
+            let reused = fs::read_to_string(out).unwrap();
+            run(config(left, right, out, threads)).unwrap();
+            outputs.push((reused, fs::read_to_string(out).unwrap()));
+        }
+
+        for file in &files {
+            fs::remove_file(file).unwrap();
+        }
+
+        for (reused, fresh) in outputs {
+            assert_eq!(reused, fresh);
+        }
+    }
+}
@@ -0,0 +1,530 @@
+use std::fmt::{Display, LowerExp};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::element::Element;
+use crate::io::{Binary, Coo, Format, MatrixFormat};
+use crate::layout::COLUMN_MAJOR_MARKER;
+use crate::matrix::Matrix;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatFormat {
+    /// Shortest representation that reads back to the same value, as `Display` prints it.
+    Shortest,
+    /// `1.5e3` style, as `LowerExp` prints it.
+    Scientific,
+}
+
+/// Where [`Matrix::write_with`] sends its output.
+pub enum WriteTarget<'a> {
+    Path(&'a str),
+    Writer(&'a mut dyn Write),
+}
+
+impl<'a> From<&'a str> for WriteTarget<'a> {
+    fn from(path: &'a str) -> WriteTarget<'a> {
+        WriteTarget::Path(path)
+    }
+}
+
+impl<'a, W: Write> From<&'a mut W> for WriteTarget<'a> {
+    fn from(writer: &'a mut W) -> WriteTarget<'a> {
+        WriteTarget::Writer(writer)
+    }
+}
+
+/// Every output knob in one place. `WriteOptions::new()` produces exactly what
+/// [`Matrix::to_file`] writes; settings left unset use the format's default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteOptions {
+    format: Format,
+    precision: Option<usize>,
+    delimiter: Option<char>,
+    align: bool,
+    header: Option<bool>,
+    float_format: FloatFormat,
+    zero_tolerance: Option<f64>,
+    atomic: bool,
+    column_major: bool,
+    annotate: bool,
+    sources: Vec<String>,
+    comments: Vec<String>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            format: Format::PlainText,
+            precision: None,
+            delimiter: None,
+            align: false,
+            header: None,
+            float_format: FloatFormat::Shortest,
+            zero_tolerance: None,
+            atomic: false,
+            column_major: false,
+            annotate: false,
+            sources: vec![],
+            comments: vec![],
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    pub fn format(mut self, format: Format) -> WriteOptions {
+        self.format = format;
+        self
+    }
+
+    /// Digits after the decimal point for floats; integers are unaffected.
+    pub fn precision(mut self, precision: usize) -> WriteOptions {
+        self.precision = Some(precision);
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> WriteOptions {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    /// Right-align every element to the width of the widest one.
+    pub fn align(mut self, align: bool) -> WriteOptions {
+        self.align = align;
+        self
+    }
+
+    /// Whether the rows and columns lines are written.
+    pub fn header(mut self, header: bool) -> WriteOptions {
+        self.header = Some(header);
+        self
+    }
+
+    pub fn float_format(mut self, float_format: FloatFormat) -> WriteOptions {
+        self.float_format = float_format;
+        self
+    }
+
+    /// Elements with magnitude at most `zero_tolerance` are left out; COO only.
+    pub fn zero_tolerance(mut self, zero_tolerance: f64) -> WriteOptions {
+        self.zero_tolerance = Some(zero_tolerance);
+        self
+    }
+
+    /// Write to a temporary file next to the target and rename it into place.
+    pub fn atomic(mut self, atomic: bool) -> WriteOptions {
+        self.atomic = atomic;
+        self
+    }
+
+    /// Write plain text one column per line, marking the header so
+    /// [`Matrix::from_file`] reads it back in the right order.
+    pub fn column_major(mut self, column_major: bool) -> WriteOptions {
+        self.column_major = column_major;
+        self
+    }
+
+    /// Precede the header with `#` comment lines giving the write time, crate
+    /// version, dimensions, min/max/mean and content hash; plain text only.
+    pub fn annotate(mut self, annotate: bool) -> WriteOptions {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Files the matrix was computed from, listed in the annotation.
+    pub fn sources(mut self, sources: Vec<String>) -> WriteOptions {
+        self.sources = sources;
+        self
+    }
+
+    /// `#` lines written at the top, above any annotation and the header, as
+    /// collected by [`Matrix::from_file_with_metadata`]; plain text and CSV only.
+    pub fn comments(mut self, comments: Vec<String>) -> WriteOptions {
+        self.comments = comments;
+        self
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(comment) = self.comments.iter().find(|comment| !comment.starts_with('#') || comment.contains('\n')) {
+            return Err(format!("Comment {:?} must be a single line starting with '#'", comment))
+        }
+
+        let name = match self.format {
+            Format::PlainText => "plain text",
+            Format::Csv => "CSV",
+            Format::Binary => "binary",
+            Format::Coo => "COO",
+        };
+
+        let incompatible = |setting: &str| Err(format!("{} output doesn't support {}", name, setting));
+
+        match self.format {
+            Format::Binary if self.precision.is_some() => incompatible("precision"),
+            Format::Binary if self.float_format != FloatFormat::Shortest => incompatible("a float format"),
+            Format::Binary | Format::Coo if self.delimiter.is_some() => incompatible("a delimiter"),
+            Format::Binary | Format::Coo if self.align => incompatible("alignment"),
+            Format::Binary | Format::Coo if self.header == Some(false) => incompatible("headerless output"),
+            Format::Csv if self.header == Some(true) => incompatible("a header"),
+            Format::PlainText | Format::Csv | Format::Binary if self.zero_tolerance.is_some() =>
+                incompatible("zero suppression"),
+            Format::Csv | Format::Binary | Format::Coo if self.column_major => incompatible("column-major order"),
+            Format::PlainText if self.column_major && self.header == Some(false) =>
+                incompatible("headerless column-major order"),
+            Format::Csv | Format::Binary | Format::Coo if self.annotate => incompatible("annotations"),
+            Format::Binary | Format::Coo if !self.comments.is_empty() => incompatible("comments"),
+            Format::PlainText if self.annotate && self.header == Some(false) =>
+                incompatible("headerless annotations"),
+            _ => Ok(())
+        }
+    }
+
+    /// Delimiter and whether a header is written, unless set explicitly.
+    fn layout_defaults(&self) -> (char, bool) {
+        match self.format {
+            Format::Csv => (',', false),
+            _ => (' ', true),
+        }
+    }
+
+    fn format_element<T: Display + LowerExp>(&self, num: &T) -> String {
+        match (self.float_format, self.precision) {
+            (FloatFormat::Shortest, None) => num.to_string(),
+            (FloatFormat::Shortest, Some(precision)) => format!("{:.*}", precision, num),
+            (FloatFormat::Scientific, None) => format!("{:e}", num),
+            (FloatFormat::Scientific, Some(precision)) => format!("{:.*e}", precision, num),
+        }
+    }
+}
+
+impl<T: FromStr + Display + LowerExp + Element> Matrix<T> {
+    /// Writes the matrix to a path or writer as described by `options`.
+    ///
+    /// Incompatible combinations, such as COO with alignment or an atomic write
+    /// to a writer, are rejected before anything is written.
+    pub fn write_with<'a>(&self, target: impl Into<WriteTarget<'a>>, options: &WriteOptions) -> Result<(), String> {
+        options.validate()?;
+
+        let target = target.into();
+
+        if options.atomic && matches!(target, WriteTarget::Writer(_)) {
+            return Err(String::from("Atomic writes need a file path, not a writer"))
+        }
+
+        let bytes = render(self, options)?;
+
+        match target {
+            WriteTarget::Writer(writer) => writer
+                .write_all(&bytes)
+                .and_then(|_| writer.flush())
+                .map_err(|error| format!("Error writing matrix: {}", error)),
+            WriteTarget::Path(path) if options.atomic => write_atomic(path, &bytes),
+            WriteTarget::Path(path) => fs::write(path, bytes)
+                .map_err(|error| format!("Error writing to file {}: {}", path, error)),
+        }
+    }
+}
+
+fn render<T: FromStr + Display + LowerExp + Element>(matrix: &Matrix<T>, options: &WriteOptions) -> Result<Vec<u8>, String> {
+    if options.format == Format::Binary {
+        let mut bytes = Vec::new();
+        Binary.write(matrix, Cursor::new(&mut bytes))?;
+        return Ok(bytes)
+    }
+
+    if options.format == Format::Coo {
+        let mut bytes = Vec::new();
+        Coo { zero_tolerance: options.zero_tolerance.unwrap_or(0.0) }
+            .write_formatted(matrix, Cursor::new(&mut bytes), |num| options.format_element(num))?;
+        return Ok(bytes)
+    }
+
+    let rows = matrix.rows();
+    let columns = matrix.columns();
+    let numbers = matrix.as_slice();
+
+    let mut text = String::new();
+
+    let (default_delimiter, default_header) = options.layout_defaults();
+
+    for comment in &options.comments {
+        text += comment;
+        text.push('\n');
+    }
+
+    if options.annotate {
+        text += &annotation(matrix, &options.sources);
+    }
+
+    if options.header.unwrap_or(default_header) {
+        text += &format!("{}\n{}\n", rows, columns);
+    }
+
+    let (elements, rows, columns) = if options.column_major {
+        text += COLUMN_MAJOR_MARKER;
+        text.push('\n');
+        let elements = matrix.to_column_major_vec().iter().map(|num| options.format_element(num)).collect();
+        (elements, columns, rows)
+    } else {
+        (numbers.iter().map(|num| options.format_element(num)).collect::<Vec<String>>(), rows, columns)
+    };
+
+    let width = if options.align {
+        elements.iter().map(|element| element.len()).max().unwrap_or(0)
+    } else {
+        0
+    };
+
+    let delimiter = options.delimiter.unwrap_or(default_delimiter).to_string();
+
+    for row in 0..rows {
+        let line: Vec<String> = elements[row * columns..(row + 1) * columns]
+            .iter()
+            .map(|element| format!("{:>width$}", element))
+            .collect();
+
+        text += &line.join(&delimiter);
+        text.push('\n');
+    }
+
+    Ok(text.into_bytes())
+}
+
+/// Renders plain text or CSV a block of rows at a time, byte for byte as
+/// [`Matrix::write_with`] would, for writers that start before the whole
+/// matrix exists. Alignment, column-major order and annotations need every
+/// element up front, so they aren't supported.
+pub(crate) struct RowRenderer<'a> {
+    options: &'a WriteOptions,
+    delimiter: String,
+}
+
+impl<'a> RowRenderer<'a> {
+    pub(crate) fn new(options: &'a WriteOptions) -> Result<RowRenderer<'a>, String> {
+        options.validate()?;
+
+        if !matches!(options.format, Format::PlainText | Format::Csv) || options.align || options.column_major || options.annotate
+            || !options.comments.is_empty() {
+            return Err(String::from("Only plain text or CSV output without alignment, column-major order, annotations or comments can be written row by row"))
+        }
+
+        let delimiter = options.delimiter.unwrap_or(options.layout_defaults().0).to_string();
+
+        Ok(RowRenderer { options, delimiter })
+    }
+
+    pub(crate) fn header(&self, rows: usize, columns: usize) -> String {
+        if self.options.header.unwrap_or(self.options.layout_defaults().1) {
+            format!("{}\n{}\n", rows, columns)
+        } else {
+            String::new()
+        }
+    }
+
+    /// `numbers` holds `rows` whole rows of `columns` elements.
+    pub(crate) fn rows<T: Display + LowerExp>(&self, numbers: &[T], rows: usize, columns: usize) -> String {
+        let mut text = String::new();
+
+        for row in 0..rows {
+            let line: Vec<String> = numbers[row * columns..(row + 1) * columns]
+                .iter()
+                .map(|num| self.options.format_element(num))
+                .collect();
+
+            text += &line.join(&self.delimiter);
+            text.push('\n');
+        }
+
+        text
+    }
+}
+
+fn annotation<T: Element>(matrix: &Matrix<T>, sources: &[String]) -> String {
+    let generated = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+
+    let mut text = format!("# generated: {} (unix seconds)\n# mm version: {}\n", generated, env!("CARGO_PKG_VERSION"));
+
+    if !sources.is_empty() {
+        text += &format!("# operands: {}\n", sources.join(" "));
+    }
+
+    text += &format!("# dims: {}\n", matrix.shape());
+
+    let numbers = matrix.as_slice();
+    if !numbers.is_empty() {
+        let values = numbers.iter().map(|num| num.to_f64());
+        let (min, max) = values.clone().fold((f64::INFINITY, f64::NEG_INFINITY),
+                                             |(min, max), num| (min.min(num), max.max(num)));
+        let mean = values.sum::<f64>() / numbers.len() as f64;
+        text += &format!("# min: {} max: {} mean: {}\n", min, max, mean);
+    }
+
+    text += &format!("# content hash: {:016x}\n", matrix.content_hash());
+    text
+}
+
+/// Scratch file an atomic write to `path` goes through before the rename.
+pub(crate) fn temp_path(path: &str) -> String {
+    format!("{}.tmp{}", path, std::process::id())
+}
+
+fn write_atomic(path: &str, bytes: &[u8]) -> Result<(), String> {
+    let temp_path = temp_path(path);
+
+    let result = fs::write(&temp_path, bytes).and_then(|_| fs::rename(&temp_path, path));
+
+    if let Err(error) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Error writing to file {}: {}", path, error))
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod write_test {
+    use std::fs;
+    use crate::io::Format;
+    use crate::matrix::Matrix;
+    use crate::test_support;
+    use crate::write::{FloatFormat, WriteOptions};
+
+    fn sample() -> Matrix<f64> {
+        Matrix::from_vec(vec!["2", "3", "1.5 -20 0", "0.25 3 100"]).unwrap()
+    }
+
+    fn written(matrix: &Matrix<f64>, options: &WriteOptions) -> Result<String, String> {
+        let mut bytes = Vec::new();
+        matrix.write_with(&mut bytes, options)?;
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    #[test]
+    fn defaults_match_to_file() {
+        let matrix = sample();
+        let path = &test_support::temp_path("write_test");
+
+        matrix.to_file(path).unwrap();
+        let expected = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(written(&matrix, &WriteOptions::new()).unwrap().into_bytes(), expected);
+
+        let integers = Matrix::<i32>::from_vec(vec!["1", "2", "7 -8"]).unwrap();
+        let mut bytes = Vec::new();
+        integers.write_with(&mut bytes, &WriteOptions::new()).unwrap();
+        assert_eq!(bytes, integers.to_string().into_bytes());
+    }
+
+    #[test]
+    fn precision_and_alignment() {
+        let options = WriteOptions::new().precision(1).align(true).header(false);
+
+        assert_eq!(written(&sample(), &options).unwrap(),
+                   "  1.5 -20.0   0.0\n  0.2   3.0 100.0\n");
+    }
+
+    #[test]
+    fn csv_with_custom_delimiter_and_scientific() {
+        let options = WriteOptions::new()
+            .format(Format::Csv)
+            .delimiter(';')
+            .float_format(FloatFormat::Scientific);
+
+        assert_eq!(written(&sample(), &options).unwrap(), "1.5e0;-2e1;0e0\n2.5e-1;3e0;1e2\n");
+    }
+
+    #[test]
+    fn coo_with_zero_tolerance() {
+        let matrix = Matrix::new(2, 2, vec![1e-12, 2.0, 0.0, -1.0]).unwrap();
+        let options = WriteOptions::new().format(Format::Coo).zero_tolerance(1e-9);
+
+        assert_eq!(written(&matrix, &options).unwrap(), "2\n2\n2\n0 1 2\n1 1 -1\n");
+    }
+
+    #[test]
+    fn atomic_write_to_path() {
+        let path = &test_support::temp_path("write_atomic");
+
+        sample().write_with(path.as_str(), &WriteOptions::new().atomic(true)).unwrap();
+        let result = Matrix::<f64>::from_file(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(result.unwrap(), sample());
+    }
+
+    #[test]
+    fn incompatible_combinations() {
+        let incompatible = [
+            WriteOptions::new().format(Format::Coo).align(true),
+            WriteOptions::new().format(Format::Coo).header(false),
+            WriteOptions::new().format(Format::Coo).delimiter(','),
+            WriteOptions::new().format(Format::Binary).precision(3),
+            WriteOptions::new().format(Format::Csv).header(true),
+            WriteOptions::new().zero_tolerance(0.1),
+            WriteOptions::new().atomic(true),
+            WriteOptions::new().format(Format::Csv).column_major(true),
+            WriteOptions::new().column_major(true).header(false),
+            WriteOptions::new().format(Format::Binary).comments(vec![String::from("# source")]),
+            WriteOptions::new().comments(vec![String::from("source")]),
+        ];
+
+        for options in incompatible {
+            assert!(written(&sample(), &options).is_err(), "{options:?} should be rejected");
+        }
+
+        let error = written(&sample(), &WriteOptions::new().format(Format::Coo).align(true)).unwrap_err();
+        assert_eq!(error, "COO output doesn't support alignment");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_coo_wrapper_matches() {
+        let matrix = Matrix::new(2, 2, vec![1e-12, 2.0, 0.0, -1.0]).unwrap();
+        let path = &test_support::temp_path("write_coo");
+
+        matrix.to_file_coo(path, 1e-9).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let options = WriteOptions::new().format(Format::Coo).zero_tolerance(1e-9);
+        assert_eq!(contents, written(&matrix, &options).unwrap());
+    }
+
+    #[test]
+    fn column_major_file_reparses() {
+        let matrix = sample();
+        let path = &test_support::temp_path("write_column_major");
+
+        matrix.write_with(path.as_str(), &WriteOptions::new().column_major(true)).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        let result = Matrix::<f64>::from_file(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(contents, "2\n3\ncolumn-major\n1.5 0.25\n-20 3\n0 100\n");
+        assert_eq!(result.unwrap(), matrix);
+    }
+
+    #[test]
+    fn annotated_output_reparses() {
+        let matrix = sample();
+        let options = WriteOptions::new().annotate(true).sources(vec![String::from("a.txt"), String::from("b.txt")]);
+
+        let contents = written(&matrix, &options).unwrap();
+        let comments: Vec<&str> = contents.lines().take_while(|line| line.starts_with('#')).collect();
+
+        assert_eq!(&comments[2..], [
+            "# operands: a.txt b.txt",
+            "# dims: 2x3",
+            "# min: -20 max: 100 mean: 14.125",
+            &format!("# content hash: {:016x}", matrix.content_hash()),
+        ]);
+        assert_eq!(Matrix::<f64>::from_vec(contents.lines().collect()).unwrap(), matrix);
+
+        assert_eq!(written(&matrix, &WriteOptions::new().format(Format::Csv).annotate(true)).unwrap_err(),
+                   "CSV output doesn't support annotations");
+    }
+}
@@ -0,0 +1,8 @@
+use std::env;
+
+/// A path in the temporary directory for the file `name`, kept apart from
+/// other test binaries running at the same time by the process id.
+pub fn temp_path(name: &str) -> String {
+    let path = env::temp_dir().join(format!("mm_integration_{}_{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
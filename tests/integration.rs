@@ -0,0 +1,177 @@
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use mm::convert::{run_convert, ConvertConfig};
+use mm::matrix::Matrix;
+use mm::multiplication::{run, Config};
+
+/// A scratch directory holding copies of the fixtures, removed on drop.
+struct Workspace {
+    dir: PathBuf,
+}
+
+impl Workspace {
+    fn new(name: &str) -> Workspace {
+        let dir = PathBuf::from(common::temp_path(name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for entry in fs::read_dir(fixtures()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_file() {
+                fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+            }
+        }
+
+        Workspace { dir }
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.join(name).to_str().unwrap().to_string()
+    }
+
+    fn run(&self, args: &[&str]) -> Result<(), String> {
+        run(config(args)?)
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+fn config(args: &[&str]) -> Result<Config, String> {
+    Config::from_iter(std::iter::once("mm").chain(args.iter().copied()).map(String::from))
+}
+
+/// Compares `actual_path` with `tests/fixtures/golden/<name>` byte for byte.
+///
+/// With `REGENERATE_GOLDEN=1` the golden file is rewritten from `actual_path`
+/// instead, so format changes show up as a diff of the fixtures.
+fn assert_golden(actual_path: &str, name: &str) {
+    let golden = fixtures().join("golden").join(name);
+    let actual = fs::read(actual_path).unwrap();
+
+    if env::var("REGENERATE_GOLDEN").as_deref() == Ok("1") {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        fs::write(&golden, &actual).unwrap();
+        return
+    }
+
+    let expected = fs::read(&golden)
+        .unwrap_or_else(|error| panic!("Missing golden file {}: {}", golden.display(), error));
+
+    assert_eq!(String::from_utf8_lossy(&actual), String::from_utf8_lossy(&expected),
+               "{} differs from golden {}", actual_path, golden.display());
+}
+
+#[test]
+fn multiply_matches_golden_plain_text() {
+    let workspace = Workspace::new("plain");
+    let output = workspace.path("c.txt");
+
+    workspace.run(&[&workspace.path("a.txt"), &workspace.path("b.txt"), &output, "2"]).unwrap();
+
+    assert_golden(&output, "a_times_b.txt");
+}
+
+#[test]
+fn multiply_matches_golden_csv() {
+    let workspace = Workspace::new("csv");
+    let output = workspace.path("c.csv");
+
+    workspace.run(&[&workspace.path("a.txt"), &workspace.path("b.txt"), &output, "3", "--format", "csv"]).unwrap();
+
+    assert_golden(&output, "a_times_b.csv");
+}
+
+#[test]
+fn config_error_paths() {
+    let cases: [(&[&str], &str); 8] = [
+        (&[], "Missing Matrix A file name"),
+        (&["a.txt"], "Missing Matrix B file name"),
+        (&["a.txt", "b.txt"], "Missing Matrix C file name"),
+        (&["a.txt", "b.txt", "c.txt"], "Missing number of threads"),
+        (&["a.txt", "b.txt", "c.txt", "two"], "Couldn't get number of threads from two"),
+        (&["a.txt", "b.txt", "c.txt", "1", "--fast"], "Unknown option --fast"),
+        (&["a.txt", "b.txt", "c.txt", "1", "--format"], "Missing value for --format"),
+        (&["a.txt", "b.txt", "c.txt", "1", "--zero-tolerance", "-1"], "Couldn't get zero tolerance from -1"),
+    ];
+
+    for (args, expected) in cases {
+        assert_eq!(config(args).unwrap_err(), expected, "args {args:?}");
+    }
+}
+
+#[test]
+fn dimension_mismatch_between_files() {
+    let workspace = Workspace::new("mismatch");
+    let output = workspace.path("c.txt");
+
+    let error = workspace.run(&[&workspace.path("a.txt"), &workspace.path("square.txt"), &output, "1"]).unwrap_err();
+
+    assert_eq!(error, "Dimension mismatch: expected 2x3, got 3x3");
+    assert!(!Path::new(&output).exists());
+}
+
+#[test]
+fn unreadable_input_path() {
+    let workspace = Workspace::new("unreadable");
+    let missing = workspace.path("missing.txt");
+
+    let error = workspace.run(&[&missing, &workspace.path("b.txt"), &workspace.path("c.txt"), "1"]).unwrap_err();
+
+    assert!(error.starts_with(&format!("Input file {} can't be read", missing)), "{error}");
+}
+
+#[test]
+fn unwritable_output_directory() {
+    let workspace = Workspace::new("unwritable");
+    let output = workspace.path("no_such_dir/c.txt");
+
+    let error = workspace.run(&[&workspace.path("a.txt"), &workspace.path("b.txt"), &output, "1"]).unwrap_err();
+
+    assert!(error.starts_with(&format!("Output file {} can't be written", output)), "{error}");
+}
+
+#[test]
+fn annotated_output_describes_product() {
+    let workspace = Workspace::new("annotate");
+    let (matrix_a, matrix_b, output) = (workspace.path("a.txt"), workspace.path("b.txt"), workspace.path("c.txt"));
+
+    workspace.run(&[&matrix_a, &matrix_b, &output, "1", "--annotate"]).unwrap();
+
+    let contents = fs::read_to_string(&output).unwrap();
+    let golden = Matrix::<f64>::from_file(fixtures().join("golden").join("a_times_b.txt").to_str().unwrap()).unwrap();
+
+    assert_eq!(Matrix::<f64>::from_file(&output).unwrap(), golden);
+    assert!(contents.contains(&format!("# operands: {} {}\n", matrix_a, matrix_b)), "{contents}");
+    assert!(contents.contains("# dims: 3x4\n"), "{contents}");
+    assert!(contents.contains(&format!("# content hash: {:016x}\n", golden.content_hash())), "{contents}");
+    assert!(config(&[&matrix_a, &matrix_b, &output, "1", "--annotate", "--format", "csv"]).is_err());
+}
+
+#[test]
+fn canonical_convert_is_byte_stable() {
+    let workspace = Workspace::new("convert");
+    let (messy, first, second) = (workspace.path("messy.csv"), workspace.path("first.txt"), workspace.path("second.txt"));
+    fs::write(&messy, "# from the old exporter\n1.000000, 2.50\n-0.0e0,0.30000000000000004\n").unwrap();
+
+    let convert = |input: &str, output: &str| {
+        let args = ["mm", "convert", input, output, "--canonical"].map(String::from);
+        run_convert(ConvertConfig::from_iter(args.into_iter())?)
+    };
+
+    convert(&messy, &first).unwrap();
+    convert(&first, &second).unwrap();
+
+    assert_eq!(fs::read_to_string(&first).unwrap(), "# from the old exporter\n2\n2\n1 2.5\n-0 0.30000000000000004\n");
+    assert_eq!(fs::read(&second).unwrap(), fs::read(&first).unwrap());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::fs;
+use mm::prelude::*;
+use common::temp_path;
+
+#[test]
+fn prelude_is_enough_to_load_multiply_and_save() {
+    let (input, output) = (temp_path("a.txt"), temp_path("c.csv"));
+    fs::write(&input, "2\n2\n1 2\n3 4\n").unwrap();
+
+    let a = Matrix::<i64>::from_file_with(&input, &ParseOptions::new().limits(Limits::new().max_elements(4))).unwrap();
+    let mut options = MultiplyOptions::new(2);
+    options.kernel = Kernel::CacheOblivious;
+    let product = multiply_with_options(&a, &a, &options).unwrap();
+    product.write_with(output.as_str(), &WriteOptions::new().format(Format::Csv)).unwrap();
+    let written = fs::read_to_string(&output).unwrap();
+
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    assert_eq!(written, "7,10\n15,22\n");
+    assert_eq!(multiply(&a, &a, 1).unwrap(), product);
+    assert_eq!(product.shape(), Shape { rows: 2, cols: 2 });
+    assert!(matches!(multiply_with_options(&a, &Matrix::new(1, 1, vec![1]).unwrap(), &options),
+                     Err(MultiplyError::DimensionMismatch { .. })));
+}
+
+/// Glob imports of the crate root and the prelude together only compile
+/// while every name they both export is the same item.
+mod root_and_prelude {
+    use mm::*;
+    use mm::prelude::*;
+
+    #[test]
+    fn shared_names_are_the_same_items() {
+        let matrix: Matrix<i64> = Matrix::new(1, 1, vec![3]).unwrap();
+        let options: MultiplyOptions<i64> = MultiplyOptions::new(1);
+
+        assert_eq!(multiply(&matrix, &matrix, 1).unwrap()[(0, 0)], 9);
+        assert_eq!((options.num_of_threads, options.kernel), (1, Kernel::Naive));
+        assert_eq!(matrix.shape(), Shape { rows: 1, cols: 1 });
+        assert_eq!(MultiplyError::NoThreads.to_string(), "Num of threads: 0 must be higher than 0");
+    }
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use std::fs;
+use mm::matrix::Matrix;
+use mm::multiplication::{multiply, run, Config};
+use common::temp_path;
+
+/// Every combination of single and multiple rows, inner dimension and
+/// columns, so both operands range over 1x1, 1xN, Nx1 and NxM.
+const ROWS: [usize; 2] = [1, 3];
+const INNER: [usize; 2] = [1, 4];
+const COLUMNS: [usize; 2] = [1, 5];
+const THREADS: [usize; 3] = [1, 2, 8];
+
+fn numbers(len: usize, offset: i64) -> Vec<f64> {
+    (0..len as i64).map(|num| (num * 3 + offset) as f64 - 2.0).collect()
+}
+
+fn reference(a: &Matrix<f64>, b: &Matrix<f64>) -> Matrix<f64> {
+    let (rows, inner, columns) = (a.rows(), a.columns(), b.columns());
+    let (a_numbers, b_numbers) = (a.get_numbers(), b.get_numbers());
+
+    let numbers = (0..rows * columns)
+        .map(|index| (0..inner).map(|k| a_numbers[index / columns * inner + k] * b_numbers[k * columns + index % columns]).sum())
+        .collect();
+
+    Matrix::new(rows, columns, numbers).unwrap()
+}
+
+fn shapes() -> impl Iterator<Item=(usize, usize, usize)> {
+    ROWS.into_iter().flat_map(|rows| INNER.into_iter()
+        .flat_map(move |inner| COLUMNS.into_iter().map(move |columns| (rows, inner, columns))))
+}
+
+#[test]
+fn library_multiply_over_shape_grid() {
+    for (rows, inner, columns) in shapes() {
+        let a = Matrix::new(rows, inner, numbers(rows * inner, 1)).unwrap();
+        let b = Matrix::new(inner, columns, numbers(inner * columns, 5)).unwrap();
+        let expected = reference(&a, &b);
+
+        for threads in THREADS {
+            let case = format!("{rows}x{inner} * {inner}x{columns} on {threads} threads");
+            let product = multiply(&a, &b, threads.min(rows));
+
+            assert_eq!(product.as_ref(), Ok(&expected), "{case}");
+            assert_eq!(Matrix::<f64>::from_vec(product.unwrap().to_string().lines().collect()).as_ref(),
+                       Ok(&expected), "{case}");
+        }
+    }
+}
+
+#[test]
+fn run_over_shape_grid() {
+    let (matrix_a, matrix_b, output) = (temp_path("a.txt"), temp_path("b.txt"), temp_path("c.txt"));
+
+    for (rows, inner, columns) in shapes() {
+        let a = Matrix::new(rows, inner, numbers(rows * inner, 1)).unwrap();
+        let b = Matrix::new(inner, columns, numbers(inner * columns, 5)).unwrap();
+        a.to_file(&matrix_a).unwrap();
+        b.to_file(&matrix_b).unwrap();
+
+        assert_eq!(Matrix::<f64>::from_file(&matrix_a).as_ref(), Ok(&a));
+
+        let expected = reference(&a, &b);
+
+        for threads in THREADS {
+            let case = format!("{rows}x{inner} * {inner}x{columns} on {threads} threads");
+            let args = ["mm", &matrix_a, &matrix_b, &output, &threads.to_string()].map(String::from);
+
+            assert_eq!(run(Config::from_iter(args.into_iter()).unwrap()), Ok(()), "{case}");
+            assert_eq!(fs::read_to_string(&output).unwrap(), expected.to_string(), "{case}");
+            assert_eq!(Matrix::<f64>::from_file(&output).as_ref(), Ok(&expected), "{case}");
+        }
+    }
+
+    for file in [&matrix_a, &matrix_b, &output] {
+        fs::remove_file(file).unwrap();
+    }
+}